@@ -0,0 +1,321 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+//! Canonical forms of bitstreams
+//!
+//! Bitstreams that behave identically can still differ in the fuses for resources that aren't used, such as ZIA rows
+//! that no AND term looks at or the register settings of a macrocell that is only used combinatorially. This module
+//! finds out which resources are live by following the logic backwards from the pins that are outputs, and resets
+//! everything else to the state that `blank_bitstream` would leave it in.
+//!
+//! The global nets and the electrical settings of the pins (termination, slew rate, I/O standards, and so on) are
+//! always kept, since they can be observed from outside of the device even when no logic uses them.
+
+use std::collections::HashSet;
+
+use jedec::*;
+
+use crate::*;
+use crate::fusemap_logical::{total_logical_fuse_count};
+use crate::util::{LinebreakSet};
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+enum LiveResource {
+    AndTerm{fb: u32, i: u32},
+    Xor{fb: u32, mc: u32},
+    Reg{fb: u32, mc: u32},
+}
+
+#[derive(Default)]
+struct Liveness {
+    resources: HashSet<LiveResource>,
+    zia_rows: HashSet<(u32, u32)>,
+    mc_feedback: HashSet<(u32, u32)>,
+    iob_feedback: HashSet<u32>,
+}
+
+// The mode of the output buffer of an IOB (if it exists), and whether it outputs the register instead of the XOR gate
+fn iob_output(bits: &XC2BitstreamBits, iob: u32) -> (XC2IOBOBufMode, bool) {
+    if let Some(iob) = bits.get_small_iob(iob as usize) {
+        (iob.obuf_mode, iob.obuf_uses_ff)
+    } else {
+        let iob = bits.get_large_iob(iob as usize).unwrap();
+        (iob.obuf_mode, iob.obuf_uses_ff)
+    }
+}
+
+fn iob_zia_mode(bits: &XC2BitstreamBits, iob: u32) -> XC2IOBZIAMode {
+    if let Some(iob) = bits.get_small_iob(iob as usize) {
+        iob.zia_mode
+    } else {
+        bits.get_large_iob(iob as usize).unwrap().zia_mode
+    }
+}
+
+fn find_live_resources(bits: &XC2BitstreamBits) -> Liveness {
+    let device = bits.device_type();
+    let mut live = Liveness::default();
+    let mut worklist = Vec::new();
+
+    // Everything starts from the output pins
+    for iob in 0..device.num_iobs() as u32 {
+        let (fb, mc) = iob_num_to_fb_mc_num(device, iob).unwrap();
+        let (obuf_mode, obuf_uses_ff) = iob_output(bits, iob);
+        match obuf_mode {
+            XC2IOBOBufMode::Disabled | XC2IOBOBufMode::CGND => continue,
+            XC2IOBOBufMode::TriStatePTB => worklist.push(LiveResource::AndTerm{fb, i: get_ptb(mc)}),
+            XC2IOBOBufMode::TriStateCTE => worklist.push(LiveResource::AndTerm{fb, i: CTE}),
+            _ => {},
+        }
+        worklist.push(if obuf_uses_ff { LiveResource::Reg{fb, mc} } else { LiveResource::Xor{fb, mc} });
+    }
+
+    while let Some(resource) = worklist.pop() {
+        if !live.resources.insert(resource) {
+            continue;
+        }
+
+        match resource {
+            LiveResource::AndTerm{fb, i} => {
+                let fb_bits = &bits.get_fb()[fb as usize];
+                let andterm = fb_bits.get_andterm(i as usize);
+                for row in 0..INPUTS_PER_ANDTERM {
+                    if !andterm.get(row) && !andterm.get_b(row) {
+                        continue;
+                    }
+
+                    live.zia_rows.insert((fb, row as u32));
+                    match *fb_bits.get_zia(row) {
+                        XC2ZIAInput::Macrocell{fb: src_fb, mc: src_mc} => {
+                            let (src_fb, src_mc) = (src_fb as u32, src_mc as u32);
+                            live.mc_feedback.insert((src_fb, src_mc));
+                            match bits.get_fb()[src_fb as usize].mcs[src_mc as usize].fb_mode {
+                                XC2MCFeedbackMode::COMB => worklist.push(LiveResource::Xor{fb: src_fb, mc: src_mc}),
+                                XC2MCFeedbackMode::REG => worklist.push(LiveResource::Reg{fb: src_fb, mc: src_mc}),
+                                XC2MCFeedbackMode::Disabled => {},
+                            }
+                        },
+                        XC2ZIAInput::IBuf{ibuf} => {
+                            live.iob_feedback.insert(ibuf as u32);
+                            if iob_zia_mode(bits, ibuf as u32) == XC2IOBZIAMode::REG {
+                                let (src_fb, src_mc) = iob_num_to_fb_mc_num(device, ibuf as u32).unwrap();
+                                worklist.push(LiveResource::Reg{fb: src_fb, mc: src_mc});
+                            }
+                        },
+                        _ => {},
+                    }
+                }
+            },
+            LiveResource::Xor{fb, mc} => {
+                let fb_bits = &bits.get_fb()[fb as usize];
+                for i in 0..ANDTERMS_PER_FB {
+                    if fb_bits.or_terms[mc as usize].get(i) {
+                        worklist.push(LiveResource::AndTerm{fb, i: i as u32});
+                    }
+                }
+                match fb_bits.mcs[mc as usize].xor_mode {
+                    XC2MCXorMode::PTC | XC2MCXorMode::PTCB => worklist.push(LiveResource::AndTerm{fb, i: get_ptc(mc)}),
+                    _ => {},
+                }
+            },
+            LiveResource::Reg{fb, mc} => {
+                let mc_bits = &bits.get_fb()[fb as usize].mcs[mc as usize];
+                if !mc_bits.ff_in_ibuf {
+                    worklist.push(LiveResource::Xor{fb, mc});
+                }
+                match mc_bits.clk_src {
+                    XC2MCRegClkSrc::PTC => worklist.push(LiveResource::AndTerm{fb, i: get_ptc(mc)}),
+                    XC2MCRegClkSrc::CTC => worklist.push(LiveResource::AndTerm{fb, i: CTC}),
+                    _ => {},
+                }
+                match mc_bits.r_src {
+                    XC2MCRegResetSrc::PTA => worklist.push(LiveResource::AndTerm{fb, i: get_pta(mc)}),
+                    XC2MCRegResetSrc::CTR => worklist.push(LiveResource::AndTerm{fb, i: CTR}),
+                    _ => {},
+                }
+                match mc_bits.s_src {
+                    XC2MCRegSetSrc::PTA => worklist.push(LiveResource::AndTerm{fb, i: get_pta(mc)}),
+                    XC2MCRegSetSrc::CTS => worklist.push(LiveResource::AndTerm{fb, i: CTS}),
+                    _ => {},
+                }
+                if mc_bits.reg_mode == XC2MCRegMode::DFFCE {
+                    worklist.push(LiveResource::AndTerm{fb, i: get_ptc(mc)});
+                }
+            },
+        }
+    }
+
+    live
+}
+
+impl XC2Bitstream {
+    /// Resets all of the resources that can't affect the behavior of the device to their blank state, so that
+    /// bitstreams that behave the same have the same fuses. See `content_hash` for comparing the result.
+    pub fn canonicalize(&mut self) {
+        let device = self.bits.device_type();
+        let live = find_live_resources(&self.bits);
+        let blank_mc = XC2Macrocell::default();
+
+        for (fb, fb_bits) in self.bits.get_fb_mut().iter_mut().enumerate() {
+            let fb = fb as u32;
+
+            for i in 0..ANDTERMS_PER_FB {
+                if !live.resources.contains(&LiveResource::AndTerm{fb, i: i as u32}) {
+                    *fb_bits.get_mut_andterm(i) = XC2PLAAndTerm::default();
+                }
+            }
+            for row in 0..INPUTS_PER_ANDTERM {
+                if !live.zia_rows.contains(&(fb, row as u32)) {
+                    *fb_bits.get_mut_zia(row) = XC2ZIAInput::default();
+                }
+            }
+
+            for mc in 0..MCS_PER_FB {
+                let mc_bits = &mut fb_bits.mcs[mc];
+                let mc = mc as u32;
+                if !live.resources.contains(&LiveResource::Xor{fb, mc}) {
+                    fb_bits.or_terms[mc as usize] = XC2PLAOrTerm::default();
+                    mc_bits.xor_mode = blank_mc.xor_mode;
+                }
+                if !live.resources.contains(&LiveResource::Reg{fb, mc}) {
+                    *mc_bits = XC2Macrocell {
+                        fb_mode: mc_bits.fb_mode,
+                        xor_mode: mc_bits.xor_mode,
+                        ..blank_mc
+                    };
+                }
+                if !live.mc_feedback.contains(&(fb, mc)) {
+                    mc_bits.fb_mode = blank_mc.fb_mode;
+                }
+            }
+        }
+
+        for iob in 0..device.num_iobs() as u32 {
+            let (obuf_mode, _) = iob_output(&self.bits, iob);
+            let output_unused = obuf_mode == XC2IOBOBufMode::Disabled || obuf_mode == XC2IOBOBufMode::CGND;
+            let feedback_unused = !live.iob_feedback.contains(&iob);
+            if let Some(iob_bits) = self.bits.get_mut_small_iob(iob as usize) {
+                if output_unused {
+                    iob_bits.obuf_uses_ff = false;
+                }
+                if feedback_unused {
+                    iob_bits.zia_mode = XC2IOBZIAMode::Disabled;
+                }
+            } else {
+                let iob_bits = self.bits.get_mut_large_iob(iob as usize).unwrap();
+                if output_unused {
+                    iob_bits.obuf_uses_ff = false;
+                }
+                if feedback_unused {
+                    iob_bits.zia_mode = XC2IOBZIAMode::Disabled;
+                }
+            }
+        }
+    }
+
+    /// Returns a hash of the part name and all of the fuses. This only depends on the contents of the bitstream, so it
+    /// is the same on every machine and with every version of this library (as long as the fuse map doesn't change).
+    /// Call `canonicalize` first to get the same hash for bitstreams that only differ in unused resources.
+    pub fn content_hash(&self) -> u64 {
+        let mut jed = JEDECFile::new(total_logical_fuse_count(self.bits.device_type()));
+        self.bits.to_jed(&mut jed, &mut LinebreakSet::new());
+        let part_name = format!("{}-{}-{}", self.bits.device_type(), self.speed_grade, self.package);
+
+        // 64-bit FNV-1a
+        let mut hash = 0xcbf29ce484222325u64;
+        for byte in part_name.bytes().chain(std::iter::once(0)).chain(jed.f.iter().map(|&x| x as u8)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Finds a ZIA row that can select the given input
+    fn zia_row(input: XC2ZIAInput) -> usize {
+        (0..INPUTS_PER_ANDTERM).find(|&row| zia_table_get_row(XC2Device::XC2C32A, row).contains(&input)).unwrap()
+    }
+
+    // One output pin (FB1_1) that is the AND of the pin of FB1_6 and registered feedback from FB1_3
+    fn design() -> XC2Bitstream {
+        let mut bitstream = XC2Bitstream::blank_bitstream(XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap());
+        bitstream.bits.get_mut_small_iob(0).unwrap().obuf_mode = XC2IOBOBufMode::PushPull;
+        bitstream.bits.get_mut_small_iob(5).unwrap().zia_mode = XC2IOBZIAMode::PAD;
+        bitstream.bits.get_global_nets_mut().gck_enable[0] = true;
+        let fb = &mut bitstream.bits.get_fb_mut()[0];
+        for &input in &[XC2ZIAInput::IBuf{ibuf: 5}, XC2ZIAInput::Macrocell{fb: 0, mc: 2}] {
+            *fb.get_mut_zia(zia_row(input)) = input;
+            fb.get_mut_andterm(20).set(zia_row(input), true);
+        }
+        fb.or_terms[0].set(20, true);
+        fb.mcs[2].fb_mode = XC2MCFeedbackMode::REG;
+        fb.mcs[2].ff_in_ibuf = true;
+        fb.mcs[2].init_state = false;
+
+        bitstream
+    }
+
+    #[test]
+    fn canonicalize_keeps_live_resources() {
+        let mut bitstream = design();
+        bitstream.canonicalize();
+        assert_eq!(bitstream.bits, design().bits);
+    }
+
+    #[test]
+    fn canonicalize_clears_dead_resources() {
+        let mut bitstream = design();
+        {
+            let fb = &mut bitstream.bits.get_fb_mut()[0];
+            // An AND term and a ZIA row that nothing uses
+            let row = zia_row(XC2ZIAInput::IBuf{ibuf: 9});
+            fb.get_mut_andterm(30).set_b(row, true);
+            *fb.get_mut_zia(row) = XC2ZIAInput::IBuf{ibuf: 9};
+            // Register settings of a macrocell that is only used combinatorially
+            fb.mcs[0].clk_src = XC2MCRegClkSrc::CTC;
+            fb.mcs[0].init_state = false;
+            // Feedback that no ZIA row selects
+            fb.mcs[4].fb_mode = XC2MCFeedbackMode::COMB;
+            fb.or_terms[4].set(30, true);
+        }
+        bitstream.bits.get_mut_small_iob(9).unwrap().zia_mode = XC2IOBZIAMode::PAD;
+        bitstream.bits.get_mut_small_iob(12).unwrap().obuf_uses_ff = true;
+        assert_ne!(bitstream.content_hash(), design().content_hash());
+
+        bitstream.canonicalize();
+        assert_eq!(bitstream.bits, design().bits);
+        assert_eq!(bitstream.content_hash(), design().content_hash());
+
+        // A change to something that is used still shows up
+        bitstream.bits.get_fb_mut()[0].mcs[2].init_state = true;
+        bitstream.canonicalize();
+        assert_ne!(bitstream.content_hash(), design().content_hash());
+    }
+}
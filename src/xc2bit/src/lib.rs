@@ -74,6 +74,8 @@ pub use crate::bitstream::{XC2Bitstream, XC2BitstreamBits,
     XC2BitsXC2C384,
     XC2BitsXC2C512};
 
+mod canonical;
+
 mod crbit;
 pub use crate::crbit::{FuseArray};
 
@@ -93,10 +95,16 @@ mod iob;
 pub use crate::iob::{XC2MCSmallIOB, XC2IOBZIAMode, XC2IOBOBufMode, XC2ExtraIBuf, XC2IOBIbufMode, XC2MCLargeIOB,
                      iob_num_to_fb_mc_num, fb_mc_num_to_iob_num};
 
+mod lint;
+pub use crate::lint::{XC2LintSeverity, XC2LintRule, XC2LintLocation, XC2LintMessage};
+
 mod mc;
 pub use crate::mc::{XC2Macrocell, XC2MCRegClkSrc, XC2MCRegResetSrc, XC2MCRegSetSrc, XC2MCRegMode, XC2MCFeedbackMode,
                     XC2MCXorMode};
 
+mod package;
+pub use crate::package::{has_package_pin_map, get_package_pin, find_package_pin, get_vref_pin};
+
 mod partdb;
 pub use crate::partdb::{XC2Device, XC2Speed, XC2Package, XC2DeviceSpeedPackage};
 
@@ -104,7 +112,11 @@ mod pla;
 pub use crate::pla::{XC2PLAAndTerm, XC2PLAOrTerm};
 
 mod structure;
-pub use crate::structure::{get_gck, get_gts, get_gsr, get_cdrst, get_dge, get_device_structure};
+pub use crate::structure::{get_gck, get_gts, get_gsr, get_cdrst, get_dge, get_fb_bank,
+    get_device_structure};
+
+mod timing;
+pub use crate::timing::{XC2ACCharacteristics, XC2TimingModel};
 
 mod zia;
 pub use crate::zia::{XC2ZIAInput, zia_table_get_row, ZIA_MAP_32, ZIA_MAP_64, ZIA_MAP_128, ZIA_MAP_256,
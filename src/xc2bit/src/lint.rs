@@ -0,0 +1,400 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+//! Design rule checks for bitstreams
+//!
+//! Any pattern of fuses that can be decoded is accepted when reading a bitstream, including ones that make no sense or
+//! that can't work on real hardware. This module looks for such configurations. Only the parts of the device that are
+//! actually used are checked, since unused macrocells and pins are often left in arbitrary states.
+
+use std::fmt;
+
+use crate::*;
+
+/// How serious a problem found by the design rule checks is
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Serialize, Deserialize)]
+pub enum XC2LintSeverity {
+    /// The configuration is suspicious but might be intended
+    Warning,
+    /// The configuration cannot work as intended
+    Error,
+}
+
+impl fmt::Display for XC2LintSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            XC2LintSeverity::Warning => "warning",
+            XC2LintSeverity::Error => "error",
+        })
+    }
+}
+
+/// The individual design rule checks
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum XC2LintRule {
+    /// The register input comes from the input pin, but the macrocell doesn't have a pin
+    FFInIbufOnBuriedMacrocell,
+    /// An output pin is enabled, but the XOR gate driving it is a constant
+    OutputWithoutDriver,
+    /// A ZIA row selects a signal, but no AND term in the function block uses it
+    UnusedZIARow,
+    /// A ZIA row that is used selects feedback from a macrocell or pin that has its feedback disabled
+    FeedbackFromUnconfiguredMacrocell,
+    /// A register is clocked by a global clock, but that global clock buffer is disabled
+    GlobalClockDisabled,
+    /// An output enable comes from a global tristate buffer that is disabled
+    GlobalTristateDisabled,
+    /// A register is set or reset by the global set/reset buffer, but it is disabled
+    GlobalSetResetDisabled,
+    /// The input and output voltage settings of a bank are different, or a legacy voltage bit is set
+    BankVoltageMismatch,
+    /// A transparent latch is set to trigger on both clock edges, which has unknown behavior
+    DDRLatch,
+    /// A pin uses DataGate, but DataGate is disabled
+    DataGateDisabled,
+    /// A pin uses VREF, but VREF is disabled
+    VrefDisabled,
+}
+
+impl XC2LintRule {
+    pub fn severity(&self) -> XC2LintSeverity {
+        match self {
+            XC2LintRule::OutputWithoutDriver |
+            XC2LintRule::UnusedZIARow |
+            XC2LintRule::BankVoltageMismatch |
+            XC2LintRule::DDRLatch => XC2LintSeverity::Warning,
+            _ => XC2LintSeverity::Error,
+        }
+    }
+}
+
+/// The part of the device that a design rule check is about
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub enum XC2LintLocation {
+    Device,
+    /// An I/O bank (numbered starting from 0)
+    Bank(usize),
+    Macrocell{fb: u32, mc: u32},
+    ZIARow{fb: u32, row: u32},
+}
+
+impl fmt::Display for XC2LintLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XC2LintLocation::Device => write!(f, "device"),
+            XC2LintLocation::Bank(bank) => write!(f, "bank {}", bank),
+            XC2LintLocation::Macrocell{fb, mc} => write!(f, "FB{}_{}", fb + 1, mc + 1),
+            XC2LintLocation::ZIARow{fb, row} => write!(f, "FB{} ZIA row {}", fb + 1, row),
+        }
+    }
+}
+
+/// A problem found by the design rule checks
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub struct XC2LintMessage {
+    pub rule: XC2LintRule,
+    pub location: XC2LintLocation,
+    pub message: String,
+}
+
+impl XC2LintMessage {
+    pub fn severity(&self) -> XC2LintSeverity {
+        self.rule.severity()
+    }
+}
+
+impl fmt::Display for XC2LintMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity(), self.location, self.message)
+    }
+}
+
+// The settings of an I/O pin that matter here, for both sizes of IOB
+struct IOBSettings {
+    zia_mode: XC2IOBZIAMode,
+    obuf_mode: XC2IOBOBufMode,
+    obuf_uses_ff: bool,
+    uses_data_gate: bool,
+    uses_vref: bool,
+}
+
+fn iob_settings(bits: &XC2BitstreamBits, fb: u32, mc: u32) -> Option<IOBSettings> {
+    let iob = fb_mc_num_to_iob_num(bits.device_type(), fb, mc)? as usize;
+    if let Some(iob) = bits.get_small_iob(iob) {
+        Some(IOBSettings {
+            zia_mode: iob.zia_mode,
+            obuf_mode: iob.obuf_mode,
+            obuf_uses_ff: iob.obuf_uses_ff,
+            uses_data_gate: false,
+            uses_vref: false,
+        })
+    } else {
+        let iob = bits.get_large_iob(iob).unwrap();
+        Some(IOBSettings {
+            zia_mode: iob.zia_mode,
+            obuf_mode: iob.obuf_mode,
+            obuf_uses_ff: iob.obuf_uses_ff,
+            uses_data_gate: iob.uses_data_gate,
+            uses_vref: iob.ibuf_mode == XC2IOBIbufMode::UsesVref,
+        })
+    }
+}
+
+// Returns (DataGate enabled, VREF enabled), which are always off on the small devices
+fn data_gate_and_vref(bits: &XC2BitstreamBits) -> (bool, bool) {
+    match bits {
+        XC2BitstreamBits::XC2C128(XC2BitsXC2C128{data_gate, use_vref, ..}) |
+        XC2BitstreamBits::XC2C256(XC2BitsXC2C256{data_gate, use_vref, ..}) |
+        XC2BitstreamBits::XC2C384(XC2BitsXC2C384{data_gate, use_vref, ..}) |
+        XC2BitstreamBits::XC2C512(XC2BitsXC2C512{data_gate, use_vref, ..}) => (*data_gate, *use_vref),
+        _ => (false, false),
+    }
+}
+
+// Returns (input voltage, output voltage) for each bank, along with whether any legacy voltage bit is set
+fn bank_voltages(bits: &XC2BitstreamBits) -> (Vec<(bool, bool)>, bool) {
+    let zip = |ivoltage: &[bool], ovoltage: &[bool]| {
+        ivoltage.iter().cloned().zip(ovoltage.iter().cloned()).collect::<Vec<_>>()
+    };
+
+    match bits {
+        XC2BitstreamBits::XC2C32(XC2BitsXC2C32{ivoltage, ovoltage, ..}) |
+        XC2BitstreamBits::XC2C64(XC2BitsXC2C64{ivoltage, ovoltage, ..}) => (vec![(*ivoltage, *ovoltage)], false),
+        XC2BitstreamBits::XC2C32A(XC2BitsXC2C32A{ivoltage, ovoltage, legacy_ivoltage, legacy_ovoltage, ..}) |
+        XC2BitstreamBits::XC2C64A(XC2BitsXC2C64A{ivoltage, ovoltage, legacy_ivoltage, legacy_ovoltage, ..}) =>
+            (zip(ivoltage, ovoltage), *legacy_ivoltage || *legacy_ovoltage),
+        XC2BitstreamBits::XC2C128(XC2BitsXC2C128{ivoltage, ovoltage, ..}) |
+        XC2BitstreamBits::XC2C256(XC2BitsXC2C256{ivoltage, ovoltage, ..}) => (zip(ivoltage, ovoltage), false),
+        XC2BitstreamBits::XC2C384(XC2BitsXC2C384{ivoltage, ovoltage, ..}) |
+        XC2BitstreamBits::XC2C512(XC2BitsXC2C512{ivoltage, ovoltage, ..}) => (zip(ivoltage, ovoltage), false),
+    }
+}
+
+impl XC2Bitstream {
+    /// Runs the design rule checks on this bitstream. The result is empty if nothing suspicious was found.
+    pub fn lint(&self) -> Vec<XC2LintMessage> {
+        let bits = &self.bits;
+        let device = bits.device_type();
+        let global_nets = bits.get_global_nets();
+        let (data_gate, use_vref) = data_gate_and_vref(bits);
+        let mut ret = Vec::new();
+        let mut add = |rule, location, message: String| {
+            ret.push(XC2LintMessage {rule, location, message});
+        };
+
+        for (fb, fb_bits) in bits.get_fb().iter().enumerate() {
+            let fb = fb as u32;
+
+            // ZIA rows
+            for row in 0..INPUTS_PER_ANDTERM {
+                let location = XC2LintLocation::ZIARow{fb, row: row as u32};
+                let used = (0..ANDTERMS_PER_FB).any(|i| {
+                    let andterm = fb_bits.get_andterm(i);
+                    andterm.get(row) || andterm.get_b(row)
+                });
+                match *fb_bits.get_zia(row) {
+                    XC2ZIAInput::Zero | XC2ZIAInput::One => {},
+                    _ if !used => add(XC2LintRule::UnusedZIARow, location,
+                        "selects a signal that no AND term uses".to_owned()),
+                    XC2ZIAInput::Macrocell{fb: src_fb, mc: src_mc} => {
+                        let src_mc_bits = &bits.get_fb()[src_fb as usize].mcs[src_mc as usize];
+                        if src_mc_bits.fb_mode == XC2MCFeedbackMode::Disabled {
+                            add(XC2LintRule::FeedbackFromUnconfiguredMacrocell, location,
+                                format!("uses feedback from FB{}_{}, which has its feedback disabled",
+                                    src_fb + 1, src_mc + 1));
+                        }
+                    },
+                    XC2ZIAInput::IBuf{ibuf} => {
+                        let (src_fb, src_mc) = iob_num_to_fb_mc_num(device, ibuf as u32).unwrap();
+                        let iob = iob_settings(bits, src_fb, src_mc).unwrap();
+                        if iob.zia_mode == XC2IOBZIAMode::Disabled {
+                            add(XC2LintRule::FeedbackFromUnconfiguredMacrocell, location,
+                                format!("uses the pin of FB{}_{}, which has its input disabled",
+                                    src_fb + 1, src_mc + 1));
+                        }
+                    },
+                    XC2ZIAInput::DedicatedInput => {},
+                }
+            }
+
+            // Macrocells and their pins
+            for mc in 0..MCS_PER_FB as u32 {
+                let location = XC2LintLocation::Macrocell{fb, mc};
+                let mc_bits = &fb_bits.mcs[mc as usize];
+                let iob = iob_settings(bits, fb, mc);
+                let output_enabled = iob.as_ref().is_some_and(|x|
+                    x.obuf_mode != XC2IOBOBufMode::Disabled && x.obuf_mode != XC2IOBOBufMode::CGND);
+                let reg_used = mc_bits.fb_mode == XC2MCFeedbackMode::REG ||
+                    iob.as_ref().is_some_and(|x| output_enabled && x.obuf_uses_ff || x.zia_mode == XC2IOBZIAMode::REG);
+
+                if mc_bits.ff_in_ibuf && iob.is_none() {
+                    add(XC2LintRule::FFInIbufOnBuriedMacrocell, location,
+                        "register is fed from the input pin, but this macrocell has no pin".to_owned());
+                }
+
+                if let Some(ref iob) = iob {
+                    let xor_is_constant = (0..ANDTERMS_PER_FB).all(|i| !fb_bits.or_terms[mc as usize].get(i)) &&
+                        (mc_bits.xor_mode == XC2MCXorMode::ZERO || mc_bits.xor_mode == XC2MCXorMode::ONE);
+                    if output_enabled && !iob.obuf_uses_ff && xor_is_constant {
+                        add(XC2LintRule::OutputWithoutDriver, location,
+                            format!("output is enabled ({}) but is driven by a constant", iob.obuf_mode));
+                    }
+
+                    let gts = match iob.obuf_mode {
+                        XC2IOBOBufMode::TriStateGTS0 => Some(0),
+                        XC2IOBOBufMode::TriStateGTS1 => Some(1),
+                        XC2IOBOBufMode::TriStateGTS2 => Some(2),
+                        XC2IOBOBufMode::TriStateGTS3 => Some(3),
+                        _ => None,
+                    };
+                    if let Some(gts) = gts {
+                        if !global_nets.gts_enable[gts] {
+                            add(XC2LintRule::GlobalTristateDisabled, location,
+                                format!("output enable comes from GTS{}, which is disabled", gts));
+                        }
+                    }
+
+                    if iob.uses_data_gate && !data_gate {
+                        add(XC2LintRule::DataGateDisabled, location,
+                            "pin uses DataGate, but DataGate is disabled".to_owned());
+                    }
+                    if iob.uses_vref && !use_vref {
+                        add(XC2LintRule::VrefDisabled, location, "pin uses VREF, but VREF is disabled".to_owned());
+                    }
+                }
+
+                if reg_used {
+                    let gck = match mc_bits.clk_src {
+                        XC2MCRegClkSrc::GCK0 => Some(0),
+                        XC2MCRegClkSrc::GCK1 => Some(1),
+                        XC2MCRegClkSrc::GCK2 => Some(2),
+                        _ => None,
+                    };
+                    if let Some(gck) = gck {
+                        if !global_nets.gck_enable[gck] {
+                            add(XC2LintRule::GlobalClockDisabled, location,
+                                format!("register is clocked by GCK{}, which is disabled", gck));
+                        }
+                    }
+
+                    if (mc_bits.s_src == XC2MCRegSetSrc::GSR || mc_bits.r_src == XC2MCRegResetSrc::GSR) &&
+                        !global_nets.gsr_enable {
+
+                        add(XC2LintRule::GlobalSetResetDisabled, location,
+                            "register is set or reset by GSR, which is disabled".to_owned());
+                    }
+
+                    if mc_bits.is_ddr && mc_bits.reg_mode == XC2MCRegMode::LATCH {
+                        add(XC2LintRule::DDRLatch, location,
+                            "latch is set to use both clock edges, which has unknown behavior".to_owned());
+                    }
+                }
+            }
+        }
+
+        // Bank voltages
+        let (voltages, legacy_voltage) = bank_voltages(bits);
+        for (bank, &(ivoltage, ovoltage)) in voltages.iter().enumerate() {
+            if ivoltage != ovoltage {
+                add(XC2LintRule::BankVoltageMismatch, XC2LintLocation::Bank(bank),
+                    format!("input voltage is set {} but output voltage is set {}",
+                        if ivoltage {"high"} else {"low"}, if ovoltage {"high"} else {"low"}));
+            }
+        }
+        if legacy_voltage {
+            add(XC2LintRule::BankVoltageMismatch, XC2LintLocation::Device,
+                "legacy voltage setting is set high".to_owned());
+        }
+
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blank(part: &str) -> XC2Bitstream {
+        XC2Bitstream::blank_bitstream(XC2DeviceSpeedPackage::from_str(part).unwrap())
+    }
+
+    fn rules(bitstream: &XC2Bitstream) -> Vec<(XC2LintRule, XC2LintLocation)> {
+        bitstream.lint().into_iter().map(|x| (x.rule, x.location)).collect()
+    }
+
+    #[test]
+    fn lint_blank() {
+        assert!(blank("xc2c32a-4-vq44").lint().is_empty());
+        assert!(blank("xc2c256-7-tq144").lint().is_empty());
+    }
+
+    #[test]
+    fn lint_macrocells() {
+        let mut bitstream = blank("xc2c32a-4-vq44");
+        {
+            let fb = &mut bitstream.bits.get_fb_mut()[0];
+            // Registered feedback clocked by a disabled GCK
+            fb.mcs[0].fb_mode = XC2MCFeedbackMode::REG;
+            // Row 0 is used, but the macrocell it selects has no feedback
+            *fb.get_mut_zia(0) = XC2ZIAInput::Macrocell{fb: 0, mc: 2};
+            fb.get_mut_andterm(0).set(0, true);
+            // Row 1 isn't used at all
+            *fb.get_mut_zia(1) = XC2ZIAInput::Macrocell{fb: 0, mc: 0};
+        }
+        // Output without anything in its OR term
+        bitstream.bits.get_mut_small_iob(1).unwrap().obuf_mode = XC2IOBOBufMode::PushPull;
+
+        assert_eq!(rules(&bitstream), vec![
+            (XC2LintRule::FeedbackFromUnconfiguredMacrocell, XC2LintLocation::ZIARow{fb: 0, row: 0}),
+            (XC2LintRule::UnusedZIARow, XC2LintLocation::ZIARow{fb: 0, row: 1}),
+            (XC2LintRule::GlobalClockDisabled, XC2LintLocation::Macrocell{fb: 0, mc: 0}),
+            (XC2LintRule::OutputWithoutDriver, XC2LintLocation::Macrocell{fb: 0, mc: 1}),
+        ]);
+        assert_eq!(bitstream.lint()[2].severity(), XC2LintSeverity::Error);
+        assert_eq!(format!("{}", bitstream.lint()[3]),
+            "warning: FB1_2: output is enabled (push-pull) but is driven by a constant");
+
+        bitstream.bits.get_global_nets_mut().gck_enable[0] = true;
+        bitstream.bits.get_fb_mut()[0].or_terms[1].set(0, true);
+        assert_eq!(rules(&bitstream).len(), 2);
+    }
+
+    #[test]
+    fn lint_buried_and_banks() {
+        let mut bitstream = blank("xc2c256-7-tq144");
+        // A macrocell without a pin
+        let buried = (0..MCS_PER_FB as u32).find(|&mc| fb_mc_num_to_iob_num(XC2Device::XC2C256, 0, mc).is_none());
+        let buried = buried.unwrap();
+        bitstream.bits.get_fb_mut()[0].mcs[buried as usize].ff_in_ibuf = true;
+        if let XC2BitstreamBits::XC2C256(ref mut bits) = bitstream.bits {
+            bits.ivoltage[1] = true;
+        }
+
+        assert_eq!(rules(&bitstream), vec![
+            (XC2LintRule::FFInIbufOnBuriedMacrocell, XC2LintLocation::Macrocell{fb: 0, mc: buried}),
+            (XC2LintRule::BankVoltageMismatch, XC2LintLocation::Bank(1)),
+        ]);
+    }
+}
@@ -0,0 +1,139 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+//! Contains the maps of which package pin each IOB is bonded out to, and of which pins are used for VREF.
+//!
+//! Only the packages whose pinouts are listed here are known. For the XC2C32A, these come from the pinout tables in
+//! DS310 (the same tables that the old crowbar tool used). For every other device and package, the lookups return
+//! `None`.
+
+use crate::*;
+
+// Package pins of the XC2C32A, indexed by FB and then by macrocell. An empty string means that the IOB isn't bonded out
+// in that package.
+static XC2C32A_QFG32: [[&str; MCS_PER_FB]; 2] = [
+    ["", "", "", "P3", "P2", "P1", "P32", "P31", "P30", "P29", "P28", "P24", "", "P23", "", ""],
+    ["P5", "", "", "", "P6", "P7", "P8", "P9", "P10", "", "", "P13", "P17", "P18", "P19", ""],
+];
+static XC2C32A_VQ44: [[&str; MCS_PER_FB]; 2] = [
+    ["P38", "P37", "P36", "P34", "P33", "P32", "P31", "P30", "P29", "P28", "P27", "P23", "P22", "P21", "P20", "P19"],
+    ["P39", "P40", "P41", "P42", "P43", "P44", "P1", "P2", "P3", "P5", "P6", "P8", "P12", "P13", "P14", "P16"],
+];
+static XC2C32A_CP56: [[&str; MCS_PER_FB]; 2] = [
+    ["F1", "E3", "E1", "D1", "C1", "A3", "A2", "B1", "A1", "C4", "C5", "C8", "A10", "B10", "C10", "E8"],
+    ["G1", "F3", "H1", "G3", "J1", "K1", "K2", "K3", "H3", "K5", "H5", "H8", "K8", "H10", "G10", "F10"],
+];
+
+fn pin_map(device_type: XC2DeviceSpeedPackage) -> Option<&'static [[&'static str; MCS_PER_FB]]> {
+    match (device_type.dev, device_type.pkg) {
+        (XC2Device::XC2C32A, XC2Package::QFG32) => Some(&XC2C32A_QFG32),
+        (XC2Device::XC2C32A, XC2Package::VQ44) => Some(&XC2C32A_VQ44),
+        (XC2Device::XC2C32A, XC2Package::CP56) => Some(&XC2C32A_CP56),
+        _ => None,
+    }
+}
+
+/// Returns whether it is known which package pin each IOB of the given part is bonded out to
+pub fn has_package_pin_map(device_type: XC2DeviceSpeedPackage) -> bool {
+    pin_map(device_type).is_some()
+}
+
+/// Returns the name of the package pin that the IOB of the given function block and macrocell is bonded out to. This
+/// is `None` if the IOB isn't bonded out in this package or if the pinout of the package isn't known. The input-only
+/// pin of the XC2C32(A) is not covered.
+pub fn get_package_pin(device_type: XC2DeviceSpeedPackage, fb: u32, mc: u32) -> Option<&'static str> {
+    let map = pin_map(device_type)?;
+    let name = *map.get(fb as usize)?.get(mc as usize)?;
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+// Pins that can be used as the VREF input of a bank, as (device, package, bank, FB, macrocell). Only the XC2C128 and
+// larger devices support VREF, and the VREF pins of their packages haven't been entered yet.
+static VREF_PINS: &[(XC2Device, XC2Package, usize, u32, u32)] = &[];
+
+/// Returns the function block and macrocell index of the IOB that is used as the VREF input of the given bank. This is
+/// `None` if the device doesn't support VREF or if the VREF pins of the package aren't known.
+pub fn get_vref_pin(device_type: XC2DeviceSpeedPackage, bank: usize) -> Option<(u32, u32)> {
+    VREF_PINS.iter()
+        .find(|x| x.0 == device_type.dev && x.1 == device_type.pkg && x.2 == bank)
+        .map(|x| (x.3, x.4))
+}
+
+/// Returns the function block and macrocell index of the IOB that is bonded out to the given package pin. The pin name
+/// is not case sensitive.
+pub fn find_package_pin(device_type: XC2DeviceSpeedPackage, name: &str) -> Option<(u32, u32)> {
+    let map = pin_map(device_type)?;
+    for (fb, fb_pins) in map.iter().enumerate() {
+        for (mc, pin) in fb_pins.iter().enumerate() {
+            if !pin.is_empty() && pin.eq_ignore_ascii_case(name) {
+                return Some((fb as u32, mc as u32));
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_pins() {
+        let vq44 = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        assert!(has_package_pin_map(vq44));
+        // GCK0 is pin 43
+        let gck0 = get_gck(XC2Device::XC2C32A, 0).unwrap();
+        assert_eq!(get_package_pin(vq44, gck0.0, gck0.1), Some("P43"));
+        assert_eq!(find_package_pin(vq44, "p43"), Some(gck0));
+        assert_eq!(find_package_pin(vq44, "P4"), None);
+
+        // Every pin appears only once
+        for &part in &["xc2c32a-4-qfg32", "xc2c32a-4-vq44", "xc2c32a-4-cp56"] {
+            let device_type = XC2DeviceSpeedPackage::from_str(part).unwrap();
+            for fb in 0..2 {
+                for mc in 0..MCS_PER_FB as u32 {
+                    if let Some(pin) = get_package_pin(device_type, fb, mc) {
+                        assert_eq!(find_package_pin(device_type, pin), Some((fb, mc)));
+                    }
+                }
+            }
+        }
+
+        let qfg32 = XC2DeviceSpeedPackage::from_str("xc2c32a-4-qfg32").unwrap();
+        assert_eq!(get_package_pin(qfg32, 0, 0), None);
+        assert_eq!(get_package_pin(qfg32, 1, 0), Some("P5"));
+
+        let tq144 = XC2DeviceSpeedPackage::from_str("xc2c128-6-tq144").unwrap();
+        assert!(!has_package_pin_map(tq144));
+        assert_eq!(get_package_pin(tq144, 0, 0), None);
+        assert_eq!(get_vref_pin(tq144, 0), None);
+        assert_eq!(get_vref_pin(vq44, 0), None);
+    }
+}
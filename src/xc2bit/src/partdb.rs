@@ -73,6 +73,16 @@ impl XC2Device {
         }
     }
 
+    /// Returns the number of I/O banks (groups of pins that share one voltage setting) for the device type
+    pub fn num_banks(&self) -> usize {
+        match *self {
+            XC2Device::XC2C32 | XC2Device::XC2C64 => 1,
+            XC2Device::XC2C32A | XC2Device::XC2C64A |
+            XC2Device::XC2C128 | XC2Device::XC2C256 => 2,
+            XC2Device::XC2C384 | XC2Device::XC2C512 => 4,
+        }
+    }
+
     pub fn is_small_iob(&self) -> bool {
         match *self {
             XC2Device::XC2C32 | XC2Device::XC2C32A |
@@ -322,6 +332,39 @@ impl XC2DeviceSpeedPackage {
             dev, spd, pkg
         })
     }
+
+    /// Returns the number of IOBs that are bonded out to a pin in this package, not counting the input-only pin of
+    /// the XC2C32(A). Which IOBs those are is only known for the packages that have a package pin map.
+    pub fn num_bonded_iobs(&self) -> usize {
+        match (self.dev, self.pkg) {
+            (XC2Device::XC2C32, XC2Package::QFG32) | (XC2Device::XC2C32A, XC2Package::QFG32) => 20,
+            (XC2Device::XC2C32, _) | (XC2Device::XC2C32A, _) => 32,
+            (XC2Device::XC2C64, XC2Package::PC44) | (XC2Device::XC2C64A, XC2Package::PC44) |
+            (XC2Device::XC2C64, XC2Package::VQ44) | (XC2Device::XC2C64A, XC2Package::VQ44) => 33,
+            (XC2Device::XC2C64, XC2Package::QFG48) | (XC2Device::XC2C64A, XC2Package::QFG48) => 37,
+            (XC2Device::XC2C64, XC2Package::CP56) | (XC2Device::XC2C64A, XC2Package::CP56) => 45,
+            (XC2Device::XC2C64, _) | (XC2Device::XC2C64A, _) => 64,
+            (XC2Device::XC2C128, XC2Package::VQ100) => 80,
+            (XC2Device::XC2C128, _) => 100,
+            (XC2Device::XC2C256, XC2Package::VQ100) => 80,
+            (XC2Device::XC2C256, XC2Package::CP132) => 106,
+            (XC2Device::XC2C256, XC2Package::TQ144) => 118,
+            (XC2Device::XC2C256, XC2Package::PQ208) => 173,
+            (XC2Device::XC2C256, _) => 184,
+            (XC2Device::XC2C384, XC2Package::TQ144) => 118,
+            (XC2Device::XC2C384, XC2Package::PQ208) => 173,
+            (XC2Device::XC2C384, XC2Package::FT256) => 212,
+            (XC2Device::XC2C384, _) => 240,
+            (XC2Device::XC2C512, XC2Package::PQ208) => 173,
+            (XC2Device::XC2C512, XC2Package::FT256) => 212,
+            (XC2Device::XC2C512, _) => 270,
+        }
+    }
+
+    /// Returns whether every IOB of the device is bonded out to a pin in this package
+    pub fn is_fully_bonded(&self) -> bool {
+        self.num_bonded_iobs() == self.dev.num_iobs()
+    }
 }
 
 #[cfg(test)]
@@ -348,6 +391,23 @@ mod tests {
         assert_eq!(XC2DeviceSpeedPackage::from_str("xc2c32a-5-vq100"), None);
     }
 
+    #[test]
+    fn bonded_iobs() {
+        assert!(XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap().is_fully_bonded());
+        assert!(XC2DeviceSpeedPackage::from_str("xc2c64a-5-vq100").unwrap().is_fully_bonded());
+        assert!(XC2DeviceSpeedPackage::from_str("xc2c256-6-ft256").unwrap().is_fully_bonded());
+        assert_eq!(XC2DeviceSpeedPackage::from_str("xc2c64a-5-vq44").unwrap().num_bonded_iobs(), 33);
+        assert!(!XC2DeviceSpeedPackage::from_str("xc2c128-6-vq100").unwrap().is_fully_bonded());
+
+        // These have to agree with the package pin maps
+        for &part in &["xc2c32a-4-qfg32", "xc2c32a-4-vq44", "xc2c32a-4-cp56"] {
+            let device_type = XC2DeviceSpeedPackage::from_str(part).unwrap();
+            let mapped = (0..2).flat_map(|fb| (0..MCS_PER_FB as u32).map(move |mc| (fb, mc)))
+                .filter(|&(fb, mc)| get_package_pin(device_type, fb, mc).is_some()).count();
+            assert_eq!(device_type.num_bonded_iobs(), mapped);
+        }
+    }
+
     #[test]
     fn malformed_part_names() {
         assert_eq!(XC2DeviceSpeedPackage::from_str("asdf"), None);
@@ -119,6 +119,21 @@ pub fn get_dge(device: XC2Device) -> Option<(u32, u32)> {
     }
 }
 
+/// Returns the I/O bank that the pins of the given function block are in, numbered from 0. All of the pins belonging to
+/// one function block are in the same bank. The XC2C32A assignment is from DS310 (FB1 is in bank 2 and vice versa).
+/// This is not known for the XC2C64A and larger devices, in which case `None` is returned.
+pub fn get_fb_bank(device: XC2Device, fb: u32) -> Option<usize> {
+    if fb as usize >= device.num_fbs() {
+        return None;
+    }
+
+    match device {
+        XC2Device::XC2C32 | XC2Device::XC2C64 => Some(0),
+        XC2Device::XC2C32A => Some(1 - fb as usize),
+        _ => None,
+    }
+}
+
 /// This function calls the passed-in callbacks to provide information about the structure of the CPLD. `node_callback`
 /// is called to "create" a new node, `wire_callback` is called to "create" a new wire, and `connection_callback` is
 /// called to connect one port on a node to a wire. The arguments to the callbacks are:
@@ -0,0 +1,232 @@
+/*
+Copyright (c) 2018, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+//! Contains a simple timing model for the device
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::*;
+
+/// Internal delays of the device, in picoseconds. These follow the internal timing model in the datasheets. The
+/// combinational and clock-to-output paths add up to the pin-to-pin characteristics published for each device and
+/// speed grade (see [`XC2ACCharacteristics`]); the split between the individual buffers is an estimate. They are good
+/// enough for back-annotated simulation, but not for timing sign-off.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub struct XC2TimingModel {
+    /// Input buffer delay
+    pub t_in: u32,
+    /// Global clock buffer delay
+    pub t_gck: u32,
+    /// Global set/reset buffer delay
+    pub t_gsr: u32,
+    /// Global tristate buffer delay
+    pub t_gts: u32,
+    /// Output buffer delay with fast slew rate
+    pub t_out: u32,
+    /// Additional output buffer delay with slow slew rate
+    pub t_slew: u32,
+    /// Output enable/disable delay
+    pub t_en: u32,
+    /// Delay through the global interconnect (ZIA)
+    pub t_zia: u32,
+    /// Delay through the AND array
+    pub t_and: u32,
+    /// Delay through the OR array
+    pub t_or: u32,
+    /// Delay through the macrocell XOR gate
+    pub t_xor: u32,
+    /// Additional delay when a product term is used as a clock or a control signal
+    pub t_ptck: u32,
+    /// Register setup time
+    pub t_su: u32,
+    /// Register hold time
+    pub t_hold: u32,
+    /// Register clock enable setup time
+    pub t_cesu: u32,
+    /// Register clock to output delay
+    pub t_co: u32,
+    /// Register asynchronous set/reset to output delay
+    pub t_ao: u32,
+}
+
+/// Pin-to-pin AC characteristics of a device and speed grade, in picoseconds, as published in the "AC Electrical
+/// Characteristics Over Recommended Operating Conditions" table of the datasheet for the device. Every speed grade of
+/// a device is a column of that same table. The T_PD1, T_PD2 and T_CO rows are used. The original XC2C32 and XC2C64
+/// use the numbers of their "A" versions.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
+pub struct XC2ACCharacteristics {
+    /// Propagation delay from an input pin to an output pin through a single product term
+    pub t_pd1: u32,
+    /// Propagation delay from an input pin to an output pin through the OR array
+    pub t_pd2: u32,
+    /// Global clock pin to output pin delay
+    pub t_co: u32,
+}
+
+impl XC2ACCharacteristics {
+    /// Returns the published characteristics for the given device and speed grade. Speed grades that the device is not
+    /// offered in are treated as its slowest grade.
+    pub fn for_device(device: XC2DeviceSpeedPackage) -> Self {
+        let (t_pd1, t_pd2, t_co) = match (device.dev, device.spd) {
+            // DS310 (XC2C32A), -4 and -6 columns
+            (XC2Device::XC2C32, XC2Speed::Speed4) | (XC2Device::XC2C32A, XC2Speed::Speed4) => (3800, 4000, 3700),
+            (XC2Device::XC2C32, _) | (XC2Device::XC2C32A, _) => (5500, 6000, 4700),
+            // DS311 (XC2C64A), -5 and -7 columns
+            (XC2Device::XC2C64, XC2Speed::Speed5) | (XC2Device::XC2C64A, XC2Speed::Speed5) => (4600, 5000, 3900),
+            (XC2Device::XC2C64, _) | (XC2Device::XC2C64A, _) => (7500, 8000, 6000),
+            // DS093 (XC2C128), -6 and -7 columns
+            (XC2Device::XC2C128, XC2Speed::Speed6) => (5700, 6000, 4200),
+            (XC2Device::XC2C128, _) => (7000, 7500, 6000),
+            // DS094 (XC2C256), -6 and -7 columns
+            (XC2Device::XC2C256, XC2Speed::Speed6) => (5700, 6000, 4500),
+            (XC2Device::XC2C256, _) => (7000, 7500, 6000),
+            // DS095 (XC2C384), -7 and -10 columns
+            (XC2Device::XC2C384, XC2Speed::Speed7) => (7100, 7500, 5800),
+            (XC2Device::XC2C384, _) => (10000, 11000, 7500),
+            // DS096 (XC2C512), -7 and -10 columns
+            (XC2Device::XC2C512, XC2Speed::Speed7) => (7100, 7500, 5800),
+            (XC2Device::XC2C512, _) => (10000, 11000, 7500),
+        };
+
+        XC2ACCharacteristics {
+            t_pd1,
+            t_pd2,
+            t_co,
+        }
+    }
+}
+
+// Estimated buffer and control delays for the fastest speed grade of a device. Slower speed grades scale these by
+// how much slower their published t_pd1 is.
+const BASE_TIMING: XC2TimingModel = XC2TimingModel {
+    t_in: 1300,
+    t_gck: 1300,
+    t_gsr: 1800,
+    t_gts: 2000,
+    t_out: 1600,
+    t_slew: 2000,
+    t_en: 2500,
+    t_zia: 0,
+    t_and: 0,
+    t_or: 0,
+    t_xor: 200,
+    t_ptck: 600,
+    t_su: 1200,
+    t_hold: 0,
+    t_cesu: 1000,
+    t_co: 0,
+    t_ao: 900,
+};
+
+impl XC2TimingModel {
+    /// Returns the timing model for the given device and speed grade
+    pub fn for_device(device: XC2DeviceSpeedPackage) -> Self {
+        let ac = XC2ACCharacteristics::for_device(device);
+        let fastest_spd = match device.dev {
+            XC2Device::XC2C32 | XC2Device::XC2C32A => XC2Speed::Speed4,
+            XC2Device::XC2C64 | XC2Device::XC2C64A => XC2Speed::Speed5,
+            XC2Device::XC2C128 | XC2Device::XC2C256 => XC2Speed::Speed6,
+            XC2Device::XC2C384 | XC2Device::XC2C512 => XC2Speed::Speed7,
+        };
+        let fastest = XC2ACCharacteristics::for_device(XC2DeviceSpeedPackage {
+            spd: fastest_spd,
+            ..device
+        });
+        let scale = |x: u32| x * ac.t_pd1 / fastest.t_pd1;
+
+        // The global interconnect gets slower as the device gets larger
+        let t_zia = scale(match device.dev {
+            XC2Device::XC2C32 | XC2Device::XC2C32A => 300,
+            XC2Device::XC2C64 | XC2Device::XC2C64A => 400,
+            XC2Device::XC2C128 => 600,
+            XC2Device::XC2C256 => 800,
+            XC2Device::XC2C384 => 1000,
+            XC2Device::XC2C512 => 1200,
+        });
+        let t_in = scale(BASE_TIMING.t_in);
+        let t_gck = scale(BASE_TIMING.t_gck);
+        let t_out = scale(BASE_TIMING.t_out);
+        let t_xor = scale(BASE_TIMING.t_xor);
+
+        XC2TimingModel {
+            t_in,
+            t_gck,
+            t_gsr: scale(BASE_TIMING.t_gsr),
+            t_gts: scale(BASE_TIMING.t_gts),
+            t_out,
+            t_slew: scale(BASE_TIMING.t_slew),
+            t_en: scale(BASE_TIMING.t_en),
+            t_zia,
+            // The AND array, OR array, and register take up whatever is left of the published pin-to-pin delays
+            t_and: ac.t_pd1 - t_in - t_zia - t_xor - t_out,
+            t_or: ac.t_pd2 - ac.t_pd1,
+            t_xor,
+            t_ptck: scale(BASE_TIMING.t_ptck),
+            t_su: scale(BASE_TIMING.t_su),
+            t_hold: scale(BASE_TIMING.t_hold),
+            t_cesu: scale(BASE_TIMING.t_cesu),
+            t_co: ac.t_co - t_gck - t_out,
+            t_ao: scale(BASE_TIMING.t_ao),
+        }
+    }
+
+    /// Pin-to-pin delay through a single product term
+    pub fn t_pd1(&self) -> u32 {
+        self.t_in + self.t_zia + self.t_and + self.t_xor + self.t_out
+    }
+
+    /// Pin-to-pin delay through the OR array
+    pub fn t_pd2(&self) -> u32 {
+        self.t_pd1() + self.t_or
+    }
+
+    /// Global clock pin to output pin delay
+    pub fn t_co_pin(&self) -> u32 {
+        self.t_gck + self.t_co + self.t_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timing_matches_ac_characteristics() {
+        for &part in &["xc2c32a-4-vq44", "xc2c32a-6-vq44", "xc2c64a-7-vq100", "xc2c128-6-tq144", "xc2c256-7-tq144",
+            "xc2c384-10-fg324", "xc2c512-7-ft256"] {
+
+            let device = XC2DeviceSpeedPackage::from_str(part).unwrap();
+            let ac = XC2ACCharacteristics::for_device(device);
+            let timing = XC2TimingModel::for_device(device);
+            assert_eq!(timing.t_pd1(), ac.t_pd1, "{}", part);
+            assert_eq!(timing.t_pd2(), ac.t_pd2, "{}", part);
+            assert_eq!(timing.t_co_pin(), ac.t_co, "{}", part);
+        }
+
+        let timing = XC2TimingModel::for_device(XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap());
+        assert_eq!(timing.t_pd1(), 3800);
+        assert_eq!(timing.t_pd2(), 4000);
+    }
+}
@@ -1,6 +1,6 @@
 use std::io::Write;
 
-fn one_set_of_reftests(outfile: &'static str, indir: &'static str) {
+fn one_set_of_reftests(outfile: &'static str, indir: &'static str, inext: &'static str) {
     let out_dir = std::env::var("OUT_DIR").unwrap();
     let destination = std::path::Path::new(&out_dir).join(outfile);
     let mut f = std::fs::File::create(&destination).unwrap();
@@ -13,7 +13,7 @@ fn one_set_of_reftests(outfile: &'static str, indir: &'static str) {
         for file in files {
             let path = file.expect("failed to get path").path();
             let ext = path.extension().expect("bogus reftest filename (doesn't have extension)");
-            if ext == "json" || ext == "fail" {
+            if ext == inext || ext == "fail" {
                 let path = path.canonicalize().unwrap();
 
                 let id_string = path.file_name().unwrap().to_str().unwrap().chars().map(|x| match x {
@@ -36,7 +36,9 @@ fn one_set_of_reftests(outfile: &'static str, indir: &'static str) {
 }
 
 fn main() {
-    one_set_of_reftests("frontend-reftests.rs", "../../tests/xc2par/frontend-reftests");
-    one_set_of_reftests("netlist-reftests.rs", "../../tests/xc2par/netlist-reftests");
-    one_set_of_reftests("par-reftests.rs", "../../tests/xc2par/par-reftests");
+    one_set_of_reftests("blif-reftests.rs", "../../tests/xc2par/blif-reftests", "blif");
+    one_set_of_reftests("frontend-reftests.rs", "../../tests/xc2par/frontend-reftests", "json");
+    one_set_of_reftests("netlist-reftests.rs", "../../tests/xc2par/netlist-reftests", "json");
+    one_set_of_reftests("par-reftests.rs", "../../tests/xc2par/par-reftests", "json");
+    one_set_of_reftests("postfit-reftests.rs", "../../tests/xc2par/postfit-reftests", "json");
 }
@@ -0,0 +1,363 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Area groups. An area group confines all of the macrocells that came from one part of the design hierarchy (every
+// macrocell whose name starts with a given instance path) to a range of function blocks. They come either from an
+// AREA_GROUP attribute on an instance in the netlist or from the options passed to the fitter. If a macrocell is
+// inside several area groups, the one for the longest (i.e. innermost) path wins.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use serde_derive::{Deserialize, Serialize};
+use slog::Drain;
+use yosys_netlist_json::Netlist;
+
+use xc2bit::*;
+
+use crate::*;
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct AreaGroup {
+    /// Hierarchical instance name (e.g. `u_core.u_fifo`) of the part of the design in this group
+    pub prefix: String,
+    pub first_fb: u32,
+    pub last_fb: u32,
+}
+
+impl AreaGroup {
+    /// Parses a range of function blocks in the form `FB1:FB4` (or just `FB3` for a single function block)
+    pub fn new(prefix: &str, range: &str) -> Option<Self> {
+        let parse_fb = |x: &str| {
+            let x = x.trim();
+            if x.len() > 2 && x[..2].eq_ignore_ascii_case("FB") {
+                x[2..].parse::<u32>().ok().filter(|&x| x > 0).map(|x| x - 1)
+            } else {
+                None
+            }
+        };
+
+        let mut range = range.split(':');
+        let first_fb = parse_fb(range.next()?)?;
+        let last_fb = if let Some(x) = range.next() { parse_fb(x)? } else { first_fb };
+        if range.next().is_some() || last_fb < first_fb {
+            return None;
+        }
+
+        Some(Self {
+            prefix: prefix.to_owned(),
+            first_fb,
+            last_fb,
+        })
+    }
+
+    /// Parses an area group in the form `u_core.u_fifo=FB1:FB4`
+    pub fn from_constraint(s: &str) -> Option<Self> {
+        let (prefix, range) = s.split_once('=')?;
+        Self::new(prefix.trim(), range)
+    }
+
+    pub fn contains_fb(&self, fb: u32) -> bool {
+        fb >= self.first_fb && fb <= self.last_fb
+    }
+
+    pub fn num_fbs(&self) -> u32 {
+        self.last_fb - self.first_fb + 1
+    }
+
+    /// Checks whether a cell or net with this name is inside of this group
+    pub fn contains_name(&self, name: &str) -> bool {
+        name.strip_prefix(self.prefix.as_str())
+            .is_some_and(|x| x.is_empty() || x.starts_with(HIERARCHY_SEPARATOR))
+    }
+}
+
+impl fmt::Display for AreaGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.first_fb == self.last_fb {
+            write!(f, "{} (FB{})", self.prefix, self.first_fb + 1)
+        } else {
+            write!(f, "{} (FB{}:FB{})", self.prefix, self.first_fb + 1, self.last_fb + 1)
+        }
+    }
+}
+
+fn collect_area_groups(netlist: &Netlist, module_name: &str, prefix: &str, stack: &mut Vec<String>,
+    ret: &mut Vec<AreaGroup>, logger: &slog::Logger) -> Result<(), FrontendError> {
+
+    let module = &netlist.modules[module_name];
+    let mut cell_names = module.cells.keys().collect::<Vec<_>>();
+    cell_names.sort();
+    for cell_name in cell_names {
+        let cell = &module.cells[cell_name];
+        if !is_user_module(netlist, &cell.cell_type) || stack.contains(&cell.cell_type) {
+            continue;
+        }
+        let full_name = format!("{}{}", prefix, cell_name);
+
+        if let Some(attrib) = cell.attributes.get("AREA_GROUP") {
+            let area_group = attrib.to_string_if_string().and_then(|x| AreaGroup::new(&full_name, x));
+            if let Some(area_group) = area_group {
+                debug!(logger, "area group - found instance with AREA_GROUP";
+                    "group" => area_group.to_string());
+                ret.push(area_group);
+            } else {
+                error!(logger, "area group - invalid AREA_GROUP";
+                    "code" => "FE031",
+                    "instance" => &full_name,
+                    "value" => attrib);
+                return Err(FrontendError::IllegalAttributeValue(attrib.clone()));
+            }
+        }
+
+        stack.push(cell.cell_type.clone());
+        collect_area_groups(netlist, &cell.cell_type, &format!("{}{}", full_name, HIERARCHY_SEPARATOR), stack, ret,
+            logger)?;
+        stack.pop();
+    }
+
+    Ok(())
+}
+
+/// Finds all of the instances with an AREA_GROUP attribute in the hierarchy below the top-level module (see
+/// `find_top_module`)
+pub fn yosys_area_groups<L: Into<Option<slog::Logger>>>(netlist: &Netlist, top: Option<&str>, logger: L)
+    -> Result<Vec<AreaGroup>, FrontendError> {
+
+    let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+    let top_module_name = find_top_module(netlist, top, &logger)?;
+
+    let mut ret = Vec::new();
+    collect_area_groups(netlist, top_module_name, "", &mut vec![top_module_name.to_owned()], &mut ret, &logger)?;
+    Ok(ret)
+}
+
+impl InputGraph {
+    /// Puts every macrocell into the innermost area group that contains it. If two groups are for the same part of
+    /// the design, the one that comes later wins.
+    pub fn apply_area_groups<L: Into<Option<slog::Logger>>>(&mut self, area_groups: &[AreaGroup], logger: L) {
+        let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+
+        for mc in self.mcs.iter_mut() {
+            let area_group = area_groups.iter()
+                .filter(|x| x.contains_name(&mc.name))
+                .max_by_key(|x| x.prefix.len());
+            if let Some(area_group) = area_group {
+                debug!(logger, "area group - assigned macrocell";
+                    "name" => &mc.name,
+                    "group" => area_group.to_string());
+            }
+            mc.area_group = area_group.cloned();
+        }
+    }
+}
+
+/// Returns every range of function blocks that has to hold more macrocells than it has sites, along with the area
+/// groups that are entirely inside of it, the number of macrocells, the number of sites, and the macrocells. Groups
+/// can overlap, so the demand of all of the groups inside a range is added up. Only the smallest ranges that overflow
+/// are returned. Input pins and the other macrocells are counted separately.
+pub fn area_group_overflows(g: &InputGraph) -> Vec<(Vec<String>, u32, u32, Vec<String>)> {
+    let mut groups = BTreeMap::new();
+    for mc in g.mcs.iter() {
+        if let Some(ref area_group) = mc.area_group {
+            let x = groups.entry((area_group.prefix.as_str(), mc.get_type().is_pininput()))
+                .or_insert_with(|| (area_group, Vec::new()));
+            x.1.push(mc.display_name().to_owned());
+        }
+    }
+
+    // Only ranges that start where a group starts and end where a group ends can be tighter than the ones around them
+    let mut ranges = BTreeSet::new();
+    for (first, _) in groups.values() {
+        for (last, _) in groups.values() {
+            if first.first_fb <= last.last_fb {
+                ranges.insert((last.last_fb - first.first_fb, first.first_fb, last.last_fb));
+            }
+        }
+    }
+
+    let mut overflowing = Vec::new();
+    let mut ret = Vec::new();
+    for is_pininput in [false, true] {
+        for &(_, first_fb, last_fb) in &ranges {
+            let inside = |x: &AreaGroup| x.first_fb >= first_fb && x.last_fb <= last_fb;
+            if overflowing.iter().any(|&(p, a, b)| p == is_pininput && a >= first_fb && b <= last_fb) {
+                continue;
+            }
+
+            let mut names = Vec::new();
+            let mut signals = Vec::new();
+            for (&(_, x_is_pininput), &(area_group, ref x_signals)) in &groups {
+                if x_is_pininput == is_pininput && inside(area_group) {
+                    names.push(area_group.to_string());
+                    signals.extend(x_signals.iter().cloned());
+                }
+            }
+
+            let available = (last_fb - first_fb + 1) * MCS_PER_FB as u32;
+            if signals.len() as u32 > available {
+                overflowing.push((is_pininput, first_fb, last_fb));
+                ret.push((names, signals.len() as u32, available, signals));
+            }
+        }
+    }
+
+    ret
+}
+
+/// Checks that all of the area groups exist on the device, that they are not too full, and that they don't conflict
+/// with any LOC constraints
+pub fn check_area_groups(g: &InputGraph, device_type: XC2DeviceSpeedPackage, logger: &slog::Logger)
+    -> PARSanityResult {
+
+    let num_fbs = device_type.dev.num_fbs() as u32;
+    for mc in g.mcs.iter() {
+        let area_group = if let Some(ref x) = mc.area_group { x } else { continue };
+
+        if area_group.last_fb >= num_fbs {
+            error!(logger, "PAR (sanity) - area group includes a FB that doesn't exist";
+                "code" => "PR021",
+                "group" => area_group.to_string(),
+                "num fbs" => num_fbs);
+            return PARSanityResult::FailureAreaGroup;
+        }
+
+        if let Some(RequestedLocation{fb, ..}) = mc.requested_loc {
+            if !area_group.contains_fb(fb) {
+                error!(logger, "PAR (sanity) - macrocell has a LOC constraint outside of its area group";
+                    "code" => "PR022",
+                    "name" => &mc.name,
+                    "group" => area_group.to_string(),
+                    "fb" => fb);
+                return PARSanityResult::FailureAreaGroup;
+            }
+        }
+    }
+
+    if let Some((area_groups, needed, available, _)) = area_group_overflows(g).into_iter().next() {
+        error!(logger, "PAR (sanity) - too many macrocells in area group. This can never fit.";
+            "code" => "PR023",
+            "groups" => area_groups.join(", "),
+            "num mcs" => needed,
+            "max mcs" => available);
+        return PARSanityResult::FailureAreaGroup;
+    }
+
+    PARSanityResult::Ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::objpool::*;
+
+    #[test]
+    fn parse_area_group() {
+        assert_eq!(AreaGroup::from_constraint("u_core=FB1:FB4"), Some(AreaGroup {
+            prefix: "u_core".to_owned(),
+            first_fb: 0,
+            last_fb: 3,
+        }));
+        assert_eq!(AreaGroup::new("u_core.u_fifo", "fb3").map(|x| x.to_string()),
+            Some("u_core.u_fifo (FB3)".to_owned()));
+        assert_eq!(AreaGroup::new("u_core", "FB4:FB1"), None);
+        assert_eq!(AreaGroup::new("u_core", "FB0"), None);
+        assert_eq!(AreaGroup::new("u_core", "FB1:FB2:FB3"), None);
+        assert_eq!(AreaGroup::from_constraint("FB1"), None);
+
+        let area_group = AreaGroup::new("u_core", "FB1").unwrap();
+        assert!(area_group.contains_name("u_core"));
+        assert!(area_group.contains_name("u_core.u_fifo.wr_ptr"));
+        assert!(!area_group.contains_name("u_core2.x"));
+    }
+
+    #[test]
+    fn overlapping_area_groups() {
+        let input = (0..35).map(|i| format!("y{} = a & b;\n", i)).collect::<String>();
+        let intermediate_graph = IntermediateGraph::from_equations(input.as_bytes(), None).unwrap();
+        let mut g = InputGraph::from_intermed_graph(&intermediate_graph, None).unwrap();
+        let group_a = AreaGroup::from_constraint("a=FB1").unwrap();
+        let group_b = AreaGroup::from_constraint("b=FB1:FB2").unwrap();
+        let assign = |g: &mut InputGraph, num_a: usize| {
+            let outputs = g.mcs.iter_mut().filter(|x| !x.get_type().is_pininput());
+            for (i, mc) in outputs.enumerate() {
+                mc.area_group = Some(if i < num_a { group_a.clone() } else { group_b.clone() });
+            }
+        };
+
+        // Each group fits on its own, but together they need more than FB1 and FB2 have
+        assign(&mut g, 10);
+        let overflows = area_group_overflows(&g);
+        assert_eq!(overflows.len(), 1);
+        assert_eq!(overflows[0].0, ["a (FB1)", "b (FB1:FB2)"]);
+        assert_eq!((overflows[0].1, overflows[0].2), (35, 32));
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        let logger = slog::Logger::root(slog::Discard, o!());
+        assert_eq!(check_area_groups(&g, device_type, &logger), PARSanityResult::FailureAreaGroup);
+
+        // Only the smallest range that overflows is reported
+        assign(&mut g, 20);
+        let overflows = area_group_overflows(&g);
+        assert_eq!(overflows.len(), 1);
+        assert_eq!(overflows[0].0, ["a (FB1)"]);
+        assert_eq!((overflows[0].1, overflows[0].2), (20, 16));
+    }
+
+    #[test]
+    fn area_group_placement() {
+        let input = String::from_utf8(include_bytes!("../../../tests/xc2par/frontend-reftests/hierarchy.json")
+            .to_vec()).unwrap();
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+
+        for &fb in &[0, 1] {
+            let grouped_input = input.replace("\"src\": \"hierarchy.v:6\"",
+                &format!("\"AREA_GROUP\": \"FB{}\", \"src\": \"hierarchy.v:6\"", fb + 1));
+            let mut session = ParSession::new(device_type, &XC2ParOptions::new(), None);
+            session.run_all(grouped_input.as_bytes()).unwrap();
+
+            let g = session.input_graph().unwrap();
+            let go = session.output_graph().unwrap();
+            let grouped_mcs = g.mcs.iter_idx().filter(|&x| g.mcs.get(x).area_group.is_some()).collect::<Vec<_>>();
+            assert!(!grouped_mcs.is_empty());
+            for mc_idx in grouped_mcs {
+                assert_eq!(go.mcs.get(ObjPoolIndex::from(mc_idx)).loc.unwrap().fb, fb);
+            }
+        }
+
+        // Constraints from the options take priority
+        let grouped_input = input.replace("\"src\": \"hierarchy.v:6\"", "\"AREA_GROUP\": \"FB1\", \"src\": \"x\"");
+        let mut options = XC2ParOptions::new();
+        options.area_group(AreaGroup::from_constraint("u_core=FB3").unwrap());
+        match ParSession::new(device_type, &options, None).run_all(grouped_input.as_bytes()) {
+            Err(PARFlowError::PARSanityCheckFailed(PARSanityResult::FailureAreaGroup, bottlenecks)) => {
+                assert_eq!(bottlenecks, [PARBottleneck::AreaGroupInvalid {
+                    group: "u_core (FB3)".to_owned(),
+                    num_fbs: 2,
+                }]);
+            },
+            _ => panic!("invalid area group not detected"),
+        }
+    }
+}
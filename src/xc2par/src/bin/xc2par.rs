@@ -47,14 +47,90 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             .long("jed")
             .overrides_with("crbit"))
 
+        .arg(Arg::with_name("blif")
+            .help("Input is a BLIF netlist (default for .blif files)")
+            .long("blif")
+            .overrides_with_all(&["pla", "eqn"]))
+        .arg(Arg::with_name("pla")
+            .help("Input is a Berkeley PLA table (default for .pla files)")
+            .long("pla")
+            .overrides_with_all(&["blif", "eqn"]))
+        .arg(Arg::with_name("eqn")
+            .help("Input is an equation file (default for .eqn files)")
+            .long("eqn")
+            .overrides_with_all(&["blif", "pla"]))
+
+        .arg(Arg::with_name("plan-pins")
+            .help("Instead of fitting a design, place a list of signals on macrocells (fully bonded packages only)")
+            .long("plan-pins"))
+
+        .arg(Arg::with_name("top")
+            .help("Name of the top-level module (default is the module with the top attribute)")
+            .long("top")
+            .value_name("MODULE")
+            .takes_value(true))
+
+        .arg(Arg::with_name("area-group")
+            .help("Keep everything below a hierarchical instance name in a range of FBs (e.g. u_core=FB1:FB4)")
+            .long("area-group")
+            .value_name("PREFIX=RANGE")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+
+        .arg(Arg::with_name("constraints")
+            .help("Read pin locations and I/O standards from a UCF file (overrides the netlist attributes)")
+            .long("constraints")
+            .value_name("FILE.ucf")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+
         .arg(Arg::with_name("max-iter")
             .help("Maximum iteration count")
             .long("max-iter")
             .takes_value(true))
+        .arg(Arg::with_name("time-limit")
+            .help("Give up on PAR after this many seconds (counted from the start of the flow)")
+            .long("time-limit")
+            .value_name("SECONDS")
+            .takes_value(true))
         .arg(Arg::with_name("rng-seed")
             .help("Seed for internal random number generator (128-bit hex)")
             .long("rng-seed")
             .takes_value(true))
+        .arg(Arg::with_name("no-minimize")
+            .help("Don't minimize logic before fitting")
+            .long("no-minimize"))
+        .arg(Arg::with_name("no-global-inference")
+            .help("Don't automatically use global buffers for clocks, output enables and set/resets")
+            .long("no-global-inference"))
+        .arg(Arg::with_name("no-register-packing")
+            .help("Don't move registers into the input/output paths of the pins they are connected to")
+            .long("no-register-packing"))
+        .arg(Arg::with_name("verify")
+            .help("Check that the logic in the bitstream is equivalent to the input netlist")
+            .long("verify"))
+        .arg(Arg::with_name("postfit")
+            .help("Also write a post-fit Verilog netlist (and an SDF file with timing next to it)")
+            .long("postfit")
+            .value_name("FILE.v")
+            .takes_value(true))
+        .arg(Arg::with_name("pinout")
+            .help("Also write the final pinout as FILE.ucf (constraints), FILE.csv, and FILE.kicad_sym (KiCad symbol)")
+            .long("pinout")
+            .value_name("FILE")
+            .takes_value(true))
+        .arg(Arg::with_name("diagnostics")
+            .help("Write all warnings and errors (with source locations where known) to a JSON file")
+            .long("diagnostics")
+            .value_name("FILE.json")
+            .takes_value(true))
+        .arg(Arg::with_name("report-json")
+            .help("Write a machine-readable report (utilization, pins, diagnostics, PAR statistics) to a JSON file")
+            .long("report-json")
+            .value_name("FILE.json")
+            .takes_value(true))
 
         .arg(Arg::with_name("part-name")
             .help("Part name (<device>-<speed>-<package>)")
@@ -69,7 +145,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             .multiple(true))
 
         .arg(Arg::with_name("INPUT")
-            .help("Input file name (Yosys JSON file)")
+            .help("Input file name (Yosys JSON, BLIF, PLA, equation file, or signal list with --plan-pins)")
             .required(true)
             .index(1))
         .arg(Arg::with_name("OUTPUT")
@@ -96,6 +172,25 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         slog::Logger::root(drain, o!())
     };
 
+    if matches.is_present("plan-pins") {
+        let in_fn = Path::new(matches.value_of_os("INPUT").unwrap());
+        let out_fn = if let Some(out_fn_str) = matches.value_of_os("OUTPUT") {
+            Path::new(out_fn_str).to_owned()
+        } else {
+            in_fn.with_extension("ucf")
+        };
+
+        let part_name_str = matches.value_of_lossy("part-name").unwrap();
+        let device_type = if let Some(x) = XC2DeviceSpeedPackage::from_str(&part_name_str) { x } else {
+            error!(log, "Invalid part name"; "name" => part_name_str.into_owned());
+            return Err(From::from("invalid part name".to_owned()));
+        };
+        let in_f = File::open(in_fn)?;
+        let out_f = File::create(out_fn)?;
+        xc2par_plan_pins(in_f, device_type, out_f, log)?;
+        return Ok(());
+    }
+
     // Handling of options
     let mut options = XC2ParOptions::new();
 
@@ -118,6 +213,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    if let Some(time_limit_str) = matches.value_of_os("time-limit") {
+        // Negative, infinite, NaN, and too large values are all rejected by try_from_secs_f64
+        let time_limit = time_limit_str.to_str().and_then(|x| x.parse::<f64>().ok())
+            .and_then(|x| std::time::Duration::try_from_secs_f64(x).ok());
+        if let Some(time_limit) = time_limit {
+            options.time_limit(Some(time_limit));
+        } else {
+            error!(log, "Illegal value for time-limit"; "value" => time_limit_str.to_string_lossy().into_owned());
+            return Err(From::from("invalid time limit".to_owned()));
+        }
+    }
+
     if let Some(rng_seed_str) = matches.value_of_os("rng-seed") {
         if let Some(rng_seed_str) = rng_seed_str.to_str() {
             let mut rng_seed = [0u32; 4];
@@ -166,6 +273,40 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    if matches.is_present("no-minimize") {
+        options.minimize_logic(false);
+    }
+    if matches.is_present("no-global-inference") {
+        options.infer_global_nets(false);
+    }
+    if matches.is_present("no-register-packing") {
+        options.pack_io_registers(false);
+    }
+    if matches.is_present("verify") {
+        options.verify_equivalence(true);
+    }
+    options.top_module(matches.value_of("top"));
+    for area_group_str in matches.values_of_lossy("area-group").unwrap_or_default() {
+        if let Some(area_group) = AreaGroup::from_constraint(&area_group_str) {
+            options.area_group(area_group);
+        } else {
+            warn!(log, "Illegal value for area-group"; "value" => area_group_str);
+        }
+    }
+    let part_name_str = matches.value_of_lossy("part-name").unwrap();
+    let device_type = if let Some(x) = XC2DeviceSpeedPackage::from_str(&part_name_str) { x } else {
+        error!(log, "Invalid part name"; "name" => part_name_str.into_owned());
+        return Err(From::from("invalid part name".to_owned()));
+    };
+    for constraints_fn in matches.values_of_os("constraints").into_iter().flatten() {
+        for constraint in parse_ucf(File::open(constraints_fn)?, device_type, log.clone())? {
+            options.pin_constraint(constraint);
+        }
+    }
+    options.postfit_netlist(matches.value_of_os("postfit"));
+    options.pinout(matches.value_of_os("pinout"));
+    options.report_json(matches.value_of_os("report-json"));
+
     // Filenames
     let in_fn = Path::new(matches.value_of_os("INPUT").unwrap());
     let out_fn = if let Some(out_fn_str) = matches.value_of_os("OUTPUT") {
@@ -180,15 +321,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         out_fn
     };
 
+    let in_ext = in_fn.extension().and_then(|x| x.to_str()).unwrap_or("").to_ascii_lowercase();
+    let input_format = if matches.is_present("blif") {
+        ParInputFormat::Blif
+    } else if matches.is_present("pla") {
+        ParInputFormat::Pla
+    } else if matches.is_present("eqn") {
+        ParInputFormat::Equations
+    } else {
+        match in_ext.as_ref() {
+            "blif" => ParInputFormat::Blif,
+            "pla" => ParInputFormat::Pla,
+            "eqn" => ParInputFormat::Equations,
+            _ => ParInputFormat::YosysJson,
+        }
+    };
+    options.input_format(input_format);
+
     // Actual work
     let in_f = File::open(in_fn)?;
     let out_f  = File::create(out_fn)?;
-    let part_name_str = matches.value_of_lossy("part-name").unwrap();
-    let device_type = if let Some(x) = XC2DeviceSpeedPackage::from_str(&part_name_str) { x } else {
-        error!(log, "Invalid part name"; "name" => part_name_str.into_owned());
-        return Err(From::from("invalid part name".to_owned()));
-    };
-    xc2par_complete_flow(&options, device_type, in_f, out_f, log)?;
+    let (result, diagnostics) = xc2par_complete_flow_with_diagnostics(&options, device_type, in_f, out_f, log);
+    if let Some(diagnostics_fn) = matches.value_of_os("diagnostics") {
+        serde_json::to_writer_pretty(File::create(diagnostics_fn)?, &diagnostics)?;
+    }
+    result?;
 
     Ok(())
 }
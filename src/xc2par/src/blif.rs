@@ -0,0 +1,731 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Reader for BLIF netlists, such as the ones written by ABC or by Yosys `write_blif`. Rather than building an
+// IntermediateGraph directly, the BLIF is turned into the same in-memory netlist that a Yosys JSON file would produce.
+// Covers from `.names` and the registers from `.latch` become generic gate and flip-flop cells, and `.subckt`/`.gate`
+// become ordinary cells, so everything else goes through the normal frontend.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::io::Read;
+use slog::Drain;
+
+use yosys_netlist_json::{AttributeVal, BitVal, Cell, Module, Netlist, Netname, Port, PortDirection, SpecialBit};
+
+#[derive(Debug)]
+pub enum BlifError {
+    ReadError(std::io::Error),
+    NoModels,
+    MalformedLine(usize, String),
+    UnsupportedDirective(usize, String),
+    UnsupportedLatch(usize, String),
+}
+
+impl error::Error for BlifError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            BlifError::ReadError(ref inner) => Some(inner),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BlifError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BlifError::ReadError(ref inner) => {
+                write!(f, "{}", inner)
+            },
+            BlifError::NoModels => {
+                write!(f, "no models in BLIF file")
+            },
+            BlifError::MalformedLine(line, ref s) => {
+                write!(f, "line {}: malformed line - {}", line, s)
+            },
+            BlifError::UnsupportedDirective(line, ref s) => {
+                write!(f, "line {}: unsupported directive - {}", line, s)
+            },
+            BlifError::UnsupportedLatch(line, ref s) => {
+                write!(f, "line {}: unsupported latch type - {}", line, s)
+            },
+        }
+    }
+}
+
+impl From<std::io::Error> for BlifError {
+    fn from(err: std::io::Error) -> Self {
+        BlifError::ReadError(err)
+    }
+}
+
+// A single-output cover from `.names`. Each row is the input plane followed by the output value.
+struct BlifNames {
+    line: usize,
+    inputs: Vec<String>,
+    output: String,
+    rows: Vec<(String, char)>,
+}
+
+struct BlifCell {
+    line: usize,
+    name: String,
+    cell_type: String,
+    connections: Vec<(String, String)>,
+    attributes: HashMap<String, AttributeVal>,
+    parameters: HashMap<String, AttributeVal>,
+}
+
+enum BlifLastItem {
+    None,
+    Names,
+    Cell,
+}
+
+#[derive(Default)]
+struct BlifModel {
+    name: String,
+    blackbox: bool,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    names: Vec<BlifNames>,
+    cells: Vec<BlifCell>,
+    // (cover index, name, value) from `.attr` lines that follow a `.names`, applied to the cell driving its output
+    names_attributes: Vec<(usize, String, AttributeVal)>,
+    // net initial values from `.latch`
+    init: Vec<(String, bool)>,
+    // from `.clock`
+    clocks: Vec<String>,
+    // (cell index, line) of each `.latch` that is clocked by the clock of the model
+    unclocked_latches: Vec<(usize, usize)>,
+}
+
+// Splits the input into logical lines, handling comments and backslash continuations. Comment characters inside a
+// quoted string (as written by Yosys for `.attr` and `.param`) are not treated as comments.
+fn logical_lines(input: &str) -> Vec<(usize, String)> {
+    let mut ret = Vec::new();
+    let mut current = String::new();
+    let mut current_line = 0;
+
+    for (i, line) in input.lines().enumerate() {
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut end = line.len();
+        for (j, c) in line.char_indices() {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' && in_string {
+                escaped = true;
+            } else if c == '"' {
+                in_string = !in_string;
+            } else if c == '#' && !in_string {
+                end = j;
+                break;
+            }
+        }
+        let line = line[..end].trim_end();
+
+        if current.is_empty() {
+            current_line = i + 1;
+        }
+        if let Some(line) = line.strip_suffix('\\') {
+            current.push_str(line);
+            current.push(' ');
+        } else {
+            current.push_str(line);
+            if !current.trim().is_empty() {
+                ret.push((current_line, current.trim().to_owned()));
+            }
+            current = String::new();
+        }
+    }
+    if !current.trim().is_empty() {
+        ret.push((current_line, current.trim().to_owned()));
+    }
+
+    ret
+}
+
+// Parses the value of an `.attr` or `.param` line. Strings are quoted, and everything else is a bit string. This uses
+// the same convention as the Yosys JSON backend, where a string that could be mistaken for a bit string gets a
+// trailing space.
+fn parse_attribute_value(line: usize, s: &str) -> Result<AttributeVal, BlifError> {
+    if s.starts_with('"') {
+        if s.len() < 2 || !s.ends_with('"') {
+            return Err(BlifError::MalformedLine(line, s.to_owned()));
+        }
+        let mut ret = String::new();
+        let mut chars = s[1..s.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                match chars.next() {
+                    Some('n') => ret.push('\n'),
+                    Some('t') => ret.push('\t'),
+                    Some(c) => ret.push(c),
+                    None => return Err(BlifError::MalformedLine(line, s.to_owned())),
+                }
+            } else {
+                ret.push(c);
+            }
+        }
+        if ret.chars().all(|c| c == '0' || c == '1' || c == 'x' || c == 'z') {
+            ret.push(' ');
+        }
+        Ok(AttributeVal::S(ret))
+    } else {
+        Ok(AttributeVal::S(s.to_owned()))
+    }
+}
+
+fn parse_models(input: &str, logger: &slog::Logger) -> Result<Vec<BlifModel>, BlifError> {
+    let mut models = Vec::new();
+    let mut model: Option<BlifModel> = None;
+    let mut last_item = BlifLastItem::None;
+
+    for (line, text) in logical_lines(input) {
+        if !text.starts_with('.') {
+            // Rows of a `.names` cover
+            let row = text.split_whitespace().collect::<Vec<_>>();
+            let names = match (&last_item, model.as_mut()) {
+                (&BlifLastItem::Names, Some(m)) => m.names.last_mut().unwrap(),
+                _ => return Err(BlifError::MalformedLine(line, text)),
+            };
+            let (plane, value) = match (names.inputs.len(), &row[..]) {
+                (0, &[value]) => ("", value),
+                (_, &[plane, value]) => (plane, value),
+                _ => return Err(BlifError::MalformedLine(line, text)),
+            };
+            if plane.len() != names.inputs.len() || plane.chars().any(|c| c != '0' && c != '1' && c != '-') ||
+                (value != "0" && value != "1") {
+
+                return Err(BlifError::MalformedLine(line, text));
+            }
+            names.rows.push((plane.to_owned(), value.chars().next().unwrap()));
+            continue;
+        }
+
+        let mut words = text.split_whitespace();
+        let directive = words.next().unwrap();
+        let args = words.collect::<Vec<_>>();
+
+        if directive == ".model" {
+            if let Some(m) = model.take() {
+                models.push(m);
+            }
+            debug!(logger, "blif - found model"; "name" => args.first().unwrap_or(&""));
+            model = Some(BlifModel {
+                name: args.first().unwrap_or(&"").to_string(),
+                ..Default::default()
+            });
+            last_item = BlifLastItem::None;
+            continue;
+        }
+
+        // Some tools leave out `.model` entirely for a single model
+        let m = model.get_or_insert_with(|| BlifModel {
+            name: "top".to_owned(),
+            ..Default::default()
+        });
+        match directive {
+            ".inputs" => {
+                m.inputs.extend(args.iter().map(|x| x.to_string()));
+                last_item = BlifLastItem::None;
+            },
+            ".outputs" => {
+                m.outputs.extend(args.iter().map(|x| x.to_string()));
+                last_item = BlifLastItem::None;
+            },
+            ".clock" => {
+                m.clocks.extend(args.iter().map(|x| x.to_string()));
+                last_item = BlifLastItem::None;
+            },
+            ".names" => {
+                if args.is_empty() {
+                    return Err(BlifError::MalformedLine(line, text));
+                }
+                m.names.push(BlifNames {
+                    line,
+                    inputs: args[..args.len() - 1].iter().map(|x| x.to_string()).collect(),
+                    output: args[args.len() - 1].to_owned(),
+                    rows: Vec::new(),
+                });
+                last_item = BlifLastItem::Names;
+            },
+            ".latch" => {
+                // .latch input output [type control] [init]
+                let (latch_type, control, init) = match args.len() {
+                    2 => (None, None, None),
+                    3 => (None, None, Some(args[2])),
+                    4 => (Some(args[2]), Some(args[3]), None),
+                    5 => (Some(args[2]), Some(args[3]), Some(args[4])),
+                    _ => return Err(BlifError::MalformedLine(line, text)),
+                };
+                // Without a type, the latch is a rising edge flip-flop
+                let (cell_type, clk_name) = match latch_type {
+                    Some("re") | None => ("$_DFF_P_", "C"),
+                    Some("fe") => ("$_DFF_N_", "C"),
+                    Some("ah") => ("$_DLATCH_P_", "E"),
+                    Some("al") => ("$_DLATCH_N_", "E"),
+                    Some(x) => return Err(BlifError::UnsupportedLatch(line, x.to_owned())),
+                };
+                // Without a control signal (or with NIL), the latch uses the clock of the model. That might not have
+                // been given yet, so it gets filled in at the end.
+                let control = match control {
+                    Some("NIL") | None => {
+                        m.unclocked_latches.push((m.cells.len(), line));
+                        ""
+                    },
+                    Some(x) => x,
+                };
+                match init {
+                    Some("0") => m.init.push((args[1].to_owned(), false)),
+                    Some("1") => m.init.push((args[1].to_owned(), true)),
+                    Some("2") | Some("3") | None => {},
+                    Some(_) => return Err(BlifError::MalformedLine(line, text)),
+                }
+
+                m.cells.push(BlifCell {
+                    line,
+                    name: args[1].to_owned(),
+                    cell_type: cell_type.to_owned(),
+                    connections: vec![
+                        (clk_name.to_owned(), control.to_owned()),
+                        ("D".to_owned(), args[0].to_owned()),
+                        ("Q".to_owned(), args[1].to_owned()),
+                    ],
+                    attributes: HashMap::new(),
+                    parameters: HashMap::new(),
+                });
+                last_item = BlifLastItem::Cell;
+            },
+            ".subckt" | ".gate" => {
+                if args.is_empty() {
+                    return Err(BlifError::MalformedLine(line, text));
+                }
+                let mut connections = Vec::new();
+                for arg in &args[1..] {
+                    let mut formal_actual = arg.splitn(2, '=');
+                    let formal = formal_actual.next().unwrap();
+                    let actual = formal_actual.next();
+                    if formal.is_empty() || actual.is_none() || actual.unwrap().is_empty() {
+                        return Err(BlifError::MalformedLine(line, text));
+                    }
+                    connections.push((formal.to_owned(), actual.unwrap().to_owned()));
+                }
+
+                m.cells.push(BlifCell {
+                    line,
+                    name: format!("$subckt${}${}", args[0], m.cells.len()),
+                    cell_type: args[0].to_owned(),
+                    connections,
+                    attributes: HashMap::new(),
+                    parameters: HashMap::new(),
+                });
+                last_item = BlifLastItem::Cell;
+            },
+            ".cname" => {
+                if args.len() != 1 {
+                    return Err(BlifError::MalformedLine(line, text));
+                }
+                match last_item {
+                    BlifLastItem::Cell => m.cells.last_mut().unwrap().name = args[0].to_owned(),
+                    _ => return Err(BlifError::MalformedLine(line, text)),
+                }
+            },
+            ".attr" | ".param" => {
+                let mut name_value = text[directive.len()..].trim_start().splitn(2, char::is_whitespace);
+                let name = name_value.next().unwrap_or("");
+                let value = name_value.next().unwrap_or("").trim();
+                if name.is_empty() {
+                    return Err(BlifError::MalformedLine(line, text));
+                }
+                let value = parse_attribute_value(line, value)?;
+                match (&last_item, directive) {
+                    (&BlifLastItem::Cell, ".attr") => {
+                        m.cells.last_mut().unwrap().attributes.insert(name.to_owned(), value);
+                    },
+                    (&BlifLastItem::Cell, _) => {
+                        m.cells.last_mut().unwrap().parameters.insert(name.to_owned(), value);
+                    },
+                    (&BlifLastItem::Names, ".attr") => {
+                        m.names_attributes.push((m.names.len() - 1, name.to_owned(), value));
+                    },
+                    _ => return Err(BlifError::MalformedLine(line, text)),
+                }
+            },
+            ".blackbox" => {
+                m.blackbox = true;
+                last_item = BlifLastItem::None;
+            },
+            ".end" => {
+                if let Some(m) = model.take() {
+                    models.push(m);
+                }
+                last_item = BlifLastItem::None;
+            },
+            _ => {
+                error!(logger, "blif - unsupported directive";
+                    "code" => "BL001", "line" => line, "directive" => directive);
+                return Err(BlifError::UnsupportedDirective(line, directive.to_owned()));
+            },
+        }
+    }
+    if let Some(m) = model.take() {
+        models.push(m);
+    }
+
+    for m in &mut models {
+        for &(cell_i, line) in &m.unclocked_latches {
+            if m.clocks.len() != 1 {
+                return Err(BlifError::UnsupportedLatch(line, format!(
+                    "no control signal, and the model has {} clocks instead of one", m.clocks.len())));
+            }
+            m.cells[cell_i].connections[0].1 = m.clocks[0].clone();
+        }
+    }
+
+    Ok(models)
+}
+
+// Builds up a Yosys module, handing out net numbers the same way Yosys does (starting from 2)
+struct ModuleBuilder {
+    module: Module,
+    net_numbers: HashMap<String, usize>,
+    constants: HashMap<String, bool>,
+    next_net: usize,
+}
+
+impl ModuleBuilder {
+    fn net(&mut self, name: &str) -> BitVal {
+        if let Some(&value) = self.constants.get(name) {
+            return BitVal::S(if value { SpecialBit::_1 } else { SpecialBit::_0 });
+        }
+
+        if let Some(&n) = self.net_numbers.get(name) {
+            return BitVal::N(n);
+        }
+
+        let n = self.next_net;
+        self.next_net += 1;
+        self.net_numbers.insert(name.to_owned(), n);
+        self.module.netnames.insert(name.to_owned(), Netname {
+            hide_name: name.starts_with('$') as usize,
+            bits: vec![BitVal::N(n)],
+            attributes: HashMap::new(),
+        });
+        BitVal::N(n)
+    }
+
+    fn anonymous_net(&mut self) -> BitVal {
+        let n = self.next_net;
+        self.next_net += 1;
+        BitVal::N(n)
+    }
+
+    fn add_cell(&mut self, name: &str, cell: Cell) -> String {
+        let mut unique_name = name.to_owned();
+        let mut i = 1;
+        while self.module.cells.contains_key(&unique_name) {
+            unique_name = format!("{}${}", name, i);
+            i += 1;
+        }
+        self.module.cells.insert(unique_name.clone(), cell);
+        unique_name
+    }
+
+    fn add_gate(&mut self, name: &str, cell_type: &str, inputs: &[BitVal], output: BitVal) -> String {
+        let mut connections = HashMap::new();
+        for (&input_name, &input) in ["A", "B"].iter().zip(inputs) {
+            connections.insert(input_name.to_owned(), vec![input]);
+        }
+        connections.insert("Y".to_owned(), vec![output]);
+
+        self.add_cell(name, Cell {
+            hide_name: name.starts_with('$') as usize,
+            cell_type: cell_type.to_owned(),
+            parameters: HashMap::new(),
+            attributes: HashMap::new(),
+            port_directions: HashMap::new(),
+            connections,
+        })
+    }
+
+    // Turns a cover into a tree of generic gates and returns the name of the gate that drives the output
+    fn add_names(&mut self, names: &BlifNames) -> Result<String, BlifError> {
+        let output = self.net(&names.output);
+        let offset = names.rows.first().map(|x| x.1 == '0').unwrap_or(false);
+        if names.rows.iter().any(|x| (x.1 == '0') != offset) {
+            return Err(BlifError::MalformedLine(names.line, format!("mixed on-set and off-set for {}",
+                names.output)));
+        }
+
+        let mut inverted_inputs = HashMap::new();
+        let mut sum: Option<BitVal> = None;
+        for (row_i, (plane, _)) in names.rows.iter().enumerate() {
+            let mut product: Option<BitVal> = None;
+            for (input_i, c) in plane.chars().enumerate() {
+                let literal = match c {
+                    '1' => self.net(&names.inputs[input_i]),
+                    '0' => {
+                        if let Some(&x) = inverted_inputs.get(&input_i) {
+                            x
+                        } else {
+                            let input = self.net(&names.inputs[input_i]);
+                            let x = self.anonymous_net();
+                            self.add_gate(&format!("{}$not{}", names.output, input_i), "$_NOT_", &[input], x);
+                            inverted_inputs.insert(input_i, x);
+                            x
+                        }
+                    },
+                    _ => continue,
+                };
+                product = Some(if let Some(product) = product {
+                    let x = self.anonymous_net();
+                    self.add_gate(&format!("{}$and{}_{}", names.output, row_i, input_i), "$_AND_",
+                        &[product, literal], x);
+                    x
+                } else {
+                    literal
+                });
+            }
+            // A row without any literals is always true
+            let product = product.unwrap_or(BitVal::S(SpecialBit::_1));
+
+            sum = Some(if let Some(sum) = sum {
+                let x = self.anonymous_net();
+                self.add_gate(&format!("{}$or{}", names.output, row_i), "$_OR_", &[sum, product], x);
+                x
+            } else {
+                product
+            });
+        }
+        let sum = sum.unwrap_or(BitVal::S(SpecialBit::_0));
+
+        Ok(self.add_gate(&names.output, if offset { "$_NOT_" } else { "$_BUF_" }, &[sum], output))
+    }
+}
+
+fn build_module(model: &BlifModel, is_top: bool, logger: &slog::Logger) -> Result<Module, BlifError> {
+    let mut builder = ModuleBuilder {
+        module: Module::default(),
+        net_numbers: HashMap::new(),
+        constants: HashMap::new(),
+        next_net: 2,
+    };
+
+    if is_top {
+        builder.module.attributes.insert("top".to_owned(), AttributeVal::N(1));
+    }
+    if model.blackbox {
+        builder.module.attributes.insert("blackbox".to_owned(), AttributeVal::N(1));
+    }
+
+    // Covers without inputs are constants (this is how Yosys writes out $true and $false). These get replaced by
+    // constant bits everywhere they are used.
+    for names in &model.names {
+        if names.inputs.is_empty() {
+            let value = names.rows.iter().any(|x| x.1 == '1');
+            debug!(logger, "blif - constant net"; "name" => &names.output, "value" => value);
+            builder.constants.insert(names.output.clone(), value);
+        }
+    }
+
+    for (name, direction) in model.inputs.iter().map(|x| (x, PortDirection::Input))
+        .chain(model.outputs.iter().map(|x| (x, PortDirection::Output))) {
+
+        let bit = builder.net(name);
+        if builder.module.ports.contains_key(name) {
+            // A net that is listed as both an input and an output is just an input that also goes straight out
+            continue;
+        }
+        builder.module.ports.insert(name.to_owned(), Port {
+            direction,
+            bits: vec![bit],
+        });
+    }
+
+    for (names_i, names) in model.names.iter().enumerate() {
+        if names.inputs.is_empty() {
+            continue;
+        }
+
+        let cell_name = builder.add_names(names)?;
+        for (_, name, value) in model.names_attributes.iter().filter(|x| x.0 == names_i) {
+            builder.module.cells.get_mut(&cell_name).unwrap().attributes.insert(name.to_owned(), value.clone());
+        }
+    }
+
+    for cell in &model.cells {
+        // Bus connections are written as one connection per bit, e.g. A[0]=x A[1]=y
+        let mut connections: HashMap<String, Vec<BitVal>> = HashMap::new();
+        for (formal, actual) in &cell.connections {
+            let bit = builder.net(actual);
+            let (port, idx) = match (formal.find('['), formal.ends_with(']')) {
+                (Some(i), true) => {
+                    let idx = formal[i + 1..formal.len() - 1].parse::<usize>()
+                        .map_err(|_| BlifError::MalformedLine(cell.line, formal.to_owned()))?;
+                    (&formal[..i], idx)
+                },
+                _ => (&formal[..], 0),
+            };
+            let bits = connections.entry(port.to_owned()).or_default();
+            if bits.len() <= idx {
+                bits.resize(idx + 1, BitVal::S(SpecialBit::X));
+            }
+            bits[idx] = bit;
+        }
+
+        builder.add_cell(&cell.name, Cell {
+            hide_name: cell.name.starts_with('$') as usize,
+            cell_type: cell.cell_type.clone(),
+            parameters: cell.parameters.clone(),
+            attributes: cell.attributes.clone(),
+            port_directions: HashMap::new(),
+            connections,
+        });
+    }
+
+    for (name, value) in &model.init {
+        let bit = builder.net(name);
+        if let BitVal::N(_) = bit {
+            builder.module.netnames.get_mut(name).unwrap().attributes.insert("init".to_owned(),
+                AttributeVal::S(if *value { "1" } else { "0" }.to_owned()));
+        }
+    }
+
+    Ok(builder.module)
+}
+
+/// Reads a BLIF netlist into the same form as a Yosys JSON netlist. The first model that isn't a black box is
+/// treated as the top-level module.
+pub fn read_blif_netlist<R, L>(mut input: R, logger: L) -> Result<Netlist, BlifError>
+    where R: Read, L: Into<Option<slog::Logger>> {
+
+    let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+
+    let mut data = String::new();
+    input.read_to_string(&mut data)?;
+    let models = parse_models(&data, &logger)?;
+
+    let top_idx = models.iter().position(|x| !x.blackbox);
+    if top_idx.is_none() {
+        error!(logger, "blif - found no models"; "code" => "BL002");
+        return Err(BlifError::NoModels);
+    }
+    let top_idx = top_idx.unwrap();
+
+    let mut netlist = Netlist {
+        creator: "xc2par BLIF reader".to_owned(),
+        modules: HashMap::new(),
+    };
+    for (i, model) in models.iter().enumerate() {
+        let logger = logger.new(o!("model" => model.name.clone()));
+        let module = build_module(model, i == top_idx, &logger)?;
+        netlist.modules.insert(model.name.clone(), module);
+    }
+
+    Ok(netlist)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::File;
+    use std::io::Read;
+
+    use crate::*;
+
+    fn run_one_reftest(input_filename: &'static str) {
+        // Read original BLIF
+        let input_path = std::path::Path::new(input_filename);
+        let yosys_netlist = read_blif_netlist(File::open(input_path).unwrap(), None).unwrap();
+        // This is what we get
+        let our_data_structure = IntermediateGraph::from_yosys_netlist(&yosys_netlist, None).unwrap();
+
+        // Read reference json
+        let mut output_path = input_path.to_path_buf();
+        output_path.set_extension("out");
+        let mut output_data = Vec::new();
+        File::open(&output_path).unwrap().read_to_end(&mut output_data).unwrap();
+        let reference_data_structure = serde_json::from_slice(&output_data).unwrap();
+
+        assert_eq!(our_data_structure, reference_data_structure);
+    }
+
+    #[test]
+    fn blif_errors_have_line_numbers() {
+        let input = ".model top\n.inputs a\n.outputs y\n.names a y\n1 1\n01 1\n.end\n";
+        match read_blif_netlist(input.as_bytes(), None) {
+            Err(BlifError::MalformedLine(6, _)) => {},
+            x => panic!("unexpected result {:?}", x),
+        }
+
+        let input = ".model top\n.inputs clk d\n.outputs q\n.latch d q as clk\n.end\n";
+        match read_blif_netlist(input.as_bytes(), None) {
+            Err(BlifError::UnsupportedLatch(4, _)) => {},
+            x => panic!("unexpected result {:?}", x),
+        }
+
+        let input = ".model top\n.inputs d\n.outputs q\n.latch d q 0\n.end\n";
+        match read_blif_netlist(input.as_bytes(), None) {
+            Err(BlifError::UnsupportedLatch(4, _)) => {},
+            x => panic!("unexpected result {:?}", x),
+        }
+
+        let input = ".model top\n.exdc\n.end\n";
+        match read_blif_netlist(input.as_bytes(), None) {
+            Err(BlifError::UnsupportedDirective(2, _)) => {},
+            x => panic!("unexpected result {:?}", x),
+        }
+    }
+
+    #[test]
+    fn blif_latch_uses_model_clock() {
+        let input = ".model top\n.inputs clk d\n.outputs q r\n.latch d q 1\n.latch d r re NIL\n.clock clk\n.end\n";
+        let netlist = read_blif_netlist(input.as_bytes(), None).unwrap();
+        let module = &netlist.modules["top"];
+        let clk = &module.ports["clk"].bits;
+        for name in &["q", "r"] {
+            let cell = &module.cells[*name];
+            assert_eq!(cell.cell_type, "$_DFF_P_");
+            assert_eq!(&cell.connections["C"], clk);
+        }
+    }
+
+    #[test]
+    fn blif_attribute_values() {
+        let input = ".model top\n.subckt X A=a\n.attr LOC \"FB1_5\"\n.attr BITS \"01\"\n.param WIDTH 0101\n.end\n";
+        let netlist = read_blif_netlist(input.as_bytes(), None).unwrap();
+        let cell = netlist.modules["top"].cells.values().next().unwrap();
+        assert_eq!(cell.attributes["LOC"].to_string_if_string(), Some("FB1_5"));
+        assert_eq!(cell.attributes["BITS"].to_string_if_string(), Some("01"));
+        assert_eq!(cell.parameters["WIDTH"].to_number(), Some(5));
+    }
+
+    // Include list of actual tests to run
+    include!(concat!(env!("OUT_DIR"), "/blif-reftests.rs"));
+}
@@ -23,6 +23,7 @@ OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
 OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 */
 
+use std::collections::HashMap;
 use std::error;
 use std::fmt;
 use serde_derive::{Deserialize, Serialize};
@@ -31,6 +32,14 @@ use xc2bit::*;
 
 use crate::*;
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum ParInputFormat {
+    YosysJson,
+    Blif,
+    Pla,
+    Equations,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub enum ParOutputFormat {
     Jed,
@@ -41,7 +50,20 @@ pub enum ParOutputFormat {
 pub struct XC2ParOptions {
     pub(crate) max_iter: u32,
     pub(crate) rng_seed: [u32; 4],
+    pub(crate) input_fmt: ParInputFormat,
+    pub(crate) top_module: Option<String>,
+    pub(crate) area_groups: Vec<AreaGroup>,
+    pub(crate) pin_constraints: Vec<PinConstraint>,
     output_fmt: ParOutputFormat,
+    pub(crate) minimize_logic: bool,
+    pub(crate) infer_global_nets: bool,
+    pub(crate) pack_io_registers: bool,
+    pub(crate) verify_equivalence: bool,
+    postfit_netlist: Option<std::path::PathBuf>,
+    report_json: Option<std::path::PathBuf>,
+    pinout: Option<std::path::PathBuf>,
+    pub(crate) time_limit: Option<std::time::Duration>,
+    pub(crate) cancel_flag: Option<std::sync::Arc<std::sync::atomic::AtomicBool>>,
 }
 
 impl XC2ParOptions {
@@ -49,7 +71,20 @@ impl XC2ParOptions {
         Self {
             max_iter: 1000,
             rng_seed: [0, 0, 0, 1],
+            input_fmt: ParInputFormat::YosysJson,
+            top_module: None,
+            area_groups: Vec::new(),
+            pin_constraints: Vec::new(),
             output_fmt: ParOutputFormat::Jed,
+            minimize_logic: true,
+            infer_global_nets: true,
+            pack_io_registers: true,
+            verify_equivalence: false,
+            postfit_netlist: None,
+            report_json: None,
+            pinout: None,
+            time_limit: None,
+            cancel_flag: None,
         }
     }
 
@@ -65,11 +100,106 @@ impl XC2ParOptions {
         self
     }
 
+    pub fn input_format(&mut self, format: ParInputFormat) -> &mut Self {
+        self.input_fmt = format;
+
+        self
+    }
+
+    /// Use the module with this name as the top-level module (instead of the one with the `top` attribute)
+    pub fn top_module<S: Into<String>>(&mut self, top_module: Option<S>) -> &mut Self {
+        self.top_module = top_module.map(|x| x.into());
+
+        self
+    }
+
+    /// Confine the part of the design below a hierarchical instance name to a range of FBs. This is in addition to
+    /// any AREA_GROUP attributes in the netlist, and takes priority over them.
+    pub fn area_group(&mut self, area_group: AreaGroup) -> &mut Self {
+        self.area_groups.push(area_group);
+
+        self
+    }
+
+    /// Lock a pin to a location and/or I/O standard (e.g. from a UCF file). This takes priority over the LOC and
+    /// IOSTANDARD attributes in the netlist.
+    pub fn pin_constraint(&mut self, constraint: PinConstraint) -> &mut Self {
+        self.pin_constraints.push(constraint);
+
+        self
+    }
+
     pub fn output_format(&mut self, format: ParOutputFormat) -> &mut Self {
         self.output_fmt = format;
 
         self
     }
+
+    pub fn minimize_logic(&mut self, minimize_logic: bool) -> &mut Self {
+        self.minimize_logic = minimize_logic;
+
+        self
+    }
+
+    /// Automatically use the global clock/output enable/set-reset buffers for signals that come straight from a pin
+    pub fn infer_global_nets(&mut self, infer_global_nets: bool) -> &mut Self {
+        self.infer_global_nets = infer_global_nets;
+
+        self
+    }
+
+    /// Move registers that are fed by an input pin or that only feed an output pin into the IOB paths of that pin
+    pub fn pack_io_registers(&mut self, pack_io_registers: bool) -> &mut Self {
+        self.pack_io_registers = pack_io_registers;
+
+        self
+    }
+
+    /// After producing the bitstream, decode it again and check that its logic matches the input netlist (see
+    /// `check_equivalence`)
+    pub fn verify_equivalence(&mut self, verify_equivalence: bool) -> &mut Self {
+        self.verify_equivalence = verify_equivalence;
+
+        self
+    }
+
+    /// Also write a post-fit Verilog netlist to the given path, along with SDF timing annotations next to it
+    pub fn postfit_netlist<P: Into<std::path::PathBuf>>(&mut self, path: Option<P>) -> &mut Self {
+        self.postfit_netlist = path.map(|x| x.into());
+
+        self
+    }
+
+    /// Also write a machine-readable report of the fit (see `FitReport`) to the given path
+    pub fn report_json<P: Into<std::path::PathBuf>>(&mut self, path: Option<P>) -> &mut Self {
+        self.report_json = path.map(|x| x.into());
+
+        self
+    }
+
+    /// Also write the final pinout next to the given path, as a UCF file of constraints, a CSV pin report, and a KiCad
+    /// symbol library (the extension of the path is replaced with .ucf, .csv, and .kicad_sym). The KiCad symbol is only
+    /// written if the package pin map is known.
+    pub fn pinout<P: Into<std::path::PathBuf>>(&mut self, path: Option<P>) -> &mut Self {
+        self.pinout = path.map(|x| x.into());
+
+        self
+    }
+
+    /// Give up on PAR once this much wall-clock time has passed since the flow started. The frontend and the passes
+    /// before PAR count towards the limit too.
+    pub fn time_limit(&mut self, time_limit: Option<std::time::Duration>) -> &mut Self {
+        self.time_limit = time_limit;
+
+        self
+    }
+
+    /// Give up on PAR as soon as this flag is set (it can be set from another thread)
+    pub fn cancel_flag(&mut self, cancel_flag: Option<std::sync::Arc<std::sync::atomic::AtomicBool>>) -> &mut Self {
+        self.cancel_flag = cancel_flag;
+
+        self
+    }
 }
 
 impl Default for XC2ParOptions {
@@ -81,22 +211,33 @@ impl Default for XC2ParOptions {
 #[derive(Debug)]
 pub enum PARFlowError {
     SerdeError(serde_json::Error),
+    BlifError(BlifError),
+    EquationError(EquationError),
     FrontendError(FrontendError),
     IntermedToInputError(IntermedToInputError),
     OutputWriteError(std::io::Error),
-    PARIterationsExceeded,
-    PARSanityCheckFailed(PARSanityResult),
+    PARIterationsExceeded(Vec<PARBottleneck>),
+    PARSanityCheckFailed(PARSanityResult, Vec<PARBottleneck>),
+    PARStopped(PARStopReason),
+    EquivalenceCheckFailed(EquivalenceReport),
+    StageNotRun(ParStage),
 }
 
 impl error::Error for PARFlowError {
     fn description(&self) -> &'static str {
         match self {
             &PARFlowError::SerdeError(_) => "json read failed",
+            &PARFlowError::BlifError(_) => "blif read failed",
+            &PARFlowError::EquationError(_) => "equation read failed",
             &PARFlowError::FrontendError(_) => "frontend pass failed",
             &PARFlowError::IntermedToInputError(_) => "intermediate pass failed",
             &PARFlowError::OutputWriteError(_) => "writing output failed",
-            &PARFlowError::PARIterationsExceeded => "",
-            &PARFlowError::PARSanityCheckFailed(_) => "",
+            &PARFlowError::PARIterationsExceeded(_) => "",
+            &PARFlowError::PARSanityCheckFailed(..) => "",
+            &PARFlowError::PARStopped(PARStopReason::Cancelled) => "PAR was cancelled",
+            &PARFlowError::PARStopped(PARStopReason::DeadlineExceeded) => "PAR time limit exceeded",
+            &PARFlowError::EquivalenceCheckFailed(_) => "bitstream does not match netlist",
+            &PARFlowError::StageNotRun(_) => "a previous PAR stage has not been run",
         }
     }
 
@@ -105,6 +246,12 @@ impl error::Error for PARFlowError {
             &PARFlowError::SerdeError(ref inner) => {
                 Some(inner)
             },
+            &PARFlowError::BlifError(ref inner) => {
+                Some(inner)
+            },
+            &PARFlowError::EquationError(ref inner) => {
+                Some(inner)
+            },
             &PARFlowError::FrontendError(ref inner) => {
                 Some(inner)
             },
@@ -122,15 +269,23 @@ impl error::Error for PARFlowError {
 impl fmt::Display for PARFlowError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &PARFlowError::PARIterationsExceeded => {
-                write!(f, "maximum iterations exceeded")
+            &PARFlowError::PARIterationsExceeded(ref bottlenecks) => {
+                write!(f, "maximum iterations exceeded")?;
+                write_bottlenecks(f, bottlenecks)
             },
-            &PARFlowError::PARSanityCheckFailed(_) => {
-                write!(f, "PAR sanity check failed")
+            &PARFlowError::PARSanityCheckFailed(_, ref bottlenecks) => {
+                write!(f, "PAR sanity check failed")?;
+                write_bottlenecks(f, bottlenecks)
             },
             &PARFlowError::SerdeError(ref inner) => {
                 write!(f, "{}", inner)
             },
+            &PARFlowError::BlifError(ref inner) => {
+                write!(f, "{}", inner)
+            },
+            &PARFlowError::EquationError(ref inner) => {
+                write!(f, "{}", inner)
+            },
             &PARFlowError::FrontendError(ref inner) => {
                 write!(f, "{}", inner)
             },
@@ -140,16 +295,58 @@ impl fmt::Display for PARFlowError {
             &PARFlowError::OutputWriteError(ref inner) => {
                 write!(f, "{}", inner)
             },
+            &PARFlowError::PARStopped(PARStopReason::Cancelled) => {
+                write!(f, "PAR was cancelled")
+            },
+            &PARFlowError::PARStopped(PARStopReason::DeadlineExceeded) => {
+                write!(f, "PAR time limit exceeded")
+            },
+            &PARFlowError::EquivalenceCheckFailed(ref report) => {
+                write!(f, "bitstream does not match netlist")?;
+                for mismatch in &report.mismatches {
+                    write!(f, "\n  {}", mismatch)?;
+                }
+                for item in &report.unmatched {
+                    write!(f, "\n  {} was not found in the placed design", item)?;
+                }
+                for item in &report.too_complex {
+                    write!(f, "\n  {} was too complex to check", item)?;
+                }
+                Ok(())
+            },
+            &PARFlowError::StageNotRun(stage) => {
+                write!(f, "the {:?} stage has not been run", stage)
+            },
         }
     }
 }
 
+fn write_bottlenecks(f: &mut fmt::Formatter, bottlenecks: &[PARBottleneck]) -> fmt::Result {
+    for bottleneck in bottlenecks {
+        write!(f, "\n  {}", bottleneck)?;
+    }
+
+    Ok(())
+}
+
 impl From<serde_json::Error> for PARFlowError {
     fn from(inner: serde_json::Error) -> Self {
         PARFlowError::SerdeError(inner)
     }
 }
 
+impl From<BlifError> for PARFlowError {
+    fn from(inner: BlifError) -> Self {
+        PARFlowError::BlifError(inner)
+    }
+}
+
+impl From<EquationError> for PARFlowError {
+    fn from(inner: EquationError) -> Self {
+        PARFlowError::EquationError(inner)
+    }
+}
+
 impl From<FrontendError> for PARFlowError {
     fn from(inner: FrontendError) -> Self {
         PARFlowError::FrontendError(inner)
@@ -171,29 +368,113 @@ impl From<std::io::Error> for PARFlowError {
 pub fn xc2par_complete_flow<R, W, L>(options: &XC2ParOptions, device_type: XC2DeviceSpeedPackage, input: R, output: W,
     logger: L) -> Result<(), PARFlowError> where R: std::io::Read, W: std::io::Write, L: Into<Option<slog::Logger>> {
 
+    xc2par_complete_flow_with_diagnostics(options, device_type, input, output, logger).0
+}
+
+/// Runs the complete flow like `xc2par_complete_flow`, but also returns all of the warnings and errors that were
+/// reported along the way. If the input was a Yosys netlist, diagnostics are annotated with the source locations
+/// recorded in it.
+pub fn xc2par_complete_flow_with_diagnostics<R, W, L>(options: &XC2ParOptions, device_type: XC2DeviceSpeedPackage,
+    input: R, output: W, logger: L) -> (Result<(), PARFlowError>, Vec<Diagnostic>)
+    where R: std::io::Read, W: std::io::Write, L: Into<Option<slog::Logger>> {
+
     let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+    let collector = DiagnosticCollector::new();
+    let logger = slog::Logger::root(slog::Duplicate::new(logger, collector.clone()).fuse(), o!());
 
-    let yosys_netlist = yosys_netlist_json::Netlist::from_reader(input)?;
-    let intermediate_graph = IntermediateGraph::from_yosys_netlist(&yosys_netlist,
-        logger.new(o!("pass" => "yosys -> intermediate")))?;
-    let mut input_graph = InputGraph::from_intermed_graph(&intermediate_graph,
-        logger.new(o!("pass" => "intermediate -> input")))?;
-    let par_result = do_par(&mut input_graph, device_type, options,
-        logger.new(o!("pass" => "PAR")));
-
-    match par_result {
-        PARResult::Success(x) => {
-            let bitstream = produce_bitstream(device_type, &input_graph, &x);
-
-            if options.output_fmt == ParOutputFormat::Jed {
-                bitstream.to_jed(output)?;
-            } else {
-                bitstream.to_crbit().write_to_writer(output)?;
-            }
-
-            Ok(())
+    let mut sources = HashMap::new();
+    let mut report = FitReport::new(device_type, options);
+    let mut result = complete_flow(options, device_type, input, output, &mut sources, &mut report, logger);
+    let mut diagnostics = collector.take();
+
+    // Not every failure is logged (e.g. the input could not be parsed at all)
+    if let Err(ref err) = result {
+        if !diagnostics.iter().any(|x| x.severity == DiagnosticSeverity::Error) {
+            diagnostics.push(Diagnostic {
+                severity: DiagnosticSeverity::Error,
+                code: "XC2PAR".to_owned(),
+                message: format!("{}", err),
+                pass: None,
+                object: None,
+                src: None,
+                details: Vec::new(),
+            });
+        }
+    }
+
+    for diagnostic in &mut diagnostics {
+        if diagnostic.src.is_none() {
+            diagnostic.src = diagnostic.object.as_ref().and_then(|x| sources.get(x)).cloned();
+        }
+    }
+
+    if let Some(ref path) = options.report_json {
+        report.error = result.as_ref().err().map(|x| format!("{}", x));
+        report.diagnostics = diagnostics.clone();
+        let write_result = std::fs::File::create(path).map_err(PARFlowError::from)
+            .and_then(|f| report.write_json(std::io::BufWriter::new(f)).map_err(PARFlowError::from));
+        if result.is_ok() {
+            result = write_result;
+        }
+    }
+
+    (result, diagnostics)
+}
+
+fn complete_flow<R, W>(options: &XC2ParOptions, device_type: XC2DeviceSpeedPackage, input: R, output: W,
+    sources: &mut HashMap<String, DiagnosticSource>, report: &mut FitReport, logger: slog::Logger)
+    -> Result<(), PARFlowError>
+    where R: std::io::Read, W: std::io::Write {
+
+    let mut session = ParSession::new(device_type, options, logger.clone());
+    let result = session.run_all(input);
+    *sources = session.source_locations().clone();
+    report.split_signals = session.split_signals().to_vec();
+
+    let bitstream = match result {
+        Ok(x) => x,
+        Err(PARFlowError::PARSanityCheckFailed(x, bottlenecks)) => {
+            report.par.bottlenecks = bottlenecks.iter().map(|x| format!("{}", x)).collect();
+            return Err(PARFlowError::PARSanityCheckFailed(x, bottlenecks));
         },
-        PARResult::FailureSanity(x) => Err(PARFlowError::PARSanityCheckFailed(x)),
-        PARResult::FailureIterationsExceeded => Err(PARFlowError::PARIterationsExceeded),
+        Err(PARFlowError::PARIterationsExceeded(bottlenecks)) => {
+            report.par.bottlenecks = bottlenecks.iter().map(|x| format!("{}", x)).collect();
+            return Err(PARFlowError::PARIterationsExceeded(bottlenecks));
+        },
+        Err(x) => return Err(x),
+    };
+    let input_graph = session.input_graph().unwrap();
+    let output_graph = session.output_graph().unwrap();
+
+    report.add_fit_result(device_type, input_graph, output_graph);
+
+    if options.output_fmt == ParOutputFormat::Jed {
+        bitstream.to_jed(output)?;
+    } else {
+        bitstream.to_crbit().write_to_writer(output)?;
     }
+
+    if let Some(ref path) = options.postfit_netlist {
+        let module_name = path.file_stem().and_then(|x| x.to_str()).unwrap_or("top");
+        let netlist = PostfitNetlist::new(device_type, module_name, input_graph, output_graph);
+        netlist.write_verilog(std::io::BufWriter::new(std::fs::File::create(path)?))?;
+        netlist.write_sdf(std::io::BufWriter::new(std::fs::File::create(path.with_extension("sdf"))?))?;
+    }
+
+    if let Some(ref path) = options.pinout {
+        let pinout = Pinout::new(device_type, input_graph, output_graph);
+        pinout.write_ucf(std::io::BufWriter::new(std::fs::File::create(path.with_extension("ucf"))?))?;
+        pinout.write_csv(std::io::BufWriter::new(std::fs::File::create(path.with_extension("csv"))?))?;
+        if has_package_pin_map(device_type) {
+            let symbol_name = path.file_stem().and_then(|x| x.to_str()).unwrap_or("xc2par");
+            pinout.write_kicad_symbol(std::io::BufWriter::new(std::fs::File::create(path.with_extension("kicad_sym"))?),
+                symbol_name)?;
+        } else {
+            warn!(logger, "pinout - the package pin map is not known, so no KiCad symbol was written";
+                "code" => "PO001",
+                "part" => format!("{}-{}-{}", device_type.dev, device_type.spd, device_type.pkg));
+        }
+    }
+
+    Ok(())
 }
@@ -0,0 +1,332 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Pin constraints in UCF files. Only the part of the format that is needed to lock down pins is understood, i.e. NET
+// statements with LOC and IOSTANDARD:
+//
+//     NET "clk" LOC = "FB2_5";
+//     NET "data[0]" LOC = "FB1_3" | IOSTANDARD = LVCMOS18;    # comment
+//     NET "q" LOC = "P38";
+//
+// A LOC can name a package pin if xc2bit has the pin map of the package, and it is then turned into the FBn_m site that
+// the pin is bonded to. When writing, package pin names are used wherever they are known.
+//
+// Constraints from a UCF file take priority over the LOC and IOSTANDARD attributes in the netlist. The pin planner and
+// the pinout written after a fit both use this format, so either of them can be fed back in to keep the pins where they
+// are.
+
+use std::error;
+use std::fmt;
+use std::io::{Read, Write};
+use slog::Drain;
+
+use xc2bit::*;
+
+use crate::*;
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PinConstraint {
+    pub name: String,
+    pub location: Option<RequestedLocation>,
+    pub io_standard: Option<IOStandard>,
+}
+
+impl PinConstraint {
+    /// Writes this constraint as one UCF statement, optionally followed by a comment. Locations are written as package
+    /// pins if the pin map of the package is known.
+    pub fn write_ucf<W: Write>(&self, mut writer: W, device_type: XC2DeviceSpeedPackage, comment: Option<&str>)
+        -> Result<(), std::io::Error> {
+
+        let mut attribs = Vec::new();
+        match self.location {
+            Some(RequestedLocation{fb, i: Some(i)}) => match get_package_pin(device_type, fb, i) {
+                Some(pin) => attribs.push(format!("LOC = \"{}\"", pin)),
+                None => attribs.push(format!("LOC = \"FB{}_{}\"", fb + 1, i + 1)),
+            },
+            Some(RequestedLocation{fb, i: None}) => attribs.push(format!("LOC = \"FB{}\"", fb + 1)),
+            None => {},
+        }
+        if let Some(io_standard) = self.io_standard {
+            attribs.push(format!("IOSTANDARD = {}", io_standard));
+        }
+
+        write!(writer, "NET \"{}\" {};", self.name, attribs.join(" | "))?;
+        if let Some(comment) = comment {
+            write!(writer, " # {}", comment)?;
+        }
+        writeln!(writer)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConstraintError {
+    ReadError(std::io::Error),
+    MalformedLine(usize, String),
+    IllegalValue(usize, String),
+}
+
+impl error::Error for ConstraintError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ConstraintError::ReadError(inner) => Some(inner),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ConstraintError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConstraintError::ReadError(inner) => {
+                write!(f, "{}", inner)
+            },
+            ConstraintError::MalformedLine(line, s) => {
+                write!(f, "line {}: malformed constraint - {}", line, s)
+            },
+            ConstraintError::IllegalValue(line, s) => {
+                write!(f, "line {}: illegal constraint value - {}", line, s)
+            },
+        }
+    }
+}
+
+impl From<std::io::Error> for ConstraintError {
+    fn from(err: std::io::Error) -> Self {
+        ConstraintError::ReadError(err)
+    }
+}
+
+fn unquote(s: &str) -> &str {
+    let s = s.trim();
+    s.strip_prefix('"').and_then(|x| x.strip_suffix('"')).unwrap_or(s)
+}
+
+/// Reads the pin constraints for the given part out of a UCF file. Several statements for the same net are merged.
+pub fn parse_ucf<R, L>(mut input: R, device_type: XC2DeviceSpeedPackage, logger: L)
+    -> Result<Vec<PinConstraint>, ConstraintError>
+    where R: Read, L: Into<Option<slog::Logger>> {
+
+    let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+
+    let mut data = String::new();
+    input.read_to_string(&mut data)?;
+
+    // Statements can span lines, so gather them up first along with the line that each one starts on
+    let mut statements = Vec::new();
+    let mut statement = String::new();
+    let mut statement_line = 1;
+    for (line_i, line) in data.lines().enumerate() {
+        for (part_i, part) in line.split('#').next().unwrap().split(';').enumerate() {
+            if part_i > 0 {
+                statements.push((statement_line, statement.trim().to_owned()));
+                statement.clear();
+            }
+            if statement.trim().is_empty() {
+                statement_line = line_i + 1;
+            }
+            statement.push_str(part);
+            statement.push(' ');
+        }
+    }
+    if !statement.trim().is_empty() {
+        statements.push((statement_line, statement.trim().to_owned()));
+    }
+
+    let mut ret: Vec<PinConstraint> = Vec::new();
+    for (line_num, statement) in statements {
+        if statement.is_empty() {
+            continue;
+        }
+
+        let malformed = || {
+            error!(logger, "constraints - malformed statement";
+                "code" => "UC001",
+                "line" => line_num,
+                "text" => &statement);
+            ConstraintError::MalformedLine(line_num, statement.clone())
+        };
+
+        let (keyword, rest) = statement.split_once(char::is_whitespace).ok_or_else(malformed)?;
+        if !keyword.eq_ignore_ascii_case("NET") {
+            warn!(logger, "constraints - ignoring unsupported statement";
+                "code" => "UC002",
+                "line" => line_num,
+                "text" => &statement);
+            continue;
+        }
+
+        // The net name is either quoted (and can then contain anything but a quote) or ends at the first space
+        let rest = rest.trim_start();
+        let (name, rest) = if let Some(rest) = rest.strip_prefix('"') {
+            rest.split_once('"').ok_or_else(malformed)?
+        } else {
+            rest.split_once(char::is_whitespace).ok_or_else(malformed)?
+        };
+
+        let mut constraint = PinConstraint {
+            name: name.to_owned(),
+            location: None,
+            io_standard: None,
+        };
+        for attrib in rest.split('|') {
+            let (key, value) = attrib.split_once('=').ok_or_else(malformed)?;
+            let key = key.trim();
+            let value = unquote(value);
+            let illegal_value = || {
+                error!(logger, "constraints - illegal value";
+                    "code" => "UC003",
+                    "line" => line_num,
+                    "name" => name,
+                    "attribute" => key,
+                    "value" => value);
+                ConstraintError::IllegalValue(line_num, format!("{} = {}", key, value))
+            };
+
+            if key.eq_ignore_ascii_case("LOC") {
+                constraint.location = if let Some((fb, mc)) = find_package_pin(device_type, value) {
+                    Some(RequestedLocation{fb, i: Some(mc)})
+                } else {
+                    match RequestedLocation::parse_location(Some(value), &logger) {
+                        Ok(Some(x)) => Some(x),
+                        _ => return Err(illegal_value()),
+                    }
+                };
+            } else if key.eq_ignore_ascii_case("IOSTANDARD") {
+                constraint.io_standard = Some(IOStandard::from_name(value).ok_or_else(illegal_value)?);
+            } else {
+                warn!(logger, "constraints - ignoring unsupported attribute";
+                    "code" => "UC002",
+                    "line" => line_num,
+                    "name" => name,
+                    "attribute" => key);
+            }
+        }
+
+        if let Some(x) = ret.iter_mut().find(|x| x.name == constraint.name) {
+            x.location = constraint.location.or(x.location);
+            x.io_standard = constraint.io_standard.or(x.io_standard);
+        } else {
+            ret.push(constraint);
+        }
+    }
+
+    Ok(ret)
+}
+
+impl InputGraph {
+    /// Applies LOC and IOSTANDARD constraints to the pins with matching names, replacing whatever the netlist asked for
+    pub fn apply_pin_constraints<L>(&mut self, constraints: &[PinConstraint], logger: L)
+        where L: Into<Option<slog::Logger>> {
+
+        let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+
+        for constraint in constraints {
+            let mc = self.mcs.iter_mut().find(|x| x.io_bits.is_some() && x.pad_name() == constraint.name);
+            let mc = if let Some(mc) = mc { mc } else {
+                warn!(logger, "constraints - no pin with this name";
+                    "code" => "UC004",
+                    "name" => &constraint.name);
+                continue;
+            };
+
+            if let Some(location) = constraint.location {
+                if mc.requested_loc.is_some_and(|x| x != location) {
+                    info!(logger, "constraints - replacing LOC attribute";
+                        "name" => &constraint.name,
+                        "fb" => location.fb,
+                        "mc" => location.i);
+                }
+                mc.requested_loc = Some(location);
+            }
+            if let Some(io_standard) = constraint.io_standard {
+                mc.io_bits.as_mut().unwrap().io_standard = Some(io_standard);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_write_ucf() {
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c128-6-tq144").unwrap();
+        let input = "
+            # pins
+            NET \"clk\" LOC = \"FB2_5\";   # GCK0
+            NET data<0> LOC=FB1 | IOSTANDARD = lvcmos18;
+            NET \"data<0>\" SLEW = FAST;
+            NET \"q\"
+                LOC = \"FB1_3\";
+            TIMESPEC TS_clk = PERIOD \"clk\" 10 ns;
+            NET \"clk\" IOSTANDARD = LVCMOS33;
+        ";
+        let constraints = parse_ucf(input.as_bytes(), device_type, None).unwrap();
+        assert_eq!(constraints, [
+            PinConstraint {
+                name: "clk".to_owned(),
+                location: Some(RequestedLocation{fb: 1, i: Some(4)}),
+                io_standard: Some(IOStandard::LVCMOS33),
+            },
+            PinConstraint {
+                name: "data<0>".to_owned(),
+                location: Some(RequestedLocation{fb: 0, i: None}),
+                io_standard: Some(IOStandard::LVCMOS18),
+            },
+            PinConstraint {
+                name: "q".to_owned(),
+                location: Some(RequestedLocation{fb: 0, i: Some(2)}),
+                io_standard: None,
+            },
+        ]);
+
+        let mut out = Vec::new();
+        for x in &constraints {
+            x.write_ucf(&mut out, device_type, None).unwrap();
+        }
+        assert_eq!(String::from_utf8(out.clone()).unwrap(),
+            "NET \"clk\" LOC = \"FB2_5\" | IOSTANDARD = LVCMOS33;\n\
+             NET \"data<0>\" LOC = \"FB1\" | IOSTANDARD = LVCMOS18;\n\
+             NET \"q\" LOC = \"FB1_3\";\n");
+        assert_eq!(parse_ucf(&out[..], device_type, None).unwrap(), constraints);
+
+        // Package pins can only be used if the pin map of the package is known
+        assert!(matches!(parse_ucf("NET \"a\" LOC = \"P12\";".as_bytes(), device_type, None),
+            Err(ConstraintError::IllegalValue(1, _))));
+        assert!(matches!(parse_ucf("\nNET \"a\" LOC;".as_bytes(), device_type, None),
+            Err(ConstraintError::MalformedLine(2, _))));
+
+        let vq44 = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        let constraints = parse_ucf("NET \"a\" LOC = \"P12\";\nNET \"b\" LOC = \"FB2_5\";".as_bytes(), vq44, None)
+            .unwrap();
+        assert_eq!(constraints[0].location, Some(RequestedLocation{fb: 1, i: Some(12)}));
+        let mut out = Vec::new();
+        for x in &constraints {
+            x.write_ucf(&mut out, vq44, None).unwrap();
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), "NET \"a\" LOC = \"P12\";\nNET \"b\" LOC = \"P43\";\n");
+    }
+}
@@ -0,0 +1,292 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Structured diagnostics. All of the passes report problems by logging them through slog with a "code" key attached.
+// The collector in here is a slog drain that captures these messages (along with the context that the passes attach to
+// them) so that they can be handed back to the caller, e.g. so that an IDE can show them next to the source code.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct DiagnosticSource {
+    pub file: String,
+    pub line: u32,
+    pub column: Option<u32>,
+}
+
+impl DiagnosticSource {
+    /// Parses a Yosys `src` attribute. These look like `file.v:12.3-12.20`, possibly with several of them separated by
+    /// `|`, in which case only the first one is used.
+    pub fn from_yosys_src(src: &str) -> Option<Self> {
+        let src = src.split('|').next().unwrap();
+        let (file, pos) = src.rsplit_once(':')?;
+        let start = pos.split('-').next().unwrap();
+        let mut start = start.split('.');
+        let line = start.next().unwrap().parse().ok()?;
+        let column = start.next().and_then(|x| x.parse().ok());
+
+        Some(Self {
+            file: file.to_owned(),
+            line,
+            column,
+        })
+    }
+}
+
+impl fmt::Display for DiagnosticSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(column) = self.column {
+            write!(f, "{}:{}:{}", self.file, self.line, column)
+        } else {
+            write!(f, "{}:{}", self.file, self.line)
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: DiagnosticSeverity,
+    pub code: String,
+    pub message: String,
+    /// The pass that emitted this diagnostic
+    pub pass: Option<String>,
+    /// The name of the cell, net, or other object this diagnostic is about
+    pub object: Option<String>,
+    pub src: Option<DiagnosticSource>,
+    /// Any other information that was attached to the message
+    pub details: Vec<(String, String)>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref src) = self.src {
+            write!(f, "{}: ", src)?;
+        }
+        let severity = match self.severity {
+            DiagnosticSeverity::Error => "error",
+            DiagnosticSeverity::Warning => "warning",
+        };
+        write!(f, "{}[{}]: {}", severity, self.code, self.message)?;
+        if !self.details.is_empty() {
+            write!(f, " ({})", self.details.iter().map(|(k, v)| format!("{}: {}", k, v)).collect::<Vec<_>>()
+                .join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+// Keys that name the object that a message is about, most specific first
+const OBJECT_KEYS: [&str; 8] = ["cell name", "net", "port", "node", "gate", "io pad name", "macrocell name", "name"];
+
+#[derive(Default)]
+struct DiagnosticKVs {
+    code: Option<String>,
+    pass: Option<String>,
+    kvs: Vec<(String, String)>,
+}
+
+impl slog::Serializer for DiagnosticKVs {
+    fn emit_arguments(&mut self, key: slog::Key, val: &fmt::Arguments) -> slog::Result {
+        match key {
+            "code" => self.code = Some(format!("{}", val)),
+            "pass" => self.pass = Some(format!("{}", val)),
+            _ => self.kvs.push((key.to_owned(), format!("{}", val))),
+        }
+
+        Ok(())
+    }
+}
+
+/// A slog drain that collects warnings and errors as `Diagnostic`s. Cloning the collector gives another handle to the
+/// same list of diagnostics.
+#[derive(Clone, Default)]
+pub struct DiagnosticCollector {
+    diagnostics: Arc<Mutex<Vec<Diagnostic>>>,
+}
+
+impl DiagnosticCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes and returns everything that has been collected so far
+    pub fn take(&self) -> Vec<Diagnostic> {
+        std::mem::take(&mut *self.diagnostics.lock().unwrap())
+    }
+}
+
+impl slog::Drain for DiagnosticCollector {
+    type Ok = ();
+    type Err = slog::Never;
+
+    fn log(&self, record: &slog::Record, values: &slog::OwnedKVList) -> Result<(), slog::Never> {
+        let severity = match record.level() {
+            slog::Level::Critical | slog::Level::Error => DiagnosticSeverity::Error,
+            slog::Level::Warning => DiagnosticSeverity::Warning,
+            _ => return Ok(()),
+        };
+
+        let mut kvs = DiagnosticKVs::default();
+        // Values attached to the message itself come before the ones attached to the logger
+        let _ = slog::KV::serialize(&record.kv(), record, &mut kvs);
+        let _ = slog::KV::serialize(values, record, &mut kvs);
+
+        let object = OBJECT_KEYS.iter().filter_map(|&key| kvs.kvs.iter().find(|x| x.0 == key)).next()
+            .map(|x| x.1.clone());
+
+        let diagnostic = Diagnostic {
+            severity,
+            code: kvs.code.unwrap_or_else(|| "XC2PAR".to_owned()),
+            message: format!("{}", record.msg()),
+            pass: kvs.pass,
+            object,
+            src: None,
+            details: kvs.kvs,
+        };
+
+        // Some messages can be logged more than once (e.g. by each PAR iteration)
+        let mut diagnostics = self.diagnostics.lock().unwrap();
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+
+        Ok(())
+    }
+}
+
+/// Collects the `src` attributes of all cells and nets in a Yosys netlist, keyed by their name
+pub fn yosys_source_locations(netlist: &yosys_netlist_json::Netlist) -> HashMap<String, DiagnosticSource> {
+    let mut ret = HashMap::new();
+
+    // The top module is processed last so that its names win if they clash with those in other modules
+    let mut modules = netlist.modules.values().collect::<Vec<_>>();
+    modules.sort_by_key(|x| x.attributes.get("top").and_then(|x| x.to_number()).unwrap_or(0));
+
+    for module in modules {
+        let cells = module.cells.iter().map(|(name, cell)| (name, &cell.attributes));
+        let netnames = module.netnames.iter().map(|(name, netname)| (name, &netname.attributes));
+        for (name, attributes) in cells.chain(netnames) {
+            if let Some(src) = attributes.get("src").and_then(|x| x.to_string_if_string())
+                .and_then(DiagnosticSource::from_yosys_src) {
+
+                ret.insert(name.to_owned(), src);
+            }
+        }
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_yosys_src() {
+        assert_eq!(DiagnosticSource::from_yosys_src("top.v:12.3-12.20"), Some(DiagnosticSource {
+            file: "top.v".to_owned(),
+            line: 12,
+            column: Some(3),
+        }));
+        assert_eq!(DiagnosticSource::from_yosys_src("C:\\x\\top.v:7|lib.v:1.1-2.2"), Some(DiagnosticSource {
+            file: "C:\\x\\top.v".to_owned(),
+            line: 7,
+            column: None,
+        }));
+        assert_eq!(DiagnosticSource::from_yosys_src("top.v"), None);
+    }
+
+    #[test]
+    fn collect_diagnostics() {
+        let collector = DiagnosticCollector::new();
+        let logger = slog::Logger::root(collector.clone(), o!("pass" => "test"));
+        let logger = logger.new(o!("cell name" => "foo"));
+
+        debug!(logger, "not a diagnostic");
+        warn!(logger, "something is odd"; "code" => "XX001", "name" => "INIT");
+        for _ in 0..2 {
+            error!(logger, "something is wrong"; "code" => "XX002");
+        }
+
+        let diagnostics = collector.take();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].severity, DiagnosticSeverity::Warning);
+        assert_eq!(diagnostics[0].code, "XX001");
+        assert_eq!(diagnostics[0].pass.as_ref().unwrap(), "test");
+        assert_eq!(diagnostics[0].object.as_ref().unwrap(), "foo");
+        assert_eq!(format!("{}", diagnostics[0]), "warning[XX001]: something is odd (name: INIT, cell name: foo)");
+        assert_eq!(diagnostics[1].severity, DiagnosticSeverity::Error);
+        assert!(collector.take().is_empty());
+    }
+
+    #[test]
+    fn flow_diagnostics_have_sources() {
+        let input = r#"{
+            "modules": {
+                "top": {
+                    "attributes": {"top": 1},
+                    "ports": {"a": {"direction": "input", "bits": [2]}},
+                    "cells": {
+                        "ibuf0": {
+                            "type": "IBUF",
+                            "parameters": {},
+                            "attributes": {"SCHMITT_TRIGGER": "bogus", "src": "top.v:3.5-3.20"},
+                            "port_directions": {},
+                            "connections": {"I": [2], "O": [3]}
+                        }
+                    },
+                    "netnames": {}
+                }
+            }
+        }"#;
+        let device_type = xc2bit::XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        let (result, diagnostics) = crate::xc2par_complete_flow_with_diagnostics(&crate::XC2ParOptions::new(),
+            device_type, input.as_bytes(), Vec::new(), None);
+
+        assert!(result.is_err());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "FE008");
+        assert_eq!(diagnostics[0].object.as_ref().unwrap(), "ibuf0");
+        assert_eq!(diagnostics[0].src.as_ref().unwrap().line, 3);
+        assert!(format!("{}", diagnostics[0]).starts_with("top.v:3:5: error[FE008]: "));
+
+        let (result, diagnostics) = crate::xc2par_complete_flow_with_diagnostics(&crate::XC2ParOptions::new(),
+            device_type, "{".as_bytes(), Vec::new(), None);
+        assert!(result.is_err());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "XC2PAR");
+    }
+}
@@ -24,17 +24,21 @@ OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 */
 
 use std::cmp::Ordering;
-use std::collections::{HashSet, HashMap};
+use std::collections::{BTreeMap, HashSet, HashMap};
 use std::iter::FromIterator;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::time::Instant;
 use serde_derive::{Deserialize, Serialize};
 use slog::Drain;
 
-use rand::{Rng, SeedableRng, XorShiftRng};
 
 use xc2bit::*;
 
 use crate::*;
 use crate::objpool::*;
+use crate::prng::ParRng;
+use crate::sitesearch::SiteSearch;
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum PARMCAssignment {
@@ -60,7 +64,7 @@ impl slog::Value for PARMCAssignment {
     }
 }
 
-type PARFBAssignment = [(PARMCAssignment, PARMCAssignment); MCS_PER_FB];
+pub(crate) type PARFBAssignment = [(PARMCAssignment, PARMCAssignment); MCS_PER_FB];
 // fb, mc, pininput?
 type PARFBAssignLoc = (u32, u32, bool);
 
@@ -120,6 +124,9 @@ pub struct OutputGraph {
     pub bufg_gts: ObjPool<OutputGraphBufgGTS>,
     pub bufg_gsr: ObjPool<OutputGraphBufgGSR>,
     pub zia: Vec<PARZIAAssignment>,
+    /// Number of PAR iterations that were needed to find this placement
+    #[serde(default)]
+    pub iterations: u32,
 }
 
 macro_rules! impl_from_ig_to_og {
@@ -186,6 +193,7 @@ impl OutputGraph {
             bufg_gts,
             bufg_gsr,
             zia,
+            iterations: 0,
         }
     }
 }
@@ -200,7 +208,7 @@ fn mcs_idx_can_be_paired(g: &InputGraph,
     mcs_can_be_paired(mc0, mc1)
 }
 
-fn mcs_can_be_paired(mc0: &InputGraphMacrocell, mc1: &InputGraphMacrocell) -> bool {
+pub(crate) fn mcs_can_be_paired(mc0: &InputGraphMacrocell, mc1: &InputGraphMacrocell) -> bool {
     let type_0 = mc0.get_type();
     let type_1 = mc1.get_type();
     match (type_0, type_1) {
@@ -217,6 +225,28 @@ fn mcs_can_be_paired(mc0: &InputGraphMacrocell, mc1: &InputGraphMacrocell) -> bo
     }
 }
 
+// Updates the "reverse" pointers from macrocells to their assigned sites
+fn update_mc_locs(go: &mut OutputGraph, mc_assignments: &[PARFBAssignment]) {
+    for (fb_i, fb) in mc_assignments.iter().enumerate() {
+        for mc_i in 0..MCS_PER_FB {
+            if let PARMCAssignment::MC(mc_idx) = fb[mc_i].0 {
+                let mc = go.mcs.get_mut(ObjPoolIndex::from(mc_idx));
+                mc.loc = Some(AssignedLocation{
+                    fb: fb_i as u32,
+                    i: mc_i as u32,
+                });
+            }
+            if let PARMCAssignment::MC(mc_idx) = fb[mc_i].1 {
+                let mc = go.mcs.get_mut(ObjPoolIndex::from(mc_idx));
+                mc.loc = Some(AssignedLocation{
+                    fb: fb_i as u32,
+                    i: mc_i as u32,
+                });
+            }
+        }
+    }
+}
+
 // First element of tuple is anything, second element can only be pin input
 pub fn greedy_initial_placement(g: &mut InputGraph, go: &mut OutputGraph, device_type: XC2DeviceSpeedPackage,
     logger: &slog::Logger) -> Option<Vec<PARFBAssignment>> {
@@ -265,6 +295,7 @@ pub fn greedy_initial_placement(g: &mut InputGraph, go: &mut OutputGraph, device
 
                     if !found_proper_gbuf {
                         error!(logger, "PAR - failed to find global buffer location matching IO pad LOC constraint";
+                            "code" => "PR001",
                             "io pad name" => &g.mcs.get(gbuf.input).name,
                             "fb" => mc_req_loc.fb,
                             "mc" => mc_req_loc.i.unwrap());
@@ -286,6 +317,7 @@ pub fn greedy_initial_placement(g: &mut InputGraph, go: &mut OutputGraph, device
                 if let Some(RequestedLocation{i: Some(idx), ..}) = gbuf.requested_loc {
                     if $set_name.contains(&idx) {
                         error!(logger, "PAR - cannot place global buffer because site is already occupied";
+                            "code" => "PR002",
                             "name" => &gbuf.name,
                             "index" => idx);
                         return None;
@@ -354,6 +386,7 @@ pub fn greedy_initial_placement(g: &mut InputGraph, go: &mut OutputGraph, device
 
                 if idx.is_none() {
                     error!(logger, "PAR - cannot place global buffer because there are no more sites";
+                        "code" => "PR003",
                         "name" => &gbuf.name);
                     return None;
                 }
@@ -400,6 +433,7 @@ pub fn greedy_initial_placement(g: &mut InputGraph, go: &mut OutputGraph, device
 
             if *x != PARMCAssignment::None {
                 error!(logger, "PAR - cannot place macrocell (fixed) because site is already occupied";
+                    "code" => "PR004",
                     "name" => &mc.name,
                     "pininput" => is_pininput,
                     "fb" => fb,
@@ -424,6 +458,7 @@ pub fn greedy_initial_placement(g: &mut InputGraph, go: &mut OutputGraph, device
                     if !mcs_idx_can_be_paired(g, mc_idx_0, mc_idx_1) {
                         error!(logger, "PAR - tried to force together macrocells that cannot both occupy the \
                             same location";
+                            "code" => "PR005",
                             "name 1" => &g.mcs.get(mc_idx_0).name,
                             "name 2" => &g.mcs.get(mc_idx_1).name,
                             "fb" => fb_i,
@@ -467,6 +502,7 @@ pub fn greedy_initial_placement(g: &mut InputGraph, go: &mut OutputGraph, device
 
             if mc_i.is_none() {
                 error!(logger, "PAR - cannot place macrocell (FB fixed) because there are no more sites";
+                    "code" => "PR006",
                     "name" => &mc.name,
                     "pininput" => is_pininput);
                 return None;
@@ -485,15 +521,27 @@ pub fn greedy_initial_placement(g: &mut InputGraph, go: &mut OutputGraph, device
         }
     }
 
-    // Now place all the other macrocells
+    // Now place all the other macrocells. Buried macrocells in an area group go first so that they don't find their
+    // FBs already filled up by macrocells that could have gone anywhere.
     // FIXME: Copypasta
-    for i in g.mcs.iter_idx() {
+    let is_grouped_buried = |i| {
+        let mc: &InputGraphMacrocell = g.mcs.get(i);
+        mc.area_group.is_some() && !mc.get_type().is_pininput()
+    };
+    let mc_order = g.mcs.iter_idx().filter(|&i| is_grouped_buried(i))
+        .chain(g.mcs.iter_idx().filter(|&i| !is_grouped_buried(i)))
+        .collect::<Vec<_>>();
+    for i in mc_order {
         let mc = g.mcs.get(i);
         let is_pininput = mc.get_type().is_pininput();
 
         if mc.requested_loc.is_none() {
             let mut fbmc_i = None;
             for fb in 0..num_fbs {
+                if !mc.can_be_placed_in_fb(fb as u32) {
+                    continue;
+                }
+
                 for i in 0..MCS_PER_FB {
                     if !is_pininput {
                         if ret[fb][i].0 != PARMCAssignment::None {
@@ -525,6 +573,7 @@ pub fn greedy_initial_placement(g: &mut InputGraph, go: &mut OutputGraph, device
 
             if fbmc_i.is_none() {
                 error!(logger, "PAR - cannot place macrocell (free) because there are no more sites";
+                    "code" => "PR007",
                     "name" => &mc.name,
                     "pininput" => is_pininput);
                 return None;
@@ -544,26 +593,53 @@ pub fn greedy_initial_placement(g: &mut InputGraph, go: &mut OutputGraph, device
     }
 
     // Update the "reverse" pointers
-    for fb_i in 0..num_fbs {
-        for mc_i in 0..MCS_PER_FB {
-            if let PARMCAssignment::MC(mc_idx) = ret[fb_i as usize][mc_i].0 {
-                let mc = go.mcs.get_mut(ObjPoolIndex::from(mc_idx));
-                mc.loc = Some(AssignedLocation{
-                    fb: fb_i as u32,
-                    i: mc_i as u32,
-                });
+    update_mc_locs(go, &ret);
+
+    Some(ret)
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum PARStopReason {
+    Cancelled,
+    DeadlineExceeded,
+}
+
+/// Conditions under which PAR gives up before it either succeeds or runs out of iterations. These are checked between
+/// iterations and also inside the backtracking searches, which can take a long time on their own.
+#[derive(Clone, Debug, Default)]
+pub struct PARStopCondition {
+    pub deadline: Option<Instant>,
+    pub cancel_flag: Option<Arc<AtomicBool>>,
+}
+
+impl PARStopCondition {
+    /// Never stops
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Starts the time limit (if any) in the options now
+    pub fn from_options(options: &XC2ParOptions) -> Self {
+        Self {
+            deadline: options.time_limit.map(|x| Instant::now() + x),
+            cancel_flag: options.cancel_flag.clone(),
+        }
+    }
+
+    pub fn check(&self) -> Option<PARStopReason> {
+        if let Some(ref cancel_flag) = self.cancel_flag {
+            if cancel_flag.load(AtomicOrdering::Relaxed) {
+                return Some(PARStopReason::Cancelled);
             }
-            if let PARMCAssignment::MC(mc_idx) = ret[fb_i as usize][mc_i].1 {
-                let mc = go.mcs.get_mut(ObjPoolIndex::from(mc_idx));
-                mc.loc = Some(AssignedLocation{
-                    fb: fb_i as u32,
-                    i: mc_i as u32,
-                });
+        }
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return Some(PARStopReason::DeadlineExceeded);
             }
         }
-    }
 
-    Some(ret)
+        None
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -573,8 +649,9 @@ pub enum AndTermAssignmentResult {
     FailurePtermExceeded(u32),
 }
 
-pub fn try_assign_andterms(g: &InputGraph, go: &mut OutputGraph, mc_assignment: &PARFBAssignment, fb_i: u32)
-    -> AndTermAssignmentResult {
+// If the search is stopped, this returns a failure
+pub fn try_assign_andterms(g: &InputGraph, go: &mut OutputGraph, mc_assignment: &PARFBAssignment, fb_i: u32,
+    stop: &PARStopCondition) -> AndTermAssignmentResult {
 
     // FIXME: Too big for default to work
     let mut ret = [
@@ -597,9 +674,11 @@ pub fn try_assign_andterms(g: &InputGraph, go: &mut OutputGraph, mc_assignment:
     let mut free_pterms = Vec::new();
 
     // Gather up all product terms and the locations at which they may be placed
-    // Place all the special product terms
+    // Place all the special product terms. Input pins with a register packed into them also need their control
+    // p-terms placed, so both slots are checked here.
     for mc_i in 0..MCS_PER_FB {
-        if let PARMCAssignment::MC(mc_g_idx) = mc_assignment[mc_i].0 {
+        for &assignment in &[mc_assignment[mc_i].0, mc_assignment[mc_i].1] {
+            let mc_g_idx = if let PARMCAssignment::MC(mc_g_idx) = assignment { mc_g_idx } else { continue };
             // FIXME: Ugly code duplication
             let this_mc = &g.mcs.get(mc_g_idx);
 
@@ -693,41 +772,28 @@ pub fn try_assign_andterms(g: &InputGraph, go: &mut OutputGraph, mc_assignment:
         }
     }
 
-    // Actually do the search to assign P-terms
-    // TODO: MRV/LCV?
-    let mut most_placed = 0;
-    fn backtrack_inner(g: &InputGraph, most_placed: &mut u32,
-        ret: &mut [HashSet<ObjPoolIndex<InputGraphPTerm>>; ANDTERMS_PER_FB],
-        candidate_sites: &[(ObjPoolIndex<InputGraphPTerm>, Vec<u32>)],
-        working_on_idx: usize) -> bool {
-
-        if working_on_idx == candidate_sites.len() {
-            // Complete assignment, we are done
-            return true;
-        }
-        let (pt_idx, ref candidate_sites_for_this_input) = candidate_sites[working_on_idx];
-        let pt = g.pterms.get(pt_idx);
-        for &candidate_pt_i in candidate_sites_for_this_input {
-            if ret[candidate_pt_i as usize].is_empty() || (g.pterms.get(*ret[candidate_pt_i as usize].iter().next().unwrap()) == pt) {
-                // It is possible to assign to this site
-                let x = ret[candidate_pt_i as usize].insert(pt_idx);
-                assert!(x);
-                *most_placed = working_on_idx as u32 + 1;
-                if backtrack_inner(g, most_placed, ret, candidate_sites, working_on_idx + 1) {
-                    return true;
-                }
-                let x = ret[candidate_pt_i as usize].remove(&pt_idx);
-                assert!(x);
+    // Actually do the search to assign P-terms. Identical p-terms are allowed to share a site.
+    let mut pterm_classes = Vec::with_capacity(pterm_and_candidate_sites.len());
+    for i in 0..pterm_and_candidate_sites.len() {
+        let pt = g.pterms.get(pterm_and_candidate_sites[i].0);
+        let class = (0..i).find(|&j| g.pterms.get(pterm_and_candidate_sites[j].0) == pt).unwrap_or(i);
+        pterm_classes.push(class);
+    }
+    let candidate_sites = pterm_and_candidate_sites.iter().map(|x| x.1.clone()).collect::<Vec<_>>();
+    let mut most_placed = match SiteSearch::new(&candidate_sites, &pterm_classes, ANDTERMS_PER_FB).solve(stop) {
+        Ok(sites) => {
+            for (&(pt_idx, _), site) in pterm_and_candidate_sites.iter().zip(sites) {
+                // The same p-term can show up more than once (e.g. a shared clock)
+                ret[site as usize].insert(pt_idx);
             }
+            pterm_and_candidate_sites.len() as u32
+        },
+        Err(most_placed) => {
+            return AndTermAssignmentResult::FailurePtermExceeded(
+                (pterm_and_candidate_sites.len() + free_pterms.len()) as u32 - most_placed);
         }
-        return false;
     };
 
-    if !backtrack_inner(g, &mut most_placed, &mut ret, &pterm_and_candidate_sites, 0) {
-        return AndTermAssignmentResult::FailurePtermExceeded(
-            (pterm_and_candidate_sites.len() + free_pterms.len()) as u32 - most_placed);
-    }
-
     // The backtracking search is completed. Greedily assign everything that is left.
     for &pt_idx in &free_pterms {
         let pt = g.pterms.get(pt_idx);
@@ -764,65 +830,69 @@ pub fn try_assign_andterms(g: &InputGraph, go: &mut OutputGraph, mc_assignment:
     AndTermAssignmentResult::Success
 }
 
-pub enum ZIAAssignmentResult {
-    Success(PARZIAAssignment),
-    FailureTooManyInputs(u32),
-    FailureUnroutable(u32),
-}
-
-pub fn try_assign_zia(g: &InputGraph, go: &mut OutputGraph, mc_assignment: &PARFBAssignment,
-    device_type: XC2DeviceSpeedPackage) -> ZIAAssignmentResult {
-
-    let mut ret_zia = PARZIAAssignment::new();
-    let mut input_to_row_map = HashMap::new();
+// Collect all of the p-terms that are used by one macrocell
+pub(crate) fn collect_mc_pterms(this_mc: &InputGraphMacrocell, collected_pterms: &mut Vec<ObjPoolIndex<InputGraphPTerm>>) {
+    if let Some(ref io_bits) = this_mc.io_bits {
+        if let Some(InputGraphIOOEType::PTerm(oe_idx)) = io_bits.oe {
+            collected_pterms.push(oe_idx);
+        }
+    }
 
-    // Collect the p-terms that will be used by this FB
-    let mut collected_pterms = Vec::new();
-    for mc_i in 0..MCS_PER_FB {
-        if let PARMCAssignment::MC(mc_g_idx) = mc_assignment[mc_i].0 {
-            // FIXME: Ugly code duplication
-            let this_mc = &g.mcs.get(mc_g_idx);
+    if let Some(ref xor_bits) = this_mc.xor_bits {
+        if let Some(ptc_node_idx) = xor_bits.andterm_input {
+            collected_pterms.push(ptc_node_idx);
+        }
 
-            if let Some(ref io_bits) = this_mc.io_bits {
-                if let Some(InputGraphIOOEType::PTerm(oe_idx)) = io_bits.oe {
-                    collected_pterms.push(oe_idx);
-                }
-            }
+        for &andterm_node_idx in &xor_bits.orterm_inputs {
+            collected_pterms.push(andterm_node_idx);
+        }
+    }
 
-            if let Some(ref xor_bits) = this_mc.xor_bits {
-                if let Some(ptc_node_idx) = xor_bits.andterm_input {
-                    collected_pterms.push(ptc_node_idx);
-                }
+    if let Some(ref reg_bits) = this_mc.reg_bits {
+        if let Some(ptc_node_idx) = reg_bits.ce_input {
+            collected_pterms.push(ptc_node_idx);
+        }
 
-                for &andterm_node_idx in &xor_bits.orterm_inputs {
-                    collected_pterms.push(andterm_node_idx);
-                }
-            }
+        if let InputGraphRegClockType::PTerm(clk_node_idx) = reg_bits.clk_input {
+            collected_pterms.push(clk_node_idx);
+        }
 
-            if let Some(ref reg_bits) = this_mc.reg_bits {
-                if let Some(ptc_node_idx) = reg_bits.ce_input {
-                    collected_pterms.push(ptc_node_idx);
-                }
+        if let Some(InputGraphRegRSType::PTerm(set_node_idx)) = reg_bits.set_input {
+            collected_pterms.push(set_node_idx);
+        }
 
-                if let InputGraphRegClockType::PTerm(clk_node_idx) = reg_bits.clk_input {
-                    collected_pterms.push(clk_node_idx);
-                }
+        if let Some(InputGraphRegRSType::PTerm(reset_node_idx)) = reg_bits.reset_input {
+            collected_pterms.push(reset_node_idx);
+        }
+    }
+}
 
-                if let Some(InputGraphRegRSType::PTerm(set_node_idx)) = reg_bits.set_input {
-                    collected_pterms.push(set_node_idx);
-                }
+// Collect all of the p-terms that will be used by the macrocells placed in this FB
+pub(crate) fn collect_fb_pterms(g: &InputGraph, mc_assignment: &PARFBAssignment)
+    -> Vec<ObjPoolIndex<InputGraphPTerm>> {
 
-                if let Some(InputGraphRegRSType::PTerm(reset_node_idx)) = reg_bits.reset_input {
-                    collected_pterms.push(reset_node_idx);
-                }
-            }
+    let mut collected_pterms = Vec::new();
+    for mc_i in 0..MCS_PER_FB {
+        if let PARMCAssignment::MC(mc_g_idx) = mc_assignment[mc_i].0 {
+            collect_mc_pterms(g.mcs.get(mc_g_idx), &mut collected_pterms);
+        }
+        // Input pins can have a register packed into them
+        if let PARMCAssignment::MC(mc_g_idx) = mc_assignment[mc_i].1 {
+            collect_mc_pterms(g.mcs.get(mc_g_idx), &mut collected_pterms);
         }
     }
 
-    // Collect the inputs that need to go into this FB
+    collected_pterms
+}
+
+// Collect the (deduplicated) inputs that need to go into a FB through the ZIA in order to feed the given p-terms.
+// The order of the result is deterministic.
+pub(crate) fn collect_fb_inputs(g: &InputGraph, collected_pterms: &[ObjPoolIndex<InputGraphPTerm>])
+    -> Vec<InputGraphPTermInput> {
+
     let mut collected_inputs_vec = Vec::new();
     let mut collected_inputs_set = HashSet::new();
-    for &pt_idx in &collected_pterms {
+    for &pt_idx in collected_pterms {
         let andterm_node = g.pterms.get(pt_idx);
         for &input_net in &andterm_node.inputs_true {
             if !collected_inputs_set.contains(&input_net) {
@@ -838,6 +908,28 @@ pub fn try_assign_zia(g: &InputGraph, go: &mut OutputGraph, mc_assignment: &PARF
         }
     }
 
+    collected_inputs_vec
+}
+
+pub enum ZIAAssignmentResult {
+    Success(PARZIAAssignment),
+    FailureTooManyInputs(u32),
+    FailureUnroutable(u32),
+}
+
+// If the search is stopped, this returns a failure
+pub fn try_assign_zia(g: &InputGraph, go: &mut OutputGraph, mc_assignment: &PARFBAssignment,
+    device_type: XC2DeviceSpeedPackage, stop: &PARStopCondition) -> ZIAAssignmentResult {
+
+    let mut ret_zia = PARZIAAssignment::new();
+    let mut input_to_row_map = HashMap::new();
+
+    // Collect the p-terms that will be used by this FB
+    let collected_pterms = collect_fb_pterms(g, mc_assignment);
+
+    // Collect the inputs that need to go into this FB
+    let collected_inputs_vec = collect_fb_inputs(g, &collected_pterms);
+
     // Must have few enough results
     if collected_inputs_vec.len() > 40 {
         return ZIAAssignmentResult::FailureTooManyInputs(collected_inputs_vec.len() as u32 - 40)
@@ -891,36 +983,21 @@ pub fn try_assign_zia(g: &InputGraph, go: &mut OutputGraph, mc_assignment: &PARF
         (*input, choice, candidate_sites_for_this_input)
     }).collect::<Vec<_>>();
 
-    // Actually do the search to assign ZIA rows
-    let mut most_routed = 0;
-    fn backtrack_inner(most_routed: &mut u32, ret: &mut PARZIAAssignment,
-        candidate_sites: &[(InputGraphPTermInput, XC2ZIAInput, Vec<usize>)],
-        working_on_idx: usize,
-        input_to_row_map: &mut HashMap<InputGraphPTermInput, u32>) -> bool {
-
-        if working_on_idx == candidate_sites.len() {
-            // Complete assignment, we are done
-            return true;
-        }
-        let (input, choice, ref candidate_sites_for_this_input) = candidate_sites[working_on_idx];
-        for &candidate_zia_row in candidate_sites_for_this_input {
-            if ret.get(candidate_zia_row) == XC2ZIAInput::One {
-                // It is possible to assign to this site
-                ret.set(candidate_zia_row, choice);
-                input_to_row_map.insert(input, candidate_zia_row as u32);
-                *most_routed = working_on_idx as u32 + 1;
-                if backtrack_inner(most_routed, ret, candidate_sites, working_on_idx + 1, input_to_row_map) {
-                    return true;
-                }
-                ret.set(candidate_zia_row, XC2ZIAInput::One);
-                input_to_row_map.remove(&input);
+    // Actually do the search to assign ZIA rows. Every row can only carry one input.
+    let classes = (0..candidate_sites.len()).collect::<Vec<_>>();
+    let candidate_rows = candidate_sites.iter()
+        .map(|x| x.2.iter().map(|&row| row as u32).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    match SiteSearch::new(&candidate_rows, &classes, INPUTS_PER_ANDTERM).solve(stop) {
+        Ok(rows) => {
+            for (&(input, choice, _), row) in candidate_sites.iter().zip(rows) {
+                ret_zia.set(row as usize, choice);
+                input_to_row_map.insert(input, row);
             }
+        },
+        Err(most_routed) => {
+            return ZIAAssignmentResult::FailureUnroutable(candidate_sites.len() as u32 - most_routed);
         }
-        return false;
-    };
-
-    if !backtrack_inner(&mut most_routed, &mut ret_zia, &candidate_sites, 0, &mut input_to_row_map) {
-        return ZIAAssignmentResult::FailureUnroutable(candidate_sites.len() as u32 - most_routed);
     }
 
     // Now we search through all the inputs and record which row they go in
@@ -947,14 +1024,14 @@ enum FBAssignmentResultInner {
 }
 
 fn try_assign_fb_inner(g: &InputGraph, go: &mut OutputGraph, mc_assignments: &[PARFBAssignment], fb_i: u32,
-    device_type: XC2DeviceSpeedPackage) -> FBAssignmentResultInner {
+    device_type: XC2DeviceSpeedPackage, stop: &PARStopCondition) -> FBAssignmentResultInner {
 
     let mut failing_score = 0;
     // TODO: Weight factors?
 
     // Can we even assign p-terms?
-    let pterm_assign_result = try_assign_andterms(g, go, &mc_assignments[fb_i as usize], fb_i);
-    let zia_assign_result = try_assign_zia(g, go, &mc_assignments[fb_i as usize], device_type);
+    let pterm_assign_result = try_assign_andterms(g, go, &mc_assignments[fb_i as usize], fb_i, stop);
+    let zia_assign_result = try_assign_zia(g, go, &mc_assignments[fb_i as usize], device_type, stop);
 
     if pterm_assign_result == AndTermAssignmentResult::Success {
         if let ZIAAssignmentResult::Success(zia_assignment) = zia_assign_result {
@@ -986,10 +1063,10 @@ fn try_assign_fb_inner(g: &InputGraph, go: &mut OutputGraph, mc_assignments: &[P
 }
 
 pub fn try_assign_fb(g: &InputGraph, go: &mut OutputGraph, mc_assignments: &[PARFBAssignment], fb_i: u32,
-    constraint_violations: &mut HashMap<PARFBAssignLoc, u32>, device_type: XC2DeviceSpeedPackage)
-    -> Option<PARZIAAssignment> {
+    constraint_violations: &mut BTreeMap<PARFBAssignLoc, u32>, device_type: XC2DeviceSpeedPackage,
+    stop: &PARStopCondition) -> Option<PARZIAAssignment> {
 
-    let initial_assign_result = try_assign_fb_inner(g, go, mc_assignments, fb_i, device_type);
+    let initial_assign_result = try_assign_fb_inner(g, go, mc_assignments, fb_i, device_type, stop);
 
     // Check for pairing violations
     // TODO: Fix copypasta
@@ -1035,12 +1112,18 @@ pub fn try_assign_fb(g: &InputGraph, go: &mut OutputGraph, mc_assignments: &[PAR
 
                     new_mc_assign[fb_i as usize][mc_i].0 = PARMCAssignment::None;
                     let new_failing_score = match try_assign_fb_inner(g, &mut dummy_go, &new_mc_assign, fb_i,
-                        device_type) {
+                        device_type, stop) {
 
                         FBAssignmentResultInner::Success(_) => 0,
                         FBAssignmentResultInner::Failure(x) => x,
                     };
 
+                    // Scores from an interrupted search are meaningless, and the caller is going to throw this result
+                    // away anyway
+                    if stop.check().is_some() {
+                        return None;
+                    }
+
                     if new_failing_score > base_failing_score {
                         panic!("scores are borked");
                     }
@@ -1073,6 +1156,10 @@ pub enum PARSanityResult {
     FailureTooManyBufgClk,
     FailureTooManyBufgGTS,
     FailureTooManyBufgGSR,
+    FailureFeatureNotSupported,
+    FailureIOBankConflict,
+    FailureAreaGroup,
+    FailureInitialPlacement,
 }
 
 // FIXME: What happens in netlist.rs and what happens here?
@@ -1087,6 +1174,7 @@ pub fn do_par_sanity_check(g: &mut InputGraph, device_type: XC2DeviceSpeedPackag
         // Note that this is a conservative fail-early check. It is incomplete because it doesn't account for
         // which macrocells can actually be paired together or which buried sites (in larger devices) can be used.
         error!(logger, "PAR (sanity) - too many total macrocells. This can never fit.";
+            "code" => "PR008",
             "num mcs" => g.mcs.len(),
             "max mcs" => 2 * (num_fbs * MCS_PER_FB));
         return PARSanityResult::FailureTooManyMCs;
@@ -1096,6 +1184,7 @@ pub fn do_par_sanity_check(g: &mut InputGraph, device_type: XC2DeviceSpeedPackag
     let pterms_set: HashSet<InputGraphPTerm> = HashSet::from_iter(g.pterms.iter().cloned());
     if pterms_set.len() > num_fbs * ANDTERMS_PER_FB {
         error!(logger, "PAR (sanity) - too many total P-terms. This can never fit.";
+            "code" => "PR009",
             "num p-terms" => pterms_set.len(),
             "max p-terms" => num_fbs * ANDTERMS_PER_FB);
         return PARSanityResult::FailureTooManyPTerms;
@@ -1104,6 +1193,7 @@ pub fn do_par_sanity_check(g: &mut InputGraph, device_type: XC2DeviceSpeedPackag
 
     if g.bufg_clks.len() > NUM_BUFG_CLK {
         error!(logger, "PAR (sanity) - too many total BUFGs. This can never fit.";
+            "code" => "PR010",
             "num BUFGs" => g.bufg_clks.len(),
             "max BUFGs" => NUM_BUFG_CLK);
         return PARSanityResult::FailureTooManyBufgClk;
@@ -1111,6 +1201,7 @@ pub fn do_par_sanity_check(g: &mut InputGraph, device_type: XC2DeviceSpeedPackag
     info!(logger, "PAR - total bufg count"; "num bufg" => g.bufg_clks.len());
     if g.bufg_gts.len() > NUM_BUFG_GTS {
         error!(logger, "PAR (sanity) - too many total BUFGTSs. This can never fit.";
+            "code" => "PR011",
             "num BUFGTSs" => g.bufg_gts.len(),
             "max BUFGTSs" => NUM_BUFG_GTS);
         return PARSanityResult::FailureTooManyBufgGTS;
@@ -1118,6 +1209,7 @@ pub fn do_par_sanity_check(g: &mut InputGraph, device_type: XC2DeviceSpeedPackag
     info!(logger, "PAR - total bufgts count"; "num bufgts" => g.bufg_gts.len());
     if g.bufg_gsr.len() > NUM_BUFG_GSR {
         error!(logger, "PAR (sanity) - too many total BUFGSRs. This can never fit.";
+            "code" => "PR012",
             "num BUFGSRs" => g.bufg_gsr.len(),
             "max BUFGSRs" => NUM_BUFG_GSR);
         return PARSanityResult::FailureTooManyBufgGSR;
@@ -1132,6 +1224,7 @@ pub fn do_par_sanity_check(g: &mut InputGraph, device_type: XC2DeviceSpeedPackag
                     if let Some(xor_ptc_node_idx) = xor_bits.andterm_input {
                         if g.pterms.get(oe_node_idx) != g.pterms.get(xor_ptc_node_idx) {
                             error!(logger, "PAR (sanity) - OE and PTC fast path both used, but they're different";
+                                "code" => "PR013",
                                 "name" => &mc.name);
                             return PARSanityResult::FailurePTCNeverSatisfiable;
                         }
@@ -1141,6 +1234,53 @@ pub fn do_par_sanity_check(g: &mut InputGraph, device_type: XC2DeviceSpeedPackag
         }
     }
 
+    // The clock divider and DataGate only exist in the larger devices
+    let cdrst_loc = get_cdrst(device_type.dev);
+    let dge_loc = get_dge(device_type.dev);
+    if (g.clock_div.is_some() && cdrst_loc.is_none()) || (g.data_gate.is_some() && dge_loc.is_none()) {
+        error!(logger, "PAR (sanity) - clock divider or DataGate used, but this device doesn't have them";
+            "code" => "PR016",
+            "device" => device_type.dev.to_string());
+        return PARSanityResult::FailureFeatureNotSupported;
+    }
+    if dge_loc.is_none() {
+        for mc in g.mcs.iter() {
+            if mc.io_bits.as_ref().is_some_and(|x| x.uses_data_gate) {
+                warn!(logger, "PAR (sanity) - DATA_GATE ignored because this device doesn't have DataGate";
+                    "code" => "PR018",
+                    "io pad name" => &mc.name);
+            }
+        }
+    }
+
+    // The CDRST and DGE signals can only come in through their dedicated pins
+    let mut dedicated_pins = Vec::new();
+    if let Some(cdrst) = g.clock_div.as_ref().and_then(|x| x.cdrst) {
+        dedicated_pins.push((cdrst, cdrst_loc.unwrap()));
+    }
+    if let Some(dge) = g.data_gate {
+        dedicated_pins.push((dge, dge_loc.unwrap()));
+    }
+    for (mc_idx, (fb, i)) in dedicated_pins {
+        let mc = g.mcs.get_mut(mc_idx);
+        if let Some(mc_loc) = mc.requested_loc {
+            if mc_loc.fb != fb || (mc_loc.i.is_some() && mc_loc.i.unwrap() != i) {
+                error!(logger, "PAR (sanity) - CDRST/DGE pin has a LOC constraint that isn't the dedicated pin";
+                    "code" => "PR017",
+                    "io pad name" => &mc.name,
+                    "fb" => fb,
+                    "mc" => i);
+                return PARSanityResult::FailureGlobalNetWrongLoc;
+            }
+        }
+
+        info!(logger, "PAR - forcing IO pad LOC due to CDRST/DGE";
+            "name" => &mc.name,
+            "fb" => fb,
+            "mc" => i);
+        mc.requested_loc = Some(RequestedLocation{fb, i: Some(i)});
+    }
+
     // Check the LOC constraints for global nets
     macro_rules! sanity_check_bufg {
         ($g_name:ident, $loc_lookup:expr) => {
@@ -1196,28 +1336,37 @@ pub fn do_par_sanity_check(g: &mut InputGraph, device_type: XC2DeviceSpeedPackag
     sanity_check_bufg!(bufg_gts, |i| get_gts(device_type.dev, i).unwrap());
     sanity_check_bufg!(bufg_gts, |_| get_gsr(device_type.dev));
 
-    PARSanityResult::Ok
+    // These go last so that they know about all of the pins that were forced to a location above
+    match assign_io_banks(g, device_type, logger) {
+        PARSanityResult::Ok => {},
+        x => return x,
+    }
+
+    check_area_groups(g, device_type, logger)
 }
 
 pub enum PARResult {
     Success(OutputGraph),
-    FailureSanity(PARSanityResult),
-    FailureIterationsExceeded,
+    FailureSanity(PARSanityResult, Vec<PARBottleneck>),
+    FailureIterationsExceeded(Vec<PARBottleneck>),
+    // Contains the best placement that was found before stopping (see `PARState::into_partial_result`)
+    Stopped(PARStopReason, OutputGraph),
 }
 
 // pub fn try_assign_fb(g: &InputGraph, go: &mut OutputGraph, mc_assignments: &[PARFBAssignment], fb_i: u32,
 //     constraint_violations: &mut HashMap<PARFBAssignLoc, u32>) -> Option<PARZIAAssignment> {
 
 pub fn try_assign_entire_chip(g: &InputGraph, go: &mut OutputGraph, mc_assignments: &[PARFBAssignment],
-    device_type: XC2DeviceSpeedPackage) -> (Vec<Option<PARZIAAssignment>>, HashMap<PARFBAssignLoc, u32>, u32) {
+    device_type: XC2DeviceSpeedPackage, stop: &PARStopCondition)
+    -> (Vec<Option<PARZIAAssignment>>, BTreeMap<PARFBAssignLoc, u32>, u32) {
 
     let num_fbs = mc_assignments.len();
 
     let mut par_results_per_fb = Vec::with_capacity(num_fbs);
-    let mut placement_violations = HashMap::new();
+    let mut placement_violations = BTreeMap::new();
     for fb_i in 0..num_fbs {
         let fb_assign_result = try_assign_fb(g, go, mc_assignments, fb_i as u32,
-            &mut placement_violations, device_type);
+            &mut placement_violations, device_type, stop);
         par_results_per_fb.push(fb_assign_result);
     }
     let mut placement_violations_score = 0;
@@ -1228,101 +1377,131 @@ pub fn try_assign_entire_chip(g: &InputGraph, go: &mut OutputGraph, mc_assignmen
     (par_results_per_fb, placement_violations, placement_violations_score)
 }
 
-pub fn do_par<L: Into<Option<slog::Logger>>>(g: &mut InputGraph, device_type: XC2DeviceSpeedPackage,
-    options: &XC2ParOptions, logger: L) -> PARResult {
+/// An in-progress placement. This allows PAR to be run one iteration at a time, which is what `do_par` does.
+pub struct PARState {
+    go: OutputGraph,
+    prng: ParRng,
+    best_placement: Vec<PARFBAssignment>,
+    best_par_results_per_fb: Vec<Option<PARZIAAssignment>>,
+    best_placement_violations: BTreeMap<PARFBAssignLoc, u32>,
+    best_placement_violations_score: u32,
+    iterations: u32,
+    stop: PARStopCondition,
+    // False if scoring the best placement was interrupted by `stop`, in which case the score cannot be trusted
+    scored: bool,
+}
 
-    let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+impl PARState {
+    /// Performs the initial greedy placement. The sanity check (`do_par_sanity_check`) should have passed before this
+    /// is called. Returns `None` if even the initial placement is impossible. The time limit in the options starts
+    /// counting now.
+    pub fn new(g: &mut InputGraph, device_type: XC2DeviceSpeedPackage, options: &XC2ParOptions,
+        logger: &slog::Logger) -> Option<Self> {
 
-    let mut go = OutputGraph::from_input_graph(g);
+        Self::with_stop_condition(g, device_type, options, PARStopCondition::from_options(options), logger)
+    }
 
-    let sanity_check = do_par_sanity_check(g, device_type, &logger);
-    if sanity_check != PARSanityResult::Ok {
-        return PARResult::FailureSanity(sanity_check);
+    /// Same as `new`, but with a stop condition that was set up earlier, so that the time limit can cover the
+    /// whole flow rather than just PAR
+    pub fn with_stop_condition(g: &mut InputGraph, device_type: XC2DeviceSpeedPackage, options: &XC2ParOptions,
+        stop: PARStopCondition, logger: &slog::Logger) -> Option<Self> {
+
+        let mut go = OutputGraph::from_input_graph(g);
+        let prng = ParRng::from_seed(options.rng_seed);
+
+        let macrocell_placement = greedy_initial_placement(g, &mut go, device_type, logger)?;
+
+        // Score whatever we got out of the greedy placement
+        let (best_par_results_per_fb, best_placement_violations, best_placement_violations_score) =
+            try_assign_entire_chip(g, &mut go, &macrocell_placement, device_type, &stop);
+        let scored = stop.check().is_none();
+
+        Some(Self {
+            go,
+            prng,
+            best_placement: macrocell_placement,
+            best_par_results_per_fb,
+            best_placement_violations,
+            best_placement_violations_score,
+            iterations: 0,
+            stop,
+            scored,
+        })
     }
 
-    let mut prng: XorShiftRng = SeedableRng::from_seed(options.rng_seed);
+    /// Returns why PAR should stop now, if it should
+    pub fn stop_reason(&self) -> Option<PARStopReason> {
+        self.stop.check()
+    }
 
-    let macrocell_placement = greedy_initial_placement(g, &mut go, device_type, &logger);
-    if macrocell_placement.is_none() {
-        // XXX this is ugly
-        return PARResult::FailureSanity(PARSanityResult::FailureTooManyMCs);
+    /// Gives up on PAR and returns the best placement found so far. Only the macrocell locations are meaningful.
+    pub fn into_partial_result(mut self) -> OutputGraph {
+        update_mc_locs(&mut self.go, &self.best_placement);
+        self.go.iterations = self.iterations;
+        self.go
     }
-    let mut macrocell_placement = macrocell_placement.unwrap();
 
-    // Score whatever we got out of the greedy placement
-    let mut best_placement = macrocell_placement.clone();
-    let (mut best_par_results_per_fb, mut best_placement_violations, mut best_placement_violations_score) =
-        try_assign_entire_chip(g, &mut go, &macrocell_placement, device_type);
+    /// Returns true once a placement without any violations has been found
+    pub fn is_done(&self) -> bool {
+        self.scored && self.best_placement_violations.is_empty()
+    }
 
-    for iter_count in 0..options.max_iter {
-        macrocell_placement = best_placement.clone();
+    /// The score of the best placement found so far. Lower is better, and 0 means that the placement is done.
+    pub fn score(&self) -> u32 {
+        self.best_placement_violations_score
+    }
 
-        if best_placement_violations.len() == 0 {
-            // It worked!
-            info!(logger, "PAR - placement successfully found");
-            for i in 0..device_type.dev.num_fbs() {
-                let result_i = std::mem::replace(&mut best_par_results_per_fb[i], None);
-                let zia = result_i.unwrap();
-                go.zia.push(zia);
-            }
+    /// The number of times `step` has been called
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
 
-            debug!(logger, "PAR - dumping final locations");
-            for mc_idx in g.mcs.iter_idx() {
-                let mc = g.mcs.get(mc_idx);
-                let mc_go = go.mcs.get(ObjPoolIndex::from(mc_idx));
-                let fb_i = mc_go.loc.unwrap().fb;
-                let mc_i = mc_go.loc.unwrap().i;
-                debug!(logger, "PAR - final assignment";
-                    "type" => "macrocell",
-                    "name" => mc.name.clone(),
-                    "fb" => fb_i,
-                    "mc" => mc_i);
-            }
-            for pterm_idx in g.pterms.iter_idx() {
-                let pterm = g.pterms.get(pterm_idx);
-                let pterm_go = go.pterms.get(ObjPoolIndex::from(pterm_idx));
-                let fb_i = pterm_go.loc.unwrap().fb;
-                let pt_i = pterm_go.loc.unwrap().i;
-                debug!(logger, "PAR - final assignment";
-                    "type" => "pterm",
-                    "name" => pterm.name.clone(),
-                    "fb" => fb_i,
-                    "idx" => pt_i);
-            }
-            for idx in g.bufg_clks.iter_idx() {
-                let bufg = g.bufg_clks.get(idx);
-                let bufg_go = go.bufg_clks.get(ObjPoolIndex::from(idx));
-                let idx = bufg_go.loc.unwrap().i;
-                debug!(logger, "PAR - final assignment";
-                    "type" => "bufg",
-                    "name" => bufg.name.clone(),
-                    "idx" => idx);
-            }
-            for idx in g.bufg_gts.iter_idx() {
-                let bufg = g.bufg_gts.get(idx);
-                let bufg_go = go.bufg_gts.get(ObjPoolIndex::from(idx));
-                let idx = bufg_go.loc.unwrap().i;
-                debug!(logger, "PAR - final assignment";
-                    "type" => "bufgts",
-                    "name" => bufg.name.clone(),
-                    "idx" => idx);
-            }
-            for idx in g.bufg_gsr.iter_idx() {
-                let bufg = g.bufg_gsr.get(idx);
-                let bufg_go = go.bufg_gsr.get(ObjPoolIndex::from(idx));
-                let idx = bufg_go.loc.unwrap().i;
-                debug!(logger, "PAR - final assignment";
-                    "type" => "bufgsr",
-                    "name" => bufg.name.clone(),
-                    "idx" => idx);
-            }
+    /// The current state of the output. Macrocell locations are those of the best placement found so far, but P-term
+    /// and ZIA assignments are only meaningful once the placement is done.
+    pub fn output_graph(&self) -> &OutputGraph {
+        &self.go
+    }
+
+    /// Explains why the best placement found so far does not work
+    pub fn explain_failure(&self, g: &InputGraph, device_type: XC2DeviceSpeedPackage) -> Vec<PARBottleneck> {
+        explain_placement_failure(g, &self.go, &self.best_placement, device_type)
+    }
 
-            return PARResult::Success(go);
+    /// Performs one iteration of moving a macrocell that has violations somewhere else
+    pub fn step(&mut self, g: &InputGraph, device_type: XC2DeviceSpeedPackage, logger: &slog::Logger) {
+        let PARState {
+            ref mut go,
+            ref mut prng,
+            ref mut best_placement,
+            ref mut best_par_results_per_fb,
+            ref mut best_placement_violations,
+            ref mut best_placement_violations_score,
+            ref stop,
+            ref mut scored,
+            ..
+        } = *self;
+
+        if !*scored {
+            // Scoring the initial placement was interrupted, so finish doing that first
+            let (par_results_per_fb, placement_violations, placement_violations_score) =
+                try_assign_entire_chip(g, go, best_placement, device_type, stop);
+            if stop.check().is_none() {
+                *best_par_results_per_fb = par_results_per_fb;
+                *best_placement_violations = placement_violations;
+                *best_placement_violations_score = placement_violations_score;
+                *scored = true;
+            }
+            return;
         }
 
+        let mut macrocell_placement = best_placement.clone();
+        // Candidate moves that were tried and then undone can leave the "reverse" pointers pointing at a placement
+        // other than the best one
+        update_mc_locs(go, &macrocell_placement);
+
         info!(logger, "PAR - new iteration";
-            "iter" => iter_count,
-            "score" => best_placement_violations_score);
+            "iter" => self.iterations,
+            "score" => *best_placement_violations_score);
 
         debug!(logger, "PAR - dumping current assignment");
         for (fb_i, fb) in best_placement.iter().enumerate() {
@@ -1334,7 +1513,7 @@ pub fn do_par<L: Into<Option<slog::Logger>>>(g: &mut InputGraph, device_type: XC
                     "pininput" => mc.1);
             }
         }
-        for (&k, &v) in &best_placement_violations {
+        for (&k, &v) in best_placement_violations.iter() {
             debug!(logger, "PAR - current violations";
                 "fb" => k.0,
                 "mc" => k.1,
@@ -1344,7 +1523,7 @@ pub fn do_par<L: Into<Option<slog::Logger>>>(g: &mut InputGraph, device_type: XC
 
         // Here, we need to swap some stuff around
         let mut bad_candidates = Vec::new();
-        for (&k, &v) in &best_placement_violations {
+        for (&k, &v) in best_placement_violations.iter() {
             bad_candidates.push((k, v));
         }
         bad_candidates.sort_unstable_by(|a, b| {
@@ -1367,7 +1546,7 @@ pub fn do_par<L: Into<Option<slog::Logger>>>(g: &mut InputGraph, device_type: XC
         });
 
         // Pick a candidate to move weighted by its badness
-        let mut move_cand_rand = prng.gen_range(0, best_placement_violations_score);
+        let mut move_cand_rand = prng.gen_range_u32(0, *best_placement_violations_score);
         let mut move_cand_idx = 0;
         while move_cand_rand >= bad_candidates[move_cand_idx].1 {
             move_cand_rand -= bad_candidates[move_cand_idx].1;
@@ -1391,7 +1570,8 @@ pub fn do_par<L: Into<Option<slog::Logger>>>(g: &mut InputGraph, device_type: XC
                 unreachable!();
             }
         };
-        let to_move_req_fb = if let Some(RequestedLocation{fb, i}) = g.mcs.get(to_move_mc_idx).requested_loc {
+        let to_move_mc = g.mcs.get(to_move_mc_idx);
+        let to_move_req_fb = if let Some(RequestedLocation{fb, i}) = to_move_mc.requested_loc {
             // Other code should never put something that is fully-LOCd into this list
             assert!(i.is_none());
             Some(fb)
@@ -1435,11 +1615,15 @@ pub fn do_par<L: Into<Option<slog::Logger>>>(g: &mut InputGraph, device_type: XC
         // Find min-conflicts site
         let mut found_anything_better = false;
         let mut all_cand_sites = Vec::new();
-        let mut new_best_placement_violations_score = best_placement_violations_score;
-        for cand_fb in 0..device_type.dev.num_fbs() {
+        let mut new_best_placement_violations_score = *best_placement_violations_score;
+        let mut stopped = false;
+        'search: for cand_fb in 0..device_type.dev.num_fbs() {
             if to_move_req_fb.is_some() && to_move_req_fb.unwrap() != cand_fb as u32 {
                 continue;
             }
+            if !to_move_mc.can_be_placed_in_fb(cand_fb as u32) {
+                continue;
+            }
 
             for cand_mc in 0..MCS_PER_FB {
                 // This site is not usable
@@ -1454,6 +1638,10 @@ pub fn do_par<L: Into<Option<slog::Logger>>>(g: &mut InputGraph, device_type: XC
                     },
                     PARMCAssignment::MC(cand_mc_idx) => {
                         let cand_mc = g.mcs.get(cand_mc_idx);
+                        if !cand_mc.can_be_placed_in_fb(move_fb) {
+                            // The thing in the target site would have to leave its area group
+                            continue;
+                        }
                         if let Some(cand_mc_req_loc) = cand_mc.requested_loc {
                             // The thing we want to swap with has a LOC restriction of some kind
                             if cand_mc_req_loc.i.is_some() {
@@ -1482,7 +1670,14 @@ pub fn do_par<L: Into<Option<slog::Logger>>>(g: &mut InputGraph, device_type: XC
 
                 // Score what we've got
                 let (par_results_per_fb, new_placement_violations, new_placement_violations_score) =
-                    try_assign_entire_chip(g, &mut go, &macrocell_placement, device_type);
+                    try_assign_entire_chip(g, go, &macrocell_placement, device_type, stop);
+
+                // If the search was interrupted, this candidate wasn't scored properly. Give up on this iteration.
+                if stop.check().is_some() {
+                    xchg_macrocells!(move_fb, move_mc, move_pininput, cand_fb as u32, cand_mc as u32);
+                    stopped = true;
+                    break 'search;
+                }
 
                 // Is it better? Remember it
                 if new_placement_violations_score < new_best_placement_violations_score {
@@ -1490,10 +1685,10 @@ pub fn do_par<L: Into<Option<slog::Logger>>>(g: &mut InputGraph, device_type: XC
                         "fb" => cand_fb, "mc" => cand_mc);
                     found_anything_better = true;
                     new_best_placement_violations_score = new_placement_violations_score;
-                    best_placement = macrocell_placement.clone();
-                    best_placement_violations = new_placement_violations;
-                    best_par_results_per_fb = par_results_per_fb;
-                    best_placement_violations_score = new_placement_violations_score;
+                    *best_placement = macrocell_placement.clone();
+                    *best_placement_violations = new_placement_violations;
+                    *best_par_results_per_fb = par_results_per_fb;
+                    *best_placement_violations_score = new_placement_violations_score;
 
                     // Is the score 0? We can immediately exit
                     if best_placement_violations.len() == 0 {
@@ -1511,9 +1706,9 @@ pub fn do_par<L: Into<Option<slog::Logger>>>(g: &mut InputGraph, device_type: XC
             }
         }
 
-        if !found_anything_better {
+        if !found_anything_better && !stopped {
             // No improvements possible. We have to do _something_, so move it somewhere random
-            let (cand_fb, cand_mc) = all_cand_sites[prng.gen_range(0, all_cand_sites.len())];
+            let (cand_fb, cand_mc) = all_cand_sites[prng.gen_range_usize(0, all_cand_sites.len())];
             info!(logger, "PAR - cell forced move";
                 "fb" => cand_fb, "mc" => cand_mc);
 
@@ -1524,17 +1719,130 @@ pub fn do_par<L: Into<Option<slog::Logger>>>(g: &mut InputGraph, device_type: XC
 
             // Score what we've got
             let (par_results_per_fb, new_placement_violations, new_placement_violations_score) =
-                try_assign_entire_chip(g, &mut go, &macrocell_placement, device_type);
+                try_assign_entire_chip(g, go, &macrocell_placement, device_type, stop);
 
             // Remember it
-            best_placement = macrocell_placement;
-            best_placement_violations = new_placement_violations;
-            best_par_results_per_fb = par_results_per_fb;
-            best_placement_violations_score = new_placement_violations_score;
+            if stop.check().is_none() {
+                *best_placement = macrocell_placement;
+                *best_placement_violations = new_placement_violations;
+                *best_par_results_per_fb = par_results_per_fb;
+                *best_placement_violations_score = new_placement_violations_score;
+            }
         }
+
+        self.iterations += 1;
+        update_mc_locs(&mut self.go, &self.best_placement);
     }
 
-    PARResult::FailureIterationsExceeded
+    /// Returns the final result once the placement is done
+    pub fn finish(mut self, g: &InputGraph, device_type: XC2DeviceSpeedPackage, logger: &slog::Logger)
+        -> OutputGraph {
+        assert!(self.is_done());
+        update_mc_locs(&mut self.go, &self.best_placement);
+        let mut go = self.go;
+
+        info!(logger, "PAR - placement successfully found"; "iterations" => self.iterations);
+        go.iterations = self.iterations;
+        for i in 0..device_type.dev.num_fbs() {
+            let result_i = std::mem::replace(&mut self.best_par_results_per_fb[i], None);
+            let zia = result_i.unwrap();
+            go.zia.push(zia);
+        }
+
+        debug!(logger, "PAR - dumping final locations");
+        for mc_idx in g.mcs.iter_idx() {
+            let mc = g.mcs.get(mc_idx);
+            let mc_go = go.mcs.get(ObjPoolIndex::from(mc_idx));
+            let fb_i = mc_go.loc.unwrap().fb;
+            let mc_i = mc_go.loc.unwrap().i;
+            debug!(logger, "PAR - final assignment";
+                "type" => "macrocell",
+                "name" => mc.name.clone(),
+                "fb" => fb_i,
+                "mc" => mc_i);
+        }
+        for pterm_idx in g.pterms.iter_idx() {
+            let pterm = g.pterms.get(pterm_idx);
+            let pterm_go = go.pterms.get(ObjPoolIndex::from(pterm_idx));
+            let fb_i = pterm_go.loc.unwrap().fb;
+            let pt_i = pterm_go.loc.unwrap().i;
+            debug!(logger, "PAR - final assignment";
+                "type" => "pterm",
+                "name" => pterm.name.clone(),
+                "fb" => fb_i,
+                "idx" => pt_i);
+        }
+        for idx in g.bufg_clks.iter_idx() {
+            let bufg = g.bufg_clks.get(idx);
+            let bufg_go = go.bufg_clks.get(ObjPoolIndex::from(idx));
+            let idx = bufg_go.loc.unwrap().i;
+            debug!(logger, "PAR - final assignment";
+                "type" => "bufg",
+                "name" => bufg.name.clone(),
+                "idx" => idx);
+        }
+        for idx in g.bufg_gts.iter_idx() {
+            let bufg = g.bufg_gts.get(idx);
+            let bufg_go = go.bufg_gts.get(ObjPoolIndex::from(idx));
+            let idx = bufg_go.loc.unwrap().i;
+            debug!(logger, "PAR - final assignment";
+                "type" => "bufgts",
+                "name" => bufg.name.clone(),
+                "idx" => idx);
+        }
+        for idx in g.bufg_gsr.iter_idx() {
+            let bufg = g.bufg_gsr.get(idx);
+            let bufg_go = go.bufg_gsr.get(ObjPoolIndex::from(idx));
+            let idx = bufg_go.loc.unwrap().i;
+            debug!(logger, "PAR - final assignment";
+                "type" => "bufgsr",
+                "name" => bufg.name.clone(),
+                "idx" => idx);
+        }
+
+        go
+    }
+}
+
+pub fn do_par<L: Into<Option<slog::Logger>>>(g: &mut InputGraph, device_type: XC2DeviceSpeedPackage,
+    options: &XC2ParOptions, logger: L) -> PARResult {
+
+    let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+    // The time limit also covers the sanity check and the initial placement
+    let stop = PARStopCondition::from_options(options);
+
+    let sanity_check = do_par_sanity_check(g, device_type, &logger);
+    if sanity_check != PARSanityResult::Ok {
+        let bottlenecks = explain_sanity_failure(g, device_type, sanity_check);
+        log_bottlenecks(&bottlenecks, &logger);
+        return PARResult::FailureSanity(sanity_check, bottlenecks);
+    }
+
+    let state = PARState::with_stop_condition(g, device_type, options, stop, &logger);
+    if state.is_none() {
+        let bottlenecks = explain_sanity_failure(g, device_type, PARSanityResult::FailureInitialPlacement);
+        log_bottlenecks(&bottlenecks, &logger);
+        return PARResult::FailureSanity(PARSanityResult::FailureInitialPlacement, bottlenecks);
+    }
+    let mut state = state.unwrap();
+
+    while !state.is_done() && state.iterations() < options.max_iter {
+        if let Some(reason) = state.stop_reason() {
+            warn!(logger, "PAR - stopped before a placement was found"; "code" => "PR015",
+                "reason" => format!("{:?}", reason), "iterations" => state.iterations(), "score" => state.score());
+            return PARResult::Stopped(reason, state.into_partial_result());
+        }
+
+        state.step(g, device_type, &logger);
+    }
+
+    if state.is_done() {
+        PARResult::Success(state.finish(g, device_type, &logger))
+    } else {
+        let bottlenecks = state.explain_failure(g, device_type);
+        log_bottlenecks(&bottlenecks, &logger);
+        PARResult::FailureIterationsExceeded(bottlenecks)
+    }
 }
 
 #[cfg(test)]
@@ -1545,18 +1853,11 @@ mod tests {
     use std::fs::File;
     use std::io::Read;
 
-    fn run_one_reftest(input_filename: &'static str) {
-        // Read original json
-        let input_path = std::path::Path::new(input_filename);
-        let mut input_data = Vec::new();
-        File::open(&input_path).unwrap().read_to_end(&mut input_data).unwrap();
-        let mut input_graph = serde_json::from_slice(&input_data).unwrap();
+    fn par_to_jed(input_data: &[u8]) -> Vec<u8> {
+        let mut input_graph = serde_json::from_slice(input_data).unwrap();
         // TODO
         let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").expect("invalid device name");
-        // This is what we get
-        let our_data_structure = if let PARResult::Success(y) = do_par(&mut input_graph, device_type,
-            &XC2ParOptions::new(), None) {
-
+        if let PARResult::Success(y) = do_par(&mut input_graph, device_type, &XC2ParOptions::new(), None) {
             // Get a bitstream result
             let bitstream = produce_bitstream(device_type, &input_graph, &y);
             let mut ret = Vec::new();
@@ -1564,7 +1865,19 @@ mod tests {
             ret
         } else {
             panic!("PAR failed!");
-        };
+        }
+    }
+
+    fn run_one_reftest(input_filename: &'static str) {
+        // Read original json
+        let input_path = std::path::Path::new(input_filename);
+        let mut input_data = Vec::new();
+        File::open(&input_path).unwrap().read_to_end(&mut input_data).unwrap();
+        // This is what we get
+        let our_data_structure = par_to_jed(&input_data);
+        // Every run must give exactly the same bytes (every HashMap/HashSet gets a different random seed, so this
+        // catches anything that depends on their iteration order)
+        assert_eq!(par_to_jed(&input_data), our_data_structure);
 
         // Read reference jed
         let mut output_path = input_path.to_path_buf();
@@ -1578,5 +1891,46 @@ mod tests {
 
     // Include list of actual tests to run
     include!(concat!(env!("OUT_DIR"), "/par-reftests.rs"));
+
+    fn yosys_to_input_graph(input_data: &[u8]) -> InputGraph {
+        let yosys_netlist = yosys_netlist_json::Netlist::from_slice(input_data).unwrap();
+        let intermed_graph = IntermediateGraph::from_yosys_netlist(&yosys_netlist, None).unwrap();
+        InputGraph::from_intermed_graph(&intermed_graph, None).unwrap()
+    }
+
+    #[test]
+    fn clock_div_data_gate() {
+        let input = include_bytes!("../../../tests/xc2par/frontend-reftests/clk-div.json");
+
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c128-6-tq144").unwrap();
+        let mut g = yosys_to_input_graph(input);
+        let go = if let PARResult::Success(x) = do_par(&mut g, device_type, &XC2ParOptions::new(), None) { x } else {
+            panic!("PAR failed!");
+        };
+        let bitstream = produce_bitstream(device_type, &g, &go);
+        if let XC2BitstreamBits::XC2C128(ref bits) = bitstream.bits {
+            assert_eq!(bits.clock_div, XC2ClockDiv {
+                div_ratio: XC2ClockDivRatio::Div4,
+                delay: true,
+                enabled: true,
+            });
+            assert!(bits.data_gate);
+            assert_eq!(bits.global_nets.gck_enable, [false, false, true]);
+        } else {
+            panic!("wrong device");
+        }
+
+        // CDRST and DGE have to be on their dedicated pins
+        for &(name, loc) in &[("ibuf_rst", get_cdrst(XC2Device::XC2C128)), ("ibuf_dge", get_dge(XC2Device::XC2C128))] {
+            let mc_idx = g.mcs.iter_idx().find(|&x| g.mcs.get(x).name == name).unwrap();
+            let mc_loc = go.mcs.get(ObjPoolIndex::from(mc_idx)).loc.unwrap();
+            assert_eq!(Some((mc_loc.fb, mc_loc.i)), loc);
+        }
+
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        let mut g = yosys_to_input_graph(input);
+        assert!(matches!(do_par(&mut g, device_type, &XC2ParOptions::new(), None),
+            PARResult::FailureSanity(PARSanityResult::FailureFeatureNotSupported, _)));
+    }
 }
 
@@ -0,0 +1,1136 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Frontend for legacy PAL/CPLD sources: Berkeley PLA tables and ABEL-style equation files. Unlike the Yosys and BLIF
+// readers, these already describe the design as sums of products, so the IntermediateGraph is built directly with one
+// ANDTERM per product term.
+//
+// Equation files are a sequence of statements terminated by semicolons:
+//
+//     NODE t;                   // t is a buried node rather than a pin
+//     y = a & b # !c;           // combinational output
+//     !z = a $ b;               // the XOR in the macrocell inverts the sum-of-products
+//     q := q $ en;              // D flip-flop (also q.D = ...), q.T = ... for a toggle flip-flop
+//     q.CLK = clk;              // clock, set, reset, clock enable, and output enable each take one p-term
+//     q.AR = rst; q.AP = 0; q.CE = en; q.OE = oe;
+//
+// The operators are `!` (not), `&` (and), `#` (or), `$` (xor), and `!$` (xnor), in decreasing order of precedence.
+// Any signal that is used but never declared or assigned is an input pin; a NODE or a control assignment without an
+// equation is an error.
+//
+// PLA files use the usual `.i`/`.o`/`.ilb`/`.ob`/`.p`/`.type`/`.phase`/`.e` directives. As an extension, `.reg` lists
+// outputs that should be D flip-flops clocked by the input named by `.clock`. An input with the same name as an output
+// refers to the fed back output.
+
+use std::collections::{HashMap, HashSet};
+use std::error;
+use std::fmt;
+use std::io::Read;
+use slog::Drain;
+
+use xc2bit::*;
+
+use crate::*;
+use crate::minimize::*;
+use crate::objpool::*;
+use crate::techmap::{cover_and, cover_or};
+
+#[derive(Debug)]
+pub enum EquationError {
+    ReadError(std::io::Error),
+    MalformedLine(usize, String),
+    TooManyVariables(String),
+    ExpressionTooComplex(String),
+    MultipleAssignments(String),
+    MissingFunction(usize, String),
+    MissingClock(String),
+    ControlTermTooComplex(String),
+    IllegalControl(String),
+    FrontendError(FrontendError),
+}
+
+impl error::Error for EquationError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            EquationError::ReadError(ref inner) => Some(inner),
+            EquationError::FrontendError(ref inner) => Some(inner),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for EquationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EquationError::ReadError(ref inner) => {
+                write!(f, "{}", inner)
+            },
+            EquationError::MalformedLine(line, ref s) => {
+                write!(f, "line {}: malformed input - {}", line, s)
+            },
+            EquationError::TooManyVariables(ref s) => {
+                write!(f, "too many variables in equation - {}", s)
+            },
+            EquationError::ExpressionTooComplex(ref s) => {
+                write!(f, "expression is too complex to expand - {}", s)
+            },
+            EquationError::MultipleAssignments(ref s) => {
+                write!(f, "signal assigned more than once - {}", s)
+            },
+            EquationError::MissingFunction(line, ref s) => {
+                write!(f, "line {}: signal is declared or has controls but no equation - {}", line, s)
+            },
+            EquationError::MissingClock(ref s) => {
+                write!(f, "register has no clock - {}", s)
+            },
+            EquationError::ControlTermTooComplex(ref s) => {
+                write!(f, "control signal needs more than one p-term - {}", s)
+            },
+            EquationError::IllegalControl(ref s) => {
+                write!(f, "illegal register control - {}", s)
+            },
+            EquationError::FrontendError(ref inner) => {
+                write!(f, "{}", inner)
+            },
+        }
+    }
+}
+
+impl From<std::io::Error> for EquationError {
+    fn from(err: std::io::Error) -> Self {
+        EquationError::ReadError(err)
+    }
+}
+
+impl From<FrontendError> for EquationError {
+    fn from(err: FrontendError) -> Self {
+        EquationError::FrontendError(err)
+    }
+}
+
+// A sum-of-products over named signals
+#[derive(Clone, Debug, Default)]
+struct Sop {
+    vars: Vec<String>,
+    cover: Vec<Cube>,
+}
+
+impl Sop {
+    fn constant(value: bool) -> Self {
+        Self {
+            vars: Vec::new(),
+            cover: if value { vec![Cube::UNIVERSE] } else { Vec::new() },
+        }
+    }
+
+    fn is_constant(&self, value: bool) -> bool {
+        if value {
+            self.cover.contains(&Cube::UNIVERSE)
+        } else {
+            self.cover.is_empty()
+        }
+    }
+}
+
+#[derive(Default)]
+struct SignalDef {
+    buried: bool,
+    // Line of the NODE declaration or the first control assignment, for errors about a missing equation
+    line: usize,
+    function: Option<Sop>,
+    invert: bool,
+    reg_mode: Option<XC2MCRegMode>,
+    clk: Option<Sop>,
+    set: Option<Sop>,
+    reset: Option<Sop>,
+    ce: Option<Sop>,
+    oe: Option<Sop>,
+}
+
+#[derive(Default)]
+struct EquationDesign {
+    // Every signal that is referenced anywhere, in order of first appearance
+    names: Vec<String>,
+    signals: HashMap<String, SignalDef>,
+}
+
+impl EquationDesign {
+    fn reference(&mut self, name: &str) {
+        if !self.names.iter().any(|x| x == name) {
+            self.names.push(name.to_owned());
+        }
+    }
+
+    fn signal(&mut self, name: &str) -> &mut SignalDef {
+        self.reference(name);
+        self.signals.entry(name.to_owned()).or_default()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Const(bool),
+    Var(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Xor(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn collect_vars(&self, vars: &mut Vec<String>) {
+        match *self {
+            Expr::Const(_) => {},
+            Expr::Var(ref name) => {
+                if !vars.contains(name) {
+                    vars.push(name.to_owned());
+                }
+            },
+            Expr::Not(ref a) => a.collect_vars(vars),
+            Expr::And(ref a, ref b) | Expr::Or(ref a, ref b) | Expr::Xor(ref a, ref b) => {
+                a.collect_vars(vars);
+                b.collect_vars(vars);
+            },
+        }
+    }
+
+    fn cover(&self, vars: &[String], budget: &mut Budget) -> Option<Vec<Cube>> {
+        Some(match *self {
+            Expr::Const(value) => Sop::constant(value).cover,
+            Expr::Var(ref name) => {
+                let i = vars.iter().position(|x| x == name).unwrap();
+                vec![Cube {pos: 1 << i, neg: 0}]
+            },
+            Expr::Not(ref a) => complement(&a.cover(vars, budget)?, budget)?,
+            Expr::And(ref a, ref b) => cover_and(&a.cover(vars, budget)?, &b.cover(vars, budget)?),
+            Expr::Or(ref a, ref b) => cover_or(&a.cover(vars, budget)?, &b.cover(vars, budget)?),
+            Expr::Xor(ref a, ref b) => {
+                let a = a.cover(vars, budget)?;
+                let b = b.cover(vars, budget)?;
+                let not_a = complement(&a, budget)?;
+                let not_b = complement(&b, budget)?;
+                cover_or(&cover_and(&a, &not_b), &cover_and(&not_a, &b))
+            },
+        })
+    }
+
+    fn to_sop(&self, name: &str, logger: &slog::Logger) -> Result<Sop, EquationError> {
+        let mut vars = Vec::new();
+        self.collect_vars(&mut vars);
+        if vars.len() > 64 {
+            error!(logger, "equations - too many variables";
+                "code" => "EN006",
+                "name" => name,
+                "variables" => vars.len());
+            return Err(EquationError::TooManyVariables(name.to_owned()));
+        }
+
+        let mut budget = Budget(MINIMIZE_BUDGET);
+        let cover = self.cover(&vars, &mut budget);
+        if cover.is_none() {
+            error!(logger, "equations - expression too complex";
+                "code" => "EN007",
+                "name" => name);
+            return Err(EquationError::ExpressionTooComplex(name.to_owned()));
+        }
+
+        Ok(Sop {
+            vars,
+            cover: cover.unwrap(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Const(bool),
+    Not,
+    And,
+    Or,
+    Xor,
+    Xnor,
+    LParen,
+    RParen,
+    Assign,
+    RegAssign,
+    Comma,
+    Dot,
+    Semicolon,
+}
+
+fn tokenize(input: &str, logger: &slog::Logger) -> Result<Vec<(usize, Token)>, EquationError> {
+    let mut ret = Vec::new();
+
+    for (line_i, line) in input.lines().enumerate() {
+        let line_num = line_i + 1;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                ' ' | '\t' | '\r' => continue,
+                '"' => break,
+                '/' if chars.peek() == Some(&'/') => break,
+                '!' if chars.peek() == Some(&'$') => {
+                    chars.next();
+                    Token::Xnor
+                },
+                '!' => Token::Not,
+                '&' => Token::And,
+                '#' => Token::Or,
+                '$' => Token::Xor,
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                '=' => Token::Assign,
+                ':' if chars.peek() == Some(&'=') => {
+                    chars.next();
+                    Token::RegAssign
+                },
+                ',' => Token::Comma,
+                '.' => Token::Dot,
+                ';' => Token::Semicolon,
+                '0' => Token::Const(false),
+                '1' => Token::Const(true),
+                'a'..='z' | 'A'..='Z' | '_' => {
+                    let mut ident = c.to_string();
+                    while let Some(&c) = chars.peek() {
+                        if c.is_ascii_alphanumeric() || c == '_' {
+                            ident.push(c);
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    Token::Ident(ident)
+                },
+                _ => {
+                    error!(logger, "equations - unexpected character";
+                        "code" => "EN001",
+                        "line" => line_num,
+                        "value" => c.to_string());
+                    return Err(EquationError::MalformedLine(line_num, format!("unexpected character '{}'", c)));
+                },
+            };
+            ret.push((line_num, token));
+        }
+    }
+
+    Ok(ret)
+}
+
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    logger: &'a slog::Logger,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|x| &x.1)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos).or_else(|| self.tokens.last()).map(|x| x.0).unwrap_or(0)
+    }
+
+    fn error<T>(&self, msg: &str) -> Result<T, EquationError> {
+        error!(self.logger, "equations - syntax error";
+            "code" => "EN002",
+            "line" => self.line(),
+            "reason" => msg);
+        Err(EquationError::MalformedLine(self.line(), msg.to_owned()))
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let ret = self.tokens.get(self.pos).map(|x| x.1.clone());
+        self.pos += 1;
+        ret
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), EquationError> {
+        if self.peek() == Some(&token) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(&format!("expected {:?}", token))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, EquationError> {
+        match self.peek() {
+            Some(Token::Ident(s)) => {
+                let s = s.to_owned();
+                self.pos += 1;
+                Ok(s)
+            },
+            _ => self.error("expected a signal name"),
+        }
+    }
+
+    // xor_expr := or_expr (('$' | '!$') or_expr)*
+    fn parse_expr(&mut self) -> Result<Expr, EquationError> {
+        let mut ret = self.parse_or()?;
+        loop {
+            match self.peek() {
+                Some(&Token::Xor) => {
+                    self.pos += 1;
+                    ret = Expr::Xor(Box::new(ret), Box::new(self.parse_or()?));
+                },
+                Some(&Token::Xnor) => {
+                    self.pos += 1;
+                    ret = Expr::Not(Box::new(Expr::Xor(Box::new(ret), Box::new(self.parse_or()?))));
+                },
+                _ => return Ok(ret),
+            }
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, EquationError> {
+        let mut ret = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            ret = Expr::Or(Box::new(ret), Box::new(self.parse_and()?));
+        }
+        Ok(ret)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, EquationError> {
+        let mut ret = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            ret = Expr::And(Box::new(ret), Box::new(self.parse_unary()?));
+        }
+        Ok(ret)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, EquationError> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            Some(Token::Const(value)) => Ok(Expr::Const(value)),
+            Some(Token::Ident(name)) => Ok(Expr::Var(name)),
+            Some(Token::LParen) => {
+                let ret = self.parse_expr()?;
+                self.expect(Token::RParen)?;
+                Ok(ret)
+            },
+            _ => {
+                self.pos -= 1;
+                self.error("expected an expression")
+            },
+        }
+    }
+}
+
+fn parse_equations(input: &str, logger: &slog::Logger) -> Result<EquationDesign, EquationError> {
+    let mut parser = Parser {
+        tokens: tokenize(input, logger)?,
+        pos: 0,
+        logger,
+    };
+    let mut design = EquationDesign::default();
+
+    while parser.peek().is_some() {
+        // Declarations
+        if let Some(Token::Ident(s)) = parser.peek() {
+            if s.eq_ignore_ascii_case("NODE") {
+                parser.pos += 1;
+                loop {
+                    let line = parser.line();
+                    let name = parser.ident()?;
+                    let signal = design.signal(&name);
+                    signal.buried = true;
+                    if signal.line == 0 {
+                        signal.line = line;
+                    }
+                    if parser.peek() != Some(&Token::Comma) {
+                        break;
+                    }
+                    parser.pos += 1;
+                }
+                parser.expect(Token::Semicolon)?;
+                continue;
+            }
+        }
+
+        // Assignments
+        let line = parser.line();
+        let invert = if parser.peek() == Some(&Token::Not) {
+            parser.pos += 1;
+            true
+        } else {
+            false
+        };
+        let name = parser.ident()?;
+        let attribute = if parser.peek() == Some(&Token::Dot) {
+            parser.pos += 1;
+            Some(parser.ident()?.to_ascii_uppercase())
+        } else {
+            None
+        };
+        let is_reg_assign = match parser.next() {
+            Some(Token::Assign) => false,
+            Some(Token::RegAssign) => true,
+            _ => {
+                parser.pos -= 1;
+                return parser.error("expected = or :=");
+            },
+        };
+        let expr = parser.parse_expr()?;
+        parser.expect(Token::Semicolon)?;
+
+        let reg_mode = match (attribute.as_ref().map(|x| x.as_ref()), is_reg_assign) {
+            (None, false) => None,
+            (None, true) | (Some("D"), false) => Some(XC2MCRegMode::DFF),
+            (Some("T"), false) => Some(XC2MCRegMode::TFF),
+            (Some(_), false) if !invert => None,
+            _ => {
+                error!(logger, "equations - bad assignment";
+                    "code" => "EN003",
+                    "line" => line,
+                    "name" => &name);
+                return Err(EquationError::MalformedLine(line, format!("bad assignment to {}", name)));
+            },
+        };
+        debug!(logger, "equations - assignment"; "name" => &name, "attribute" => &attribute, "line" => line);
+
+        let sop_name = match attribute {
+            Some(ref attribute) => format!("{}.{}", name, attribute),
+            None => name.clone(),
+        };
+        let sop = expr.to_sop(&sop_name, logger)?;
+        for var in &sop.vars {
+            design.reference(var);
+        }
+
+        let signal = design.signal(&name);
+        let slot = match attribute.as_ref().map(|x| x.as_ref()) {
+            None | Some("D") | Some("T") => {
+                signal.invert = invert;
+                signal.reg_mode = reg_mode;
+                &mut signal.function
+            },
+            Some("CLK") => &mut signal.clk,
+            Some("AP") => &mut signal.set,
+            Some("AR") => &mut signal.reset,
+            Some("CE") => &mut signal.ce,
+            Some("OE") => &mut signal.oe,
+            Some(x) => {
+                error!(logger, "equations - unknown attribute";
+                    "code" => "EN004",
+                    "line" => line,
+                    "name" => &name,
+                    "value" => x);
+                return Err(EquationError::MalformedLine(line, format!("unknown attribute {}", x)));
+            },
+        };
+        if signal.line == 0 {
+            signal.line = line;
+        }
+        if slot.is_some() {
+            error!(logger, "equations - multiple assignments";
+                "code" => "EN005",
+                "line" => line,
+                "name" => &sop_name);
+            return Err(EquationError::MultipleAssignments(sop_name));
+        }
+        *slot = Some(sop);
+    }
+
+    Ok(design)
+}
+
+fn parse_pla(input: &str, logger: &slog::Logger) -> Result<EquationDesign, EquationError> {
+    let mut num_inputs = None;
+    let mut num_outputs = None;
+    let mut input_names: Option<Vec<String>> = None;
+    let mut output_names: Option<Vec<String>> = None;
+    let mut phase: Option<Vec<bool>> = None;
+    let mut registered = Vec::new();
+    let mut clock = None;
+    let mut rows = Vec::new();
+    let mut last_line = 0;
+
+    for (line_i, line) in input.lines().enumerate() {
+        let line_num = line_i + 1;
+        last_line = line_num;
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let malformed = || {
+            error!(logger, "pla - malformed line";
+                "code" => "EN008",
+                "line" => line_num,
+                "value" => line);
+            EquationError::MalformedLine(line_num, line.to_owned())
+        };
+        match words[0] {
+            ".i" | ".o" | ".p" => {
+                if words.len() != 2 {
+                    return Err(malformed());
+                }
+                let n = words[1].parse::<usize>().map_err(|_| malformed())?;
+                match words[0] {
+                    ".i" => num_inputs = Some(n),
+                    ".o" => num_outputs = Some(n),
+                    _ => {},
+                }
+            },
+            ".ilb" => input_names = Some(words[1..].iter().map(|x| x.to_string()).collect()),
+            ".ob" => output_names = Some(words[1..].iter().map(|x| x.to_string()).collect()),
+            ".type" => {
+                // Only the ON-set is used, and every type other than a bare OFF-set lists it
+                if words.len() != 2 || words[1] == "r" {
+                    return Err(malformed());
+                }
+            },
+            ".phase" => {
+                if words.len() != 2 || words[1].chars().any(|c| c != '0' && c != '1') {
+                    return Err(malformed());
+                }
+                phase = Some(words[1].chars().map(|c| c == '1').collect());
+            },
+            ".reg" => registered.extend(words[1..].iter().map(|x| (x.to_string(), line_num))),
+            ".clock" => {
+                if words.len() != 2 {
+                    return Err(malformed());
+                }
+                clock = Some(words[1].to_owned());
+            },
+            ".e" | ".end" => break,
+            x if x.starts_with('.') => {
+                error!(logger, "pla - unsupported directive";
+                    "code" => "EN009",
+                    "line" => line_num,
+                    "value" => x);
+                return Err(EquationError::MalformedLine(line_num, format!("unsupported directive {}", x)));
+            },
+            _ => {
+                let num_inputs = num_inputs.ok_or_else(malformed)?;
+                let row = words.concat();
+                if row.len() != num_inputs + num_outputs.ok_or_else(malformed)? {
+                    return Err(malformed());
+                }
+                let (input_plane, output_plane) = row.split_at(num_inputs);
+                let mut cube = Cube::UNIVERSE;
+                for (i, c) in input_plane.chars().enumerate() {
+                    match c {
+                        '1' => cube.pos |= 1 << i,
+                        '0' => cube.neg |= 1 << i,
+                        '-' | '2' => {},
+                        _ => return Err(malformed()),
+                    }
+                }
+                let mut outputs = Vec::new();
+                for c in output_plane.chars() {
+                    match c {
+                        '1' | '4' => outputs.push(true),
+                        '0' | '-' | '~' | '2' | '3' => outputs.push(false),
+                        _ => return Err(malformed()),
+                    }
+                }
+                rows.push((cube, outputs));
+            },
+        }
+    }
+
+    let num_inputs = num_inputs.unwrap_or(0);
+    if num_inputs > 64 {
+        error!(logger, "pla - too many inputs";
+            "code" => "EN010",
+            "inputs" => num_inputs);
+        return Err(EquationError::TooManyVariables(".i".to_owned()));
+    }
+    let num_outputs = num_outputs.unwrap_or(0);
+    let input_names = input_names.unwrap_or_else(|| (0..num_inputs).map(|i| format!("i{}", i)).collect());
+    let output_names = output_names.unwrap_or_else(|| (0..num_outputs).map(|i| format!("o{}", i)).collect());
+    if input_names.len() != num_inputs || output_names.len() != num_outputs ||
+        phase.as_ref().map(|x| x.len() != num_outputs).unwrap_or(false) {
+
+        error!(logger, "pla - mismatched number of inputs or outputs";
+            "code" => "EN011",
+            "line" => last_line,
+            "inputs" => num_inputs,
+            "outputs" => num_outputs);
+        return Err(EquationError::MalformedLine(last_line, "mismatched number of inputs or outputs".to_owned()));
+    }
+    debug!(logger, "pla - read table"; "inputs" => num_inputs, "outputs" => num_outputs, "rows" => rows.len());
+
+    for &(ref name, line) in &registered {
+        if !output_names.contains(name) {
+            error!(logger, "pla - registered signal is not an output";
+                "code" => "EN012",
+                "line" => line,
+                "name" => name);
+            return Err(EquationError::MalformedLine(line, format!("registered signal {} is not an output", name)));
+        }
+    }
+
+    let mut design = EquationDesign::default();
+    for name in &input_names {
+        design.reference(name);
+    }
+    for (output_i, name) in output_names.iter().enumerate() {
+        let mut cover = rows.iter().filter(|x| x.1[output_i]).map(|x| x.0).collect::<Vec<_>>();
+        remove_contained(&mut cover);
+
+        let is_registered = registered.iter().any(|x| &x.0 == name);
+        let clk = if is_registered {
+            if clock.is_none() {
+                error!(logger, "pla - registered output without a clock";
+                    "code" => "EN013",
+                    "name" => name);
+                return Err(EquationError::MissingClock(name.to_owned()));
+            }
+            Some(Sop {
+                vars: vec![clock.clone().unwrap()],
+                cover: vec![Cube {pos: 1, neg: 0}],
+            })
+        } else {
+            None
+        };
+
+        let signal = design.signal(name);
+        signal.function = Some(Sop {
+            vars: input_names.clone(),
+            cover,
+        });
+        signal.invert = !phase.as_ref().map(|x| x[output_i]).unwrap_or(true);
+        signal.reg_mode = if is_registered { Some(XC2MCRegMode::DFF) } else { None };
+        signal.clk = clk;
+    }
+    if let Some(clock) = clock {
+        design.reference(&clock);
+    }
+
+    Ok(design)
+}
+
+struct GraphBuilder {
+    nodes: ObjPool<IntermediateGraphNode>,
+    nets: ObjPool<IntermediateGraphNet>,
+    signal_nets: HashMap<String, ObjPoolIndex<IntermediateGraphNet>>,
+}
+
+impl GraphBuilder {
+    fn new_net(&mut self, name: String) -> ObjPoolIndex<IntermediateGraphNet> {
+        self.nets.insert(IntermediateGraphNet {
+            name: Some(name),
+            source: None,
+            sinks: Vec::new(),
+        })
+    }
+
+    fn add_andterm(&mut self, name: String, sop: &Sop, cube: &Cube) -> ObjPoolIndex<IntermediateGraphNet> {
+        let output = self.new_net(name.clone());
+        let mut inputs_true = Vec::new();
+        let mut inputs_comp = Vec::new();
+        for (i, var) in sop.vars.iter().enumerate() {
+            if cube.pos & (1 << i) != 0 {
+                inputs_true.push(self.signal_nets[var]);
+            }
+            if cube.neg & (1 << i) != 0 {
+                inputs_comp.push(self.signal_nets[var]);
+            }
+        }
+
+        self.nodes.insert(IntermediateGraphNode {
+            name,
+            variant: IntermediateGraphNodeVariant::AndTerm {
+                inputs_true,
+                inputs_comp,
+                output,
+            },
+            location: None,
+        });
+
+        output
+    }
+
+    fn add_xor(&mut self, name: &str, sop: &Sop, invert_out: bool, output: ObjPoolIndex<IntermediateGraphNet>) {
+        let orterm_input = if sop.cover.is_empty() {
+            None
+        } else {
+            let mut inputs = Vec::new();
+            for (i, cube) in sop.cover.iter().enumerate() {
+                inputs.push(self.add_andterm(format!("{}_PT{}", name, i), sop, cube));
+            }
+
+            let or_output = self.new_net(format!("{}_OR", name));
+            self.nodes.insert(IntermediateGraphNode {
+                name: format!("{}_OR", name),
+                variant: IntermediateGraphNodeVariant::OrTerm {
+                    inputs,
+                    output: or_output,
+                },
+                location: None,
+            });
+            Some(or_output)
+        };
+
+        self.nodes.insert(IntermediateGraphNode {
+            name: name.to_owned(),
+            variant: IntermediateGraphNodeVariant::Xor {
+                orterm_input,
+                andterm_input: None,
+                invert_out,
+                output,
+            },
+            location: None,
+        });
+    }
+
+    // Register and IO buffer controls each get exactly one p-term. Returns None if the control is never active.
+    fn add_control(&mut self, name: String, sop: &Sop, logger: &slog::Logger)
+        -> Result<Option<ObjPoolIndex<IntermediateGraphNet>>, EquationError> {
+
+        match sop.cover.len() {
+            0 => Ok(None),
+            1 => Ok(Some(self.add_andterm(name, sop, &sop.cover[0]))),
+            n => {
+                error!(logger, "equations - control term too complex";
+                    "code" => "EN014",
+                    "name" => &name,
+                    "terms" => n);
+                Err(EquationError::ControlTermTooComplex(name))
+            },
+        }
+    }
+}
+
+fn build_graph(design: &EquationDesign, logger: &slog::Logger) -> Result<IntermediateGraph, EquationError> {
+    let mut nets = ObjPool::new();
+    let vdd_net = nets.insert(IntermediateGraphNet {
+        name: Some(String::from("<internal virtual Vdd net>")),
+        source: None,
+        sinks: Vec::new(),
+    });
+    let vss_net = nets.insert(IntermediateGraphNet {
+        name: Some(String::from("<internal virtual Vss net>")),
+        source: None,
+        sinks: Vec::new(),
+    });
+    let mut b = GraphBuilder {
+        nodes: ObjPool::new(),
+        nets,
+        signal_nets: HashMap::new(),
+    };
+
+    for name in &design.names {
+        let net = b.new_net(name.to_owned());
+        b.signal_nets.insert(name.to_owned(), net);
+    }
+
+    for name in &design.names {
+        let net = b.signal_nets[name];
+        let signal = design.signals.get(name);
+        let function = signal.and_then(|x| x.function.as_ref());
+        if function.is_none() {
+            // Only signals that are referenced but never declared or assigned become input pins
+            if let Some(signal) = signal {
+                error!(logger, "equations - signal has no function";
+                    "code" => "EN015",
+                    "line" => signal.line,
+                    "name" => name);
+                return Err(EquationError::MissingFunction(signal.line, name.to_owned()));
+            }
+
+            debug!(logger, "equations - adding input pin"; "name" => name);
+            b.nodes.insert(IntermediateGraphNode {
+                name: name.to_owned(),
+                variant: IntermediateGraphNodeVariant::InBuf {
+                    output: net,
+                    schmitt_trigger: false,
+                    termination_enabled: false,
+                    uses_data_gate: false,
+                    io_standard: None,
+                    port_name: Some(name.to_owned()),
+                },
+                location: None,
+            });
+            continue;
+        }
+        let signal = signal.unwrap();
+        let function = function.unwrap();
+
+        if let Some(reg_mode) = signal.reg_mode {
+            debug!(logger, "equations - adding register"; "name" => name);
+            let d_net = b.new_net(format!("{}_D", name));
+            b.add_xor(&format!("{}_D", name), function, signal.invert, d_net);
+
+            let clk_input = match signal.clk {
+                Some(ref clk) => b.add_control(format!("{}_CLK", name), clk, logger)?,
+                None => None,
+            };
+            if clk_input.is_none() {
+                error!(logger, "equations - register without a clock";
+                    "code" => "EN016",
+                    "name" => name);
+                return Err(EquationError::MissingClock(name.to_owned()));
+            }
+            let set_input = match signal.set {
+                Some(ref set) => b.add_control(format!("{}_AP", name), set, logger)?,
+                None => None,
+            };
+            let reset_input = match signal.reset {
+                Some(ref reset) => b.add_control(format!("{}_AR", name), reset, logger)?,
+                None => None,
+            };
+            let (mode, ce_input) = match signal.ce {
+                Some(ref ce) if !ce.is_constant(true) => {
+                    // Only D flip-flops have a clock enable, and one that is never active makes no sense
+                    if reg_mode != XC2MCRegMode::DFF || ce.is_constant(false) {
+                        error!(logger, "equations - illegal clock enable";
+                            "code" => "EN017",
+                            "name" => name);
+                        return Err(EquationError::IllegalControl(format!("{}.CE", name)));
+                    }
+                    (XC2MCRegMode::DFFCE, b.add_control(format!("{}_CE", name), ce, logger)?)
+                },
+                _ => (reg_mode, None),
+            };
+
+            b.nodes.insert(IntermediateGraphNode {
+                name: format!("{}_REG", name),
+                variant: IntermediateGraphNodeVariant::Reg {
+                    mode,
+                    clkinv: false,
+                    clkddr: false,
+                    init_state: false,
+                    set_input,
+                    reset_input,
+                    ce_input,
+                    dt_input: d_net,
+                    clk_input: clk_input.unwrap(),
+                    output: net,
+                },
+                location: None,
+            });
+        } else {
+            debug!(logger, "equations - adding combinational logic"; "name" => name);
+            b.add_xor(&format!("{}_XOR", name), function, signal.invert, net);
+        }
+
+        if !signal.buried {
+            let oe = match signal.oe {
+                Some(ref oe) if oe.is_constant(true) => None,
+                Some(ref oe) => Some(b.add_control(format!("{}_OE", name), oe, logger)?.unwrap_or(vss_net)),
+                None => None,
+            };
+
+            b.nodes.insert(IntermediateGraphNode {
+                name: name.to_owned(),
+                variant: IntermediateGraphNodeVariant::IOBuf {
+                    input: Some(net),
+                    oe,
+                    output: None,
+                    schmitt_trigger: false,
+                    termination_enabled: false,
+                    slew_is_fast: false,
+                    uses_data_gate: false,
+                    io_standard: None,
+                    port_name: Some(name.to_owned()),
+                },
+                location: None,
+            });
+        }
+    }
+
+    connect_nets(&b.nodes, &mut b.nets, vdd_net, vss_net, &HashSet::new(), logger)?;
+
+    Ok(IntermediateGraph {
+        nodes: b.nodes,
+        nets: b.nets,
+        vdd_net,
+        vss_net,
+    })
+}
+
+impl IntermediateGraph {
+    /// Builds a graph from an ABEL-style equation file
+    pub fn from_equations<R, L>(mut input: R, logger: L) -> Result<Self, EquationError>
+        where R: Read, L: Into<Option<slog::Logger>> {
+
+        let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+
+        let mut data = String::new();
+        if let Err(e) = input.read_to_string(&mut data) {
+            error!(logger, "equations - could not read input";
+                "code" => "EN018",
+                "reason" => e.to_string());
+            return Err(e.into());
+        }
+        let design = parse_equations(&data, &logger)?;
+        build_graph(&design, &logger)
+    }
+
+    /// Builds a graph from a Berkeley PLA file
+    pub fn from_pla<R, L>(mut input: R, logger: L) -> Result<Self, EquationError>
+        where R: Read, L: Into<Option<slog::Logger>> {
+
+        let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+
+        let mut data = String::new();
+        if let Err(e) = input.read_to_string(&mut data) {
+            error!(logger, "equations - could not read input";
+                "code" => "EN018",
+                "reason" => e.to_string());
+            return Err(e.into());
+        }
+        let design = parse_pla(&data, &logger)?;
+        build_graph(&design, &logger)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_node<'a>(g: &'a IntermediateGraph, name: &str) -> &'a IntermediateGraphNodeVariant {
+        &g.nodes.iter().find(|x| x.name == name).unwrap().variant
+    }
+
+    fn fits(g: &IntermediateGraph) -> bool {
+        let mut input_graph = InputGraph::from_intermed_graph(g, None).unwrap();
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        matches!(do_par(&mut input_graph, device_type, &XC2ParOptions::new(), None), PARResult::Success(_))
+    }
+
+    #[test]
+    fn equations_counter() {
+        let input = r#"
+            " two bit counter with an enable and a tristated carry output
+            NODE t;
+            q0 := q0 $ en;
+            q1.T = q0 & en;
+            q0.CLK = clk; q1.CLK = clk;
+            q0.AR = rst; q1.AR = rst;
+            !carry = !(q0 & q1);    // inverted in the macrocell
+            carry.OE = oe;
+            t = a !$ b;
+        "#;
+        let g = IntermediateGraph::from_equations(input.as_bytes(), None).unwrap();
+
+        if let &IntermediateGraphNodeVariant::Reg{mode, reset_input, set_input, ..} = find_node(&g, "q1_REG") {
+            assert_eq!(mode, XC2MCRegMode::TFF);
+            assert!(reset_input.is_some());
+            assert!(set_input.is_none());
+        } else {
+            panic!("q1 is not a register");
+        }
+        if let &IntermediateGraphNodeVariant::Xor{invert_out, ..} = find_node(&g, "carry_XOR") {
+            assert!(invert_out);
+        } else {
+            panic!("carry is not combinational");
+        }
+        if let &IntermediateGraphNodeVariant::IOBuf{oe, ..} = find_node(&g, "carry") {
+            assert!(oe.is_some());
+        } else {
+            panic!("carry is not an output");
+        }
+        // XNOR needs two product terms, and buried nodes don't get a pin
+        assert!(g.nodes.iter().any(|x| x.name == "t_XOR_PT1"));
+        assert!(!g.nodes.iter().any(|x| x.name == "t_XOR_PT2"));
+        assert!(!g.nodes.iter().any(|x| x.name == "t"));
+        assert!(g.nodes.iter().any(|x| x.name == "clk"));
+
+        assert!(fits(&g));
+    }
+
+    #[test]
+    fn equations_errors() {
+        match IntermediateGraph::from_equations("q := a;\n".as_bytes(), None) {
+            Err(EquationError::MissingClock(_)) => {},
+            x => panic!("unexpected result {:?}", x),
+        }
+        match IntermediateGraph::from_equations("q := a;\nq.CLK = c # d;\n".as_bytes(), None) {
+            Err(EquationError::ControlTermTooComplex(_)) => {},
+            x => panic!("unexpected result {:?}", x),
+        }
+        match IntermediateGraph::from_equations("y = a;\ny = b;\n".as_bytes(), None) {
+            Err(EquationError::MultipleAssignments(_)) => {},
+            x => panic!("unexpected result {:?}", x),
+        }
+        match IntermediateGraph::from_equations("y = a &\n& b;\n".as_bytes(), None) {
+            Err(EquationError::MalformedLine(2, _)) => {},
+            x => panic!("unexpected result {:?}", x),
+        }
+        match IntermediateGraph::from_equations("y = a;\nNODE t;\n".as_bytes(), None) {
+            Err(EquationError::MissingFunction(2, _)) => {},
+            x => panic!("unexpected result {:?}", x),
+        }
+        match IntermediateGraph::from_equations("y = a;\n\nz.CLK = c;\n".as_bytes(), None) {
+            Err(EquationError::MissingFunction(3, _)) => {},
+            x => panic!("unexpected result {:?}", x),
+        }
+        match IntermediateGraph::from_pla(".i 1\n.o 1\n.reg q\n.clock c\n1 1\n".as_bytes(), None) {
+            Err(EquationError::MalformedLine(3, _)) => {},
+            x => panic!("unexpected result {:?}", x),
+        }
+    }
+
+    #[test]
+    fn pla_phase_and_registers() {
+        let input = "
+            # full adder with a registered carry
+            .i 3
+            .o 2
+            .ilb a b cin
+            .ob sum cout
+            .phase 01
+            .reg cout
+            .clock clk
+            .p 7
+            001 10
+            010 10
+            100 10
+            111 10
+            11- 01
+            1-1 01
+            -11 01
+            .e
+        ";
+        let g = IntermediateGraph::from_pla(input.as_bytes(), None).unwrap();
+
+        if let &IntermediateGraphNodeVariant::Xor{invert_out, ..} = find_node(&g, "sum_XOR") {
+            assert!(invert_out);
+        } else {
+            panic!("sum is not combinational");
+        }
+        if let &IntermediateGraphNodeVariant::Reg{mode, ..} = find_node(&g, "cout_REG") {
+            assert_eq!(mode, XC2MCRegMode::DFF);
+        } else {
+            panic!("cout is not a register");
+        }
+        if let IntermediateGraphNodeVariant::OrTerm{ref inputs, ..} = *find_node(&g, "cout_D_OR") {
+            assert_eq!(inputs.len(), 3);
+        } else {
+            panic!("cout has no OR term");
+        }
+
+        assert!(fits(&g));
+    }
+}
@@ -0,0 +1,1002 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Equivalence checking between the netlist and the bitstream that was produced for it. The bitstream is decoded on its
+// own (PLA, XOR, register, ZIA, and IOB settings), and the logic driving every output pin and every register is
+// compared against the logic in the IntermediateGraph using BDDs. Both sides are written in terms of the values on the
+// input pins and the outputs of the registers, which are matched up with the placed macrocells by the names of their
+// nets (or of their cells, if the nets have no names). Anything that can't be matched up is reported instead.
+//
+// Registers are compared by their next-state function, so a clock enable that was folded into the logic (or a D
+// flip-flop that was turned into a T flip-flop) still compares equal. Their clocks (including the polarity and whether
+// both edges are used), sets, resets, and initial states are compared as well. A clock that goes through the clock
+// divider is only compared by which pin it comes from and the divider settings; the reset input of the divider is not
+// checked. A design is only considered equivalent if everything could be matched up and checked.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use serde_derive::{Deserialize, Serialize};
+use slog::Drain;
+
+use xc2bit::*;
+
+use crate::*;
+use crate::objpool::*;
+
+type BddRef = u32;
+const BDD_FALSE: BddRef = 0;
+const BDD_TRUE: BddRef = 1;
+// Give up on a design rather than use an unbounded amount of memory
+const BDD_MAX_NODES: usize = 1 << 21;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+enum BddOp {
+    And,
+    Or,
+    Xor,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+struct BddNode {
+    var: u32,
+    lo: BddRef,
+    hi: BddRef,
+}
+
+// Reduced ordered BDDs, with the variables ordered by when they were first used. Every operation returns `None` once
+// the node limit has been hit.
+struct Bdd {
+    nodes: Vec<BddNode>,
+    unique: HashMap<BddNode, BddRef>,
+    cache: HashMap<(BddOp, BddRef, BddRef), BddRef>,
+    var_names: Vec<String>,
+    vars: HashMap<String, BddRef>,
+}
+
+impl Bdd {
+    fn new() -> Self {
+        let terminal = |x| BddNode {var: u32::MAX, lo: x, hi: x};
+
+        Self {
+            nodes: vec![terminal(BDD_FALSE), terminal(BDD_TRUE)],
+            unique: HashMap::new(),
+            cache: HashMap::new(),
+            var_names: Vec::new(),
+            vars: HashMap::new(),
+        }
+    }
+
+    fn var(&mut self, name: &str) -> Option<BddRef> {
+        if let Some(&x) = self.vars.get(name) {
+            return Some(x);
+        }
+
+        let var = self.var_names.len() as u32;
+        self.var_names.push(name.to_owned());
+        let x = self.mk(var, BDD_FALSE, BDD_TRUE)?;
+        self.vars.insert(name.to_owned(), x);
+        Some(x)
+    }
+
+    fn mk(&mut self, var: u32, lo: BddRef, hi: BddRef) -> Option<BddRef> {
+        if lo == hi {
+            return Some(lo);
+        }
+
+        let node = BddNode {var, lo, hi};
+        if let Some(&x) = self.unique.get(&node) {
+            return Some(x);
+        }
+        if self.nodes.len() >= BDD_MAX_NODES {
+            return None;
+        }
+        let x = self.nodes.len() as BddRef;
+        self.nodes.push(node);
+        self.unique.insert(node, x);
+        Some(x)
+    }
+
+    fn apply(&mut self, op: BddOp, a: BddRef, b: BddRef) -> Option<BddRef> {
+        match op {
+            BddOp::And => {
+                if a == BDD_FALSE || b == BDD_FALSE {
+                    return Some(BDD_FALSE);
+                }
+                if a == BDD_TRUE {
+                    return Some(b);
+                }
+                if b == BDD_TRUE || a == b {
+                    return Some(a);
+                }
+            },
+            BddOp::Or => {
+                if a == BDD_TRUE || b == BDD_TRUE {
+                    return Some(BDD_TRUE);
+                }
+                if a == BDD_FALSE {
+                    return Some(b);
+                }
+                if b == BDD_FALSE || a == b {
+                    return Some(a);
+                }
+            },
+            BddOp::Xor => {
+                if a == BDD_FALSE {
+                    return Some(b);
+                }
+                if b == BDD_FALSE {
+                    return Some(a);
+                }
+                if a == b {
+                    return Some(BDD_FALSE);
+                }
+            },
+        }
+
+        // All of the operations are commutative
+        let key = (op, a.min(b), a.max(b));
+        if let Some(&x) = self.cache.get(&key) {
+            return Some(x);
+        }
+
+        let node_a = self.nodes[a as usize];
+        let node_b = self.nodes[b as usize];
+        let var = node_a.var.min(node_b.var);
+        let (a_lo, a_hi) = if node_a.var == var { (node_a.lo, node_a.hi) } else { (a, a) };
+        let (b_lo, b_hi) = if node_b.var == var { (node_b.lo, node_b.hi) } else { (b, b) };
+        let lo = self.apply(op, a_lo, b_lo)?;
+        let hi = self.apply(op, a_hi, b_hi)?;
+        let x = self.mk(var, lo, hi)?;
+        self.cache.insert(key, x);
+        Some(x)
+    }
+
+    fn and(&mut self, a: BddRef, b: BddRef) -> Option<BddRef> {
+        self.apply(BddOp::And, a, b)
+    }
+
+    fn or(&mut self, a: BddRef, b: BddRef) -> Option<BddRef> {
+        self.apply(BddOp::Or, a, b)
+    }
+
+    fn xor(&mut self, a: BddRef, b: BddRef) -> Option<BddRef> {
+        self.apply(BddOp::Xor, a, b)
+    }
+
+    fn not(&mut self, a: BddRef) -> Option<BddRef> {
+        self.apply(BddOp::Xor, a, BDD_TRUE)
+    }
+
+    fn mux(&mut self, sel: BddRef, if_true: BddRef, if_false: BddRef) -> Option<BddRef> {
+        let not_sel = self.not(sel)?;
+        let a = self.and(sel, if_true)?;
+        let b = self.and(not_sel, if_false)?;
+        self.or(a, b)
+    }
+
+    // Returns values for the variables along one path that makes `f` true. `f` must not be false.
+    fn satisfying_assignment(&self, mut f: BddRef) -> Vec<(String, bool)> {
+        let mut ret = Vec::new();
+        while f != BDD_TRUE {
+            let node = self.nodes[f as usize];
+            let value = node.hi != BDD_FALSE;
+            ret.push((self.var_names[node.var as usize].clone(), value));
+            f = if value { node.hi } else { node.lo };
+        }
+
+        ret
+    }
+
+    // Variables that aren't in the assignment are taken to be false
+    fn eval(&self, mut f: BddRef, assignment: &[(String, bool)]) -> bool {
+        while f > BDD_TRUE {
+            let node = self.nodes[f as usize];
+            let name = &self.var_names[node.var as usize];
+            let value = assignment.iter().any(|x| &x.0 == name && x.1);
+            f = if value { node.hi } else { node.lo };
+        }
+
+        f == BDD_TRUE
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum EquivalenceCheckItem {
+    /// The value driven onto an output pin (while it is enabled)
+    Output(String),
+    OutputEnable(String),
+    /// The value that a register takes on at the next clock
+    RegisterInput(String),
+    /// The clock of a register, after its polarity is applied
+    RegisterClock(String),
+    /// Whether a register is triggered on both clock edges
+    RegisterDualEdge(String),
+    RegisterSet(String),
+    RegisterReset(String),
+    RegisterInitState(String),
+}
+
+impl EquivalenceCheckItem {
+    // Whether this is a fixed setting rather than a function of the inputs
+    fn is_setting(&self) -> bool {
+        matches!(self, EquivalenceCheckItem::RegisterDualEdge(_) | EquivalenceCheckItem::RegisterInitState(_))
+    }
+}
+
+impl fmt::Display for EquivalenceCheckItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EquivalenceCheckItem::Output(name) => write!(f, "output \"{}\"", name),
+            EquivalenceCheckItem::OutputEnable(name) => write!(f, "output enable of \"{}\"", name),
+            EquivalenceCheckItem::RegisterInput(name) => write!(f, "next state of register \"{}\"", name),
+            EquivalenceCheckItem::RegisterClock(name) => write!(f, "clock of register \"{}\"", name),
+            EquivalenceCheckItem::RegisterDualEdge(name) => write!(f, "dual edge clocking of register \"{}\"", name),
+            EquivalenceCheckItem::RegisterSet(name) => write!(f, "set of register \"{}\"", name),
+            EquivalenceCheckItem::RegisterReset(name) => write!(f, "reset of register \"{}\"", name),
+            EquivalenceCheckItem::RegisterInitState(name) => write!(f, "initial state of register \"{}\"", name),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct EquivalenceMismatch {
+    pub item: EquivalenceCheckItem,
+    /// Values of the input pins and register outputs that show the difference. Anything not listed is 0. This is
+    /// empty for settings that don't depend on the inputs.
+    pub counterexample: Vec<(String, bool)>,
+    pub netlist_value: bool,
+    pub bitstream_value: bool,
+}
+
+impl fmt::Display for EquivalenceMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} is {} in the netlist but {} in the bitstream", self.item,
+            self.netlist_value as u8, self.bitstream_value as u8)?;
+        if self.item.is_setting() {
+            return Ok(());
+        }
+        write!(f, " when ")?;
+        if self.counterexample.is_empty() {
+            write!(f, "all inputs are 0")
+        } else {
+            let values = self.counterexample.iter().map(|(name, value)| format!("{}={}", name, *value as u8));
+            write!(f, "{}", values.collect::<Vec<_>>().join(" "))
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+pub struct EquivalenceReport {
+    /// Number of functions that were proven to be equivalent
+    pub equivalent: usize,
+    pub mismatches: Vec<EquivalenceMismatch>,
+    /// Things in the netlist that couldn't be found in the placed design
+    pub unmatched: Vec<EquivalenceCheckItem>,
+    /// Things that needed too much memory to check
+    pub too_complex: Vec<EquivalenceCheckItem>,
+}
+
+impl EquivalenceReport {
+    /// Whether everything was checked and found to match. Anything that couldn't be matched up or was too complex to
+    /// check counts against this.
+    pub fn is_equivalent(&self) -> bool {
+        self.mismatches.is_empty() && self.unmatched.is_empty() && self.too_complex.is_empty()
+    }
+}
+
+// The functions in the IntermediateGraph
+struct NetlistFunctions<'a> {
+    g: &'a IntermediateGraph,
+    memo: HashMap<ObjPoolIndex<IntermediateGraphNet>, BddRef>,
+    visiting: HashSet<ObjPoolIndex<IntermediateGraphNet>>,
+}
+
+impl<'a> NetlistFunctions<'a> {
+    fn net_name(&self, net: ObjPoolIndex<IntermediateGraphNet>) -> String {
+        let net_obj = self.g.nets.get(net);
+        net_obj.name.clone()
+            .or_else(|| net_obj.source.map(|x| self.g.nodes.get(x).name.clone()))
+            .unwrap_or_else(|| format!("net {}", net.get_raw_i()))
+    }
+
+    fn eval(&mut self, bdd: &mut Bdd, net: ObjPoolIndex<IntermediateGraphNet>) -> Option<BddRef> {
+        if net == self.g.vdd_net {
+            return Some(BDD_TRUE);
+        }
+        if net == self.g.vss_net {
+            return Some(BDD_FALSE);
+        }
+        if let Some(&x) = self.memo.get(&net) {
+            return Some(x);
+        }
+        let source = if let Some(x) = self.g.nets.get(net).source { x } else {
+            return bdd.var(&self.net_name(net));
+        };
+        if !self.visiting.insert(net) {
+            // A combinational loop, which can only be compared as an opaque signal
+            return bdd.var(&self.net_name(net));
+        }
+
+        let ret = match self.g.nodes.get(source).variant {
+            IntermediateGraphNodeVariant::AndTerm{ref inputs_true, ref inputs_comp, ..} => {
+                let mut x = BDD_TRUE;
+                for &input in inputs_true {
+                    let y = self.eval(bdd, input)?;
+                    x = bdd.and(x, y)?;
+                }
+                for &input in inputs_comp {
+                    let y = self.eval(bdd, input)?;
+                    let y = bdd.not(y)?;
+                    x = bdd.and(x, y)?;
+                }
+                x
+            },
+            IntermediateGraphNodeVariant::OrTerm{ref inputs, ..} => {
+                let mut x = BDD_FALSE;
+                for &input in inputs {
+                    let y = self.eval(bdd, input)?;
+                    x = bdd.or(x, y)?;
+                }
+                x
+            },
+            IntermediateGraphNodeVariant::Xor{orterm_input, andterm_input, invert_out, ..} => {
+                let a = orterm_input.map_or(Some(BDD_FALSE), |x| self.eval(bdd, x))?;
+                let b = andterm_input.map_or(Some(BDD_FALSE), |x| self.eval(bdd, x))?;
+                let x = bdd.xor(a, b)?;
+                if invert_out { bdd.not(x)? } else { x }
+            },
+            IntermediateGraphNodeVariant::BufgClk{input, ..} => {
+                self.eval(bdd, input)?
+            },
+            IntermediateGraphNodeVariant::ClkDiv{input, div_ratio, delay, ..} => {
+                let name = clock_div_name(&self.net_name(input), div_ratio, delay);
+                bdd.var(&name)?
+            },
+            IntermediateGraphNodeVariant::BufgGTS{input, invert, ..} |
+            IntermediateGraphNodeVariant::BufgGSR{input, invert, ..} => {
+                let x = self.eval(bdd, input)?;
+                if invert { bdd.not(x)? } else { x }
+            },
+            // Pins and registers are the variables
+            _ => bdd.var(&self.net_name(net))?,
+        };
+
+        self.visiting.remove(&net);
+        self.memo.insert(net, ret);
+        Some(ret)
+    }
+}
+
+// The functions in the bitstream, along with the names of what was placed where
+struct BitstreamFunctions<'a> {
+    device: XC2Device,
+    bits: &'a XC2BitstreamBits,
+    pad_names: HashMap<(u32, u32), String>,
+    reg_names: HashMap<(u32, u32), String>,
+    xor_memo: HashMap<(u32, u32), BddRef>,
+    visiting: HashSet<(u32, u32)>,
+}
+
+impl<'a> BitstreamFunctions<'a> {
+    fn pad(&self, bdd: &mut Bdd, fb: u32, mc: u32) -> Option<BddRef> {
+        match self.pad_names.get(&(fb, mc)) {
+            Some(name) => bdd.var(name),
+            None => bdd.var(&format!("FB{}_{} pad", fb + 1, mc + 1)),
+        }
+    }
+
+    fn reg(&self, bdd: &mut Bdd, fb: u32, mc: u32) -> Option<BddRef> {
+        match self.reg_names.get(&(fb, mc)) {
+            Some(name) => bdd.var(name),
+            None => bdd.var(&format!("FB{}_{} register", fb + 1, mc + 1)),
+        }
+    }
+
+    fn macrocell(&self, fb: u32, mc: u32) -> &'a XC2Macrocell {
+        &self.bits.get_fb()[fb as usize].mcs[mc as usize]
+    }
+
+    // (ZIA mode, output buffer mode, whether the output comes from the register)
+    fn iob_modes(&self, iob: u32) -> (XC2IOBZIAMode, XC2IOBOBufMode, bool) {
+        if let Some(x) = self.bits.get_small_iob(iob as usize) {
+            (x.zia_mode, x.obuf_mode, x.obuf_uses_ff)
+        } else {
+            let x = self.bits.get_large_iob(iob as usize).unwrap();
+            (x.zia_mode, x.obuf_mode, x.obuf_uses_ff)
+        }
+    }
+
+    fn zia(&mut self, bdd: &mut Bdd, fb: u32, row: usize) -> Option<BddRef> {
+        match *self.bits.get_fb()[fb as usize].get_zia(row) {
+            XC2ZIAInput::Macrocell{fb: src_fb, mc: src_mc} => {
+                let (src_fb, src_mc) = (src_fb as u32, src_mc as u32);
+                match self.macrocell(src_fb, src_mc).fb_mode {
+                    XC2MCFeedbackMode::COMB => self.xor_out(bdd, src_fb, src_mc),
+                    XC2MCFeedbackMode::REG => self.reg(bdd, src_fb, src_mc),
+                    XC2MCFeedbackMode::Disabled => bdd.var(&format!("FB{}_{} feedback", src_fb + 1, src_mc + 1)),
+                }
+            },
+            XC2ZIAInput::IBuf{ibuf} => {
+                let (src_fb, src_mc) = iob_num_to_fb_mc_num(self.device, ibuf as u32).unwrap();
+                match self.iob_modes(ibuf as u32).0 {
+                    XC2IOBZIAMode::PAD => self.pad(bdd, src_fb, src_mc),
+                    XC2IOBZIAMode::REG => self.reg(bdd, src_fb, src_mc),
+                    XC2IOBZIAMode::Disabled => bdd.var(&format!("FB{}_{} input", src_fb + 1, src_mc + 1)),
+                }
+            },
+            XC2ZIAInput::DedicatedInput => self.pad(bdd, self.device.num_fbs() as u32, 0),
+            XC2ZIAInput::Zero => Some(BDD_FALSE),
+            XC2ZIAInput::One => Some(BDD_TRUE),
+        }
+    }
+
+    fn andterm(&mut self, bdd: &mut Bdd, fb: u32, i: u32) -> Option<BddRef> {
+        let andterm = *self.bits.get_fb()[fb as usize].get_andterm(i as usize);
+
+        let mut x = BDD_TRUE;
+        for row in 0..INPUTS_PER_ANDTERM {
+            if andterm.get(row) {
+                let y = self.zia(bdd, fb, row)?;
+                x = bdd.and(x, y)?;
+            }
+            if andterm.get_b(row) {
+                let y = self.zia(bdd, fb, row)?;
+                let y = bdd.not(y)?;
+                x = bdd.and(x, y)?;
+            }
+        }
+        Some(x)
+    }
+
+    fn xor_out(&mut self, bdd: &mut Bdd, fb: u32, mc: u32) -> Option<BddRef> {
+        if let Some(&x) = self.xor_memo.get(&(fb, mc)) {
+            return Some(x);
+        }
+        if !self.visiting.insert((fb, mc)) {
+            return bdd.var(&format!("FB{}_{} XOR", fb + 1, mc + 1));
+        }
+
+        let or_term = self.bits.get_fb()[fb as usize].or_terms[mc as usize];
+        let mut x = BDD_FALSE;
+        for i in 0..ANDTERMS_PER_FB {
+            if or_term.get(i) {
+                let y = self.andterm(bdd, fb, i as u32)?;
+                x = bdd.or(x, y)?;
+            }
+        }
+        let x = match self.macrocell(fb, mc).xor_mode {
+            XC2MCXorMode::ZERO => x,
+            XC2MCXorMode::ONE => bdd.not(x)?,
+            XC2MCXorMode::PTC => {
+                let ptc = self.andterm(bdd, fb, get_ptc(mc))?;
+                bdd.xor(x, ptc)?
+            },
+            XC2MCXorMode::PTCB => {
+                let ptc = self.andterm(bdd, fb, get_ptc(mc))?;
+                let y = bdd.xor(x, ptc)?;
+                bdd.not(y)?
+            },
+        };
+
+        self.visiting.remove(&(fb, mc));
+        self.xor_memo.insert((fb, mc), x);
+        Some(x)
+    }
+
+    fn reg_next(&mut self, bdd: &mut Bdd, fb: u32, mc: u32) -> Option<BddRef> {
+        let mc_bits = self.macrocell(fb, mc);
+        let d = if mc_bits.ff_in_ibuf { self.pad(bdd, fb, mc)? } else { self.xor_out(bdd, fb, mc)? };
+        let q = self.reg(bdd, fb, mc)?;
+        match mc_bits.reg_mode {
+            XC2MCRegMode::DFF | XC2MCRegMode::LATCH => Some(d),
+            XC2MCRegMode::TFF => bdd.xor(q, d),
+            XC2MCRegMode::DFFCE => {
+                let ce = self.andterm(bdd, fb, get_ptc(mc))?;
+                bdd.mux(ce, d, q)
+            },
+        }
+    }
+
+    fn reg_clock(&mut self, bdd: &mut Bdd, fb: u32, mc: u32) -> Option<BddRef> {
+        let mc_bits = self.macrocell(fb, mc);
+        let gck = |i: usize, this: &Self, bdd: &mut Bdd| -> Option<BddRef> {
+            let (gck_fb, gck_mc) = get_gck(this.device, i).unwrap();
+            this.pad(bdd, gck_fb, gck_mc)
+        };
+        let clk = match mc_bits.clk_src {
+            XC2MCRegClkSrc::GCK0 => gck(0, self, bdd)?,
+            XC2MCRegClkSrc::GCK1 => gck(1, self, bdd)?,
+            XC2MCRegClkSrc::GCK2 => match self.bits.get_clock_div() {
+                Some(div) if div.enabled => {
+                    let (gck_fb, gck_mc) = get_gck(self.device, 2).unwrap();
+                    let pad_name = self.pad_names.get(&(gck_fb, gck_mc)).cloned()
+                        .unwrap_or_else(|| format!("FB{}_{} pad", gck_fb + 1, gck_mc + 1));
+                    bdd.var(&clock_div_name(&pad_name, div.div_ratio, div.delay))?
+                },
+                _ => gck(2, self, bdd)?,
+            },
+            XC2MCRegClkSrc::PTC => self.andterm(bdd, fb, get_ptc(mc))?,
+            XC2MCRegClkSrc::CTC => self.andterm(bdd, fb, CTC)?,
+        };
+        if mc_bits.clk_invert_pol { bdd.not(clk) } else { Some(clk) }
+    }
+
+    fn gsr(&self, bdd: &mut Bdd) -> Option<BddRef> {
+        let (gsr_fb, gsr_mc) = get_gsr(self.device);
+        let x = self.pad(bdd, gsr_fb, gsr_mc)?;
+        if self.bits.get_global_nets().gsr_invert { bdd.not(x) } else { Some(x) }
+    }
+
+    fn reg_set(&mut self, bdd: &mut Bdd, fb: u32, mc: u32) -> Option<BddRef> {
+        match self.macrocell(fb, mc).s_src {
+            XC2MCRegSetSrc::Disabled => Some(BDD_FALSE),
+            XC2MCRegSetSrc::PTA => self.andterm(bdd, fb, get_pta(mc)),
+            XC2MCRegSetSrc::GSR => self.gsr(bdd),
+            XC2MCRegSetSrc::CTS => self.andterm(bdd, fb, CTS),
+        }
+    }
+
+    fn reg_reset(&mut self, bdd: &mut Bdd, fb: u32, mc: u32) -> Option<BddRef> {
+        match self.macrocell(fb, mc).r_src {
+            XC2MCRegResetSrc::Disabled => Some(BDD_FALSE),
+            XC2MCRegResetSrc::PTA => self.andterm(bdd, fb, get_pta(mc)),
+            XC2MCRegResetSrc::GSR => self.gsr(bdd),
+            XC2MCRegResetSrc::CTR => self.andterm(bdd, fb, CTR),
+        }
+    }
+
+    // Returns (enable, value) for an output pin, or `None` inside if the output buffer is disabled
+    fn output(&mut self, bdd: &mut Bdd, fb: u32, mc: u32) -> Option<Option<(BddRef, BddRef)>> {
+        let iob = if let Some(x) = fb_mc_num_to_iob_num(self.device, fb, mc) { x } else { return Some(None) };
+        let (_, obuf_mode, obuf_uses_ff) = self.iob_modes(iob);
+        if obuf_mode == XC2IOBOBufMode::Disabled {
+            return Some(None);
+        }
+
+        let data = if obuf_uses_ff { self.reg(bdd, fb, mc)? } else { self.xor_out(bdd, fb, mc)? };
+        let gts = |i: usize, this: &Self, bdd: &mut Bdd| -> Option<BddRef> {
+            let (gts_fb, gts_mc) = get_gts(this.device, i).unwrap();
+            let x = this.pad(bdd, gts_fb, gts_mc)?;
+            if this.bits.get_global_nets().gts_invert[i] { bdd.not(x) } else { Some(x) }
+        };
+        let (enable, value) = match obuf_mode {
+            XC2IOBOBufMode::Disabled => unreachable!(),
+            XC2IOBOBufMode::PushPull => (BDD_TRUE, data),
+            // Pulled low when the data is 1
+            XC2IOBOBufMode::OpenDrain => (data, BDD_FALSE),
+            XC2IOBOBufMode::CGND => (BDD_TRUE, BDD_FALSE),
+            XC2IOBOBufMode::TriStatePTB => (self.andterm(bdd, fb, get_ptb(mc))?, data),
+            XC2IOBOBufMode::TriStateCTE => (self.andterm(bdd, fb, CTE)?, data),
+            XC2IOBOBufMode::TriStateGTS0 => (gts(0, self, bdd)?, data),
+            XC2IOBOBufMode::TriStateGTS1 => (gts(1, self, bdd)?, data),
+            XC2IOBOBufMode::TriStateGTS2 => (gts(2, self, bdd)?, data),
+            XC2IOBOBufMode::TriStateGTS3 => (gts(3, self, bdd)?, data),
+        };
+        Some(Some((enable, value)))
+    }
+}
+
+fn compare_functions(item: EquivalenceCheckItem, bdd: &mut Bdd, expected: Option<BddRef>, actual: Option<BddRef>,
+    report: &mut EquivalenceReport, logger: &slog::Logger) {
+
+    let diff = match (expected, actual) {
+        (Some(expected), Some(actual)) => bdd.xor(expected, actual).map(|x| (expected, x)),
+        _ => None,
+    };
+
+    match diff {
+        None => {
+            warn!(logger, "equivalence - too complex to check";
+                "code" => "EQ003",
+                "item" => format!("{}", item));
+            report.too_complex.push(item);
+        },
+        Some((_, BDD_FALSE)) => {
+            debug!(logger, "equivalence - equivalent"; "item" => format!("{}", item));
+            report.equivalent += 1;
+        },
+        Some((expected, diff)) => {
+            let counterexample = bdd.satisfying_assignment(diff);
+            let netlist_value = bdd.eval(expected, &counterexample);
+            let mismatch = EquivalenceMismatch {
+                item,
+                counterexample,
+                netlist_value,
+                bitstream_value: !netlist_value,
+            };
+            error!(logger, "equivalence - bitstream does not match netlist";
+                "code" => "EQ001",
+                "mismatch" => format!("{}", mismatch));
+            report.mismatches.push(mismatch);
+        },
+    }
+}
+
+fn compare_settings(item: EquivalenceCheckItem, expected: bool, actual: bool, report: &mut EquivalenceReport,
+    logger: &slog::Logger) {
+
+    if expected == actual {
+        debug!(logger, "equivalence - equivalent"; "item" => format!("{}", item));
+        report.equivalent += 1;
+    } else {
+        let mismatch = EquivalenceMismatch {
+            item,
+            counterexample: Vec::new(),
+            netlist_value: expected,
+            bitstream_value: actual,
+        };
+        error!(logger, "equivalence - bitstream does not match netlist";
+            "code" => "EQ001",
+            "mismatch" => format!("{}", mismatch));
+        report.mismatches.push(mismatch);
+    }
+}
+
+// The clock divider is opaque, so both sides call its output by the pin it comes from and its settings
+fn clock_div_name(pad_name: &str, div_ratio: XC2ClockDivRatio, delay: bool) -> String {
+    format!("{} {:?}{}", pad_name, div_ratio, if delay { " delayed" } else { "" })
+}
+
+fn report_unmatched(item: EquivalenceCheckItem, report: &mut EquivalenceReport, logger: &slog::Logger) {
+    warn!(logger, "equivalence - not found in the placed design";
+        "code" => "EQ002",
+        "item" => format!("{}", item));
+    report.unmatched.push(item);
+}
+
+// Name of the signal that is driven out of an output pin, which is what the pin is called on the placed macrocell
+fn output_name(intermed: &IntermediateGraph, node: &IntermediateGraphNode) -> String {
+    let (input, oe, output) = match node.variant {
+        IntermediateGraphNodeVariant::IOBuf{input: Some(input), oe, output, ..} => (input, oe, output),
+        _ => unreachable!(),
+    };
+    // An open drain output is driven by its output enable
+    let data = match oe {
+        Some(oe) if input == intermed.vss_net => oe,
+        _ => input,
+    };
+    let net_name = if data == intermed.vdd_net || data == intermed.vss_net {
+        output.and_then(|x| intermed.nets.get(x).name.clone())
+    } else {
+        intermed.nets.get(data).name.clone()
+    };
+
+    net_name.unwrap_or_else(|| node.name.clone())
+}
+
+// Finds the macrocell that a node in the netlist was placed into, by the name of the net that it drives if possible.
+// Otherwise, the name of the node itself is used, since it becomes part of the name of the macrocell.
+fn find_macrocell<F, G>(g: &InputGraph, node_name: &str, is_candidate: F, matches_net: G)
+    -> Option<ObjPoolIndex<InputGraphMacrocell>>
+    where F: Fn(&InputGraphMacrocell) -> bool, G: Fn(&InputGraphMacrocell) -> bool {
+
+    let candidates = g.mcs.iter_idx().filter(|&x| is_candidate(g.mcs.get(x))).collect::<Vec<_>>();
+    let by_net = candidates.iter().filter(|&&x| matches_net(g.mcs.get(x))).collect::<Vec<_>>();
+    if by_net.len() == 1 {
+        return Some(*by_net[0]);
+    }
+
+    let component = format!("_{}_", node_name);
+    let by_node = candidates.iter().filter(|&&x| format!("_{}_", g.mcs.get(x).name).contains(&component))
+        .collect::<Vec<_>>();
+    if by_node.len() == 1 {
+        Some(*by_node[0])
+    } else {
+        None
+    }
+}
+
+/// Checks that the logic in a bitstream matches the netlist it was produced from. `g` and `go` are only used to find
+/// out which signal was placed where.
+pub fn check_equivalence<L>(intermed: &IntermediateGraph, g: &InputGraph, go: &OutputGraph, bitstream: &XC2Bitstream,
+    logger: L) -> EquivalenceReport where L: Into<Option<slog::Logger>> {
+
+    let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+
+    let mut netlist = NetlistFunctions {
+        g: intermed,
+        memo: HashMap::new(),
+        visiting: HashSet::new(),
+    };
+
+    // The variables on the bitstream side are named after the netlist nodes that were placed there
+    let site_of = |mc_idx: ObjPoolIndex<InputGraphMacrocell>| {
+        let loc = go.mcs.get(ObjPoolIndex::from(mc_idx)).loc.unwrap();
+        (loc.fb, loc.i)
+    };
+    let mut pad_names = HashMap::new();
+    let mut reg_names = HashMap::new();
+    let mut output_sites = HashMap::new();
+    let mut reg_sites = HashMap::new();
+    for node_idx in intermed.nodes.iter_idx() {
+        let node = intermed.nodes.get(node_idx);
+        match node.variant {
+            IntermediateGraphNodeVariant::InBuf{output, ..} |
+            IntermediateGraphNodeVariant::IOBuf{output: Some(output), ..} => {
+                let name = netlist.net_name(output);
+                let net_name = intermed.nets.get(output).name.as_deref();
+                if let Some(mc_idx) = find_macrocell(g, &node.name, |mc| mc.io_bits.is_some(),
+                    |mc| net_name.is_some() && mc.net_names.pin.as_deref() == net_name) {
+
+                    pad_names.insert(site_of(mc_idx), name);
+                }
+            },
+            _ => {},
+        }
+        match node.variant {
+            IntermediateGraphNodeVariant::IOBuf{input: Some(_), ..} => {
+                let name = output_name(intermed, node);
+                if let Some(mc_idx) = find_macrocell(g, &node.name,
+                    |mc| mc.io_bits.as_ref().is_some_and(|x| x.input.is_some()),
+                    |mc| mc.pad_name() == name) {
+
+                    output_sites.insert(node_idx, site_of(mc_idx));
+                }
+            },
+            IntermediateGraphNodeVariant::Reg{dt_input, output, ..} => {
+                // A register that was moved into an output pin takes on the name of the pin, but keeps the name of
+                // its input
+                let name = netlist.net_name(output);
+                let dt_name = intermed.nets.get(dt_input).name.as_deref();
+                if let Some(mc_idx) = find_macrocell(g, &node.name, |mc| mc.reg_bits.is_some(),
+                    |mc| mc.net_names.reg.as_ref() == Some(&name) ||
+                        dt_name.is_some() && mc.net_names.xor.as_deref() == dt_name) {
+
+                    reg_names.insert(site_of(mc_idx), name);
+                    reg_sites.insert(node_idx, site_of(mc_idx));
+                }
+            },
+            _ => {},
+        }
+    }
+
+    let mut bdd = Bdd::new();
+    let mut bitstream = BitstreamFunctions {
+        device: bitstream.bits.device_type(),
+        bits: &bitstream.bits,
+        pad_names,
+        reg_names,
+        xor_memo: HashMap::new(),
+        visiting: HashSet::new(),
+    };
+
+    let mut report = EquivalenceReport::default();
+    // Bail out of everything once the node limit is hit, since nothing else will fit either
+    let mut out_of_memory = false;
+    for node_idx in intermed.nodes.iter_idx() {
+        let node = intermed.nodes.get(node_idx);
+        match node.variant {
+            IntermediateGraphNodeVariant::IOBuf{input: Some(input), oe, ..} => {
+                let name = output_name(intermed, node);
+
+                let site = output_sites.get(&node_idx).copied();
+                let actual = site.and_then(|(fb, mc)| if out_of_memory { None } else {
+                    bitstream.output(&mut bdd, fb, mc)
+                });
+                let actual = match actual {
+                    Some(Some(x)) => Some(x),
+                    Some(None) => {
+                        report_unmatched(EquivalenceCheckItem::Output(name), &mut report, &logger);
+                        continue;
+                    },
+                    None if site.is_none() => {
+                        report_unmatched(EquivalenceCheckItem::Output(name), &mut report, &logger);
+                        continue;
+                    },
+                    None => None,
+                };
+
+                let expected_enable = if out_of_memory { None } else {
+                    oe.map_or(Some(BDD_TRUE), |x| netlist.eval(&mut bdd, x))
+                };
+                let expected_value = expected_enable.and_then(|_| netlist.eval(&mut bdd, input));
+                let gated = |bdd: &mut Bdd, x: Option<(BddRef, BddRef)>| x.and_then(|(en, val)| bdd.and(en, val));
+                let expected = expected_enable.zip(expected_value);
+                let expected_gated = gated(&mut bdd, expected);
+                let actual_gated = gated(&mut bdd, actual);
+                compare_functions(EquivalenceCheckItem::OutputEnable(name.clone()), &mut bdd,
+                    expected.map(|x| x.0), actual.map(|x| x.0), &mut report, &logger);
+                compare_functions(EquivalenceCheckItem::Output(name), &mut bdd, expected_gated, actual_gated,
+                    &mut report, &logger);
+                out_of_memory |= actual.is_none() || expected.is_none();
+            },
+            IntermediateGraphNodeVariant::Reg{mode, clkinv, clkddr, init_state, set_input, reset_input, ce_input,
+                dt_input, clk_input, output} => {
+
+                let name = netlist.net_name(output);
+                let (fb, mc) = if let Some(&x) = reg_sites.get(&node_idx) { x } else {
+                    report_unmatched(EquivalenceCheckItem::RegisterInput(name), &mut report, &logger);
+                    continue;
+                };
+
+                let mc_bits = bitstream.macrocell(fb, mc);
+                compare_settings(EquivalenceCheckItem::RegisterDualEdge(name.clone()), clkddr, mc_bits.is_ddr,
+                    &mut report, &logger);
+                compare_settings(EquivalenceCheckItem::RegisterInitState(name.clone()), init_state,
+                    mc_bits.init_state, &mut report, &logger);
+
+                let expected = if out_of_memory { None } else {
+                    netlist.eval(&mut bdd, clk_input).and_then(|x| if clkinv { bdd.not(x) } else { Some(x) })
+                };
+                let actual = if out_of_memory { None } else { bitstream.reg_clock(&mut bdd, fb, mc) };
+                compare_functions(EquivalenceCheckItem::RegisterClock(name.clone()), &mut bdd, expected, actual,
+                    &mut report, &logger);
+                out_of_memory |= actual.is_none() || expected.is_none();
+
+                let expected = if out_of_memory { None } else {
+                    set_input.map_or(Some(BDD_FALSE), |x| netlist.eval(&mut bdd, x))
+                };
+                let actual = if out_of_memory { None } else { bitstream.reg_set(&mut bdd, fb, mc) };
+                compare_functions(EquivalenceCheckItem::RegisterSet(name.clone()), &mut bdd, expected, actual,
+                    &mut report, &logger);
+                out_of_memory |= actual.is_none() || expected.is_none();
+
+                let expected = if out_of_memory { None } else {
+                    reset_input.map_or(Some(BDD_FALSE), |x| netlist.eval(&mut bdd, x))
+                };
+                let actual = if out_of_memory { None } else { bitstream.reg_reset(&mut bdd, fb, mc) };
+                compare_functions(EquivalenceCheckItem::RegisterReset(name.clone()), &mut bdd, expected, actual,
+                    &mut report, &logger);
+                out_of_memory |= actual.is_none() || expected.is_none();
+
+                let expected = (|| {
+                    if out_of_memory {
+                        return None;
+                    }
+                    let q = bdd.var(&name)?;
+                    let d = netlist.eval(&mut bdd, dt_input)?;
+                    let next = match mode {
+                        XC2MCRegMode::DFF | XC2MCRegMode::LATCH | XC2MCRegMode::DFFCE => d,
+                        XC2MCRegMode::TFF => bdd.xor(q, d)?,
+                    };
+                    match ce_input {
+                        Some(ce) => {
+                            let ce = netlist.eval(&mut bdd, ce)?;
+                            bdd.mux(ce, next, q)
+                        },
+                        None => Some(next),
+                    }
+                })();
+                let actual = if out_of_memory { None } else { bitstream.reg_next(&mut bdd, fb, mc) };
+                compare_functions(EquivalenceCheckItem::RegisterInput(name), &mut bdd, expected, actual, &mut report,
+                    &logger);
+                out_of_memory |= actual.is_none() || expected.is_none();
+            },
+            _ => {},
+        }
+    }
+
+    info!(logger, "equivalence - done";
+        "equivalent" => report.equivalent,
+        "mismatches" => report.mismatches.len(),
+        "unmatched" => report.unmatched.len(),
+        "too complex" => report.too_complex.len());
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fit(input: &str) -> (IntermediateGraph, InputGraph, OutputGraph, XC2Bitstream) {
+        let intermed = IntermediateGraph::from_equations(input.as_bytes(), None).unwrap();
+        let mut g = InputGraph::from_intermed_graph(&intermed, None).unwrap();
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        let go = match do_par(&mut g, device_type, &XC2ParOptions::new(), None) {
+            PARResult::Success(x) => x,
+            _ => panic!("design did not fit"),
+        };
+        let bitstream = produce_bitstream(device_type, &g, &go);
+        (intermed, g, go, bitstream)
+    }
+
+    #[test]
+    fn equivalence_counter() {
+        let input = r#"
+            q0 := !q0;
+            q1.T = q0 & en;
+            q0.CLK = clk; q1.CLK = clk;
+            q1.AR = a & b;
+            y = q1 & !a # a & b;
+            z = y $ q0;
+            z.OE = oe;
+        "#;
+        let (intermed, g, go, mut bitstream) = fit(input);
+
+        let report = check_equivalence(&intermed, &g, &go, &bitstream, None);
+        assert!(report.is_equivalent(), "{:?}", report.mismatches);
+        assert!(report.unmatched.is_empty(), "{:?}", report.unmatched);
+        // Four outputs (the registers are also pins) with their enables, plus the next state, clock, dual edge
+        // setting, set, reset, and initial state of two registers
+        assert_eq!(report.equivalent, 20);
+
+        // Break the output by flipping its XOR
+        let y_idx = g.mcs.iter_idx().find(|&x| g.mcs.get(x).pad_name() == "y").unwrap();
+        let y_loc = go.mcs.get(ObjPoolIndex::from(y_idx)).loc.unwrap();
+        let xor_mode = &mut bitstream.bits.get_fb_mut()[y_loc.fb as usize].mcs[y_loc.i as usize].xor_mode;
+        *xor_mode = if *xor_mode == XC2MCXorMode::ZERO { XC2MCXorMode::ONE } else { XC2MCXorMode::ZERO };
+
+        let report = check_equivalence(&intermed, &g, &go, &bitstream, None);
+        let mismatch = report.mismatches.iter().find(|x| x.item == EquivalenceCheckItem::Output("y".to_owned()));
+        let mismatch = mismatch.unwrap();
+        assert_ne!(mismatch.netlist_value, mismatch.bitstream_value);
+
+        // The counterexample has to actually show the difference
+        let value = |name: &str| mismatch.counterexample.iter().any(|x| x.0 == name && x.1);
+        let y = value("q1") && !value("a") || value("a") && value("b");
+        assert_eq!(mismatch.netlist_value, y);
+        assert!(format!("{}", mismatch).starts_with("output \"y\" is "));
+
+        // Break the registers by inverting the clock of q0 and the initial state of q1
+        let reg_loc = |name: &str| {
+            let idx = g.mcs.iter_idx().find(|&x| g.mcs.get(x).pad_name() == name).unwrap();
+            go.mcs.get(ObjPoolIndex::from(idx)).loc.unwrap()
+        };
+        let (q0_loc, q1_loc) = (reg_loc("q0"), reg_loc("q1"));
+        bitstream.bits.get_fb_mut()[q0_loc.fb as usize].mcs[q0_loc.i as usize].clk_invert_pol ^= true;
+        bitstream.bits.get_fb_mut()[q1_loc.fb as usize].mcs[q1_loc.i as usize].init_state ^= true;
+        let q1_reset = &mut bitstream.bits.get_fb_mut()[q1_loc.fb as usize].mcs[q1_loc.i as usize].r_src;
+        assert_eq!(*q1_reset, XC2MCRegResetSrc::PTA);
+        *q1_reset = XC2MCRegResetSrc::Disabled;
+
+        let report = check_equivalence(&intermed, &g, &go, &bitstream, None);
+        let mismatch = |item: EquivalenceCheckItem| report.mismatches.iter().find(|x| x.item == item).unwrap();
+        let clk = mismatch(EquivalenceCheckItem::RegisterClock("q0".to_owned()));
+        assert_eq!(clk.netlist_value, clk.counterexample.iter().any(|x| x.0 == "clk" && x.1));
+        let init = mismatch(EquivalenceCheckItem::RegisterInitState("q1".to_owned()));
+        assert_eq!(format!("{}", init), "initial state of register \"q1\" is 0 in the netlist but 1 in the bitstream");
+        let reset = mismatch(EquivalenceCheckItem::RegisterReset("q1".to_owned()));
+        assert!(reset.netlist_value && !reset.bitstream_value);
+    }
+
+    #[test]
+    fn equivalence_requires_everything_checked() {
+        let mut report = EquivalenceReport::default();
+        assert!(report.is_equivalent());
+        report.unmatched.push(EquivalenceCheckItem::RegisterInput("q".to_owned()));
+        assert!(!report.is_equivalent());
+        report.unmatched.clear();
+        report.too_complex.push(EquivalenceCheckItem::Output("y".to_owned()));
+        assert!(!report.is_equivalent());
+    }
+
+    #[test]
+    fn equivalence_packed_registers() {
+        // r is packed into the input pin a and s is packed into the output pin y
+        let input = r#"
+            NODE r; NODE s;
+            r := a; s := b & c;
+            r.CLK = clk; s.CLK = clk;
+            y = s;
+            z = r & d;
+        "#;
+        let (intermed, g, go, bitstream) = fit(input);
+
+        let report = check_equivalence(&intermed, &g, &go, &bitstream, None);
+        assert!(report.is_equivalent(), "{:?}", report.mismatches);
+        assert!(report.unmatched.is_empty(), "{:?}", report.unmatched);
+        assert_eq!(report.equivalent, 16);
+    }
+}
@@ -0,0 +1,645 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// This pass tries to explain to the user _why_ a design could not be fit into a device. It is only run after PAR has
+// already failed, so it can afford to recompute things that the main engine has already thrown away.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use serde_derive::{Deserialize, Serialize};
+
+use xc2bit::*;
+
+use crate::*;
+use crate::engine::{PARFBAssignment, PARStopCondition, collect_fb_inputs, collect_fb_pterms, collect_mc_pterms,
+    mcs_can_be_paired};
+use crate::objpool::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum PARGlobalBufferKind {
+    Clk,
+    GTS,
+    GSR,
+}
+
+impl fmt::Display for PARGlobalBufferKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PARGlobalBufferKind::Clk => write!(f, "BUFG"),
+            PARGlobalBufferKind::GTS => write!(f, "BUFGTS"),
+            PARGlobalBufferKind::GSR => write!(f, "BUFGSR"),
+        }
+    }
+}
+
+// Note that all FB and macrocell indices in here are 0-based (like everywhere else inside the PAR engine). They are
+// converted to the 1-based names used in LOC constraints only when displayed.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum PARBottleneck {
+    DeviceTooManyMacrocells {
+        needed: u32,
+        available: u32,
+    },
+    DeviceTooManyPTerms {
+        needed: u32,
+        available: u32,
+    },
+    DeviceTooManyGlobalBuffers {
+        kind: PARGlobalBufferKind,
+        needed: u32,
+        available: u32,
+        signals: Vec<String>,
+    },
+    PTCConflict {
+        signal: String,
+    },
+    GlobalBufferUnsatisfiable {
+        kind: PARGlobalBufferKind,
+        signal: String,
+        reason: String,
+    },
+    GlobalBufferConflict {
+        kind: PARGlobalBufferKind,
+        index: u32,
+        signals: Vec<String>,
+    },
+    IOBankConflict {
+        bank: Option<u32>,
+        signals: Vec<String>,
+    },
+    AreaGroupInvalid {
+        group: String,
+        num_fbs: u32,
+    },
+    AreaGroupLOCConflict {
+        group: String,
+        signal: String,
+        fb: u32,
+    },
+    AreaGroupOverfull {
+        groups: Vec<String>,
+        needed: u32,
+        available: u32,
+        signals: Vec<String>,
+    },
+    LOCInvalid {
+        signal: String,
+        fb: u32,
+        mc: Option<u32>,
+    },
+    LOCSiteConflict {
+        fb: u32,
+        mc: u32,
+        signals: Vec<String>,
+    },
+    LOCFBOverfull {
+        fb: u32,
+        needed: u32,
+        available: u32,
+        signals: Vec<String>,
+    },
+    MacrocellPairingConflict {
+        fb: u32,
+        mc: u32,
+        signals: Vec<String>,
+    },
+    FBTooManyPTerms {
+        fb: u32,
+        needed: u32,
+        available: u32,
+        signals: Vec<String>,
+    },
+    FBPTermLOCUnsatisfiable {
+        fb: u32,
+        signals: Vec<String>,
+    },
+    FBControlPTermConflict {
+        fb: u32,
+        signals: Vec<String>,
+    },
+    FBTooManyZIAInputs {
+        fb: u32,
+        needed: u32,
+        available: u32,
+        signals: Vec<String>,
+    },
+    FBZIAUnroutable {
+        fb: u32,
+        signals: Vec<String>,
+    },
+}
+
+impl fmt::Display for PARBottleneck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PARBottleneck::DeviceTooManyMacrocells{needed, available} => {
+                write!(f, "design needs {} macrocells but the device only has {}", needed, available)
+            },
+            PARBottleneck::DeviceTooManyPTerms{needed, available} => {
+                write!(f, "design needs {} unique p-terms but the device only has {}", needed, available)
+            },
+            PARBottleneck::DeviceTooManyGlobalBuffers{kind, needed, available, ref signals} => {
+                write!(f, "design needs {} {}s but the device only has {} - {}",
+                    needed, kind, available, signals.join(", "))
+            },
+            PARBottleneck::PTCConflict{ref signal} => {
+                write!(f, "{} uses both a clock enable and an XOR p-term, but they are different", signal)
+            },
+            PARBottleneck::GlobalBufferUnsatisfiable{kind, ref signal, ref reason} => {
+                write!(f, "{} for {} cannot be placed - {}", kind, signal, reason)
+            },
+            PARBottleneck::GlobalBufferConflict{kind, index, ref signals} => {
+                write!(f, "{} {} is requested by multiple signals - {}", kind, index, signals.join(", "))
+            },
+            PARBottleneck::IOBankConflict{bank, ref signals} => {
+                if let Some(bank) = bank {
+                    write!(f, "pins in bank {} need different I/O voltages - {}", bank, signals.join(", "))
+                } else {
+                    write!(f, "pins need different I/O voltages, but can't be put in separate banks - {}",
+                        signals.join(", "))
+                }
+            },
+            PARBottleneck::AreaGroupInvalid{ref group, num_fbs} => {
+                write!(f, "area group {} goes past the last FB of the device (FB{})", group, num_fbs)
+            },
+            PARBottleneck::AreaGroupLOCConflict{ref group, ref signal, fb} => {
+                write!(f, "{} is constrained to FB{}, which is outside of its area group {}", signal, fb + 1, group)
+            },
+            PARBottleneck::AreaGroupOverfull{ref groups, needed, available, ref signals} => {
+                if groups.len() == 1 {
+                    write!(f, "{} macrocells are in area group {} but it only has {} - {}",
+                        needed, groups[0], available, signals.join(", "))
+                } else {
+                    write!(f, "{} macrocells are in area groups {} but they only have {} together - {}",
+                        needed, groups.join(", "), available, signals.join(", "))
+                }
+            },
+            PARBottleneck::LOCInvalid{ref signal, fb, mc} => {
+                if let Some(mc) = mc {
+                    write!(f, "{} is constrained to nonexistent location FB{}_{}", signal, fb + 1, mc + 1)
+                } else {
+                    write!(f, "{} is constrained to nonexistent location FB{}", signal, fb + 1)
+                }
+            },
+            PARBottleneck::LOCSiteConflict{fb, mc, ref signals} => {
+                write!(f, "multiple signals are constrained to FB{}_{} - {}", fb + 1, mc + 1, signals.join(", "))
+            },
+            PARBottleneck::LOCFBOverfull{fb, needed, available, ref signals} => {
+                write!(f, "{} macrocells are constrained to FB{} but it only has {} - {}",
+                    needed, fb + 1, available, signals.join(", "))
+            },
+            PARBottleneck::MacrocellPairingConflict{fb, mc, ref signals} => {
+                write!(f, "signals cannot share the macrocell at FB{}_{} - {}", fb + 1, mc + 1, signals.join(", "))
+            },
+            PARBottleneck::FBTooManyPTerms{fb, needed, available, ref signals} => {
+                write!(f, "FB{} needs {} p-terms but only has {} - {}",
+                    fb + 1, needed, available, signals.join(", "))
+            },
+            PARBottleneck::FBPTermLOCUnsatisfiable{fb, ref signals} => {
+                write!(f, "p-term LOC constraints in FB{} cannot be satisfied - {}", fb + 1, signals.join(", "))
+            },
+            PARBottleneck::FBControlPTermConflict{fb, ref signals} => {
+                write!(f, "control p-terms in FB{} conflict with each other - {}", fb + 1, signals.join(", "))
+            },
+            PARBottleneck::FBTooManyZIAInputs{fb, needed, available, ref signals} => {
+                write!(f, "FB{} needs {} ZIA inputs but only has {} - {}",
+                    fb + 1, needed, available, signals.join(", "))
+            },
+            PARBottleneck::FBZIAUnroutable{fb, ref signals} => {
+                write!(f, "ZIA inputs to FB{} cannot all be routed - {}", fb + 1, signals.join(", "))
+            },
+        }
+    }
+}
+
+pub(crate) fn log_bottlenecks(bottlenecks: &[PARBottleneck], logger: &slog::Logger) {
+    for bottleneck in bottlenecks {
+        error!(logger, "PAR - design does not fit";
+            "code" => "PR014",
+            "reason" => format!("{}", bottleneck));
+    }
+}
+
+fn mc_names(g: &InputGraph, mcs: &[ObjPoolIndex<InputGraphMacrocell>]) -> Vec<String> {
+    mcs.iter().map(|&x| g.mcs.get(x).display_name().to_owned()).collect()
+}
+
+fn explain_global_buffers(g: &InputGraph, device_type: XC2DeviceSpeedPackage, ret: &mut Vec<PARBottleneck>) {
+    macro_rules! explain_bufg {
+        ($g_name:ident, $kind:expr, $cnt_name:ident, $loc_lookup:expr) => {
+            let mut requested_sites = BTreeMap::new();
+            for buf in g.$g_name.iter() {
+                let pin_mc = g.mcs.get(buf.input);
+                let pin_loc = pin_mc.requested_loc;
+                let signal = pin_mc.signal_name(InputGraphPTermInputType::Pin).to_owned();
+
+                if let Some(RequestedLocation{i: Some(buf_i), ..}) = buf.requested_loc {
+                    if buf_i as usize >= $cnt_name {
+                        ret.push(PARBottleneck::GlobalBufferUnsatisfiable {
+                            kind: $kind,
+                            signal,
+                            reason: format!("buffer index {} does not exist", buf_i),
+                        });
+                        continue;
+                    }
+
+                    let site = $loc_lookup(buf_i as usize);
+                    if let Some(pin_loc) = pin_loc {
+                        if pin_loc.fb != site.0 || pin_loc.i.is_some_and(|x| x != site.1) {
+                            ret.push(PARBottleneck::GlobalBufferUnsatisfiable {
+                                kind: $kind,
+                                signal: signal.clone(),
+                                reason: format!("buffer index {} is driven from FB{}_{}, not the requested pin LOC",
+                                    buf_i, site.0 + 1, site.1 + 1),
+                            });
+                        }
+                    }
+
+                    requested_sites.entry(buf_i).or_insert_with(Vec::new).push(signal);
+                } else if let Some(pin_loc) = pin_loc {
+                    let any_site = (0..$cnt_name).any(|i| {
+                        let site = $loc_lookup(i);
+                        site.0 == pin_loc.fb && pin_loc.i.map_or(true, |x| x == site.1)
+                    });
+
+                    if !any_site {
+                        ret.push(PARBottleneck::GlobalBufferUnsatisfiable {
+                            kind: $kind,
+                            signal,
+                            reason: String::from("the pin LOC is not a dedicated global pin"),
+                        });
+                    }
+                }
+            }
+
+            for (buf_i, signals) in requested_sites {
+                if signals.len() > 1 {
+                    ret.push(PARBottleneck::GlobalBufferConflict {
+                        kind: $kind,
+                        index: buf_i,
+                        signals,
+                    });
+                }
+            }
+        }
+    }
+
+    explain_bufg!(bufg_clks, PARGlobalBufferKind::Clk, NUM_BUFG_CLK, |i| get_gck(device_type.dev, i).unwrap());
+    explain_bufg!(bufg_gts, PARGlobalBufferKind::GTS, NUM_BUFG_GTS, |i| get_gts(device_type.dev, i).unwrap());
+    explain_bufg!(bufg_gsr, PARGlobalBufferKind::GSR, NUM_BUFG_GSR, |_| get_gsr(device_type.dev));
+}
+
+fn explain_loc_conflicts(g: &InputGraph, device_type: XC2DeviceSpeedPackage, ret: &mut Vec<PARBottleneck>) {
+    let num_fbs = device_type.dev.num_fbs() as u32;
+    let has_extra_inpin = device_type.dev == XC2Device::XC2C32 || device_type.dev == XC2Device::XC2C32A;
+
+    // BTreeMaps are used so that the output order is stable
+    let mut full_locs = BTreeMap::new();
+    let mut fb_locs = BTreeMap::new();
+    for mc_idx in g.mcs.iter_idx() {
+        let mc = g.mcs.get(mc_idx);
+        let is_pininput = mc.get_type().is_pininput();
+
+        if let Some(loc) = mc.requested_loc {
+            let is_extra_inpin = has_extra_inpin && is_pininput && loc.fb == num_fbs && loc.i.unwrap_or(0) == 0;
+            if (loc.fb >= num_fbs && !is_extra_inpin) || loc.i.is_some_and(|x| x as usize >= MCS_PER_FB) {
+                ret.push(PARBottleneck::LOCInvalid {
+                    signal: mc.display_name().to_owned(),
+                    fb: loc.fb,
+                    mc: loc.i,
+                });
+                continue;
+            }
+
+            if let Some(i) = loc.i {
+                full_locs.entry((loc.fb, i, is_pininput)).or_insert_with(Vec::new).push(mc_idx);
+            }
+            fb_locs.entry((loc.fb, is_pininput)).or_insert_with(Vec::new).push(mc_idx);
+        }
+    }
+
+    for (&(fb, mc, _), mcs) in &full_locs {
+        if mcs.len() > 1 {
+            ret.push(PARBottleneck::LOCSiteConflict {
+                fb,
+                mc,
+                signals: mc_names(g, mcs),
+            });
+        }
+    }
+
+    for (&(fb, mc, is_pininput), mcs_0) in &full_locs {
+        if is_pininput {
+            continue;
+        }
+
+        if let Some(mcs_1) = full_locs.get(&(fb, mc, true)) {
+            if !mcs_can_be_paired(g.mcs.get(mcs_0[0]), g.mcs.get(mcs_1[0])) {
+                ret.push(PARBottleneck::LOCSiteConflict {
+                    fb,
+                    mc,
+                    signals: mc_names(g, &[mcs_0[0], mcs_1[0]]),
+                });
+            }
+        }
+    }
+
+    for (&(fb, _), mcs) in &fb_locs {
+        if mcs.len() > MCS_PER_FB {
+            ret.push(PARBottleneck::LOCFBOverfull {
+                fb,
+                needed: mcs.len() as u32,
+                available: MCS_PER_FB as u32,
+                signals: mc_names(g, mcs),
+            });
+        }
+    }
+}
+
+pub fn explain_sanity_failure(g: &InputGraph, device_type: XC2DeviceSpeedPackage, result: PARSanityResult)
+    -> Vec<PARBottleneck> {
+
+    let num_fbs = device_type.dev.num_fbs();
+    let mut ret = Vec::new();
+
+    match result {
+        PARSanityResult::Ok => {},
+        // Nothing about the design can be changed to get around this
+        PARSanityResult::FailureFeatureNotSupported => {},
+        PARSanityResult::FailureTooManyMCs => {
+            ret.push(PARBottleneck::DeviceTooManyMacrocells {
+                needed: g.mcs.len() as u32,
+                available: (2 * num_fbs * MCS_PER_FB) as u32,
+            });
+        },
+        PARSanityResult::FailureTooManyPTerms => {
+            let pterms_set: HashSet<&InputGraphPTerm> = g.pterms.iter().collect();
+            ret.push(PARBottleneck::DeviceTooManyPTerms {
+                needed: pterms_set.len() as u32,
+                available: (num_fbs * ANDTERMS_PER_FB) as u32,
+            });
+        },
+        PARSanityResult::FailureTooManyBufgClk | PARSanityResult::FailureTooManyBufgGTS |
+        PARSanityResult::FailureTooManyBufgGSR => {
+            macro_rules! too_many_bufg {
+                ($g_name:ident, $kind:expr, $cnt_name:ident) => {
+                    ret.push(PARBottleneck::DeviceTooManyGlobalBuffers {
+                        kind: $kind,
+                        needed: g.$g_name.len() as u32,
+                        available: $cnt_name as u32,
+                        signals: g.$g_name.iter().map(|x|
+                            g.mcs.get(x.input).signal_name(InputGraphPTermInputType::Pin).to_owned()).collect(),
+                    })
+                }
+            }
+
+            match result {
+                PARSanityResult::FailureTooManyBufgClk =>
+                    too_many_bufg!(bufg_clks, PARGlobalBufferKind::Clk, NUM_BUFG_CLK),
+                PARSanityResult::FailureTooManyBufgGTS =>
+                    too_many_bufg!(bufg_gts, PARGlobalBufferKind::GTS, NUM_BUFG_GTS),
+                _ =>
+                    too_many_bufg!(bufg_gsr, PARGlobalBufferKind::GSR, NUM_BUFG_GSR),
+            }
+        },
+        PARSanityResult::FailureIOBankConflict => {
+            let conflicts = io_bank_conflicts(g, device_type.dev);
+            if conflicts.is_empty() {
+                ret.push(PARBottleneck::IOBankConflict {
+                    bank: None,
+                    signals: g.mcs.iter().filter_map(|mc| mc.io_bits.as_ref().and_then(|x| x.io_standard)
+                        .map(|x| format!("{} ({})", mc.pad_name(), x))).collect(),
+                });
+            }
+            for (bank, signals) in conflicts {
+                ret.push(PARBottleneck::IOBankConflict {
+                    bank: Some(bank as u32),
+                    signals,
+                });
+            }
+        },
+        PARSanityResult::FailureAreaGroup => {
+            let mut invalid_groups = BTreeSet::new();
+            for mc in g.mcs.iter() {
+                let area_group = if let Some(ref x) = mc.area_group { x } else { continue };
+                if area_group.last_fb >= num_fbs as u32 {
+                    if invalid_groups.insert(area_group.to_string()) {
+                        ret.push(PARBottleneck::AreaGroupInvalid {
+                            group: area_group.to_string(),
+                            num_fbs: num_fbs as u32,
+                        });
+                    }
+                } else if let Some(loc) = mc.requested_loc.filter(|x| !area_group.contains_fb(x.fb)) {
+                    ret.push(PARBottleneck::AreaGroupLOCConflict {
+                        group: area_group.to_string(),
+                        signal: mc.display_name().to_owned(),
+                        fb: loc.fb,
+                    });
+                }
+            }
+            for (area_groups, needed, available, signals) in area_group_overflows(g) {
+                ret.push(PARBottleneck::AreaGroupOverfull {
+                    groups: area_groups,
+                    needed,
+                    available,
+                    signals,
+                });
+            }
+        },
+        PARSanityResult::FailurePTCNeverSatisfiable => {
+            for mc in g.mcs.iter() {
+                if let (Some(ref reg_bits), Some(ref xor_bits)) = (&mc.reg_bits, &mc.xor_bits) {
+                    if let (Some(ce), Some(ptc)) = (reg_bits.ce_input, xor_bits.andterm_input) {
+                        if g.pterms.get(ce) != g.pterms.get(ptc) {
+                            ret.push(PARBottleneck::PTCConflict {
+                                signal: mc.display_name().to_owned(),
+                            });
+                        }
+                    }
+                }
+            }
+        },
+        PARSanityResult::FailureGlobalNetWrongLoc | PARSanityResult::FailureInitialPlacement => {
+            explain_global_buffers(g, device_type, &mut ret);
+            explain_loc_conflicts(g, device_type, &mut ret);
+        },
+    }
+
+    ret
+}
+
+pub fn explain_placement_failure(g: &InputGraph, go: &OutputGraph, placement: &[PARFBAssignment],
+    device_type: XC2DeviceSpeedPackage) -> Vec<PARBottleneck> {
+
+    let mut ret = Vec::new();
+
+    // Make sure that the locations in the output graph actually correspond to the placement being examined
+    let mut go = go.clone();
+    for (fb_i, fb) in placement.iter().enumerate() {
+        for (mc_i, mc) in fb.iter().enumerate() {
+            for &x in &[mc.0, mc.1] {
+                if let PARMCAssignment::MC(mc_idx) = x {
+                    go.mcs.get_mut(ObjPoolIndex::from(mc_idx)).loc = Some(AssignedLocation {
+                        fb: fb_i as u32,
+                        i: mc_i as u32,
+                    });
+                }
+            }
+        }
+    }
+
+    for (fb_i, mc_assignment) in placement.iter().enumerate() {
+        let fb_i = fb_i as u32;
+
+        for (mc_i, mc) in mc_assignment.iter().enumerate() {
+            if let (PARMCAssignment::MC(mc_idx_0), PARMCAssignment::MC(mc_idx_1)) = *mc {
+                if !mcs_can_be_paired(g.mcs.get(mc_idx_0), g.mcs.get(mc_idx_1)) {
+                    ret.push(PARBottleneck::MacrocellPairingConflict {
+                        fb: fb_i,
+                        mc: mc_i as u32,
+                        signals: mc_names(g, &[mc_idx_0, mc_idx_1]),
+                    });
+                }
+            }
+        }
+
+        // Macrocells using p-terms, heaviest users first
+        let mut pterm_users = Vec::new();
+        for mc in mc_assignment.iter() {
+            if let PARMCAssignment::MC(mc_idx) = mc.0 {
+                let mut mc_pterms = Vec::new();
+                collect_mc_pterms(g.mcs.get(mc_idx), &mut mc_pterms);
+                if !mc_pterms.is_empty() {
+                    pterm_users.push((mc_pterms.len(), mc_idx));
+                }
+            }
+        }
+        pterm_users.sort_by_key(|x| std::cmp::Reverse(x.0));
+        let pterm_users = pterm_users.into_iter().map(|x| x.1).collect::<Vec<_>>();
+
+        let collected_pterms = collect_fb_pterms(g, mc_assignment);
+        let collected_inputs = collect_fb_inputs(g, &collected_pterms);
+        let input_names = collected_inputs.iter()
+            .map(|&(input_type, mc_idx)| g.mcs.get(mc_idx).signal_name(input_type).to_owned())
+            .collect::<Vec<_>>();
+        let unique_pterms: HashSet<&InputGraphPTerm> = collected_pterms.iter().map(|&x| g.pterms.get(x)).collect();
+
+        let mut obviously_too_big = false;
+        if unique_pterms.len() > ANDTERMS_PER_FB {
+            obviously_too_big = true;
+            ret.push(PARBottleneck::FBTooManyPTerms {
+                fb: fb_i,
+                needed: unique_pterms.len() as u32,
+                available: ANDTERMS_PER_FB as u32,
+                signals: mc_names(g, &pterm_users),
+            });
+        }
+        if collected_inputs.len() > INPUTS_PER_ANDTERM {
+            obviously_too_big = true;
+            ret.push(PARBottleneck::FBTooManyZIAInputs {
+                fb: fb_i,
+                needed: collected_inputs.len() as u32,
+                available: INPUTS_PER_ANDTERM as u32,
+                signals: input_names.clone(),
+            });
+        }
+        if obviously_too_big {
+            continue;
+        }
+
+        // The resource counts are fine, so this must be due to the more specific placement rules
+        match try_assign_andterms(g, &mut go, mc_assignment, fb_i, &PARStopCondition::none()) {
+            AndTermAssignmentResult::Success => {},
+            AndTermAssignmentResult::FailurePtermLOCUnsatisfiable(_) => {
+                let mut signals = Vec::new();
+                for &pt_idx in &collected_pterms {
+                    let pt = g.pterms.get(pt_idx);
+                    if let Some(RequestedLocation{i: Some(_), ..}) = pt.requested_loc {
+                        signals.push(pt.name.clone());
+                    }
+                }
+                ret.push(PARBottleneck::FBPTermLOCUnsatisfiable {
+                    fb: fb_i,
+                    signals,
+                });
+            },
+            AndTermAssignmentResult::FailurePtermExceeded(_) => {
+                ret.push(PARBottleneck::FBControlPTermConflict {
+                    fb: fb_i,
+                    signals: mc_names(g, &pterm_users),
+                });
+            },
+        }
+
+        if let ZIAAssignmentResult::FailureUnroutable(_) = try_assign_zia(g, &mut go, mc_assignment, device_type,
+            &PARStopCondition::none()) {
+            ret.push(PARBottleneck::FBZIAUnroutable {
+                fb: fb_i,
+                signals: input_names,
+            });
+        }
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::File;
+    use std::io::Read;
+
+    #[test]
+    fn explain_conflicting_locs() {
+        let mut input_data = Vec::new();
+        File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/../../tests/xc2par/par-reftests/out01.json")).unwrap()
+            .read_to_end(&mut input_data).unwrap();
+        let mut input_graph: InputGraph = serde_json::from_slice(&input_data).unwrap();
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+
+        // Force both macrocells onto the same site
+        let first_loc = input_graph.mcs.iter().next().unwrap().requested_loc;
+        for mc in input_graph.mcs.iter_mut() {
+            mc.requested_loc = first_loc;
+        }
+
+        if let PARResult::FailureSanity(result, bottlenecks) =
+            do_par(&mut input_graph, device_type, &XC2ParOptions::new(), None) {
+
+            assert_eq!(result, PARSanityResult::FailureInitialPlacement);
+            assert_eq!(bottlenecks.len(), 1);
+            if let PARBottleneck::LOCSiteConflict{fb, mc, ref signals} = bottlenecks[0] {
+                assert_eq!(fb, 0);
+                assert_eq!(mc, 8);
+                assert_eq!(signals.len(), 2);
+            } else {
+                panic!("wrong bottleneck reported");
+            }
+        } else {
+            panic!("PAR should have failed");
+        }
+    }
+}
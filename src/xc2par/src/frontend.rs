@@ -26,7 +26,10 @@ OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 use std::error;
 use std::fmt;
 use std::collections::{HashMap, HashSet};
+use crate::hierarchy::*;
+use crate::iostandard::*;
 use crate::objpool::*;
+use crate::techmap::*;
 use serde_derive::{Deserialize, Serialize};
 use slog;
 use slog::Drain;
@@ -78,6 +81,17 @@ pub enum IntermediateGraphNodeVariant {
         output: ObjPoolIndex<IntermediateGraphNet>,
         invert: bool,
     },
+    // The clock divider is hard-wired between the GCK2 pin and the GCK2 global net
+    ClkDiv {
+        input: ObjPoolIndex<IntermediateGraphNet>,
+        output: ObjPoolIndex<IntermediateGraphNet>,
+        div_ratio: XC2ClockDivRatio,
+        delay: bool,
+        cdrst: Option<ObjPoolIndex<IntermediateGraphNet>>,
+    },
+    DataGate {
+        input: ObjPoolIndex<IntermediateGraphNet>,
+    },
     IOBuf {
         input: Option<ObjPoolIndex<IntermediateGraphNet>>,
         oe: Option<ObjPoolIndex<IntermediateGraphNet>>,
@@ -86,12 +100,21 @@ pub enum IntermediateGraphNodeVariant {
         termination_enabled: bool,
         slew_is_fast: bool,
         uses_data_gate: bool,
+        #[serde(default)]
+        io_standard: Option<IOStandard>,
+        // Name of the top-level port that the pad is connected to, if it is known
+        #[serde(default)]
+        port_name: Option<String>,
     },
     InBuf {
         output: ObjPoolIndex<IntermediateGraphNet>,
         schmitt_trigger: bool,
         termination_enabled: bool,
         uses_data_gate: bool,
+        #[serde(default)]
+        io_standard: Option<IOStandard>,
+        #[serde(default)]
+        port_name: Option<String>,
     },
 }
 
@@ -121,6 +144,8 @@ pub struct IntermediateGraph {
 pub enum FrontendError {
     MultipleToplevelModules,
     NoToplevelModules,
+    TopModuleNotFound(String),
+    RecursiveInstance(String),
     UnsupportedCellType(String),
     MultipleNetDrivers(String),
     NoNetDrivers(String),
@@ -133,6 +158,9 @@ pub enum FrontendError {
     MissingRequiredParameter(String),
     MismatchedInputCount,
     ParseIntError(::std::num::ParseIntError),
+    CombinationalLoop(String),
+    IllegalGenericCell(String),
+    UnbufferedInoutPort(String),
 }
 
 impl error::Error for FrontendError {
@@ -186,9 +214,24 @@ impl fmt::Display for FrontendError {
             &FrontendError::NoToplevelModules => {
                 write!(f, "no top-level modules")
             },
+            FrontendError::TopModuleNotFound(s) => {
+                write!(f, "top-level module not found - {}", s)
+            },
+            FrontendError::RecursiveInstance(s) => {
+                write!(f, "module instantiates itself - {}", s)
+            },
             &FrontendError::MismatchedInputCount => {
                 write!(f, "mismatched input count")
             },
+            &FrontendError::CombinationalLoop(ref s) => {
+                write!(f, "combinational loop through cell - {}", s)
+            },
+            &FrontendError::IllegalGenericCell(ref s) => {
+                write!(f, "cannot map generic cell - {}", s)
+            },
+            &FrontendError::UnbufferedInoutPort(ref s) => {
+                write!(f, "inout port without an IO buffer - {}", s)
+            },
         }
     }
 }
@@ -199,41 +242,81 @@ impl From<::std::num::ParseIntError> for FrontendError {
     }
 }
 
+// Decodes the `init` attribute of a net name into one value per bit. Bits that are `x` have no initial value.
+fn netname_init_bits(name: &str, netname: &yosys_netlist_json::Netname, logger: &slog::Logger)
+    -> Result<Vec<Option<bool>>, FrontendError> {
+
+    let width = netname.bits.len();
+    match netname.attributes.get("init") {
+        None => Ok(vec![None; width]),
+        Some(yosys_netlist_json::AttributeVal::N(n)) => {
+            Ok((0..width).map(|i| Some(i < usize::BITS as usize && (*n >> i) & 1 != 0)).collect())
+        },
+        Some(yosys_netlist_json::AttributeVal::S(s)) => {
+            // The string is written with the most significant bit first
+            let mut ret = Vec::with_capacity(width);
+            let mut chars = s.chars().rev();
+            for _ in 0..width {
+                ret.push(match chars.next() {
+                    Some('0') => Some(false),
+                    Some('1') => Some(true),
+                    Some('x') | Some('z') | None => None,
+                    Some(_) => {
+                        error!(logger, "nets - invalid init value";
+                            "code" => "FE025",
+                            "name" => name,
+                            "value" => s);
+                        return Err(FrontendError::IllegalStringAttributeValue(s.to_owned()));
+                    }
+                });
+            }
+            Ok(ret)
+        },
+    }
+}
+
+// Checks for either the Yosys `keep` attribute or a `KEEP` constraint on a net name
+fn netname_has_keep(netname: &yosys_netlist_json::Netname) -> bool {
+    ["keep", "KEEP"].iter().any(|&attr_name| match netname.attributes.get(attr_name) {
+        None => false,
+        Some(attr) => if let Some(s) = attr.to_string_if_string() {
+            s.eq_ignore_ascii_case("true") || s.eq_ignore_ascii_case("yes")
+        } else {
+            attr.to_number() != Some(0)
+        },
+    })
+}
+
 impl IntermediateGraph {
     pub fn from_yosys_netlist<L: Into<Option<slog::Logger>>>(
         yosys_net: &yosys_netlist_json::Netlist, logger: L) -> Result<Self, FrontendError> {
 
+        Self::from_yosys_netlist_with_top(yosys_net, None, logger)
+    }
+
+    /// Same as `from_yosys_netlist`, except that the top-level module can be chosen by name instead of by the `top`
+    /// attribute. Any other modules that the top-level module instantiates are flattened into it.
+    pub fn from_yosys_netlist_with_top<L: Into<Option<slog::Logger>>>(
+        yosys_net: &yosys_netlist_json::Netlist, top: Option<&str>, logger: L) -> Result<Self, FrontendError> {
+
         let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
 
-        let mut top_module_name = "";
-        let mut top_module_found = false;
-        for (module_name, module) in &yosys_net.modules {
-            debug!(logger, "found yosys netlist module"; "module name" => module_name);
-
-            if let Some(top_attr) = module.attributes.get("top") {
-                if let Some(n) = top_attr.to_number() {
-                    if n != 0 {
-                        // Claims to be a top-level module
-                        debug!(logger, "found toplevel yosys netlist module"; "module name" => module_name);
-
-                        if top_module_found {
-                            error!(logger, "found multiple toplevel yosys netlist modules";
-                                "second module name" => module_name);
-                            return Err(FrontendError::MultipleToplevelModules);
-                        }
+        let flat_net = flatten_yosys_netlist(yosys_net, top, logger.clone())?;
+        Self::from_flat_yosys_netlist(&flat_net, logger)
+    }
 
-                        top_module_found = true;
-                        top_module_name = module_name;
-                    }
-                }
-            }
-        }
+    /// Same as `from_yosys_netlist`, except that the netlist must already have been flattened into a single module
+    /// by `flatten_yosys_netlist`. This avoids flattening twice when the caller also needs the flat netlist.
+    pub fn from_flat_yosys_netlist<L: Into<Option<slog::Logger>>>(
+        flat_net: &yosys_netlist_json::Netlist, logger: L) -> Result<Self, FrontendError> {
 
-        if !top_module_found {
-            error!(logger, "found no toplevel yosys netlist modules");
-            return Err(FrontendError::NoToplevelModules);
+        let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+
+        let mut modules = flat_net.modules.iter();
+        let (top_module_name, top_module) = modules.next().ok_or(FrontendError::NoToplevelModules)?;
+        if modules.next().is_some() {
+            return Err(FrontendError::MultipleToplevelModules);
         }
-        let top_module = yosys_net.modules.get(top_module_name).unwrap();
 
         let logger = logger.new(o!("top module" => top_module_name.to_owned()));
 
@@ -254,12 +337,19 @@ impl IntermediateGraph {
         // This maps from a Yosys net number to an internal net number
         let mut net_map: HashMap<usize, ObjPoolIndex<IntermediateGraphNet>> = HashMap::new();
 
-        // Keep track of module ports
+        // Keep track of module ports, along with the name of each bit for naming the IO buffers connected to them
         let mut module_ports = HashSet::new();
-        for (_, port) in &top_module.ports {
-            for yosys_edge_idx in &port.bits {
+        let mut port_bit_names = HashMap::new();
+        for (port_name, port) in &top_module.ports {
+            for (i, yosys_edge_idx) in port.bits.iter().enumerate() {
                 if let &yosys_netlist_json::BitVal::N(n) = yosys_edge_idx {
                     module_ports.insert(n);
+                    let name = if port.bits.len() == 1 {
+                        port_name.to_owned()
+                    } else {
+                        format!("{}[{}]", port_name, i)
+                    };
+                    port_bit_names.insert(n, name);
                 }
             }
         }
@@ -268,6 +358,35 @@ impl IntermediateGraph {
         let mut cell_names = top_module.cells.keys().collect::<Vec<_>>();
         cell_names.sort();
 
+        // Module ports that are used by something other than an IO buffer need to have one created for them (this
+        // happens when the netlist came from a generic synthesis script). Only the pad side of the IO buffers is
+        // actually skipped.
+        let mut padded_ports = HashSet::new();
+        let mut unpadded_ports = HashSet::new();
+        for &cell_name in &cell_names {
+            let cell = &top_module.cells[cell_name];
+            for (connection_name, connection_vec) in &cell.connections {
+                let is_pad = match (cell.cell_type.as_ref(), connection_name.as_ref()) {
+                    ("IBUF", "I") | ("IOBUFE", "IO") => true,
+                    _ => false,
+                };
+                for connection in connection_vec.iter() {
+                    if let &yosys_netlist_json::BitVal::N(n) = connection {
+                        if module_ports.contains(&n) {
+                            if is_pad {
+                                padded_ports.insert(n);
+                            } else {
+                                unpadded_ports.insert(n);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        let module_ports = module_ports.into_iter()
+            .filter(|n| padded_ports.contains(n) || !unpadded_ports.contains(n))
+            .collect::<HashSet<_>>();
+
         // Cells can refer to a net, so loop through these as well
         for &cell_name in &cell_names {
             let cell = &top_module.cells[cell_name];
@@ -308,9 +427,15 @@ impl IntermediateGraph {
         // net names
         let mut netname_names = top_module.netnames.keys().collect::<Vec<_>>();
         netname_names.sort();
+        // Initial values and `keep` from the net names are remembered so that they can be applied to the registers
+        // and generic gates that drive these nets
+        let mut net_init: HashMap<ObjPoolIndex<IntermediateGraphNet>, (bool, &str)> = HashMap::new();
+        let mut keep_nets = Vec::new();
         for netname_name in netname_names {
             let netname_obj = &top_module.netnames[netname_name];
-            for yosys_edge_idx in &netname_obj.bits {
+            let init_bits = netname_init_bits(netname_name, netname_obj, &logger)?;
+            let keep = netname_has_keep(netname_obj);
+            for (bit_i, yosys_edge_idx) in netname_obj.bits.iter().enumerate() {
                 if let &yosys_netlist_json::BitVal::N(yosys_edge_idx) = yosys_edge_idx {
                     // Don't create nets for the pad side of io buffers
                     if module_ports.contains(&yosys_edge_idx) {
@@ -320,7 +445,22 @@ impl IntermediateGraph {
                         continue;
                     }
 
-                    if net_map.get(&yosys_edge_idx).is_none() {
+                    let our_edge_idx = if let Some(&existing_net_our_idx) = net_map.get(&yosys_edge_idx) {
+                        // Naming an existing one
+                        debug!(logger, "nets - naming existing net";
+                            "name" => netname_name,
+                            "index" => yosys_edge_idx);
+                        let existing_net = nets.get_mut(existing_net_our_idx);
+                        if let Some(ref old_name) = existing_net.name {
+                            warn!(logger, "nets - overwrote net name";
+                                "code" => "FE003",
+                                "old name" => old_name,
+                                "new name" => netname_name,
+                                "index" => yosys_edge_idx);
+                        }
+                        existing_net.name = Some(netname_name.to_owned());
+                        existing_net_our_idx
+                    } else {
                         // Need to add a new one
                         debug!(logger, "nets - adding new net because of netname";
                             "name" => netname_name,
@@ -331,20 +471,30 @@ impl IntermediateGraph {
                             sinks: Vec::new(),
                         });
                         net_map.insert(yosys_edge_idx, our_edge_idx);
-                    } else {
-                        // Naming an existing one
-                        debug!(logger, "nets - naming existing net";
+                        our_edge_idx
+                    };
+
+                    if let Some(init_state) = init_bits[bit_i] {
+                        if let Some(&(old_init_state, old_name)) = net_init.get(&our_edge_idx) {
+                            if old_init_state != init_state {
+                                warn!(logger, "nets - conflicting init values, keeping the first one";
+                                    "code" => "FE026",
+                                    "old name" => old_name,
+                                    "old value" => old_init_state,
+                                    "new name" => netname_name,
+                                    "new value" => init_state,
+                                    "index" => yosys_edge_idx);
+                            }
+                        } else {
+                            net_init.insert(our_edge_idx, (init_state, netname_name));
+                        }
+                    }
+
+                    if keep && !keep_nets.contains(&our_edge_idx) {
+                        debug!(logger, "nets - keeping net";
                             "name" => netname_name,
                             "index" => yosys_edge_idx);
-                        let existing_net_our_idx = net_map.get(&yosys_edge_idx).unwrap();
-                        let existing_net = nets.get_mut(*existing_net_our_idx);
-                        if let Some(ref old_name) = existing_net.name {
-                            warn!(logger, "nets - overwrote net name";
-                                "old name" => old_name,
-                                "new name" => netname_name,
-                                "index" => yosys_edge_idx);
-                        }
-                        existing_net.name = Some(netname_name.to_owned());
+                        keep_nets.push(our_edge_idx);
                     }
                 }
             }
@@ -352,6 +502,8 @@ impl IntermediateGraph {
 
         // Now we can actually process objects
         let mut nodes = ObjPool::new();
+        let mut generic_gates = Vec::new();
+        let mut generic_ffs = Vec::new();
 
         let bitval_to_net = |bitval, conn_name: &str, logger: &slog::Logger| {
             match bitval {
@@ -361,6 +513,7 @@ impl IntermediateGraph {
                 // We should never see an x/z in our processing
                 _ => {
                     error!(logger, "cells - illegal bit value";
+                        "code" => "FE004",
                         "connection name" => conn_name,
                         "value" => bitval);
                     Err(FrontendError::IllegalBitValue(bitval))
@@ -379,6 +532,7 @@ impl IntermediateGraph {
                 let param_option = cell_obj.parameters.get(name);
                 if param_option.is_none() {
                     error!(logger, "cells - missing required parameter";
+                        "code" => "FE005",
                         "name" => name);
                     return Err(FrontendError::MissingRequiredParameter(name.to_owned()));
                 }
@@ -390,6 +544,7 @@ impl IntermediateGraph {
                     return Ok(n)
                 } else {
                     error!(logger, "cells - parameter not a number";
+                        "code" => "FE006",
                         "name" => name,
                         "value" => param_option_copy);
                     return Err(FrontendError::IllegalAttributeValue(param_option_copy.clone()));
@@ -410,6 +565,7 @@ impl IntermediateGraph {
                     return Ok(Some(s))
                 } else {
                     error!(logger, "cells - parameter not a string";
+                        "code" => "FE007",
                         "name" => name,
                         "value" => param_option_copy);
                     return Err(FrontendError::IllegalAttributeValue(param_option_copy.clone()));
@@ -432,6 +588,7 @@ impl IntermediateGraph {
                         false
                     } else {
                         error!(logger, "cells - parameter not a boolean";
+                            "code" => "FE008",
                             "name" => name,
                             "value" => attrib);
                         return Err(FrontendError::IllegalStringAttributeValue(attrib.to_owned()));
@@ -441,17 +598,38 @@ impl IntermediateGraph {
                 })
             };
 
+            // Helper to retrieve the optional IOSTANDARD attribute
+            let optional_io_standard_attrib = || -> Result<Option<IOStandard>, FrontendError> {
+                let attrib = optional_string_attrib("IOSTANDARD")?;
+                if let Some(attrib) = attrib {
+                    if let Some(io_standard) = IOStandard::from_name(attrib) {
+                        debug!(logger, "cells - I/O standard";
+                            "value" => io_standard.to_string());
+                        Ok(Some(io_standard))
+                    } else {
+                        error!(logger, "cells - invalid I/O standard";
+                            "code" => "FE024",
+                            "value" => attrib);
+                        Err(FrontendError::IllegalStringAttributeValue(attrib.to_owned()))
+                    }
+                } else {
+                    Ok(None)
+                }
+            };
+
             // Helper to retrieve a single net that is definitely required
             let single_required_connection = |name: &str, logger: &slog::Logger| {
                 let conn_obj = cell_obj.connections.get(name);
                 if conn_obj.is_none() {
                     error!(logger, "cells - missing required connection";
+                        "code" => "FE009",
                         "connection name" => name);
                     return Err(FrontendError::MissingRequiredConnection(name.to_owned()));
                 }
                 let conn_obj = conn_obj.unwrap();
                 if conn_obj.len() != 1 {
                     error!(logger, "cells - too many nets";
+                        "code" => "FE010",
                         "connection name" => name);
                     return Err(FrontendError::TooManyConnections(name.to_owned()));
                 }
@@ -472,6 +650,7 @@ impl IntermediateGraph {
                 let conn_obj = conn_obj.unwrap();
                 if conn_obj.len() != 1 {
                     error!(logger, "cells - too many nets";
+                        "code" => "FE010",
                         "connection name" => name);
                     return Err(FrontendError::TooManyConnections(name.to_owned()));
                 }
@@ -483,11 +662,20 @@ impl IntermediateGraph {
                 return Ok(Some(result));
             };
 
+            // Helper to find the name of the module port that the pad of an IO buffer is connected to
+            let pad_port_name = |name: &str| {
+                cell_obj.connections.get(name)
+                    .and_then(|x| x.first())
+                    .and_then(|x| if let &yosys_netlist_json::BitVal::N(n) = x { port_bit_names.get(&n) } else { None })
+                    .cloned()
+            };
+
             // Helper to retrieve an array of nets that is required
             let multiple_required_connection = |name: &str, logger: &slog::Logger| {
                 let conn_obj = cell_obj.connections.get(name);
                 if conn_obj.is_none() {
                     error!(logger, "cells - missing required connection";
+                        "code" => "FE009",
                         "connection name" => name);
                     return Err(FrontendError::MissingRequiredConnection(name.to_owned()));
                 }
@@ -522,6 +710,7 @@ impl IntermediateGraph {
                             false
                         } else {
                             error!(logger, "cells - IOBUFE - invalid slew rate";
+                                "code" => "FE011",
                                 "value" => attrib);
                             return Err(FrontendError::IllegalStringAttributeValue(attrib.to_owned()));
                         }
@@ -542,6 +731,8 @@ impl IntermediateGraph {
                             termination_enabled: optional_string_bool_attrib("TERM")?,
                             slew_is_fast,
                             uses_data_gate,
+                            io_standard: optional_io_standard_attrib()?,
+                            port_name: pad_port_name("IO"),
                         },
                         location: RequestedLocation::parse_location(optional_string_attrib("LOC")?, &logger)?,
                     });
@@ -559,6 +750,8 @@ impl IntermediateGraph {
                             schmitt_trigger: optional_string_bool_attrib("SCHMITT_TRIGGER")?,
                             termination_enabled: optional_string_bool_attrib("TERM")?,
                             uses_data_gate,
+                            io_standard: optional_io_standard_attrib()?,
+                            port_name: pad_port_name("I"),
                         },
                         location: RequestedLocation::parse_location(optional_string_attrib("LOC")?, &logger)?,
                     });
@@ -572,6 +765,7 @@ impl IntermediateGraph {
 
                     if num_true_inputs != inputs_true.len() || num_comp_inputs != inputs_comp.len() {
                         error!(logger, "cells - ANDTERM - mismatched number of inputs";
+                            "code" => "FE012",
                             "true input attrib" => num_true_inputs,
                             "comp input attrib" => num_comp_inputs,
                             "true input conn" => inputs_true.len(),
@@ -596,6 +790,7 @@ impl IntermediateGraph {
 
                     if num_inputs != inputs.len() {
                         error!(logger, "cells - ORTERM - mismatched number of inputs";
+                            "code" => "FE013",
                             "input attrib" => num_inputs,
                             "input conn" => inputs.len());
                         return Err(FrontendError::MismatchedInputCount);
@@ -654,6 +849,53 @@ impl IntermediateGraph {
                         location: RequestedLocation::parse_location(optional_string_attrib("LOC")?, &logger)?,
                     });
                 },
+                "CLK_DIV" => {
+                    let divide = numeric_param("DIVIDE")?;
+                    let div_ratio = match divide {
+                        2 => XC2ClockDivRatio::Div2,
+                        4 => XC2ClockDivRatio::Div4,
+                        6 => XC2ClockDivRatio::Div6,
+                        8 => XC2ClockDivRatio::Div8,
+                        10 => XC2ClockDivRatio::Div10,
+                        12 => XC2ClockDivRatio::Div12,
+                        14 => XC2ClockDivRatio::Div14,
+                        16 => XC2ClockDivRatio::Div16,
+                        _ => {
+                            error!(logger, "cells - CLK_DIV - invalid division ratio";
+                                "code" => "FE023",
+                                "value" => divide);
+                            return Err(FrontendError::IllegalAttributeValue(
+                                cell_obj.parameters["DIVIDE"].clone()));
+                        }
+                    };
+
+                    let delay = if cell_obj.parameters.contains_key("DELAY") {
+                        numeric_param("DELAY")? != 0
+                    } else {
+                        false
+                    };
+
+                    nodes.insert(IntermediateGraphNode {
+                        name: cell_name.to_owned(),
+                        variant: IntermediateGraphNodeVariant::ClkDiv {
+                            input: single_required_connection("I", &logger)?,
+                            output: single_required_connection("O", &logger)?,
+                            div_ratio,
+                            delay,
+                            cdrst: single_optional_connection("CDRST", &logger)?,
+                        },
+                        location: RequestedLocation::parse_location(optional_string_attrib("LOC")?, &logger)?,
+                    });
+                },
+                "DATAGATE" => {
+                    nodes.insert(IntermediateGraphNode {
+                        name: cell_name.to_owned(),
+                        variant: IntermediateGraphNodeVariant::DataGate {
+                            input: single_required_connection("DGE", &logger)?,
+                        },
+                        location: None,
+                    });
+                },
                 "FDCP" | "FDCP_N" | "FDDCP" |
                 "LDCP" | "LDCP_N" |
                 "FTCP" | "FTCP_N" | "FTDCP" |
@@ -684,129 +926,148 @@ impl IntermediateGraph {
                     let dt_name = if mode == XC2MCRegMode::TFF {"T"} else {"D"};
                     let clk_name = if mode == XC2MCRegMode::LATCH {"G"} else {"C"};
 
+                    // The INIT parameter on the cell takes priority over an init attribute on the output net, but
+                    // either one is enough
+                    let output = single_required_connection("Q", &logger)?;
+                    let init_state = if cell_obj.parameters.contains_key("INIT") || !net_init.contains_key(&output) {
+                        let init_state = numeric_param("INIT")? != 0;
+                        if let Some(&(net_init_state, net_name)) = net_init.get(&output) {
+                            if net_init_state != init_state {
+                                warn!(logger, "cells - INIT parameter conflicts with net init value";
+                                    "code" => "FE027",
+                                    "net" => net_name,
+                                    "parameter value" => init_state,
+                                    "net value" => net_init_state);
+                            }
+                        }
+                        init_state
+                    } else {
+                        net_init[&output].0
+                    };
+
                     nodes.insert(IntermediateGraphNode {
                         name: cell_name.to_owned(),
                         variant: IntermediateGraphNodeVariant::Reg {
                             mode,
                             clkinv,
                             clkddr,
-                            init_state: numeric_param("INIT")? != 0,
+                            init_state,
                             set_input: single_optional_connection("PRE", &logger)?,
                             reset_input: single_optional_connection("CLR", &logger)?,
                             ce_input,
                             dt_input: single_required_connection(dt_name, &logger)?,
                             clk_input: single_required_connection(clk_name, &logger)?,
-                            output: single_required_connection("Q", &logger)?,
+                            output,
                         },
                         location: RequestedLocation::parse_location(optional_string_attrib("LOC")?, &logger)?,
                     });
                 }
-                _ => {
-                    error!(logger, "cells - unsupported cell type");
-                    return Err(FrontendError::UnsupportedCellType(cell_obj.cell_type.to_owned()));
+                cell_type => {
+                    if let Some(kind) = GenericGateKind::from_cell_type(cell_type) {
+                        let mut inputs = Vec::new();
+                        for &input_name in kind.input_names() {
+                            inputs.push(single_required_connection(input_name, &logger)?);
+                        }
+
+                        generic_gates.push(GenericGate {
+                            name: cell_name.to_owned(),
+                            kind,
+                            inputs,
+                            output: single_required_connection("Y", &logger)?,
+                        });
+                    } else if let Some(ff_type) = GenericFFType::from_cell_type(cell_type) {
+                        let en = if let Some(en_pol) = ff_type.en_pol {
+                            Some((single_required_connection("E", &logger)?, en_pol))
+                        } else {
+                            None
+                        };
+                        let set = if let Some((set_name, set_pol)) = ff_type.set {
+                            Some((single_required_connection(set_name, &logger)?, set_pol))
+                        } else {
+                            None
+                        };
+                        let reset = if let Some((reset_name, reset_pol)) = ff_type.reset {
+                            Some((single_required_connection(reset_name, &logger)?, reset_pol))
+                        } else {
+                            None
+                        };
+
+                        let q = single_required_connection("Q", &logger)?;
+                        generic_ffs.push(GenericFF {
+                            name: cell_name.to_owned(),
+                            location: RequestedLocation::parse_location(optional_string_attrib("LOC")?, &logger)?,
+                            mode: ff_type.mode,
+                            clk_pol: ff_type.clk_pol,
+                            clk: single_required_connection(ff_type.clk_name, &logger)?,
+                            en,
+                            set,
+                            reset,
+                            d: single_required_connection("D", &logger)?,
+                            q,
+                            init_state: net_init.get(&q).is_some_and(|x| x.0),
+                        });
+                    } else {
+                        error!(logger, "cells - unsupported cell type"; "code" => "FE014");
+                        return Err(FrontendError::UnsupportedCellType(cell_obj.cell_type.to_owned()));
+                    }
                 }
             }
         }
 
-        // Now that we are done processing, hook up sources/sinks in the edges
-
-        // This helper is used to check if a net already has a source and raise an error if it does
-        let set_net_source = |nets: &mut ObjPool<IntermediateGraphNet>, output, x| {
-            let output_net = nets.get_mut(output);
-            if output_net.source.is_some() {
-                error!(logger, "connectivity - multiple drivers for net";
-                    "net" => &output_net.name,
-                    "old driver" => output_net.source.unwrap(),
-                    "new driver" => x);
-                // FIXME: Wtf is &"lit".to_owned()?!
-                return Err(FrontendError::MultipleNetDrivers(output_net.name.as_ref()
-                    .unwrap_or(&"<no name>".to_owned()).to_owned()));
-            }
-            debug!(logger, "connectivity - connecting driver";
-                "net" => &output_net.name,
-                "driver" => x);
-            output_net.source = Some(x);
-            Ok(())
-        };
+        // Generic cells need to be mapped onto the same nodes as everything else
+        let mut generic_ports = Vec::new();
+        let mut port_names = top_module.ports.keys().collect::<Vec<_>>();
+        port_names.sort();
+        for port_name in port_names {
+            let port = &top_module.ports[port_name];
+            for (i, bitval) in port.bits.iter().enumerate() {
+                let name = if port.bits.len() == 1 {
+                    port_name.to_owned()
+                } else {
+                    format!("{}[{}]", port_name, i)
+                };
 
-        for node_idx in nodes.iter_idx() {
-            let node = nodes.get(node_idx);
-            match node.variant {
-                IntermediateGraphNodeVariant::AndTerm{ref inputs_true, ref inputs_comp, output} => {
-                    for &input in inputs_true {
-                        nets.get_mut(input).sinks.push(node_idx);
-                    }
-                    for &input in inputs_comp {
-                        nets.get_mut(input).sinks.push(node_idx);
-                    }
-                    set_net_source(&mut nets, output, node_idx)?;
-                },
-                IntermediateGraphNodeVariant::OrTerm{ref inputs, output} => {
-                    for &input in inputs {
-                        nets.get_mut(input).sinks.push(node_idx);
-                    }
-                    set_net_source(&mut nets, output, node_idx)?;
-                },
-                IntermediateGraphNodeVariant::Xor{orterm_input, andterm_input, output, ..} => {
-                    if orterm_input.is_some() {
-                        nets.get_mut(orterm_input.unwrap()).sinks.push(node_idx);
-                    }
-                    if andterm_input.is_some() {
-                        nets.get_mut(andterm_input.unwrap()).sinks.push(node_idx);
-                    }
-                    set_net_source(&mut nets, output, node_idx)?;
-                },
-                IntermediateGraphNodeVariant::Reg{set_input, reset_input, ce_input, dt_input, clk_input, output, ..} => {
-                    if set_input.is_some() {
-                        nets.get_mut(set_input.unwrap()).sinks.push(node_idx);
-                    }
-                    if reset_input.is_some() {
-                        nets.get_mut(reset_input.unwrap()).sinks.push(node_idx);
-                    }
-                    if ce_input.is_some() {
-                        nets.get_mut(ce_input.unwrap()).sinks.push(node_idx);
-                    }
-                    nets.get_mut(dt_input).sinks.push(node_idx);
-                    nets.get_mut(clk_input).sinks.push(node_idx);
-                    set_net_source(&mut nets, output, node_idx)?;
-                },
-                IntermediateGraphNodeVariant::BufgClk{input, output, ..} |
-                IntermediateGraphNodeVariant::BufgGTS{input, output, ..} |
-                IntermediateGraphNodeVariant::BufgGSR{input, output, ..} => {
-                    nets.get_mut(input).sinks.push(node_idx);
-                    set_net_source(&mut nets, output, node_idx)?;
-                },
-                IntermediateGraphNodeVariant::IOBuf{input, oe, output, ..} => {
-                    if input.is_some() {
-                        nets.get_mut(input.unwrap()).sinks.push(node_idx);
-                    }
-                    if oe.is_some() {
-                        nets.get_mut(oe.unwrap()).sinks.push(node_idx);
-                    }
-                    if output.is_some() {
-                        set_net_source(&mut nets, output.unwrap(), node_idx)?;
-                    }
-                },
-                IntermediateGraphNodeVariant::InBuf{output, ..} => {
-                    set_net_source(&mut nets, output, node_idx)?;
-                },
-            }
-        }
+                let net = match bitval {
+                    &yosys_netlist_json::BitVal::N(n) if unpadded_ports.contains(&n) && !padded_ports.contains(&n) =>
+                        *net_map.get(&n).unwrap(),
+                    &yosys_netlist_json::BitVal::S(yosys_netlist_json::SpecialBit::_0) => vss_net,
+                    &yosys_netlist_json::BitVal::S(yosys_netlist_json::SpecialBit::_1) => vdd_net,
+                    _ => continue,
+                };
 
-        // Check for undriven nets
-        for net_idx in nets.iter_idx() {
-            if net_idx == vdd_net || net_idx == vss_net {
-                continue;
-            }
+                let direction = match port.direction {
+                    yosys_netlist_json::PortDirection::Input => GenericPortDirection::Input,
+                    yosys_netlist_json::PortDirection::Output => GenericPortDirection::Output,
+                    yosys_netlist_json::PortDirection::InOut => {
+                        error!(logger, "ports - inout port without IO buffer"; "code" => "FE015", "port" => &name);
+                        return Err(FrontendError::UnbufferedInoutPort(name));
+                    },
+                };
+                if direction == GenericPortDirection::Input && (net == vdd_net || net == vss_net) {
+                    continue;
+                }
 
-            let net = nets.get(net_idx);
-            if net.source.is_none() {
-                error!(logger, "connectivity - undriven net";
-                    "net" => &net.name);
-                return Err(FrontendError::NoNetDrivers(net.name.as_ref()
-                    .unwrap_or(&"<no name>".to_owned()).to_owned()));
+                generic_ports.push(GenericPort {
+                    name,
+                    direction,
+                    net,
+                });
             }
         }
+        let generic = GenericLogic {
+            gates: generic_gates,
+            ffs: generic_ffs,
+            ports: generic_ports,
+            keep_nets,
+        };
+        let generic_internal_nets = if !generic.is_empty() {
+            lower_generic_logic(&mut nodes, &mut nets, vdd_net, vss_net, generic, &logger)?
+        } else {
+            HashSet::new()
+        };
+
+        // Now that we are done processing, hook up sources/sinks in the edges
+        connect_nets(&nodes, &mut nets, vdd_net, vss_net, &generic_internal_nets, &logger)?;
 
         Ok(Self {
             nodes,
@@ -869,6 +1130,7 @@ impl IntermediateGraph {
                             if node_idx != source_node_idx {
                                 // Trying to go from a different pin into the direct input path of this pin
                                 error!(logger, "gather - invalid path IOBUFE -> FF -> IOBUFE";
+                                    "code" => "FE016",
                                     "node 1" => &node.name,
                                     "node 2" => &source_node.name);
                                 return Err(GatherMacrocellError::IllegalNodeDriver(node.name.to_owned()));
@@ -876,12 +1138,14 @@ impl IntermediateGraph {
                             // Otherwise ignore this for now. This is a bit strange, but possible.
                         } else {
                             error!(logger, "gather - invalid path ![XOR|IOBUFE] -> FF -> IOBUFE";
+                                "code" => "FE017",
                                 "iobuf node" => &node.name,
                                 "invalid node" => &source_node.name);
                             return Err(GatherMacrocellError::IllegalNodeDriver(node.name.to_owned()));
                         }
                     } else {
                         error!(logger, "gather - invalid path ![XOR|FF] -> IOBUFE";
+                            "code" => "FE018",
                             "iobuf node" => &node.name,
                             "invalid node" => &source_node.name);
                         return Err(GatherMacrocellError::IllegalNodeDriver(node.name.to_owned()));
@@ -907,6 +1171,7 @@ impl IntermediateGraph {
                     if let IntermediateGraphNodeVariant::Reg{..} = sink_node.variant {
                         if maybe_reg_index.is_some() {
                             error!(logger, "gather - invalid multiple FF sinks for node";
+                                "code" => "FE019",
                                 "node" => &node.name,
                                 "ff node 1" => maybe_reg_name.unwrap(),
                                 "ff node 2" => &sink_node.name);
@@ -946,6 +1211,7 @@ impl IntermediateGraph {
                     if let IntermediateGraphNodeVariant::Reg{..} = sink_node.variant {
                         if maybe_reg_index.is_some() {
                             error!(logger, "gather - invalid multiple FF sinks for node";
+                                "code" => "FE019",
                                 "node" => &node.name,
                                 "ff node 1" => maybe_reg_name.unwrap(),
                                 "ff node 2" => &sink_node.name);
@@ -1017,6 +1283,125 @@ impl fmt::Display for GatherMacrocellError {
     }
 }
 
+// Fills in the sources and sinks of every net from the nodes that are connected to it, checking that every net has
+// exactly one driver. Nets in `internal_nets` are allowed to be undriven.
+pub(crate) fn connect_nets(nodes: &ObjPool<IntermediateGraphNode>, nets: &mut ObjPool<IntermediateGraphNet>,
+    vdd_net: ObjPoolIndex<IntermediateGraphNet>, vss_net: ObjPoolIndex<IntermediateGraphNet>,
+    internal_nets: &HashSet<ObjPoolIndex<IntermediateGraphNet>>, logger: &slog::Logger) -> Result<(), FrontendError> {
+
+    // This helper is used to check if a net already has a source and raise an error if it does
+    let set_net_source = |nets: &mut ObjPool<IntermediateGraphNet>, output, x| {
+        let output_net = nets.get_mut(output);
+        if output_net.source.is_some() {
+            error!(logger, "connectivity - multiple drivers for net";
+                "code" => "FE020",
+                "net" => &output_net.name,
+                "old driver" => output_net.source.unwrap(),
+                "new driver" => x);
+            // FIXME: Wtf is &"lit".to_owned()?!
+            return Err(FrontendError::MultipleNetDrivers(output_net.name.as_ref()
+                .unwrap_or(&"<no name>".to_owned()).to_owned()));
+        }
+        debug!(logger, "connectivity - connecting driver";
+            "net" => &output_net.name,
+            "driver" => x);
+        output_net.source = Some(x);
+        Ok(())
+    };
+
+    for node_idx in nodes.iter_idx() {
+        let node = nodes.get(node_idx);
+        match node.variant {
+            IntermediateGraphNodeVariant::AndTerm{ref inputs_true, ref inputs_comp, output} => {
+                for &input in inputs_true {
+                    nets.get_mut(input).sinks.push(node_idx);
+                }
+                for &input in inputs_comp {
+                    nets.get_mut(input).sinks.push(node_idx);
+                }
+                set_net_source(nets, output, node_idx)?;
+            },
+            IntermediateGraphNodeVariant::OrTerm{ref inputs, output} => {
+                for &input in inputs {
+                    nets.get_mut(input).sinks.push(node_idx);
+                }
+                set_net_source(nets, output, node_idx)?;
+            },
+            IntermediateGraphNodeVariant::Xor{orterm_input, andterm_input, output, ..} => {
+                if orterm_input.is_some() {
+                    nets.get_mut(orterm_input.unwrap()).sinks.push(node_idx);
+                }
+                if andterm_input.is_some() {
+                    nets.get_mut(andterm_input.unwrap()).sinks.push(node_idx);
+                }
+                set_net_source(nets, output, node_idx)?;
+            },
+            IntermediateGraphNodeVariant::Reg{set_input, reset_input, ce_input, dt_input, clk_input, output, ..} => {
+                if set_input.is_some() {
+                    nets.get_mut(set_input.unwrap()).sinks.push(node_idx);
+                }
+                if reset_input.is_some() {
+                    nets.get_mut(reset_input.unwrap()).sinks.push(node_idx);
+                }
+                if ce_input.is_some() {
+                    nets.get_mut(ce_input.unwrap()).sinks.push(node_idx);
+                }
+                nets.get_mut(dt_input).sinks.push(node_idx);
+                nets.get_mut(clk_input).sinks.push(node_idx);
+                set_net_source(nets, output, node_idx)?;
+            },
+            IntermediateGraphNodeVariant::BufgClk{input, output, ..} |
+            IntermediateGraphNodeVariant::BufgGTS{input, output, ..} |
+            IntermediateGraphNodeVariant::BufgGSR{input, output, ..} => {
+                nets.get_mut(input).sinks.push(node_idx);
+                set_net_source(nets, output, node_idx)?;
+            },
+            IntermediateGraphNodeVariant::ClkDiv{input, output, cdrst, ..} => {
+                nets.get_mut(input).sinks.push(node_idx);
+                if let Some(cdrst) = cdrst {
+                    nets.get_mut(cdrst).sinks.push(node_idx);
+                }
+                set_net_source(nets, output, node_idx)?;
+            },
+            IntermediateGraphNodeVariant::DataGate{input} => {
+                nets.get_mut(input).sinks.push(node_idx);
+            },
+            IntermediateGraphNodeVariant::IOBuf{input, oe, output, ..} => {
+                if input.is_some() {
+                    nets.get_mut(input.unwrap()).sinks.push(node_idx);
+                }
+                if oe.is_some() {
+                    nets.get_mut(oe.unwrap()).sinks.push(node_idx);
+                }
+                if output.is_some() {
+                    set_net_source(nets, output.unwrap(), node_idx)?;
+                }
+            },
+            IntermediateGraphNodeVariant::InBuf{output, ..} => {
+                set_net_source(nets, output, node_idx)?;
+            },
+        }
+    }
+
+    // Check for undriven nets
+    for net_idx in nets.iter_idx() {
+        if net_idx == vdd_net || net_idx == vss_net {
+            continue;
+        }
+
+        let net = nets.get(net_idx);
+        if net.source.is_none() && !internal_nets.contains(&net_idx) {
+            error!(logger, "connectivity - undriven net";
+                "code" => "FE021",
+                "net" => &net.name);
+            return Err(FrontendError::NoNetDrivers(net.name.as_ref()
+                .unwrap_or(&"<no name>".to_owned()).to_owned()));
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub struct RequestedLocation {
     pub fb: u32,
@@ -1024,7 +1409,7 @@ pub struct RequestedLocation {
 }
 
 impl RequestedLocation {
-    fn parse_location(loc: Option<&str>, logger: &slog::Logger) -> Result<Option<Self>, FrontendError> {
+    pub(crate) fn parse_location(loc: Option<&str>, logger: &slog::Logger) -> Result<Option<Self>, FrontendError> {
         if loc.is_none() {
             return Ok(None);
         }
@@ -1079,6 +1464,7 @@ impl RequestedLocation {
         let result = f(loc);
         if result.is_err() {
             error!(logger, "loc - malformed";
+                "code" => "FE022",
                 "loc" => loc);
         }
         result
@@ -0,0 +1,429 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Global net inference. Clocks, output enables and asynchronous set/resets that come straight from an input pin (i.e.
+// through a p-term that is just that one pin) are moved onto the global GCK/GTS/GSR buffers if the pin can be placed
+// at the matching dedicated pin. This saves p-terms and ZIA inputs, and the global nets are also much faster.
+
+use std::collections::HashMap;
+use std::fmt;
+use slog::Drain;
+use serde_derive::{Deserialize, Serialize};
+
+use xc2bit::*;
+
+use crate::*;
+use crate::objpool::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum GlobalNetKind {
+    Clock,
+    OutputEnable,
+    SetReset,
+}
+
+impl GlobalNetKind {
+    fn buffer_name(&self) -> &'static str {
+        match *self {
+            GlobalNetKind::Clock => "BUFG",
+            GlobalNetKind::OutputEnable => "BUFGTS",
+            GlobalNetKind::SetReset => "BUFGSR",
+        }
+    }
+
+    // The (FB, MC) of every pin that can drive this kind of global net
+    fn dedicated_pins(&self, device: XC2Device) -> Vec<(u32, u32)> {
+        match *self {
+            GlobalNetKind::Clock => (0..NUM_BUFG_CLK).filter_map(|i| get_gck(device, i)).collect(),
+            GlobalNetKind::OutputEnable => (0..NUM_BUFG_GTS).filter_map(|i| get_gts(device, i)).collect(),
+            GlobalNetKind::SetReset => vec![get_gsr(device)],
+        }
+    }
+}
+
+/// Returns which global buffer (and which one of them) the pin at the given FB/MC can drive, if any
+pub fn global_pin_at(device: XC2Device, fb: u32, mc: u32) -> Option<(GlobalNetKind, usize)> {
+    [GlobalNetKind::Clock, GlobalNetKind::OutputEnable, GlobalNetKind::SetReset].iter()
+        .find_map(|&kind| kind.dedicated_pins(device).iter().position(|&x| x == (fb, mc)).map(|i| (kind, i)))
+}
+
+/// The datasheet name of a dedicated global pin, e.g. GCK0 or GSR
+pub fn global_pin_name(kind: GlobalNetKind, idx: usize) -> String {
+    match kind {
+        GlobalNetKind::Clock => format!("GCK{}", idx),
+        GlobalNetKind::OutputEnable => format!("GTS{}", idx),
+        GlobalNetKind::SetReset => "GSR".to_owned(),
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct PromotedGlobalNet {
+    pub name: String,
+    pub kind: GlobalNetKind,
+    pub fanout: usize,
+}
+
+impl fmt::Display for PromotedGlobalNet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{}\" ({} loads) now uses a {}", self.name, self.fanout, self.kind.buffer_name())
+    }
+}
+
+// Something that is currently driven by a p-term that could be replaced by a global net
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+enum GlobalNetUse {
+    // The bool is whether the clock needs to be inverted
+    Clock(ObjPoolIndex<InputGraphMacrocell>, bool),
+    OutputEnable(ObjPoolIndex<InputGraphMacrocell>),
+    Set(ObjPoolIndex<InputGraphMacrocell>),
+    Reset(ObjPoolIndex<InputGraphMacrocell>),
+}
+
+struct GlobalNetCandidate {
+    kind: GlobalNetKind,
+    pin: ObjPoolIndex<InputGraphMacrocell>,
+    // Clocks can be inverted at each register, so this is always false for them
+    invert: bool,
+    uses: Vec<GlobalNetUse>,
+}
+
+// Returns the pin and polarity if the p-term is nothing but one input pin
+fn single_pin_pterm(pt: &InputGraphPTerm) -> Option<(ObjPoolIndex<InputGraphMacrocell>, bool)> {
+    if pt.requested_loc.is_some() {
+        return None;
+    }
+
+    match (&pt.inputs_true[..], &pt.inputs_comp[..]) {
+        (&[(InputGraphPTermInputType::Pin, mc)], &[]) => Some((mc, false)),
+        (&[], &[(InputGraphPTermInputType::Pin, mc)]) => Some((mc, true)),
+        _ => None,
+    }
+}
+
+impl InputGraph {
+    fn global_net_candidates(&self) -> Vec<GlobalNetCandidate> {
+        let mut ret: Vec<GlobalNetCandidate> = Vec::new();
+        let mut add_use = |kind, pt_idx, make_use: &dyn Fn(bool) -> GlobalNetUse| {
+            if let Some((pin, invert)) = single_pin_pterm(self.pterms.get(pt_idx)) {
+                let this_use = make_use(invert);
+                let invert = kind != GlobalNetKind::Clock && invert;
+                if let Some(x) = ret.iter_mut().find(|x| x.kind == kind && x.pin == pin && x.invert == invert) {
+                    x.uses.push(this_use);
+                } else {
+                    ret.push(GlobalNetCandidate {kind, pin, invert, uses: vec![this_use]});
+                }
+            }
+        };
+
+        for (mc_idx, mc) in self.mcs.iter_idx().map(|x| (x, self.mcs.get(x))) {
+            if let Some(ref reg_bits) = mc.reg_bits {
+                if let InputGraphRegClockType::PTerm(pt_idx) = reg_bits.clk_input {
+                    add_use(GlobalNetKind::Clock, pt_idx, &|invert| GlobalNetUse::Clock(mc_idx, invert));
+                }
+                if let Some(InputGraphRegRSType::PTerm(pt_idx)) = reg_bits.set_input {
+                    add_use(GlobalNetKind::SetReset, pt_idx, &|_| GlobalNetUse::Set(mc_idx));
+                }
+                if let Some(InputGraphRegRSType::PTerm(pt_idx)) = reg_bits.reset_input {
+                    add_use(GlobalNetKind::SetReset, pt_idx, &|_| GlobalNetUse::Reset(mc_idx));
+                }
+            }
+
+            if let Some(ref io_bits) = mc.io_bits {
+                if let Some(InputGraphIOOEType::PTerm(pt_idx)) = io_bits.oe {
+                    add_use(GlobalNetKind::OutputEnable, pt_idx, &|_| GlobalNetUse::OutputEnable(mc_idx));
+                }
+            }
+        }
+
+        // Only macrocells that actually have a pin can drive a global net
+        ret.retain(|x| self.mcs.get(x.pin).io_bits.is_some());
+        // Highest fanout gets first pick of the global buffers. The sort is stable, so ties go in netlist order.
+        ret.sort_by_key(|x| std::cmp::Reverse(x.uses.len()));
+        ret
+    }
+
+    // Returns the pin, polarity, and requested location of every existing global buffer of the given kind
+    fn existing_global_buffers(&self, kind: GlobalNetKind) -> Vec<(ObjPoolIndex<InputGraphMacrocell>, bool,
+        Option<RequestedLocation>)> {
+
+        match kind {
+            GlobalNetKind::Clock => self.bufg_clks.iter().map(|x| (x.input, false, x.requested_loc)).collect(),
+            GlobalNetKind::OutputEnable => self.bufg_gts.iter().map(|x| (x.input, x.invert, x.requested_loc)).collect(),
+            GlobalNetKind::SetReset => self.bufg_gsr.iter().map(|x| (x.input, x.invert, x.requested_loc)).collect(),
+        }
+    }
+
+    /// Moves clocks, output enables, and asynchronous set/resets that are driven directly by an input pin onto global
+    /// buffers wherever the pin can be placed at the corresponding dedicated GCK/GTS/GSR pin. Signals with the most
+    /// loads are promoted first. Returns a list of the signals that were promoted.
+    pub fn infer_global_nets<L: Into<Option<slog::Logger>>>(&mut self, device: XC2Device, logger: L)
+        -> Vec<PromotedGlobalNet> {
+
+        let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+
+        // Pins that the user explicitly placed somewhere
+        let mut pin_owners = HashMap::new();
+        for (mc_idx, mc) in self.mcs.iter_idx().map(|x| (x, self.mcs.get(x))) {
+            if let Some(RequestedLocation{fb, i: Some(i)}) = mc.requested_loc {
+                pin_owners.insert((fb, i), mc_idx);
+            }
+        }
+
+        let mut ret = Vec::new();
+        for kind in &[GlobalNetKind::Clock, GlobalNetKind::OutputEnable, GlobalNetKind::SetReset] {
+            let kind = *kind;
+            let all_pins = kind.dedicated_pins(device);
+            let existing = self.existing_global_buffers(kind);
+
+            // Figure out which dedicated pins are still free. Buffers that don't have a location yet will need one.
+            let mut free_pins = all_pins.clone();
+            let mut unplaced = 0;
+            for &(input, _, requested_loc) in &existing {
+                let pin = match (requested_loc, self.mcs.get(input).requested_loc) {
+                    (Some(RequestedLocation{i: Some(i), ..}), _) => all_pins.get(i as usize).cloned(),
+                    (_, Some(RequestedLocation{fb, i: Some(i)})) => Some((fb, i)),
+                    _ => None,
+                };
+                if let Some(pin) = pin {
+                    free_pins.retain(|&x| x != pin);
+                } else {
+                    unplaced += 1;
+                }
+            }
+
+            for cand in self.global_net_candidates().into_iter().filter(|x| x.kind == kind) {
+                let pin_mc = self.mcs.get(cand.pin);
+                let name = pin_mc.signal_name(InputGraphPTermInputType::Pin).to_owned();
+
+                let existing_idx = existing.iter().position(|x| x.0 == cand.pin);
+                if let Some(existing_idx) = existing_idx {
+                    if existing[existing_idx].1 != cand.invert {
+                        // Already has a buffer, but with the other polarity
+                        continue;
+                    }
+                } else {
+                    let requested_loc = pin_mc.requested_loc;
+                    let usable_pins = free_pins.iter().cloned().filter(|&(fb, i)| {
+                        let owner_ok = pin_owners.get(&(fb, i)).map(|&x| x == cand.pin).unwrap_or(true);
+                        let loc_ok = match requested_loc {
+                            None => true,
+                            Some(RequestedLocation{fb: req_fb, i: None}) => fb == req_fb,
+                            Some(RequestedLocation{fb: req_fb, i: Some(req_i)}) => fb == req_fb && i == req_i,
+                        };
+                        owner_ok && loc_ok
+                    }).collect::<Vec<_>>();
+                    let unowned_pins = free_pins.iter().filter(|x| !pin_owners.contains_key(x)).count();
+
+                    if usable_pins.is_empty() || (requested_loc.is_none() && unowned_pins <= unplaced) {
+                        if kind == GlobalNetKind::Clock {
+                            if requested_loc.is_some() && usable_pins.is_empty() {
+                                warn!(logger,
+                                    "global nets - clock cannot use a global clock because of its pin placement";
+                                    "code" => "GB001",
+                                    "name" => &name,
+                                    "loads" => cand.uses.len());
+                            } else {
+                                warn!(logger, "global nets - no global clock left for clock";
+                                    "code" => "GB002",
+                                    "name" => &name,
+                                    "loads" => cand.uses.len());
+                            }
+                        } else {
+                            debug!(logger, "global nets - no global buffer available";
+                                "kind" => format!("{:?}", kind),
+                                "name" => &name);
+                        }
+                        continue;
+                    }
+
+                    if requested_loc.is_some() {
+                        free_pins.retain(|&x| x != usable_pins[0]);
+                    } else {
+                        // This will get a location during PAR
+                        unplaced += 1;
+                    }
+                }
+
+                info!(logger, "global nets - promoting signal to a global buffer";
+                    "kind" => format!("{:?}", kind),
+                    "name" => &name,
+                    "loads" => cand.uses.len());
+                self.promote_global_net(&cand, existing_idx, &name);
+                ret.push(PromotedGlobalNet {
+                    name,
+                    kind,
+                    fanout: cand.uses.len(),
+                });
+            }
+        }
+
+        if !ret.is_empty() {
+            self.remove_unused_pterms();
+            self.recompute_feedback_used();
+        }
+
+        ret
+    }
+
+    fn promote_global_net(&mut self, cand: &GlobalNetCandidate, existing_idx: Option<usize>, name: &str) {
+        let buf_name = format!("{}_{}", name, cand.kind.buffer_name());
+
+        macro_rules! get_or_insert_buf {
+            ($pool:ident, $new:expr) => {
+                if let Some(i) = existing_idx {
+                    self.$pool.iter_idx().nth(i).unwrap()
+                } else {
+                    self.$pool.insert($new)
+                }
+            }
+        }
+
+        match cand.kind {
+            GlobalNetKind::Clock => {
+                let buf = get_or_insert_buf!(bufg_clks, InputGraphBufgClk {
+                    name: buf_name,
+                    requested_loc: None,
+                    input: cand.pin,
+                });
+                for &x in &cand.uses {
+                    if let GlobalNetUse::Clock(mc_idx, invert) = x {
+                        let reg_bits = self.mcs.get_mut(mc_idx).reg_bits.as_mut().unwrap();
+                        reg_bits.clk_input = InputGraphRegClockType::GCK(buf);
+                        if invert {
+                            reg_bits.clkinv = !reg_bits.clkinv;
+                        }
+                    }
+                }
+            },
+            GlobalNetKind::OutputEnable => {
+                let buf = get_or_insert_buf!(bufg_gts, InputGraphBufgGTS {
+                    name: buf_name,
+                    requested_loc: None,
+                    input: cand.pin,
+                    invert: cand.invert,
+                });
+                for &x in &cand.uses {
+                    if let GlobalNetUse::OutputEnable(mc_idx) = x {
+                        self.mcs.get_mut(mc_idx).io_bits.as_mut().unwrap().oe = Some(InputGraphIOOEType::GTS(buf));
+                    }
+                }
+            },
+            GlobalNetKind::SetReset => {
+                let buf = get_or_insert_buf!(bufg_gsr, InputGraphBufgGSR {
+                    name: buf_name,
+                    requested_loc: None,
+                    input: cand.pin,
+                    invert: cand.invert,
+                });
+                for &x in &cand.uses {
+                    match x {
+                        GlobalNetUse::Set(mc_idx) => {
+                            self.mcs.get_mut(mc_idx).reg_bits.as_mut().unwrap().set_input =
+                                Some(InputGraphRegRSType::GSR(buf));
+                        },
+                        GlobalNetUse::Reset(mc_idx) => {
+                            self.mcs.get_mut(mc_idx).reg_bits.as_mut().unwrap().reset_input =
+                                Some(InputGraphRegRSType::GSR(buf));
+                        },
+                        _ => {},
+                    }
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESIGN: &str = r#"
+        q0 := !q0; q1 := q1 $ q0;
+        q0.CLK = clk; q1.CLK = !clk;
+        q0.AR = rst; q1.AR = rst;
+        y = q1 & !a; y.OE = !oe;
+    "#;
+
+    fn input_graph() -> InputGraph {
+        let g = IntermediateGraph::from_equations(DESIGN.as_bytes(), None).unwrap();
+        InputGraph::from_intermed_graph(&g, None).unwrap()
+    }
+
+    fn find_pin(g: &InputGraph, name: &str) -> ObjPoolIndex<InputGraphMacrocell> {
+        g.mcs.iter_idx().find(|&x| g.mcs.get(x).signal_name(InputGraphPTermInputType::Pin) == name).unwrap()
+    }
+
+    #[test]
+    fn globals_promoted() {
+        let mut g = input_graph();
+        let num_pterms = g.pterms.len();
+        let promoted = g.infer_global_nets(XC2Device::XC2C32A, None);
+
+        assert_eq!(promoted.iter().map(|x| (x.name.as_str(), x.kind, x.fanout)).collect::<Vec<_>>(), vec![
+            ("clk", GlobalNetKind::Clock, 2),
+            ("oe", GlobalNetKind::OutputEnable, 1),
+            ("rst", GlobalNetKind::SetReset, 2),
+        ]);
+        // Every clock/reset/output enable had its own p-term
+        assert_eq!(g.pterms.len(), num_pterms - 5);
+        assert_eq!(g.bufg_clks.len(), 1);
+        assert!(g.bufg_gts.iter().next().unwrap().invert);
+        assert!(!g.bufg_gsr.iter().next().unwrap().invert);
+
+        // One of the registers is clocked on the other edge
+        let clkinvs = g.mcs.iter().filter_map(|x| x.reg_bits.as_ref()).map(|x| {
+            assert!(matches!(x.clk_input, InputGraphRegClockType::GCK(_)));
+            assert!(matches!(x.reset_input, Some(InputGraphRegRSType::GSR(_))));
+            x.clkinv
+        }).collect::<Vec<_>>();
+        assert_eq!(clkinvs.len(), 2);
+        assert!(clkinvs[0] != clkinvs[1]);
+
+        // The pins are no longer needed in the ZIA
+        assert!(!g.mcs.get(find_pin(&g, "clk")).io_feedback_used);
+        assert!(g.mcs.get(find_pin(&g, "a")).io_feedback_used);
+
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        assert!(matches!(do_par(&mut g, device_type, &XC2ParOptions::new(), None), PARResult::Success(_)));
+    }
+
+    #[test]
+    fn globals_clock_pin_placement() {
+        let mut g = input_graph();
+        // Not a GCK pin
+        let clk = find_pin(&g, "clk");
+        g.mcs.get_mut(clk).requested_loc = Some(RequestedLocation{fb: 0, i: Some(0)});
+
+        let collector = DiagnosticCollector::new();
+        let logger = slog::Logger::root(collector.clone(), o!());
+        let promoted = g.infer_global_nets(XC2Device::XC2C32A, logger);
+
+        assert!(promoted.iter().all(|x| x.kind != GlobalNetKind::Clock));
+        assert_eq!(g.bufg_clks.len(), 0);
+        let diagnostics = collector.take();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "GB001");
+    }
+}
@@ -0,0 +1,388 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Flattening of hierarchical Yosys netlists. Cells that instantiate another module of the netlist (one that isn't a
+// black box) are replaced by a copy of the contents of that module. Everything that gets copied in is named with the
+// path of instance names leading to it separated by dots (e.g. `u_core.u_fifo.wr_ptr`), so these names still show up
+// in reports and can still be used in constraints.
+
+use std::collections::{HashMap, HashSet};
+use slog::Drain;
+use yosys_netlist_json::{AttributeVal, BitVal, Module, Netlist, Netname};
+
+use crate::*;
+
+/// Separates the instance names in the name of a cell or net that came from a submodule
+pub const HIERARCHY_SEPARATOR: char = '.';
+
+pub(crate) fn is_user_module(netlist: &Netlist, cell_type: &str) -> bool {
+    netlist.modules.get(cell_type)
+        .is_some_and(|x| x.attributes.get("blackbox").and_then(|x| x.to_number()).unwrap_or(0) == 0)
+}
+
+/// Finds the top-level module of a netlist. If a name is given, that module is used regardless of any `top`
+/// attributes. Otherwise, exactly one module must have a `top` attribute.
+pub fn find_top_module<'a>(netlist: &'a Netlist, top: Option<&str>, logger: &slog::Logger)
+    -> Result<&'a str, FrontendError> {
+
+    let mut module_names = netlist.modules.keys().collect::<Vec<_>>();
+    module_names.sort();
+
+    if let Some(top) = top {
+        return if let Some(&module_name) = module_names.iter().find(|&&x| x == top) {
+            debug!(logger, "using requested toplevel yosys netlist module"; "module name" => module_name);
+            Ok(module_name)
+        } else {
+            error!(logger, "requested toplevel yosys netlist module does not exist";
+                "code" => "FE028",
+                "module name" => top);
+            Err(FrontendError::TopModuleNotFound(top.to_owned()))
+        };
+    }
+
+    let mut top_module_name = None;
+    for module_name in module_names {
+        let module = &netlist.modules[module_name];
+        debug!(logger, "found yosys netlist module"; "module name" => module_name);
+
+        if module.attributes.get("top").and_then(|x| x.to_number()).unwrap_or(0) != 0 {
+            // Claims to be a top-level module
+            debug!(logger, "found toplevel yosys netlist module"; "module name" => module_name);
+
+            if top_module_name.is_some() {
+                error!(logger, "found multiple toplevel yosys netlist modules";
+                    "code" => "FE001",
+                    "second module name" => module_name);
+                return Err(FrontendError::MultipleToplevelModules);
+            }
+
+            top_module_name = Some(module_name.as_str());
+        }
+    }
+
+    if let Some(top_module_name) = top_module_name {
+        Ok(top_module_name)
+    } else {
+        error!(logger, "found no toplevel yosys netlist modules"; "code" => "FE002");
+        Err(FrontendError::NoToplevelModules)
+    }
+}
+
+struct Flattener<'a> {
+    netlist: &'a Netlist,
+    module: Module,
+    next_bit: usize,
+    // Bits that turned out to be the same net. This happens when a module connects two of its ports together.
+    aliases: HashMap<usize, BitVal>,
+    logger: &'a slog::Logger,
+}
+
+fn resolve_alias(aliases: &HashMap<usize, BitVal>, mut bit: BitVal) -> BitVal {
+    while let BitVal::N(n) = bit {
+        if let Some(&x) = aliases.get(&n) {
+            bit = x;
+        } else {
+            break;
+        }
+    }
+    bit
+}
+
+impl<'a> Flattener<'a> {
+    fn connect(&mut self, a: BitVal, b: BitVal) {
+        let a = resolve_alias(&self.aliases, a);
+        let b = resolve_alias(&self.aliases, b);
+        match (a, b) {
+            _ if a == b => {},
+            // Bits that were seen first (e.g. the ports of the top-level module) are kept
+            (BitVal::N(a), BitVal::N(b)) => {
+                self.aliases.insert(a.max(b), BitVal::N(a.min(b)));
+            },
+            (BitVal::N(n), x) | (x, BitVal::N(n)) => {
+                self.aliases.insert(n, x);
+            },
+            _ => {
+                warn!(self.logger, "flatten - module connects two different constants together";
+                    "code" => "FE030",
+                    "first" => a,
+                    "second" => b);
+            },
+        }
+    }
+
+    // Bits of the top-level module are used as-is, and all other bits are translated through the map for the
+    // instance. Bits that only exist inside the instance get a new number.
+    fn map_bit(&mut self, bit_map: &mut Option<HashMap<usize, BitVal>>, bit: BitVal) -> BitVal {
+        match (bit, bit_map) {
+            (BitVal::N(n), Some(bit_map)) => {
+                *bit_map.entry(n).or_insert_with(|| {
+                    self.next_bit += 1;
+                    BitVal::N(self.next_bit - 1)
+                })
+            },
+            _ => bit,
+        }
+    }
+
+    fn flatten(&mut self, module_name: &str, prefix: &str, bit_map: &mut Option<HashMap<usize, BitVal>>,
+        stack: &mut Vec<&'a str>) -> Result<(), FrontendError> {
+
+        let netlist = self.netlist;
+        let module = &netlist.modules[module_name];
+
+        let mut cell_names = module.cells.keys().collect::<Vec<_>>();
+        cell_names.sort();
+        for cell_name in cell_names {
+            let cell = &module.cells[cell_name];
+            let full_name = format!("{}{}", prefix, cell_name);
+
+            if !is_user_module(netlist, &cell.cell_type) {
+                let mut cell = cell.clone();
+                for connection in cell.connections.values_mut() {
+                    for bit in connection.iter_mut() {
+                        *bit = self.map_bit(bit_map, *bit);
+                    }
+                }
+                self.module.cells.insert(full_name, cell);
+                continue;
+            }
+
+            let (submodule_name, submodule) = netlist.modules.get_key_value(&cell.cell_type).unwrap();
+            if stack.contains(&submodule_name.as_str()) {
+                error!(self.logger, "flatten - module instantiates itself";
+                    "code" => "FE029",
+                    "instance" => &full_name,
+                    "module name" => submodule_name);
+                return Err(FrontendError::RecursiveInstance(full_name));
+            }
+            debug!(self.logger, "flatten - instantiating module";
+                "instance" => &full_name,
+                "module name" => submodule_name);
+
+            // Hook the ports of the submodule up to whatever the instance is connected to. Ports that aren't
+            // connected end up as nets that only exist inside the instance.
+            let mut sub_bit_map = HashMap::new();
+            let mut port_names = submodule.ports.keys().collect::<Vec<_>>();
+            port_names.sort();
+            for port_name in port_names {
+                let port = &submodule.ports[port_name];
+                for (i, &sub_bit) in port.bits.iter().enumerate() {
+                    let outer_bit = match cell.connections.get(port_name).and_then(|x| x.get(i)) {
+                        Some(&x) => self.map_bit(bit_map, x),
+                        None => continue,
+                    };
+                    if let BitVal::N(n) = sub_bit {
+                        if let Some(&existing_bit) = sub_bit_map.get(&n) {
+                            self.connect(existing_bit, outer_bit);
+                        } else {
+                            sub_bit_map.insert(n, outer_bit);
+                        }
+                    } else {
+                        self.connect(sub_bit, outer_bit);
+                    }
+                }
+            }
+
+            stack.push(submodule_name);
+            self.flatten(submodule_name, &format!("{}{}", full_name, HIERARCHY_SEPARATOR), &mut Some(sub_bit_map),
+                stack)?;
+            stack.pop();
+        }
+
+        // The ports of a submodule are already named by the module that instantiates it
+        let port_bits = if bit_map.is_some() {
+            module.ports.values().flat_map(|x| x.bits.iter().cloned()).collect::<HashSet<_>>()
+        } else {
+            HashSet::new()
+        };
+        let mut netname_names = module.netnames.keys().collect::<Vec<_>>();
+        netname_names.sort();
+        for netname_name in netname_names {
+            let netname = &module.netnames[netname_name];
+            if bit_map.is_some() && netname.bits.iter().all(|x| port_bits.contains(x)) {
+                continue;
+            }
+
+            let bits = netname.bits.iter().map(|&x| self.map_bit(bit_map, x)).collect();
+            self.module.netnames.insert(format!("{}{}", prefix, netname_name), Netname {
+                hide_name: netname.hide_name,
+                bits,
+                attributes: netname.attributes.clone(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Turns a netlist into one that only has a single top-level module, with every module that it instantiates
+/// (directly or indirectly) copied into it. The top-level module is found using `find_top_module`.
+pub fn flatten_yosys_netlist<L: Into<Option<slog::Logger>>>(netlist: &Netlist, top: Option<&str>, logger: L)
+    -> Result<Netlist, FrontendError> {
+
+    let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+    let top_module_name = find_top_module(netlist, top, &logger)?;
+    let top_module = &netlist.modules[top_module_name];
+
+    // New nets are numbered after all of the ones in the top-level module
+    let port_bits = top_module.ports.values().flat_map(|x| x.bits.iter());
+    let cell_bits = top_module.cells.values().flat_map(|x| x.connections.values().flat_map(|x| x.iter()));
+    let netname_bits = top_module.netnames.values().flat_map(|x| x.bits.iter());
+    let next_bit = port_bits.chain(cell_bits).chain(netname_bits)
+        .filter_map(|x| if let &BitVal::N(n) = x { Some(n + 1) } else { None })
+        .max().unwrap_or(0);
+
+    let mut flattener = Flattener {
+        netlist,
+        module: Module {
+            attributes: top_module.attributes.clone(),
+            ports: top_module.ports.clone(),
+            cells: HashMap::new(),
+            netnames: HashMap::new(),
+        },
+        next_bit,
+        aliases: HashMap::new(),
+        logger: &logger,
+    };
+    flattener.flatten(top_module_name, "", &mut None, &mut vec![top_module_name])?;
+
+    let mut module = flattener.module;
+    if !flattener.aliases.is_empty() {
+        let port_bits = module.ports.values_mut().flat_map(|x| x.bits.iter_mut());
+        let cell_bits = module.cells.values_mut().flat_map(|x| x.connections.values_mut().flat_map(|x| x.iter_mut()));
+        let netname_bits = module.netnames.values_mut().flat_map(|x| x.bits.iter_mut());
+        for bit in port_bits.chain(cell_bits).chain(netname_bits) {
+            *bit = resolve_alias(&flattener.aliases, *bit);
+        }
+    }
+    module.attributes.insert("top".to_owned(), AttributeVal::N(1));
+
+    info!(logger, "flatten - flattened netlist";
+        "top module" => top_module_name,
+        "cells" => module.cells.len());
+
+    let mut modules = HashMap::new();
+    modules.insert(top_module_name.to_owned(), module);
+    Ok(Netlist {
+        creator: netlist.creator.clone(),
+        modules,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `top` instantiates `sub` as `u_sub`, which passes `a` straight through to `y` and also inverts `z` into `z_n`
+    const NETLIST: &[u8] = br#"{
+        "modules": {
+            "top": {
+                "attributes": {"top": 1},
+                "ports": {
+                    "a": {"direction": "input", "bits": [2]},
+                    "y": {"direction": "output", "bits": [3]}
+                },
+                "cells": {
+                    "u_sub": {"type": "sub", "connections": {"a": [2], "y": [3], "z": ["0"]}}
+                },
+                "netnames": {
+                    "a": {"bits": [2]},
+                    "y": {"bits": [3]}
+                }
+            },
+            "sub": {
+                "ports": {
+                    "a": {"direction": "input", "bits": [2]},
+                    "y": {"direction": "output", "bits": [2]},
+                    "z": {"direction": "input", "bits": [3]}
+                },
+                "cells": {
+                    "inv": {"type": "$_NOT_", "connections": {"A": [3], "Y": [4]}}
+                },
+                "netnames": {
+                    "a": {"bits": [2]},
+                    "z_n": {"bits": [4]}
+                }
+            },
+            "loop": {
+                "cells": {
+                    "u_loop": {"type": "loop", "connections": {}}
+                }
+            }
+        }
+    }"#;
+
+    fn logger() -> slog::Logger {
+        slog::Logger::root(slog::Discard, o!())
+    }
+
+    #[test]
+    fn flatten_hierarchy() {
+        let netlist = Netlist::from_slice(NETLIST).unwrap();
+        let flat = flatten_yosys_netlist(&netlist, None, logger()).unwrap();
+        assert_eq!(flat.modules.len(), 1);
+        let module = &flat.modules["top"];
+
+        // The pass-through turns the two top-level ports into the same net
+        assert_eq!(module.ports["y"].bits, module.ports["a"].bits);
+
+        // Only the names that don't duplicate a port are copied, with the instance name in front
+        let mut netname_names = module.netnames.keys().collect::<Vec<_>>();
+        netname_names.sort();
+        assert_eq!(netname_names, ["a", "u_sub.z_n", "y"]);
+
+        let inv = &module.cells["u_sub.inv"];
+        assert_eq!(inv.connections["A"], [BitVal::S(yosys_netlist_json::SpecialBit::_0)]);
+        assert_eq!(inv.connections["Y"], module.netnames["u_sub.z_n"].bits);
+        assert!(!module.cells.contains_key("u_sub"));
+    }
+
+    #[test]
+    fn select_top_module() {
+        let netlist = Netlist::from_slice(NETLIST).unwrap();
+        assert_eq!(find_top_module(&netlist, None, &logger()), Ok("top"));
+        assert_eq!(find_top_module(&netlist, Some("sub"), &logger()), Ok("sub"));
+        assert_eq!(find_top_module(&netlist, Some("nope"), &logger()),
+            Err(FrontendError::TopModuleNotFound("nope".to_owned())));
+
+        let flat = flatten_yosys_netlist(&netlist, Some("sub"), logger()).unwrap();
+        assert!(flat.modules["sub"].cells.contains_key("inv"));
+        assert_eq!(flat.modules["sub"].attributes["top"].to_number(), Some(1));
+
+        assert_eq!(flatten_yosys_netlist(&netlist, Some("loop"), logger()),
+            Err(FrontendError::RecursiveInstance("u_loop".to_owned())));
+    }
+
+    #[test]
+    fn convert_flat_netlist() {
+        let input = include_bytes!("../../../tests/xc2par/frontend-reftests/hierarchy.json");
+        let netlist = Netlist::from_slice(input).unwrap();
+        let flat = flatten_yosys_netlist(&netlist, None, logger()).unwrap();
+        assert_eq!(IntermediateGraph::from_flat_yosys_netlist(&flat, logger()),
+            IntermediateGraph::from_yosys_netlist(&netlist, logger()));
+        assert_eq!(IntermediateGraph::from_flat_yosys_netlist(&netlist, logger()),
+            Err(FrontendError::MultipleToplevelModules));
+    }
+}
@@ -0,0 +1,393 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// I/O standards and banks. Every pin in an I/O bank shares the same VCCIO, so all of the pins that ask for a particular
+// I/O standard have to be kept in banks that are compatible with it. The SSTL and HSTL standards also need a reference
+// voltage, which comes in on one of the VREF pins of the same bank. On the devices where xc2bit doesn't know which
+// bank each function block is in, all of the banks get the same VCCIO instead.
+
+use std::fmt;
+
+use serde_derive::{Deserialize, Serialize};
+
+use xc2bit::*;
+
+use crate::*;
+
+// Only class I of SSTL/HSTL is supported by the Coolrunner-II
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum IOStandard {
+    LVTTL,
+    LVCMOS33,
+    LVCMOS25,
+    LVCMOS18,
+    LVCMOS15,
+    SSTL2,
+    SSTL3,
+    HSTL,
+}
+
+impl IOStandard {
+    /// Parses the value of an IOSTANDARD attribute (ignoring case). The SSTL/HSTL standards can be named either with
+    /// or without the `_I` suffix.
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s.to_ascii_uppercase().as_ref() {
+            "LVTTL" => Some(IOStandard::LVTTL),
+            "LVCMOS33" => Some(IOStandard::LVCMOS33),
+            "LVCMOS25" => Some(IOStandard::LVCMOS25),
+            "LVCMOS18" => Some(IOStandard::LVCMOS18),
+            "LVCMOS15" => Some(IOStandard::LVCMOS15),
+            "SSTL2" | "SSTL2_I" => Some(IOStandard::SSTL2),
+            "SSTL3" | "SSTL3_I" => Some(IOStandard::SSTL3),
+            "HSTL" | "HSTL_I" => Some(IOStandard::HSTL),
+            _ => None,
+        }
+    }
+
+    /// The bank VCCIO that this standard needs, in millivolts
+    pub fn vccio_mv(&self) -> u32 {
+        match *self {
+            IOStandard::LVTTL | IOStandard::LVCMOS33 | IOStandard::SSTL3 => 3300,
+            IOStandard::LVCMOS25 | IOStandard::SSTL2 => 2500,
+            IOStandard::LVCMOS18 => 1800,
+            IOStandard::LVCMOS15 | IOStandard::HSTL => 1500,
+        }
+    }
+
+    /// Returns true if the bank needs to be set to the "high" (2.5V/3.3V) voltage range rather than the "low"
+    /// (1.5V/1.8V) one
+    pub fn is_high_voltage(&self) -> bool {
+        self.vccio_mv() >= 2500
+    }
+
+    /// Returns true if the input buffer is a differential one that compares against VREF
+    pub fn uses_vref(&self) -> bool {
+        matches!(*self, IOStandard::SSTL2 | IOStandard::SSTL3 | IOStandard::HSTL)
+    }
+}
+
+impl fmt::Display for IOStandard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            IOStandard::LVTTL => "LVTTL",
+            IOStandard::LVCMOS33 => "LVCMOS33",
+            IOStandard::LVCMOS25 => "LVCMOS25",
+            IOStandard::LVCMOS18 => "LVCMOS18",
+            IOStandard::LVCMOS15 => "LVCMOS15",
+            IOStandard::SSTL2 => "SSTL2_I",
+            IOStandard::SSTL3 => "SSTL3_I",
+            IOStandard::HSTL => "HSTL_I",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// The bank that a pin was constrained to, if the constraint is specific enough to know it
+fn requested_bank(mc: &InputGraphMacrocell, device: XC2Device) -> Option<usize> {
+    mc.requested_loc.and_then(|loc| get_fb_bank(device, loc.fb))
+}
+
+/// Returns, for each bank that has pins constrained into it that need different voltages, the names of those pins
+pub fn io_bank_conflicts(g: &InputGraph, device: XC2Device) -> Vec<(usize, Vec<String>)> {
+    let mut ret = Vec::new();
+
+    for bank in 0..device.num_banks() {
+        let mut vccio = None;
+        let mut signals = Vec::new();
+        let mut conflict = false;
+        for mc in g.mcs.iter() {
+            if let Some(io_standard) = mc.io_bits.as_ref().and_then(|x| x.io_standard) {
+                if requested_bank(mc, device) == Some(bank) {
+                    if vccio.is_some_and(|x| x != io_standard.vccio_mv()) {
+                        conflict = true;
+                    }
+                    vccio = Some(io_standard.vccio_mv());
+                    signals.push(format!("{} ({})", mc.pad_name(), io_standard));
+                }
+            }
+        }
+
+        if conflict {
+            ret.push((bank, signals));
+        }
+    }
+
+    ret
+}
+
+// Picks the function block in the bank that has the fewest pins constrained to it already
+fn least_used_fb_in_bank(g: &InputGraph, device: XC2Device, bank: usize) -> u32 {
+    (0..device.num_fbs() as u32)
+        .filter(|&fb| get_fb_bank(device, fb) == Some(bank))
+        .min_by_key(|&fb| g.mcs.iter().filter(|mc| mc.requested_loc.is_some_and(|loc| loc.fb == fb)).count())
+        .unwrap()
+}
+
+/// Checks that the requested I/O standards can all be satisfied by the banks of the device. If the design needs more
+/// than one VCCIO, pins that aren't already constrained to a bank are constrained to a function block in a compatible
+/// one. The VREF pin of every bank that has pins that need one is then reserved. If the banks of the device aren't
+/// known, all of the pins have to agree on the VCCIO.
+pub fn assign_io_banks(g: &mut InputGraph, device_type: XC2DeviceSpeedPackage, logger: &slog::Logger)
+    -> PARSanityResult {
+
+    let device = device_type.dev;
+    let io_standards = g.mcs.iter_idx()
+        .filter_map(|mc_idx| g.mcs.get(mc_idx).io_bits.as_ref().and_then(|x| x.io_standard).map(|x| (mc_idx, x)))
+        .collect::<Vec<_>>();
+    if io_standards.is_empty() {
+        return PARSanityResult::Ok;
+    }
+
+    if device.is_small_iob() {
+        for &(mc_idx, io_standard) in &io_standards {
+            if io_standard.uses_vref() {
+                error!(logger, "PAR (sanity) - I/O standard needs VREF, but this device doesn't support it";
+                    "code" => "PR019",
+                    "io pad name" => &g.mcs.get(mc_idx).name,
+                    "iostandard" => io_standard.to_string(),
+                    "device" => device.to_string());
+                return PARSanityResult::FailureFeatureNotSupported;
+            }
+        }
+    }
+
+    if get_fb_bank(device, 0).is_none() {
+        let first_vccio = io_standards[0].1.vccio_mv();
+        for &(mc_idx, io_standard) in &io_standards {
+            if io_standard.uses_vref() {
+                error!(logger, "PAR (sanity) - I/O standard needs VREF, but the banks of this device are unknown";
+                    "code" => "PR025",
+                    "io pad name" => &g.mcs.get(mc_idx).name,
+                    "iostandard" => io_standard.to_string(),
+                    "device" => device.to_string());
+                return PARSanityResult::FailureFeatureNotSupported;
+            }
+            if io_standard.vccio_mv() != first_vccio {
+                error!(logger, "PAR (sanity) - design needs more than one I/O voltage, but the banks of this device \
+                    are unknown";
+                    "code" => "PR026",
+                    "io pad name" => &g.mcs.get(mc_idx).name,
+                    "iostandard" => io_standard.to_string(),
+                    "device" => device.to_string());
+                return PARSanityResult::FailureIOBankConflict;
+            }
+        }
+        return PARSanityResult::Ok;
+    }
+
+    // Pins that were constrained by the user decide the voltage of their bank
+    let mut bank_vccio = vec![None; device.num_banks()];
+    for &(mc_idx, io_standard) in &io_standards {
+        let mc = g.mcs.get(mc_idx);
+        if let Some(bank) = requested_bank(mc, device) {
+            if bank_vccio[bank].is_some_and(|x| x != io_standard.vccio_mv()) {
+                error!(logger, "PAR (sanity) - pins with incompatible I/O standards are constrained to the same bank";
+                    "code" => "PR020",
+                    "io pad name" => &mc.name,
+                    "iostandard" => io_standard.to_string(),
+                    "bank" => bank);
+                return PARSanityResult::FailureIOBankConflict;
+            }
+            bank_vccio[bank] = Some(io_standard.vccio_mv());
+        }
+    }
+
+    // If everything uses the same voltage and nothing needs VREF, the placer is free to put pins anywhere
+    let needs_vref = io_standards.iter().any(|x| x.1.uses_vref());
+    let mixed_vccio = io_standards.iter().any(|x| x.1.vccio_mv() != io_standards[0].1.vccio_mv());
+    if mixed_vccio || needs_vref {
+        for &(mc_idx, io_standard) in &io_standards {
+            if requested_bank(g.mcs.get(mc_idx), device).is_some() {
+                continue;
+            }
+
+            let vccio = io_standard.vccio_mv();
+            let bank = bank_vccio.iter().position(|&x| x == Some(vccio))
+                .or_else(|| bank_vccio.iter().position(|x| x.is_none()));
+            let bank = if let Some(bank) = bank { bank } else {
+                error!(logger, "PAR (sanity) - design needs more I/O voltages than the device has banks";
+                    "code" => "PR024",
+                    "io pad name" => &g.mcs.get(mc_idx).name,
+                    "iostandard" => io_standard.to_string(),
+                    "num banks" => device.num_banks());
+                return PARSanityResult::FailureIOBankConflict;
+            };
+            bank_vccio[bank] = Some(vccio);
+
+            // A LOC constraint that only names an invalid FB is left alone for the placer to complain about
+            if g.mcs.get(mc_idx).requested_loc.is_some() {
+                continue;
+            }
+            let fb = least_used_fb_in_bank(g, device, bank);
+            info!(logger, "PAR - constraining IO pad to a bank due to IOSTANDARD";
+                "name" => &g.mcs.get(mc_idx).name,
+                "iostandard" => io_standard.to_string(),
+                "bank" => bank,
+                "fb" => fb);
+            g.mcs.get_mut(mc_idx).requested_loc = Some(RequestedLocation{fb, i: None});
+        }
+    }
+
+    // Reserve the VREF pin of each bank that needs it. This has to cope with being run again on the same graph.
+    for bank in 0..device.num_banks() {
+        let vref_standard = io_standards.iter()
+            .filter(|x| x.1.uses_vref() && requested_bank(g.mcs.get(x.0), device) == Some(bank))
+            .map(|x| x.1).next();
+        let vref_standard = if let Some(x) = vref_standard { x } else { continue };
+        let has_vref = g.mcs.iter().any(|mc|
+            mc.io_bits.as_ref().is_some_and(|x| x.is_vref) && requested_bank(mc, device) == Some(bank));
+        if has_vref {
+            continue;
+        }
+
+        let (fb, mc) = if let Some(x) = get_vref_pin(device_type, bank) { x } else {
+            error!(logger, "PAR (sanity) - I/O standard needs VREF, but the VREF pins of this package are unknown";
+                "code" => "PR027",
+                "iostandard" => vref_standard.to_string(),
+                "bank" => bank,
+                "device" => device.to_string(),
+                "package" => device_type.pkg.to_string());
+            return PARSanityResult::FailureFeatureNotSupported;
+        };
+        let name = format!("VREF_BANK{}", bank);
+        info!(logger, "PAR - reserving VREF pin";
+            "name" => &name,
+            "bank" => bank,
+            "fb" => fb,
+            "mc" => mc);
+        g.mcs.insert(InputGraphMacrocell {
+            name,
+            requested_loc: Some(RequestedLocation{fb, i: Some(mc)}),
+            io_bits: Some(InputGraphIOBuf {
+                input: None,
+                oe: None,
+                schmitt_trigger: false,
+                termination_enabled: false,
+                slew_is_fast: false,
+                uses_data_gate: false,
+                io_standard: Some(vref_standard),
+                is_vref: true,
+            }),
+            reg_bits: None,
+            xor_bits: None,
+            io_feedback_used: false,
+            reg_feedback_used: false,
+            xor_feedback_used: false,
+            net_names: InputGraphMacrocellNetNames::default(),
+            area_group: None,
+        });
+    }
+
+    PARSanityResult::Ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::objpool::ObjPoolIndex;
+
+    const INPUT: &[u8] = include_bytes!("../../../tests/xc2par/frontend-reftests/iostandard.json");
+
+    fn with_q_standard(standard: &str) -> String {
+        String::from_utf8(INPUT.to_vec()).unwrap().replace("\"sstl2_i\"", standard)
+    }
+
+    fn run(device_type: XC2DeviceSpeedPackage, input: &str) -> Result<(ParSession<'_>, XC2Bitstream), PARFlowError> {
+        let mut session = ParSession::new(device_type, &XC2ParOptions::new(), None);
+        let bitstream = session.run_all(input.as_bytes())?;
+        Ok((session, bitstream))
+    }
+
+    #[test]
+    fn io_banks_xc2c32a() {
+        // d is locked into FB1 (bank 1) and needs 2.5V, so q has to go into FB2 (bank 0) to get 1.8V
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        let input = with_q_standard("\"LVCMOS18\"");
+        let (session, bitstream) = run(device_type, &input).unwrap();
+        if let XC2BitstreamBits::XC2C32A(ref bits) = bitstream.bits {
+            assert_eq!(bits.ivoltage, [false, true]);
+            assert_eq!(bits.ovoltage, [false, true]);
+        } else {
+            panic!("wrong device");
+        }
+        let g = session.input_graph().unwrap();
+        let go = session.output_graph().unwrap();
+        let q_idx = g.mcs.iter_idx().find(|&x| g.mcs.get(x).io_bits.as_ref()
+            .is_some_and(|x| x.io_standard == Some(IOStandard::LVCMOS18))).unwrap();
+        assert_eq!(go.mcs.get(ObjPoolIndex::from(q_idx)).loc.unwrap().fb, 1);
+
+        // Two different voltages can't share a bank
+        match run(device_type, &with_q_standard("\"LVCMOS18\", \"LOC\": \"FB1\"")) {
+            Err(PARFlowError::PARSanityCheckFailed(PARSanityResult::FailureIOBankConflict, bottlenecks)) => {
+                assert!(matches!(bottlenecks[..], [PARBottleneck::IOBankConflict{bank: Some(1), ..}]));
+            },
+            _ => panic!("bank conflict not detected"),
+        }
+
+        // Three voltages don't fit into two banks
+        let three_input = with_q_standard("\"LVCMOS18\"").replacen("\"attributes\": {\n          },",
+            "\"attributes\": {\n            \"IOSTANDARD\": \"LVCMOS33\"\n          },", 1);
+        match run(device_type, &three_input) {
+            Err(PARFlowError::PARSanityCheckFailed(PARSanityResult::FailureIOBankConflict, bottlenecks)) => {
+                assert!(matches!(bottlenecks[..], [PARBottleneck::IOBankConflict{bank: None, ..}]));
+            },
+            _ => panic!("too many voltages not detected"),
+        }
+
+        // There are no VREF inputs on this device
+        match run(device_type, &with_q_standard("\"sstl2_i\"")) {
+            Err(PARFlowError::PARSanityCheckFailed(PARSanityResult::FailureFeatureNotSupported, _)) => {},
+            _ => panic!("SSTL accepted on a device without VREF"),
+        }
+    }
+
+    #[test]
+    fn io_banks_unknown() {
+        // The banks of the XC2C128 aren't known, so all of them are set to the one voltage that the design uses
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c128-6-tq144").unwrap();
+        let (_, bitstream) = run(device_type, &with_q_standard("\"LVCMOS25\"")).unwrap();
+        if let XC2BitstreamBits::XC2C128(ref bits) = bitstream.bits {
+            assert_eq!(bits.ivoltage, [true, true]);
+            assert_eq!(bits.ovoltage, [true, true]);
+            assert!(!bits.use_vref);
+        } else {
+            panic!("wrong device");
+        }
+
+        // and two different voltages can't be used even in different FBs
+        match run(device_type, &with_q_standard("\"LVCMOS33\", \"LOC\": \"FB2\"")) {
+            Err(PARFlowError::PARSanityCheckFailed(PARSanityResult::FailureIOBankConflict, bottlenecks)) => {
+                assert!(matches!(bottlenecks[..], [PARBottleneck::IOBankConflict{bank: None, ..}]));
+            },
+            _ => panic!("voltage conflict not detected"),
+        }
+
+        match run(device_type, &with_q_standard("\"sstl2_i\"")) {
+            Err(PARFlowError::PARSanityCheckFailed(PARSanityResult::FailureFeatureNotSupported, _)) => {},
+            _ => panic!("SSTL accepted without a known VREF pin"),
+        }
+    }
+}
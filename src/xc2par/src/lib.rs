@@ -26,15 +26,47 @@ OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 #[macro_use]
 extern crate slog;
 
+mod areagroup;
+pub use crate::areagroup::*;
+
+mod blif;
+pub use crate::blif::*;
+
 mod compiler_driver;
 pub use crate::compiler_driver::*;
 
+mod constraints;
+pub use crate::constraints::*;
+
+mod diagnostics;
+pub use crate::diagnostics::*;
+
+mod equivalence;
+pub use crate::equivalence::*;
+
 mod frontend;
 pub use crate::frontend::*;
 
+mod globals;
+pub use crate::globals::*;
+
+mod hierarchy;
+pub use crate::hierarchy::*;
+
+mod iostandard;
+pub use crate::iostandard::*;
+
 mod engine;
 pub use crate::engine::*;
 
+mod equations;
+pub use crate::equations::*;
+
+mod explain;
+pub use crate::explain::*;
+
+mod minimize;
+
 mod netlist;
 pub use crate::netlist::*;
 
@@ -42,3 +74,30 @@ mod objpool;
 
 mod output;
 pub use crate::output::*;
+
+mod pack;
+pub use crate::pack::*;
+
+mod pinout;
+pub use crate::pinout::*;
+
+mod pinplan;
+pub use crate::pinplan::*;
+
+mod postfit;
+pub use crate::postfit::*;
+
+mod prng;
+
+mod report;
+pub use crate::report::*;
+
+mod session;
+pub use crate::session::*;
+
+mod sitesearch;
+
+mod split;
+pub use crate::split::*;
+
+mod techmap;
@@ -0,0 +1,563 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Two-level logic minimization of the sum-of-products feeding each macrocell. This is a (much) simplified version of
+// the Espresso heuristic: every cube is expanded as far as possible against the OFF-set, and then redundant cubes
+// are removed. Both the function and its complement are minimized so that the cheaper one can be chosen using the
+// XOR gate polarity.
+
+use std::collections::{HashMap, HashSet};
+use slog::Drain;
+
+use crate::*;
+use crate::objpool::*;
+
+// Every recursive step counts against this so that pathological functions cannot make the fitter hang. If it runs
+// out, the macrocell is simply left alone.
+pub(crate) const MINIMIZE_BUDGET: u32 = 100000;
+
+// A product term over at most 64 variables. A set bit in `pos` means that the variable appears uncomplemented, and
+// a set bit in `neg` means that it appears complemented. Cubes with both bits set for a variable are empty and are
+// never stored.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct Cube {
+    pub(crate) pos: u64,
+    pub(crate) neg: u64,
+}
+
+impl Cube {
+    pub(crate) const UNIVERSE: Cube = Cube {pos: 0, neg: 0};
+
+    pub(crate) fn num_literals(&self) -> u32 {
+        self.pos.count_ones() + self.neg.count_ones()
+    }
+
+    pub(crate) fn contains(&self, other: &Cube) -> bool {
+        (self.pos & !other.pos) == 0 && (self.neg & !other.neg) == 0
+    }
+
+    pub(crate) fn intersects(&self, other: &Cube) -> bool {
+        (self.pos & other.neg) == 0 && (self.neg & other.pos) == 0
+    }
+}
+
+pub(crate) struct Budget(pub(crate) u32);
+
+impl Budget {
+    pub(crate) fn spend(&mut self) -> Option<()> {
+        if self.0 == 0 {
+            None
+        } else {
+            self.0 -= 1;
+            Some(())
+        }
+    }
+}
+
+fn cofactor(cover: &[Cube], c: &Cube) -> Vec<Cube> {
+    cover.iter().filter(|d| d.intersects(c)).map(|d| Cube {
+        pos: d.pos & !c.pos,
+        neg: d.neg & !c.neg,
+    }).collect()
+}
+
+// Picks the variable that appears in the most cubes, preferring variables that appear in both polarities. Returns
+// None if the cover is unate.
+fn most_binate_var(cover: &[Cube]) -> Option<(u32, bool)> {
+    let mut best = None;
+    let mut best_score = (false, 0);
+    for var in 0..64 {
+        let bit = 1u64 << var;
+        let num_pos = cover.iter().filter(|c| c.pos & bit != 0).count();
+        let num_neg = cover.iter().filter(|c| c.neg & bit != 0).count();
+        if num_pos + num_neg == 0 {
+            continue;
+        }
+
+        let score = (num_pos > 0 && num_neg > 0, num_pos + num_neg);
+        if best.is_none() || score > best_score {
+            best = Some(var);
+            best_score = score;
+        }
+    }
+
+    best.map(|x| (x, best_score.0))
+}
+
+fn tautology(cover: &[Cube], budget: &mut Budget) -> Option<bool> {
+    budget.spend()?;
+
+    if cover.contains(&Cube::UNIVERSE) {
+        return Some(true);
+    }
+    if cover.is_empty() {
+        return Some(false);
+    }
+
+    match most_binate_var(cover) {
+        // A unate cover without the universal cube can never be a tautology
+        None | Some((_, false)) => Some(false),
+        Some((var, true)) => {
+            let bit = 1u64 << var;
+            Some(tautology(&cofactor(cover, &Cube {pos: bit, neg: 0}), budget)? &&
+                tautology(&cofactor(cover, &Cube {pos: 0, neg: bit}), budget)?)
+        }
+    }
+}
+
+pub(crate) fn remove_contained(cover: &mut Vec<Cube>) {
+    // Bigger cubes (fewer literals) first so that they are the ones that are kept
+    cover.sort_by_key(|c| (c.num_literals(), c.pos, c.neg));
+    let mut ret: Vec<Cube> = Vec::with_capacity(cover.len());
+    for c in cover.iter() {
+        if !ret.iter().any(|x| x.contains(c)) {
+            ret.push(*c);
+        }
+    }
+    *cover = ret;
+}
+
+pub(crate) fn complement(cover: &[Cube], budget: &mut Budget) -> Option<Vec<Cube>> {
+    budget.spend()?;
+
+    if cover.is_empty() {
+        return Some(vec![Cube::UNIVERSE]);
+    }
+    if cover.contains(&Cube::UNIVERSE) {
+        return Some(Vec::new());
+    }
+    if cover.len() == 1 {
+        // De Morgan
+        let c = cover[0];
+        let mut ret = Vec::new();
+        for var in 0..64 {
+            let bit = 1u64 << var;
+            if c.pos & bit != 0 {
+                ret.push(Cube {pos: 0, neg: bit});
+            }
+            if c.neg & bit != 0 {
+                ret.push(Cube {pos: bit, neg: 0});
+            }
+        }
+        return Some(ret);
+    }
+
+    let (var, _) = most_binate_var(cover).unwrap();
+    let bit = 1u64 << var;
+    let mut ret = Vec::new();
+    for c in complement(&cofactor(cover, &Cube {pos: bit, neg: 0}), budget)? {
+        ret.push(Cube {pos: c.pos | bit, neg: c.neg});
+    }
+    for c in complement(&cofactor(cover, &Cube {pos: 0, neg: bit}), budget)? {
+        ret.push(Cube {pos: c.pos, neg: c.neg | bit});
+    }
+    remove_contained(&mut ret);
+    Some(ret)
+}
+
+fn expand(cover: &[Cube], off: &[Cube]) -> Vec<Cube> {
+    let mut ret = Vec::with_capacity(cover.len());
+    for c in cover {
+        let mut c = *c;
+        for var in 0..64 {
+            let bit = 1u64 << var;
+            if (c.pos | c.neg) & bit == 0 {
+                continue;
+            }
+
+            let expanded = Cube {pos: c.pos & !bit, neg: c.neg & !bit};
+            if !off.iter().any(|x| x.intersects(&expanded)) {
+                c = expanded;
+            }
+        }
+        ret.push(c);
+    }
+    remove_contained(&mut ret);
+    ret
+}
+
+fn irredundant(cover: &[Cube], budget: &mut Budget) -> Option<Vec<Cube>> {
+    let mut ret = cover.to_owned();
+    // Try to get rid of the smallest cubes first
+    let mut i = ret.len();
+    while i > 0 {
+        i -= 1;
+        let c = ret[i];
+        let others = ret.iter().enumerate().filter(|&(j, _)| j != i).map(|(_, x)| *x).collect::<Vec<_>>();
+        if tautology(&cofactor(&others, &c), budget)? {
+            ret.remove(i);
+        }
+    }
+    Some(ret)
+}
+
+pub(crate) fn minimize_cover(on: &[Cube], off: &[Cube], budget: &mut Budget) -> Option<Vec<Cube>> {
+    let expanded = expand(on, off);
+    irredundant(&expanded, budget)
+}
+
+fn cover_cost(cover: &[Cube]) -> (usize, u32) {
+    (cover.len(), cover.iter().map(|c| c.num_literals()).sum())
+}
+
+// Returns the minimized covers of both the function and its complement
+pub(crate) fn minimize_both_polarities(on: &[Cube]) -> Option<(Vec<Cube>, Vec<Cube>)> {
+    let mut budget = Budget(MINIMIZE_BUDGET);
+
+    let mut on = on.to_owned();
+    remove_contained(&mut on);
+    let off = complement(&on, &mut budget)?;
+
+    let on_min = minimize_cover(&on, &off, &mut budget)?;
+    let off_min = minimize_cover(&off, &on, &mut budget)?;
+    Some((on_min, off_min))
+}
+
+// Returns the minimized cover and whether or not it is for the complement of the function
+pub(crate) fn minimize_function(on: &[Cube]) -> Option<(Vec<Cube>, bool)> {
+    let (on_min, off_min) = minimize_both_polarities(on)?;
+
+    if cover_cost(&off_min) < cover_cost(&on_min) {
+        Some((off_min, true))
+    } else {
+        Some((on_min, false))
+    }
+}
+
+type PTermKey = (Vec<InputGraphPTermInput>, Vec<InputGraphPTermInput>);
+
+fn pterm_key(pt: &InputGraphPTerm) -> PTermKey {
+    let mut inputs_true = pt.inputs_true.clone();
+    let mut inputs_comp = pt.inputs_comp.clone();
+    sort_pterm_inputs(&mut inputs_true);
+    sort_pterm_inputs(&mut inputs_comp);
+    (inputs_true, inputs_comp)
+}
+
+fn cube_to_pterm_inputs(c: &Cube, vars: &[InputGraphPTermInput]) -> PTermKey {
+    let mut inputs_true = Vec::new();
+    let mut inputs_comp = Vec::new();
+    for (var, &input) in vars.iter().enumerate() {
+        if c.pos & (1u64 << var) != 0 {
+            inputs_true.push(input);
+        }
+        if c.neg & (1u64 << var) != 0 {
+            inputs_comp.push(input);
+        }
+    }
+    (inputs_true, inputs_comp)
+}
+
+// Like `cover_cost`, but p-terms that are identical to one used by another macrocell don't count, since they can
+// share a p-term site if the macrocells end up in the same function block
+fn shared_cover_cost(cover: &[Cube], vars: &[InputGraphPTermInput], pterm_uses: &HashMap<PTermKey, usize>)
+    -> (usize, u32) {
+
+    let unshared = cover.iter().filter(|c| !pterm_uses.contains_key(&cube_to_pterm_inputs(c, vars))).count();
+    (unshared, cover_cost(cover).1)
+}
+
+fn add_pterm_use(pterm_uses: &mut HashMap<PTermKey, usize>, key: PTermKey) {
+    *pterm_uses.entry(key).or_insert(0) += 1;
+}
+
+fn remove_pterm_use(pterm_uses: &mut HashMap<PTermKey, usize>, key: &PTermKey) {
+    let uses = pterm_uses.get_mut(key).unwrap();
+    *uses -= 1;
+    if *uses == 0 {
+        pterm_uses.remove(key);
+    }
+}
+
+impl InputGraph {
+    /// Minimizes the sum-of-products feeding each macrocell, choosing the XOR polarity that needs fewer p-terms.
+    /// P-terms that are identical to one used by another macrocell are counted as free, so that covers which can
+    /// share p-term sites with other macrocells in the same function block are preferred. P-terms are regenerated
+    /// with their inputs in a canonical order.
+    pub fn minimize_logic<L: Into<Option<slog::Logger>>>(&mut self, logger: L) {
+        let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+
+        let mut pterm_uses = HashMap::new();
+        for mc in self.mcs.iter() {
+            if let Some(ref xor_bits) = mc.xor_bits {
+                for &pt_idx in &xor_bits.orterm_inputs {
+                    add_pterm_use(&mut pterm_uses, pterm_key(self.pterms.get(pt_idx)));
+                }
+            }
+        }
+
+        let mut changed_anything = false;
+        for mc_idx in self.mcs.iter_idx().collect::<Vec<_>>() {
+            let mc = self.mcs.get(mc_idx);
+            let xor_bits = if let Some(ref xor_bits) = mc.xor_bits { xor_bits } else { continue; };
+
+            // The PTC input to the XOR gate cannot be folded into the sum-of-products
+            if xor_bits.andterm_input.is_some() || xor_bits.orterm_inputs.is_empty() {
+                continue;
+            }
+            // Don't touch anything that the user explicitly placed
+            if xor_bits.orterm_inputs.iter().any(|&x| self.pterms.get(x).requested_loc.is_some()) {
+                continue;
+            }
+
+            // Assign a bit to each variable, in a canonical order
+            let mut vars = Vec::new();
+            for &pt_idx in &xor_bits.orterm_inputs {
+                let pt = self.pterms.get(pt_idx);
+                vars.extend(pt.inputs_true.iter().chain(pt.inputs_comp.iter()).cloned());
+            }
+            sort_pterm_inputs(&mut vars);
+            vars.dedup();
+            if vars.len() > 64 {
+                continue;
+            }
+            let var_map = vars.iter().enumerate().map(|(i, &x)| (x, i)).collect::<HashMap<_, _>>();
+
+            let mut on = Vec::with_capacity(xor_bits.orterm_inputs.len());
+            for &pt_idx in &xor_bits.orterm_inputs {
+                let pt = self.pterms.get(pt_idx);
+                let mut cube = Cube::UNIVERSE;
+                for input in pt.inputs_true.iter() {
+                    cube.pos |= 1u64 << var_map[input];
+                }
+                for input in pt.inputs_comp.iter() {
+                    cube.neg |= 1u64 << var_map[input];
+                }
+                on.push(cube);
+            }
+
+            let (on_min, off_min) = if let Some(x) = minimize_both_polarities(&on) { x } else {
+                warn!(logger, "minimize - gave up on macrocell, function is too complex";
+                    "code" => "MN001",
+                    "name" => &mc.name);
+                continue;
+            };
+
+            // This macrocell's own p-terms can't be shared with itself
+            let old_keys = xor_bits.orterm_inputs.iter().map(|&x| pterm_key(self.pterms.get(x))).collect::<Vec<_>>();
+            for key in &old_keys {
+                remove_pterm_use(&mut pterm_uses, key);
+            }
+
+            let on_cost = shared_cover_cost(&on_min, &vars, &pterm_uses);
+            let off_cost = shared_cover_cost(&off_min, &vars, &pterm_uses);
+            let (new_cover, flip, new_cost) = if off_cost < on_cost {
+                (off_min, true, off_cost)
+            } else {
+                (on_min, false, on_cost)
+            };
+
+            if new_cost >= shared_cover_cost(&on, &vars, &pterm_uses) {
+                for key in old_keys {
+                    add_pterm_use(&mut pterm_uses, key);
+                }
+                continue;
+            }
+
+            info!(logger, "minimize - simplified macrocell logic";
+                "name" => &mc.name,
+                "old p-terms" => on.len(),
+                "new p-terms" => new_cover.len(),
+                "new unshared p-terms" => new_cost.0,
+                "old literals" => cover_cost(&on).1,
+                "new literals" => new_cost.1,
+                "inverted" => flip);
+
+            let mc_name = mc.name.clone();
+            let mut new_orterm_inputs = Vec::with_capacity(new_cover.len());
+            for (i, c) in new_cover.iter().enumerate() {
+                let (inputs_true, inputs_comp) = cube_to_pterm_inputs(c, &vars);
+                add_pterm_use(&mut pterm_uses, (inputs_true.clone(), inputs_comp.clone()));
+
+                new_orterm_inputs.push(self.pterms.insert(InputGraphPTerm {
+                    name: format!("{}_PT{}", mc_name, i),
+                    requested_loc: None,
+                    inputs_true,
+                    inputs_comp,
+                }));
+            }
+
+            let xor_bits = self.mcs.get_mut(mc_idx).xor_bits.as_mut().unwrap();
+            xor_bits.orterm_inputs = new_orterm_inputs;
+            if flip {
+                xor_bits.invert_out = !xor_bits.invert_out;
+            }
+            changed_anything = true;
+        }
+
+        if changed_anything {
+            self.remove_unused_pterms();
+            self.recompute_feedback_used();
+        }
+    }
+
+    // Rebuilds the p-term pool without any p-terms that are no longer referenced by anything
+    pub(crate) fn remove_unused_pterms(&mut self) {
+        let mut old_pterms = std::mem::replace(&mut self.pterms, ObjPool::new());
+        let mut pterm_map = HashMap::new();
+
+        {
+            let pterms = &mut self.pterms;
+            let mut remap = |x: &mut ObjPoolIndex<InputGraphPTerm>| {
+                *x = *pterm_map.entry(*x).or_insert_with(|| {
+                    let pt = std::mem::replace(old_pterms.get_mut(*x), InputGraphPTerm {
+                        name: String::new(),
+                        requested_loc: None,
+                        inputs_true: Vec::new(),
+                        inputs_comp: Vec::new(),
+                    });
+                    pterms.insert(pt)
+                });
+            };
+
+            for mc in self.mcs.iter_mut() {
+                if let Some(ref mut io_bits) = mc.io_bits {
+                    if let Some(InputGraphIOOEType::PTerm(ref mut x)) = io_bits.oe {
+                        remap(x);
+                    }
+                }
+
+                if let Some(ref mut xor_bits) = mc.xor_bits {
+                    if let Some(ref mut x) = xor_bits.andterm_input {
+                        remap(x);
+                    }
+                    for x in xor_bits.orterm_inputs.iter_mut() {
+                        remap(x);
+                    }
+                }
+
+                if let Some(ref mut reg_bits) = mc.reg_bits {
+                    if let Some(ref mut x) = reg_bits.ce_input {
+                        remap(x);
+                    }
+                    if let InputGraphRegClockType::PTerm(ref mut x) = reg_bits.clk_input {
+                        remap(x);
+                    }
+                    if let Some(InputGraphRegRSType::PTerm(ref mut x)) = reg_bits.set_input {
+                        remap(x);
+                    }
+                    if let Some(InputGraphRegRSType::PTerm(ref mut x)) = reg_bits.reset_input {
+                        remap(x);
+                    }
+                }
+            }
+        }
+    }
+
+    // Minimization can remove the last use of a feedback path, so the flags need to be regenerated
+    pub(crate) fn recompute_feedback_used(&mut self) {
+        let mut used = HashSet::new();
+        for pt in self.pterms.iter() {
+            for &x in pt.inputs_true.iter().chain(pt.inputs_comp.iter()) {
+                used.insert(x);
+            }
+        }
+
+        for (mc_idx, mc) in self.mcs.iter_mut_idx() {
+            mc.io_feedback_used = used.contains(&(InputGraphPTermInputType::Pin, mc_idx));
+            mc.xor_feedback_used = used.contains(&(InputGraphPTermInputType::Xor, mc_idx));
+            mc.reg_feedback_used = used.contains(&(InputGraphPTermInputType::Reg, mc_idx));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    fn eval(cover: &[Cube], assignment: u64) -> bool {
+        cover.iter().any(|c| (c.pos & !assignment) == 0 && (c.neg & assignment) == 0)
+    }
+
+    fn random_cover<R: Rng>(rng: &mut R, num_vars: u32, num_cubes: usize) -> Vec<Cube> {
+        let mut ret = Vec::new();
+        for _ in 0..num_cubes {
+            let mut c = Cube::UNIVERSE;
+            for var in 0..num_vars {
+                match rng.gen_range(0, 3) {
+                    0 => c.pos |= 1 << var,
+                    1 => c.neg |= 1 << var,
+                    _ => {},
+                }
+            }
+            ret.push(c);
+        }
+        ret
+    }
+
+    #[test]
+    fn minimize_preserves_function() {
+        let mut rng: XorShiftRng = SeedableRng::from_seed([1, 2, 3, 4]);
+        for _ in 0..200 {
+            let num_vars = rng.gen_range(1, 8);
+            let num_cubes = rng.gen_range(1, 12);
+            let on = random_cover(&mut rng, num_vars, num_cubes);
+            let (result, flip) = minimize_function(&on).unwrap();
+
+            assert!(cover_cost(&result).0 <= on.len());
+            for assignment in 0..(1u64 << num_vars) {
+                assert_eq!(eval(&on, assignment), eval(&result, assignment) != flip);
+            }
+        }
+    }
+
+    #[test]
+    fn minimize_merges_adjacent() {
+        // a&b | a&!b == a
+        let on = vec![Cube {pos: 0b11, neg: 0}, Cube {pos: 0b01, neg: 0b10}];
+        let (result, flip) = minimize_function(&on).unwrap();
+        assert_eq!(result, vec![Cube {pos: 0b01, neg: 0}]);
+        assert!(!flip);
+    }
+
+    #[test]
+    fn minimize_picks_polarity() {
+        // !(a&b&c) is 3 p-terms, but its complement is 1
+        let on = vec![Cube {pos: 0, neg: 0b001}, Cube {pos: 0, neg: 0b010}, Cube {pos: 0, neg: 0b100}];
+        let (result, flip) = minimize_function(&on).unwrap();
+        assert_eq!(result, vec![Cube {pos: 0b111, neg: 0}]);
+        assert!(flip);
+    }
+
+    fn minimized_graph(input: &str) -> InputGraph {
+        let intermed = IntermediateGraph::from_equations(input.as_bytes(), None).unwrap();
+        let mut g = InputGraph::from_intermed_graph(&intermed, None).unwrap();
+        g.minimize_logic(None);
+        g
+    }
+
+    #[test]
+    fn minimize_shares_pterms() {
+        // y becomes a&b # a&c, where a&c is the same p-term as c&a in z
+        let g = minimized_graph("y = a & b # a & !b & c;\nz = c & a # d;\n");
+        assert_eq!(g.pterms.iter().collect::<HashSet<_>>().len(), 3);
+
+        // Both polarities of the XNOR cost the same, but the complement shares a&!b with w
+        let g = minimized_graph("x = a & b # !a & !b;\nw = a & !b # c;\n");
+        assert_eq!(g.pterms.iter().collect::<HashSet<_>>().len(), 3);
+    }
+}
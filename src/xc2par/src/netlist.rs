@@ -23,7 +23,7 @@ OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
 OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 */
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::error;
 use std::fmt;
 use std::hash::{Hash, Hasher};
@@ -63,6 +63,11 @@ pub struct InputGraphIOBuf {
     pub termination_enabled: bool,
     pub slew_is_fast: bool,
     pub uses_data_gate: bool,
+    #[serde(default)]
+    pub io_standard: Option<IOStandard>,
+    // Set on the pins that were reserved by PAR to bring in the reference voltage for SSTL/HSTL inputs
+    #[serde(default)]
+    pub is_vref: bool,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
@@ -103,6 +108,16 @@ pub struct InputGraphXor {
     pub invert_out: bool,
 }
 
+// Names of the nets (from the original netlist) that are driven by each part of a macrocell. These are only used
+// for reporting things back to the user. For the pin, this is the name of the top-level port if it is known, since the
+// net coming out of the input buffer usually only has a name made up by synthesis.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct InputGraphMacrocellNetNames {
+    pub pin: Option<String>,
+    pub xor: Option<String>,
+    pub reg: Option<String>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct InputGraphMacrocell {
     pub name: String,
@@ -113,6 +128,10 @@ pub struct InputGraphMacrocell {
     pub io_feedback_used: bool,
     pub reg_feedback_used: bool,
     pub xor_feedback_used: bool,
+    #[serde(default)]
+    pub net_names: InputGraphMacrocellNetNames,
+    #[serde(default)]
+    pub area_group: Option<AreaGroup>,
 }
 
 // BuriedComb is compatible with PinInputUnreg and PinInputReg.
@@ -178,6 +197,17 @@ pub struct InputGraphBufgGSR {
     pub invert: bool,
 }
 
+// The clock divider always drives the GCK2 global net, so it is represented by the BUFG for that net plus these
+// additional settings
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct InputGraphClockDiv {
+    pub name: String,
+    pub bufg: ObjPoolIndex<InputGraphBufgClk>,
+    pub div_ratio: XC2ClockDivRatio,
+    pub delay: bool,
+    pub cdrst: Option<ObjPoolIndex<InputGraphMacrocell>>,
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct InputGraph {
     pub mcs: ObjPool<InputGraphMacrocell>,
@@ -185,6 +215,11 @@ pub struct InputGraph {
     pub bufg_clks: ObjPool<InputGraphBufgClk>,
     pub bufg_gts: ObjPool<InputGraphBufgGTS>,
     pub bufg_gsr: ObjPool<InputGraphBufgGSR>,
+    #[serde(default)]
+    pub clock_div: Option<InputGraphClockDiv>,
+    /// The pin that is used as the DataGate enable (DGE) signal
+    #[serde(default)]
+    pub data_gate: Option<ObjPoolIndex<InputGraphMacrocell>>,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
@@ -224,6 +259,46 @@ impl slog::Value for InputGraphAnyPoolIdx {
 }
 
 impl InputGraphMacrocell {
+    // Name of the signal that a p-term input of the given type actually sees. Falls back to the cell name if the
+    // net did not have a name.
+    pub fn signal_name(&self, input_type: InputGraphPTermInputType) -> &str {
+        let net_name = match input_type {
+            InputGraphPTermInputType::Pin => &self.net_names.pin,
+            InputGraphPTermInputType::Xor => &self.net_names.xor,
+            InputGraphPTermInputType::Reg => &self.net_names.reg,
+        };
+
+        net_name.as_ref().map(|x| x.as_str()).unwrap_or(&self.name)
+    }
+
+    // Name of the "most important" signal of this macrocell, used when the macrocell as a whole is being reported
+    pub fn display_name(&self) -> &str {
+        self.net_names.reg.as_ref()
+            .or(self.net_names.xor.as_ref())
+            .or(self.net_names.pin.as_ref())
+            .map(|x| x.as_str()).unwrap_or(&self.name)
+    }
+
+    /// Returns the name of the signal that goes through the IO pad of this macrocell. This is the name of the
+    /// top-level port if it is known.
+    pub fn pad_name(&self) -> &str {
+        if let Some(ref port_name) = self.net_names.pin {
+            return port_name;
+        }
+
+        let signal_name = match self.io_bits.as_ref().and_then(|x| x.input) {
+            None => &self.net_names.pin,
+            Some(InputGraphIOInputType::Xor) => &self.net_names.xor,
+            Some(InputGraphIOInputType::Reg) => &self.net_names.reg,
+        };
+        signal_name.as_ref().map(|x| x.as_str()).unwrap_or(&self.name)
+    }
+
+    /// Checks whether this macrocell is allowed to go into the given FB by its area group (if it has one)
+    pub fn can_be_placed_in_fb(&self, fb: u32) -> bool {
+        self.area_group.as_ref().is_none_or(|x| x.contains_fb(fb))
+    }
+
     pub fn get_type(&self) -> InputGraphMacrocellType {
         if self.io_bits.is_some() {
             if self.io_bits.as_ref().unwrap().input.is_some() {
@@ -264,16 +339,22 @@ impl Eq for InputGraphPTerm { }
 
 impl Hash for InputGraphPTerm {
     // WARNING WARNING assumes that there are no duplicates in the inputs (why would there be?)
+    // The inputs are hashed in the canonical order so that this agrees with the set comparison in PartialEq
     fn hash<H: Hasher>(&self, state: &mut H) {
-        for x in &self.inputs_true {
-            x.hash(state);
-        }
-        for x in &self.inputs_comp {
-            x.hash(state);
-        }
+        let mut inputs_true = self.inputs_true.clone();
+        let mut inputs_comp = self.inputs_comp.clone();
+        sort_pterm_inputs(&mut inputs_true);
+        sort_pterm_inputs(&mut inputs_comp);
+        inputs_true.hash(state);
+        inputs_comp.hash(state);
     }
 }
 
+// Sorts p-term inputs into a canonical order
+pub(crate) fn sort_pterm_inputs(inputs: &mut [InputGraphPTermInput]) {
+    inputs.sort_by_key(|&(input_type, mc_idx)| (mc_idx.get_raw_i(), input_type as u32));
+}
+
 fn combine_names(old: &str, additional: &str) -> String {
     if old == "" {
         additional.to_owned()
@@ -295,6 +376,7 @@ fn combine_locs(old: Option<RequestedLocation>, additional: Option<RequestedLoca
 
         if old_.fb != new.fb {
             error!(logger, "intermed2input - LOCs that need to agree do not agree on FB";
+                "code" => "NL001",
                 "first index" => old_.fb,
                 "second index" => new.fb);
             Err(IntermedToInputError::LocMismatchedFB(old_.fb, new.fb))
@@ -309,6 +391,7 @@ fn combine_locs(old: Option<RequestedLocation>, additional: Option<RequestedLoca
 
                 if old_i != new_i {
                     error!(logger, "intermed2input - LOCs that need to agree do not agree on macrocell";
+                        "code" => "NL002",
                         "first index" => old_i,
                         "second index" => new_i);
                     Err(IntermedToInputError::LocMismatchedMC(old_i, new_i))
@@ -329,6 +412,7 @@ pub enum IntermedToInputError {
     LocMismatchedFB(u32, u32),
     LocMismatchedMC(u32, u32),
     SanityCheckError(&'static str),
+    DuplicateDedicatedResource(String),
 }
 
 impl error::Error for IntermedToInputError {
@@ -339,28 +423,31 @@ impl error::Error for IntermedToInputError {
 
 impl fmt::Display for IntermedToInputError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            &IntermedToInputError::WrongConnectionType(ref s) => {
+        match *self {
+            IntermedToInputError::WrongConnectionType(ref s) => {
                 write!(f, "node is connected to another node of the wrong type - {}", s)
             },
-            &IntermedToInputError::WrongTiedValue(ref s) => {
+            IntermedToInputError::WrongTiedValue(ref s) => {
                 write!(f, "node input is tied to a disallowed constant - {}", s)
             },
-            &IntermedToInputError::WrongPTermInputs(ref s) => {
+            IntermedToInputError::WrongPTermInputs(ref s) => {
                 write!(f, "p-term inputs incorrect (duplicate or in true+comp) - {}", s)
             },
-            &IntermedToInputError::TooManyFeedbacksUsed(ref s) => {
+            IntermedToInputError::TooManyFeedbacksUsed(ref s) => {
                 write!(f, "too many feedback paths used (XOR + register + IO) - {}", s)
             },
-            &IntermedToInputError::SanityCheckError(s) => {
+            IntermedToInputError::SanityCheckError(s) => {
                 write!(f, "sanity check failed - {}", s)
             },
-            &IntermedToInputError::LocMismatchedFB(i1, i2) => {
+            IntermedToInputError::LocMismatchedFB(i1, i2) => {
                 write!(f, "two LOC constraints have mismatched FB index - {}/{}", i1, i2)
             },
-            &IntermedToInputError::LocMismatchedMC(i1, i2) => {
+            IntermedToInputError::LocMismatchedMC(i1, i2) => {
                 write!(f, "two LOC constraints have mismatched macrocell index - {}/{}", i1, i2)
             },
+            IntermedToInputError::DuplicateDedicatedResource(ref s) => {
+                write!(f, "more than one cell needs the same dedicated resource - {}", s)
+            },
         }
     }
 }
@@ -378,6 +465,8 @@ impl InputGraph {
         let mut bufg_clks = ObjPool::new();
         let mut bufg_gts = ObjPool::new();
         let mut bufg_gsr = ObjPool::new();
+        let mut clock_div = None;
+        let mut data_gate = None;
 
         // These are used to map nodes in the intermediate graph to nodes in the new graph
         let mut mcs_map = HashMap::new();
@@ -387,8 +476,7 @@ impl InputGraph {
         let mut bufg_gsr_map = HashMap::new();
 
         // This is for sanity checking to make sure the entire input gets consumed
-        // FIXME: HashSets are not necessarily the most efficient data structure here
-        let mut consumed_inputs = HashSet::new();
+        let mut consumed_inputs = BTreeSet::new();
 
         // The first step is to invoke the "old" macrocell-gathering function so that:
         // * we can generate our macrocells in the "correct" order
@@ -406,6 +494,8 @@ impl InputGraph {
                 io_feedback_used: false,
                 reg_feedback_used: false,
                 xor_feedback_used: false,
+                net_names: InputGraphMacrocellNetNames::default(),
+                area_group: None,
             };
 
             let newg_idx = mcs.insert(dummy_mc);
@@ -500,6 +590,8 @@ impl InputGraph {
             bufg_clks: &'a mut ObjPool<InputGraphBufgClk>,
             bufg_gts: &'a mut ObjPool<InputGraphBufgGTS>,
             bufg_gsr: &'a mut ObjPool<InputGraphBufgGSR>,
+            clock_div: &'a mut Option<InputGraphClockDiv>,
+            data_gate: &'a mut Option<ObjPoolIndex<InputGraphMacrocell>>,
 
             mcs_map: &'a mut HashMap<ObjPoolIndex<IntermediateGraphNode>, ObjPoolIndex<InputGraphMacrocell>>,
             pterms_map: &'a mut HashMap<ObjPoolIndex<IntermediateGraphNode>, ObjPoolIndex<InputGraphPTerm>>,
@@ -507,7 +599,7 @@ impl InputGraph {
             bufg_gts_map: &'a mut HashMap<ObjPoolIndex<IntermediateGraphNode>, ObjPoolIndex<InputGraphBufgGTS>>,
             bufg_gsr_map: &'a mut HashMap<ObjPoolIndex<IntermediateGraphNode>, ObjPoolIndex<InputGraphBufgGSR>>,
 
-            consumed_inputs: &'a mut HashSet<usize>,
+            consumed_inputs: &'a mut BTreeSet<usize>,
         }
 
         fn process_one_intermed_node<'a>(s: &mut process_one_intermed_node_state<'a>,
@@ -527,8 +619,11 @@ impl InputGraph {
                         InputGraphAnyPoolIdx::Macrocell(*s.mcs_map.get(&n_idx).unwrap()),
                     IntermediateGraphNodeVariant::AndTerm{..} =>
                         InputGraphAnyPoolIdx::PTerm(*s.pterms_map.get(&n_idx).unwrap()),
-                    IntermediateGraphNodeVariant::BufgClk{..} =>
+                    IntermediateGraphNodeVariant::BufgClk{..} |
+                    IntermediateGraphNodeVariant::ClkDiv{..} =>
                         InputGraphAnyPoolIdx::BufgClk(*s.bufg_clks_map.get(&n_idx).unwrap()),
+                    IntermediateGraphNodeVariant::DataGate{..} =>
+                        InputGraphAnyPoolIdx::Macrocell(s.data_gate.unwrap()),
                     IntermediateGraphNodeVariant::BufgGTS{..} =>
                         InputGraphAnyPoolIdx::BufgGTS(*s.bufg_gts_map.get(&n_idx).unwrap()),
                     IntermediateGraphNodeVariant::BufgGSR{..} =>
@@ -543,8 +638,8 @@ impl InputGraph {
             s.consumed_inputs.insert(n_idx.get_raw_i());
 
             match n.variant {
-                IntermediateGraphNodeVariant::IOBuf{oe, input, schmitt_trigger, termination_enabled,
-                    slew_is_fast, uses_data_gate, ..} => {
+                IntermediateGraphNodeVariant::IOBuf{oe, input, output, schmitt_trigger, termination_enabled,
+                    slew_is_fast, uses_data_gate, io_standard, ref port_name} => {
 
                     let newg_idx = *s.mcs_map.get(&n_idx).unwrap();
 
@@ -586,6 +681,7 @@ impl InputGraph {
                                         },
                                         _ => {
                                             error!(logger, "intermed2input - input is not an XOR or a register";
+                                                "code" => "NL003",
                                                 "name" => &n.name);
                                             return Err(IntermedToInputError::WrongConnectionType(n.name.to_owned()));
                                         },
@@ -623,6 +719,7 @@ impl InputGraph {
                                     },
                                     _ => {
                                         error!(logger, "intermed2input - input is not an XOR or a register";
+                                            "code" => "NL003",
                                             "name" => &n.name);
                                         return Err(IntermedToInputError::WrongConnectionType(n.name.to_owned()));
                                     },
@@ -656,6 +753,7 @@ impl InputGraph {
                                         InputGraphAnyPoolIdx::BufgGTS(x) => InputGraphIOOEType::GTS(x),
                                         _ => {
                                             error!(logger, "intermed2input - OE is not a P-term or GTS";
+                                                "code" => "NL004",
                                                 "name" => &n.name);
                                             return Err(IntermedToInputError::WrongConnectionType(n.name.to_owned()));
                                         },
@@ -676,6 +774,9 @@ impl InputGraph {
                         assert!(newg_n.io_bits.is_none());
                         newg_n.name = combine_names(&newg_n.name, &n.name);
                         newg_n.requested_loc = combine_locs(newg_n.requested_loc, n.location, logger)?;
+                        let g = s.g;
+                        newg_n.net_names.pin = port_name.clone()
+                            .or_else(|| output.and_then(|x| g.nets.get(x).name.clone()));
                         newg_n.io_bits = Some(InputGraphIOBuf {
                             input,
                             oe,
@@ -683,12 +784,16 @@ impl InputGraph {
                             termination_enabled,
                             slew_is_fast,
                             uses_data_gate,
+                            io_standard,
+                            is_vref: false,
                         });
                     }
 
                     Ok(InputGraphAnyPoolIdx::Macrocell(newg_idx))
                 },
-                IntermediateGraphNodeVariant::InBuf{schmitt_trigger, termination_enabled, uses_data_gate, ..} => {
+                IntermediateGraphNodeVariant::InBuf{output, schmitt_trigger, termination_enabled, uses_data_gate,
+                    io_standard, ref port_name} => {
+
                     let newg_idx = *s.mcs_map.get(&n_idx).unwrap();
 
                     {
@@ -700,6 +805,8 @@ impl InputGraph {
                         assert!(newg_n.io_bits.is_none());
                         newg_n.name = combine_names(&newg_n.name, &n.name);
                         newg_n.requested_loc = combine_locs(newg_n.requested_loc, n.location, logger)?;
+                        let g = s.g;
+                        newg_n.net_names.pin = port_name.clone().or_else(|| g.nets.get(output).name.clone());
                         newg_n.io_bits = Some(InputGraphIOBuf {
                             input: None,
                             oe: None,
@@ -707,12 +814,14 @@ impl InputGraph {
                             termination_enabled,
                             slew_is_fast: true,
                             uses_data_gate,
+                            io_standard,
+                            is_vref: false,
                         });
                     }
 
                     Ok(InputGraphAnyPoolIdx::Macrocell(newg_idx))
                 },
-                IntermediateGraphNodeVariant::Xor{orterm_input, andterm_input, invert_out, ..} => {
+                IntermediateGraphNodeVariant::Xor{orterm_input, andterm_input, invert_out, output} => {
                     let newg_idx = *s.mcs_map.get(&n_idx).unwrap();
 
                     // Visit OR gate
@@ -734,6 +843,7 @@ impl InputGraph {
                                     s.g.nodes.get(input_n).variant {} else {
 
                                     error!(logger, "intermed2input - input is not a P-term";
+                                        "code" => "NL005",
                                         "name" => &s.g.nodes.get(input_n).name);
                                     return Err(IntermedToInputError::WrongConnectionType(
                                         s.g.nodes.get(input_n).name.to_owned()));
@@ -748,6 +858,7 @@ impl InputGraph {
                             }
                         } else {
                             error!(logger, "intermed2input - input is not an OR term";
+                                "code" => "NL006",
                                 "name" => &n.name);
                             return Err(IntermedToInputError::WrongConnectionType(n.name.to_owned()));
                         }
@@ -769,6 +880,7 @@ impl InputGraph {
                                     Some(x)
                                 } else {
                                     error!(logger, "intermed2input - PTC input is not a P-term";
+                                        "code" => "NL007",
                                         "name" => &n.name);
                                     return Err(IntermedToInputError::WrongConnectionType(n.name.to_owned()));
                                 }
@@ -787,6 +899,7 @@ impl InputGraph {
                         assert!(newg_n.xor_bits.is_none());
                         newg_n.name = combine_names(&newg_n.name, &n.name);
                         newg_n.requested_loc = combine_locs(newg_n.requested_loc, n.location, logger)?;
+                        newg_n.net_names.xor = s.g.nets.get(output).name.clone();
                         newg_n.xor_bits = Some(InputGraphXor {
                             orterm_inputs,
                             andterm_input: ptc_input,
@@ -809,6 +922,7 @@ impl InputGraph {
                             IntermediateGraphNodeVariant::InBuf{..} => InputGraphPTermInputType::Pin,
                             _ => {
                                 error!(logger, "intermed2input - input is not a register, XOR, or IO pad";
+                                    "code" => "NL008",
                                     "name" => &n.name);
                                 return Err(IntermedToInputError::WrongConnectionType(n.name.to_owned()));
                             },
@@ -843,6 +957,7 @@ impl InputGraph {
                             IntermediateGraphNodeVariant::InBuf{..} => InputGraphPTermInputType::Pin,
                             _ => {
                                 error!(logger, "intermed2input - input is not a register, XOR, or IO pad";
+                                    "code" => "NL008",
                                     "name" => &n.name);
                                 return Err(IntermedToInputError::WrongConnectionType(n.name.to_owned()));
                             },
@@ -886,7 +1001,7 @@ impl InputGraph {
                     panic!("Internal error - unexpected OR gate here");
                 },
                 IntermediateGraphNodeVariant::Reg{mode, clkinv, clkddr, init_state, set_input, reset_input,
-                    ce_input, dt_input, clk_input, ..} => {
+                    ce_input, dt_input, clk_input, output} => {
 
                     // This one once again expects the item to already be present
                     let newg_idx = *s.mcs_map.get(&n_idx).unwrap();
@@ -904,6 +1019,7 @@ impl InputGraph {
                             },
                             _ => {
                                 error!(logger, "intermed2input - D/T input is not an XOR or IO pad";
+                                    "code" => "NL009",
                                     "name" => &n.name);
                                 return Err(IntermedToInputError::WrongConnectionType(n.name.to_owned()));
                             },
@@ -929,6 +1045,7 @@ impl InputGraph {
                             InputGraphRegClockType::GCK(x)
                         } else {
                             error!(logger, "intermed2input - clock input is not a P-term or global clock";
+                                "code" => "NL010",
                                 "name" => &n.name);
                             return Err(IntermedToInputError::WrongConnectionType(n.name.to_owned()));
                         }
@@ -943,6 +1060,7 @@ impl InputGraph {
                                 Some(x)
                             } else {
                                 error!(logger, "intermed2input - CE input is not a P-term";
+                                    "code" => "NL011",
                                     "name" => &n.name);
                                 return Err(IntermedToInputError::WrongConnectionType(n.name.to_owned()));
                             }
@@ -958,6 +1076,7 @@ impl InputGraph {
                                 None
                             } else if set_input.unwrap() == s.g.vdd_net {
                                 error!(logger, "intermed2input - set input cannot be tied high";
+                                    "code" => "NL012",
                                     "name" => &n.name);
                                 return Err(IntermedToInputError::WrongTiedValue(n.name.to_owned()));
                             } else {
@@ -969,6 +1088,7 @@ impl InputGraph {
                                     Some(InputGraphRegRSType::GSR(x))
                                 } else {
                                     error!(logger, "intermed2input - set input is not a P-term or GSR";
+                                        "code" => "NL013",
                                         "name" => &n.name);
                                     return Err(IntermedToInputError::WrongConnectionType(n.name.to_owned()));
                                 }
@@ -985,6 +1105,7 @@ impl InputGraph {
                                 None
                             } else if reset_input.unwrap() == s.g.vdd_net {
                                 error!(logger, "intermed2input - reset input cannot be tied high";
+                                    "code" => "NL014",
                                     "name" => &n.name);
                                 return Err(IntermedToInputError::WrongTiedValue(n.name.to_owned()));
                             } else {
@@ -996,6 +1117,7 @@ impl InputGraph {
                                     Some(InputGraphRegRSType::GSR(x))
                                 } else {
                                     error!(logger, "intermed2input - reset input is not a P-term or GSR";
+                                        "code" => "NL015",
                                         "name" => &n.name);
                                     return Err(IntermedToInputError::WrongConnectionType(n.name.to_owned()));
                                 }
@@ -1014,6 +1136,7 @@ impl InputGraph {
                         assert!(newg_n.reg_bits.is_none());
                         newg_n.name = combine_names(&newg_n.name, &n.name);
                         newg_n.requested_loc = combine_locs(newg_n.requested_loc, n.location, logger)?;
+                        newg_n.net_names.reg = s.g.nets.get(output).name.clone();
                         newg_n.reg_bits = Some(InputGraphReg {
                             mode,
                             clkinv,
@@ -1038,6 +1161,7 @@ impl InputGraph {
                         IntermediateGraphNodeVariant::InBuf{..} => {},
                         _ => {
                             error!(logger, "intermed2input - input is not an IO pad";
+                                "code" => "NL016",
                                 "name" => &n.name);
                             return Err(IntermedToInputError::WrongConnectionType(n.name.to_owned()));
                         },
@@ -1070,6 +1194,7 @@ impl InputGraph {
                         IntermediateGraphNodeVariant::InBuf{..} => {},
                         _ => {
                             error!(logger, "intermed2input - input is not an IO pad";
+                                "code" => "NL016",
                                 "name" => &n.name);
                             return Err(IntermedToInputError::WrongConnectionType(n.name.to_owned()));
                         },
@@ -1103,6 +1228,7 @@ impl InputGraph {
                         IntermediateGraphNodeVariant::InBuf{..} => {},
                         _ => {
                             error!(logger, "intermed2input - input is not an IO pad";
+                                "code" => "NL016",
                                 "name" => &n.name);
                             return Err(IntermedToInputError::WrongConnectionType(n.name.to_owned()));
                         },
@@ -1129,6 +1255,81 @@ impl InputGraph {
                     s.bufg_gsr_map.insert(n_idx, newg_idx);
                     Ok(InputGraphAnyPoolIdx::BufgGSR(newg_idx))
                 },
+                IntermediateGraphNodeVariant::ClkDiv{input, div_ratio, delay, cdrst, ..} => {
+                    if s.clock_div.is_some() {
+                        error!(logger, "intermed2input - more than one clock divider";
+                            "code" => "NL026",
+                            "name" => &n.name);
+                        return Err(IntermedToInputError::DuplicateDedicatedResource(n.name.to_owned()));
+                    }
+
+                    let input_newg = process_pad_input(s, &n.name, input, logger)?;
+                    let cdrst = if let Some(cdrst) = cdrst {
+                        Some(process_pad_input(s, &n.name, cdrst, logger)?)
+                    } else {
+                        None
+                    };
+
+                    // The divided clock can only go out on GCK2
+                    let newg_n = InputGraphBufgClk {
+                        name: n.name.clone(),
+                        requested_loc: combine_locs(n.location, Some(RequestedLocation{fb: 0, i: Some(2)}), logger)?,
+                        input: input_newg,
+                    };
+
+                    let newg_idx = s.bufg_clks.insert(newg_n);
+                    info!(logger, "intermed2input - adding CLK_DIV";
+                        "name" => &n.name,
+                        "intermed" => n_idx,
+                        "inputgraph" => newg_idx);
+                    s.bufg_clks_map.insert(n_idx, newg_idx);
+                    *s.clock_div = Some(InputGraphClockDiv {
+                        name: n.name.clone(),
+                        bufg: newg_idx,
+                        div_ratio,
+                        delay,
+                        cdrst,
+                    });
+                    Ok(InputGraphAnyPoolIdx::BufgClk(newg_idx))
+                },
+                IntermediateGraphNodeVariant::DataGate{input} => {
+                    let input_newg = process_pad_input(s, &n.name, input, logger)?;
+                    if s.data_gate.is_some() && *s.data_gate != Some(input_newg) {
+                        error!(logger, "intermed2input - more than one DataGate enable pin";
+                            "code" => "NL027",
+                            "name" => &n.name);
+                        return Err(IntermedToInputError::DuplicateDedicatedResource(n.name.to_owned()));
+                    }
+
+                    info!(logger, "intermed2input - adding DataGate enable";
+                        "name" => &n.name,
+                        "intermed" => n_idx,
+                        "inputgraph" => input_newg);
+                    *s.data_gate = Some(input_newg);
+                    Ok(InputGraphAnyPoolIdx::Macrocell(input_newg))
+                },
+            }
+        }
+
+        // Processes the IO pad that drives a dedicated input of a special-purpose cell
+        fn process_pad_input<'a>(s: &mut process_one_intermed_node_state<'a>, name: &str,
+            input: ObjPoolIndex<IntermediateGraphNet>, logger: &slog::Logger)
+            -> Result<ObjPoolIndex<InputGraphMacrocell>, IntermedToInputError> {
+
+            let input_n = s.g.nets.get(input).source;
+            let is_pad = input_n.is_some_and(|x| matches!(s.g.nodes.get(x).variant,
+                IntermediateGraphNodeVariant::IOBuf{..} | IntermediateGraphNodeVariant::InBuf{..}));
+            if !is_pad {
+                error!(logger, "intermed2input - input is not an IO pad";
+                    "code" => "NL016",
+                    "name" => name);
+                return Err(IntermedToInputError::WrongConnectionType(name.to_owned()));
+            }
+
+            if let InputGraphAnyPoolIdx::Macrocell(x) = process_one_intermed_node(s, input_n.unwrap(), logger)? {
+                Ok(x)
+            } else {
+                panic!("Internal error - not a macrocell?");
             }
         }
 
@@ -1141,6 +1342,8 @@ impl InputGraph {
                 bufg_clks: &mut bufg_clks,
                 bufg_gts: &mut bufg_gts,
                 bufg_gsr: &mut bufg_gsr,
+                clock_div: &mut clock_div,
+                data_gate: &mut data_gate,
                 mcs_map: &mut mcs_map,
                 pterms_map: &mut pterms_map,
                 bufg_clks_map: &mut bufg_clks_map,
@@ -1151,6 +1354,16 @@ impl InputGraph {
             for oldg_mc in gathered_mcs {
                 process_one_intermed_node(&mut s, oldg_mc, &logger)?;
             }
+            // These do something even if nothing else is connected to them
+            for n_idx in g.nodes.iter_idx() {
+                match g.nodes.get(n_idx).variant {
+                    IntermediateGraphNodeVariant::ClkDiv{..} |
+                    IntermediateGraphNodeVariant::DataGate{..} => {
+                        process_one_intermed_node(&mut s, n_idx, &logger)?;
+                    },
+                    _ => {},
+                }
+            }
         }
 
         // Check to make sure we visited all the nodes
@@ -1166,6 +1379,8 @@ impl InputGraph {
             bufg_clks,
             bufg_gts,
             bufg_gsr,
+            clock_div,
+            data_gate,
         };
 
         ret.unfuse_pterms(&logger);
@@ -1180,6 +1395,7 @@ impl InputGraph {
             if x.io_feedback_used && x.io_bits.is_none() {
                 error!(logger, "intermed2input (sanity) - the IO part of the macrocell was used, \
                     but the IO data is missing";
+                    "code" => "NL017",
                     "name" => &x.name);
                 return Err(IntermedToInputError::SanityCheckError(
                     "Used IO input but there is no IO data?"));
@@ -1188,6 +1404,7 @@ impl InputGraph {
             if x.reg_feedback_used && x.reg_bits.is_none() {
                 error!(logger, "intermed2input (sanity) - the register part of the macrocell was used, \
                     but the register data is missing";
+                    "code" => "NL018",
                     "name" => &x.name);
                 return Err(IntermedToInputError::SanityCheckError(
                     "Used register input but there is no register data?"));
@@ -1196,6 +1413,7 @@ impl InputGraph {
             if x.xor_feedback_used && x.xor_bits.is_none() {
                 error!(logger, "intermed2input (sanity) - the XOR part of the macrocell was used, \
                     but the XOR data is missing";
+                    "code" => "NL019",
                     "name" => &x.name);
                 return Err(IntermedToInputError::SanityCheckError(
                     "Used XOR input but there is no XOR data?"));
@@ -1203,6 +1421,7 @@ impl InputGraph {
 
             if x.io_feedback_used && x.reg_feedback_used && x.xor_feedback_used {
                 error!(logger, "intermed2input (sanity) - used more than the possible feedback paths";
+                    "code" => "NL020",
                     "name" => &x.name);
                 return Err(IntermedToInputError::TooManyFeedbacksUsed(x.name.to_owned()));
             }
@@ -1212,6 +1431,7 @@ impl InputGraph {
 
                 if x.xor_bits.is_some() {
                     error!(logger, "intermed2input (sanity) - an input pin, but with XOR data?";
+                        "code" => "NL021",
                         "name" => &x.name);
                     return Err(IntermedToInputError::SanityCheckError(
                         "An input pin, but with XOR data?"));
@@ -1228,17 +1448,20 @@ impl InputGraph {
 
             if inputs_true_set.len() != pt.inputs_true.len() {
                 error!(logger, "intermed2input (sanity) - duplicate true input to p-term";
+                    "code" => "NL022",
                     "name" => &pt.name);
                 return Err(IntermedToInputError::WrongPTermInputs(pt.name.to_owned()));
             }
             if inputs_comp_set.len() != pt.inputs_comp.len() {
                 error!(logger, "intermed2input (sanity) - duplicate comp input to p-term";
+                    "code" => "NL023",
                     "name" => &pt.name);
                 return Err(IntermedToInputError::WrongPTermInputs(pt.name.to_owned()));
             }
 
             if inputs_true_set.intersection(&inputs_comp_set).count() != 0 {
                 error!(logger, "intermed2input (sanity) - input to both true and comp input of p-term";
+                    "code" => "NL024",
                     "name" => &pt.name);
                 return Err(IntermedToInputError::WrongPTermInputs(pt.name.to_owned()));
             }
@@ -1251,6 +1474,7 @@ impl InputGraph {
                     if let Some(pt_req_loc) = self.pterms.get($pt).requested_loc {
                         if $mc_req_loc.fb != pt_req_loc.fb {
                             error!(logger, "intermed2input (sanity) - macrocell uses P-term in wrong FB";
+                                "code" => "NL025",
                                 "macrocell name" => &x.name,
                                 "macrocell FB" => $mc_req_loc.fb,
                                 "p-term FB" => pt_req_loc.fb);
@@ -1389,6 +1613,33 @@ mod tests {
     use std::fs::File;
     use std::io::Read;
 
+    #[test]
+    fn gather_is_deterministic() {
+        // Every thread gets its own RandomState keys, so any dependence on hash iteration order shows up as a
+        // difference between the two threads
+        let fit = || {
+            let input = r#"
+                q0 := !q0; q1 := q1 $ q0; q2 := q2 $ (q1 & q0); q3 := q3 $ (q2 & q1 & q0);
+                q0.CLK = clk; q1.CLK = clk; q2.CLK = clk; q3.CLK = clk;
+                q0.AR = rst; q1.AR = rst; q2.AR = rst; q3.AR = rst;
+                y0 = q0 & !a # q3 & b; y1 = q1 & !a # q2 & b; y2 = q0 & q1 & q2 # !b;
+                y0.OE = en; y1.OE = en;
+            "#;
+            let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+            let mut options = XC2ParOptions::new();
+            options.input_format(ParInputFormat::Equations);
+            let mut session = ParSession::new(device_type, &options, None);
+            session.run_all(input.as_bytes()).unwrap();
+            let input_graph = serde_json::to_string(session.input_graph().unwrap()).unwrap();
+            let output_graph = serde_json::to_string(session.output_graph().unwrap()).unwrap();
+            (input_graph, output_graph)
+        };
+
+        let here = fit();
+        assert_eq!(here, fit());
+        assert_eq!(here, std::thread::spawn(fit).join().unwrap());
+    }
+
     fn run_one_reftest(input_filename: &'static str) {
         // Read original json
         let input_path = std::path::Path::new(input_filename);
@@ -1408,6 +1659,19 @@ mod tests {
         assert_eq!(our_data_structure, reference_data_structure);
     }
 
+    #[test]
+    fn pin_net_names_are_port_names() {
+        let input = include_bytes!("../../../tests/xc2par/frontend-reftests/sanity.json");
+        let yosys_netlist = yosys_netlist_json::Netlist::from_slice(input).unwrap();
+        let intermed_graph = IntermediateGraph::from_yosys_netlist(&yosys_netlist, None).unwrap();
+        let g = InputGraph::from_intermed_graph(&intermed_graph, None).unwrap();
+
+        // The nets coming out of the input buffers are only named $auto$iopadmap...
+        let mut pad_names = g.mcs.iter().filter(|x| x.io_bits.is_some()).map(|x| x.pad_name()).collect::<Vec<_>>();
+        pad_names.sort();
+        assert_eq!(pad_names, ["a", "b", "clk_", "q1"]);
+    }
+
     // Include list of actual tests to run
     include!(concat!(env!("OUT_DIR"), "/netlist-reftests.rs"));
 }
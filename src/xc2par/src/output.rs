@@ -123,8 +123,11 @@ pub fn produce_bitstream(device_type: XC2DeviceSpeedPackage, g: &InputGraph, go:
                 } else {
                     let iob_bit = result.bits.get_mut_large_iob(i_iob as usize).unwrap();
 
-                    // TODO: Pins that are/use Vref
-                    iob_bit.ibuf_mode = if io_bits.schmitt_trigger {
+                    iob_bit.ibuf_mode = if io_bits.is_vref {
+                        XC2IOBIbufMode::IsVref
+                    } else if io_bits.io_standard.is_some_and(|x| x.uses_vref()) {
+                        XC2IOBIbufMode::UsesVref
+                    } else if io_bits.schmitt_trigger {
                         XC2IOBIbufMode::NoVrefSt
                     } else {
                         XC2IOBIbufMode::NoVrefNoSt
@@ -305,6 +308,24 @@ pub fn produce_bitstream(device_type: XC2DeviceSpeedPackage, g: &InputGraph, go:
         }
     }
 
+    // Clock divider and DataGate
+    match result.bits {
+        XC2BitstreamBits::XC2C128(XC2BitsXC2C128{ref mut clock_div, ref mut data_gate, ..}) |
+        XC2BitstreamBits::XC2C256(XC2BitsXC2C256{ref mut clock_div, ref mut data_gate, ..}) |
+        XC2BitstreamBits::XC2C384(XC2BitsXC2C384{ref mut clock_div, ref mut data_gate, ..}) |
+        XC2BitstreamBits::XC2C512(XC2BitsXC2C512{ref mut clock_div, ref mut data_gate, ..}) => {
+            if let Some(ref g_clock_div) = g.clock_div {
+                *clock_div = XC2ClockDiv {
+                    div_ratio: g_clock_div.div_ratio,
+                    delay: g_clock_div.delay,
+                    enabled: true,
+                };
+            }
+            *data_gate = g.data_gate.is_some();
+        },
+        _ => {},
+    }
+
     // XXX TODO other global bits
     if let XC2BitstreamBits::XC2C32A(XC2BitsXC2C32A{ref mut legacy_ivoltage, ref mut legacy_ovoltage,
         ref mut ivoltage, ref mut ovoltage, ..}) = result.bits {
@@ -315,5 +336,44 @@ pub fn produce_bitstream(device_type: XC2DeviceSpeedPackage, g: &InputGraph, go:
         *ovoltage = [false, false];
     }
 
+    // Bank voltages. Banks without any pins that have an IOSTANDARD are left at the default (low) setting.
+    let mut bank_high_voltage = vec![false; device_type.dev.num_banks()];
+    for mc_idx in g.mcs.iter_idx() {
+        if let Some(io_standard) = g.mcs.get(mc_idx).io_bits.as_ref().and_then(|x| x.io_standard) {
+            let loc = go.mcs.get(ObjPoolIndex::from(mc_idx)).loc.unwrap();
+            if let Some(bank) = get_fb_bank(device_type.dev, loc.fb) {
+                bank_high_voltage[bank] = io_standard.is_high_voltage();
+            } else {
+                // The banks of this device aren't known, so the sanity check made sure that every pin agrees
+                bank_high_voltage = vec![io_standard.is_high_voltage(); bank_high_voltage.len()];
+            }
+        }
+    }
+    let any_vref = g.mcs.iter().any(|mc| mc.io_bits.as_ref().is_some_and(|x| x.is_vref));
+    match result.bits {
+        XC2BitstreamBits::XC2C32(XC2BitsXC2C32{ref mut ivoltage, ref mut ovoltage, ..}) |
+        XC2BitstreamBits::XC2C64(XC2BitsXC2C64{ref mut ivoltage, ref mut ovoltage, ..}) => {
+            *ivoltage = bank_high_voltage[0];
+            *ovoltage = bank_high_voltage[0];
+        },
+        XC2BitstreamBits::XC2C32A(XC2BitsXC2C32A{ref mut ivoltage, ref mut ovoltage, ..}) |
+        XC2BitstreamBits::XC2C64A(XC2BitsXC2C64A{ref mut ivoltage, ref mut ovoltage, ..}) => {
+            ivoltage.copy_from_slice(&bank_high_voltage);
+            ovoltage.copy_from_slice(&bank_high_voltage);
+        },
+        XC2BitstreamBits::XC2C128(XC2BitsXC2C128{ref mut ivoltage, ref mut ovoltage, ref mut use_vref, ..}) |
+        XC2BitstreamBits::XC2C256(XC2BitsXC2C256{ref mut ivoltage, ref mut ovoltage, ref mut use_vref, ..}) => {
+            ivoltage.copy_from_slice(&bank_high_voltage);
+            ovoltage.copy_from_slice(&bank_high_voltage);
+            *use_vref = any_vref;
+        },
+        XC2BitstreamBits::XC2C384(XC2BitsXC2C384{ref mut ivoltage, ref mut ovoltage, ref mut use_vref, ..}) |
+        XC2BitstreamBits::XC2C512(XC2BitsXC2C512{ref mut ivoltage, ref mut ovoltage, ref mut use_vref, ..}) => {
+            ivoltage.copy_from_slice(&bank_high_voltage);
+            ovoltage.copy_from_slice(&bank_high_voltage);
+            *use_vref = any_vref;
+        },
+    }
+
     result
 }
@@ -0,0 +1,317 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Packing of registers into the IOB paths. An input pin can feed the register in its own macrocell directly (without
+// going through the ZIA and a p-term), and the register in a macrocell can drive the output buffer of that macrocell
+// directly (without the XOR gate). Registers that are only a copy of an input pin, or that only feed an output pin,
+// are moved into the macrocell of that pin so that these fast paths get used. This also frees up a macrocell.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use slog::Drain;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::*;
+use crate::objpool::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum PackedRegisterKind {
+    Input,
+    Output,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct PackedRegister {
+    pub name: String,
+    pub kind: PackedRegisterKind,
+    pub pin: String,
+}
+
+impl fmt::Display for PackedRegister {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            PackedRegisterKind::Input =>
+                write!(f, "\"{}\" now uses the input register of pin \"{}\"", self.name, self.pin),
+            PackedRegisterKind::Output => write!(f, "\"{}\" now directly drives pin \"{}\"", self.name, self.pin),
+        }
+    }
+}
+
+// If the XOR gate only copies a single input (without inverting it), returns that input
+fn xor_copy_of(g: &InputGraph, xor_bits: &InputGraphXor) -> Option<InputGraphPTermInput> {
+    let pt_idx = match (&xor_bits.orterm_inputs[..], xor_bits.andterm_input) {
+        (&[pt_idx], None) | (&[], Some(pt_idx)) => pt_idx,
+        _ => return None,
+    };
+    let pt = g.pterms.get(pt_idx);
+    if xor_bits.invert_out || pt.requested_loc.is_some() || pt.inputs_true.len() != 1 || !pt.inputs_comp.is_empty() {
+        return None;
+    }
+
+    Some(pt.inputs_true[0])
+}
+
+// The register can only move if the locations that were asked for don't disagree
+fn locs_compatible(from: &InputGraphMacrocell, to: &InputGraphMacrocell) -> bool {
+    from.requested_loc.is_none() || from.requested_loc == to.requested_loc
+}
+
+impl InputGraph {
+    /// Moves registers that are fed directly by an input pin into the input register path of that pin, and moves
+    /// buried registers that feed nothing but an output pin into the macrocell of that pin. Only registers in buried
+    /// macrocells are moved, and the macrocell they came from is removed. Returns a list of the registers that were
+    /// moved.
+    pub fn pack_io_registers<L: Into<Option<slog::Logger>>>(&mut self, logger: L) -> Vec<PackedRegister> {
+        let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+
+        // Everything that uses each feedback path, other than the p-term itself
+        let mut users = HashMap::new();
+        for pt_idx in self.pterms.iter_idx() {
+            let pt = self.pterms.get(pt_idx);
+            for &x in pt.inputs_true.iter().chain(pt.inputs_comp.iter()) {
+                users.entry(x).or_insert_with(HashSet::new).insert(pt_idx);
+            }
+        }
+        let mut pterm_users = HashMap::new();
+        for mc in self.mcs.iter() {
+            if let Some(ref xor_bits) = mc.xor_bits {
+                for &pt_idx in xor_bits.orterm_inputs.iter().chain(xor_bits.andterm_input.iter()) {
+                    *pterm_users.entry(pt_idx).or_insert(0) += 1;
+                }
+            }
+            if let Some(ref reg_bits) = mc.reg_bits {
+                let control_pterms = [
+                    reg_bits.ce_input,
+                    if let InputGraphRegClockType::PTerm(x) = reg_bits.clk_input { Some(x) } else { None },
+                    if let Some(InputGraphRegRSType::PTerm(x)) = reg_bits.set_input { Some(x) } else { None },
+                    if let Some(InputGraphRegRSType::PTerm(x)) = reg_bits.reset_input { Some(x) } else { None },
+                ];
+                for pt_idx in control_pterms.iter().flatten() {
+                    *pterm_users.entry(*pt_idx).or_insert(0) += 1;
+                }
+            }
+            if let Some(InputGraphIOOEType::PTerm(pt_idx)) = mc.io_bits.as_ref().and_then(|x| x.oe) {
+                *pterm_users.entry(pt_idx).or_insert(0) += 1;
+            }
+        }
+
+        let mut ret = Vec::new();
+        let mut removed = HashSet::new();
+        // Macrocells whose feedback paths now come from somewhere else
+        let mut moved_feedback = HashMap::new();
+        for mc_idx in self.mcs.iter_idx().collect::<Vec<_>>() {
+            let mc = self.mcs.get(mc_idx);
+            if mc.io_bits.is_some() || removed.contains(&mc_idx) {
+                continue;
+            }
+            let (reg_bits, xor_bits) = match (&mc.reg_bits, &mc.xor_bits) {
+                (Some(reg_bits), Some(xor_bits)) => (reg_bits, xor_bits),
+                _ => continue,
+            };
+
+            // A register that just copies an input pin
+            if reg_bits.dt_input == InputGraphRegInputType::Xor && !mc.xor_feedback_used {
+                if let Some((InputGraphPTermInputType::Pin, pin_idx)) = xor_copy_of(self, xor_bits) {
+                    let pin = self.mcs.get(pin_idx);
+                    if pin.io_bits.as_ref().is_some_and(|x| x.input.is_none()) && pin.reg_bits.is_none() &&
+                        pin.xor_bits.is_none() && locs_compatible(mc, pin) {
+
+                        info!(logger, "pack - moving register into input pin";
+                            "name" => &mc.name,
+                            "pin" => &pin.name);
+                        ret.push(PackedRegister {
+                            name: mc.display_name().to_owned(),
+                            kind: PackedRegisterKind::Input,
+                            pin: pin.pad_name().to_owned(),
+                        });
+
+                        let mut reg_bits = reg_bits.clone();
+                        reg_bits.dt_input = InputGraphRegInputType::Pin;
+                        let reg_name = mc.net_names.reg.clone();
+                        let pin = self.mcs.get_mut(pin_idx);
+                        pin.reg_bits = Some(reg_bits);
+                        pin.net_names.reg = reg_name;
+                        moved_feedback.insert((InputGraphPTermInputType::Reg, mc_idx),
+                            (InputGraphPTermInputType::Reg, pin_idx));
+                        removed.insert(mc_idx);
+                        continue;
+                    }
+                }
+            }
+
+            // A register that only feeds a copy of itself in an output pin
+            let reg_users = users.get(&(InputGraphPTermInputType::Reg, mc_idx));
+            let copy_pt = match reg_users {
+                Some(x) if x.len() == 1 => *x.iter().next().unwrap(),
+                _ => continue,
+            };
+            if pterm_users.get(&copy_pt) != Some(&1) {
+                continue;
+            }
+            let out_idx = self.mcs.iter_idx().find(|&x| {
+                let out = self.mcs.get(x);
+                out.xor_bits.as_ref().is_some_and(|xor_bits| xor_copy_of(self, xor_bits) ==
+                    Some((InputGraphPTermInputType::Reg, mc_idx)))
+            });
+            let out_idx = if let Some(x) = out_idx { x } else { continue };
+            let out = self.mcs.get(out_idx);
+            if out.io_bits.as_ref().is_some_and(|x| x.input == Some(InputGraphIOInputType::Xor)) &&
+                out.reg_bits.is_none() && !out.xor_feedback_used && locs_compatible(mc, out) {
+
+                info!(logger, "pack - moving register into output pin";
+                    "name" => &mc.name,
+                    "pin" => &out.name);
+                ret.push(PackedRegister {
+                    name: mc.display_name().to_owned(),
+                    kind: PackedRegisterKind::Output,
+                    pin: out.pad_name().to_owned(),
+                });
+
+                // The register only ever shows up on the pin, so it takes on the name of the pin
+                let mc = self.mcs.get(mc_idx).clone();
+                let pad_name = out.pad_name().to_owned();
+                let out = self.mcs.get_mut(out_idx);
+                out.reg_bits = mc.reg_bits;
+                out.xor_bits = mc.xor_bits;
+                out.net_names.reg = Some(pad_name);
+                out.net_names.xor = mc.net_names.xor;
+                out.io_bits.as_mut().unwrap().input = Some(InputGraphIOInputType::Reg);
+                moved_feedback.insert((InputGraphPTermInputType::Xor, mc_idx),
+                    (InputGraphPTermInputType::Xor, out_idx));
+                removed.insert(mc_idx);
+            }
+        }
+
+        if !ret.is_empty() {
+            for pt in self.pterms.iter_mut() {
+                for x in pt.inputs_true.iter_mut().chain(pt.inputs_comp.iter_mut()) {
+                    if let Some(&y) = moved_feedback.get(x) {
+                        *x = y;
+                    }
+                }
+            }
+            self.remove_mcs(&removed);
+            self.recompute_feedback_used();
+        }
+
+        ret
+    }
+
+    // Rebuilds the macrocell pool without the given macrocells, along with any p-terms that only they were using.
+    // Nothing else may refer to them anymore.
+    fn remove_mcs(&mut self, removed: &HashSet<ObjPoolIndex<InputGraphMacrocell>>) {
+        let old_mcs = std::mem::replace(&mut self.mcs, ObjPool::new());
+        let mut mc_map = HashMap::new();
+        for (mc_idx, mc) in old_mcs.iter_idx().zip(old_mcs.iter()) {
+            if !removed.contains(&mc_idx) {
+                mc_map.insert(mc_idx, self.mcs.insert(mc.clone()));
+            }
+        }
+        self.remove_unused_pterms();
+
+        for pt in self.pterms.iter_mut() {
+            for x in pt.inputs_true.iter_mut().chain(pt.inputs_comp.iter_mut()) {
+                x.1 = mc_map[&x.1];
+            }
+        }
+        for x in self.bufg_clks.iter_mut() {
+            x.input = mc_map[&x.input];
+        }
+        for x in self.bufg_gts.iter_mut() {
+            x.input = mc_map[&x.input];
+        }
+        for x in self.bufg_gsr.iter_mut() {
+            x.input = mc_map[&x.input];
+        }
+        if let Some(ref mut clock_div) = self.clock_div {
+            clock_div.cdrst = clock_div.cdrst.map(|x| mc_map[&x]);
+        }
+        self.data_gate = self.data_gate.map(|x| mc_map[&x]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use xc2bit::*;
+
+    // r is a copy of pin a, s only feeds pin y, and t is inverted on the way in so it has to stay where it is
+    const DESIGN: &str = r#"
+        NODE r; NODE s; NODE t;
+        r := a; s := b & c; t := !e;
+        r.CLK = clk; s.CLK = clk; t.CLK = clk;
+        y = s;
+        z = r & d # t;
+    "#;
+
+    fn input_graph() -> InputGraph {
+        let g = IntermediateGraph::from_equations(DESIGN.as_bytes(), None).unwrap();
+        InputGraph::from_intermed_graph(&g, None).unwrap()
+    }
+
+    fn find_mc(g: &InputGraph, name: &str) -> ObjPoolIndex<InputGraphMacrocell> {
+        g.mcs.iter_idx().find(|&x| g.mcs.get(x).pad_name() == name).unwrap()
+    }
+
+    #[test]
+    fn pack_io_registers() {
+        let mut g = input_graph();
+        let num_mcs = g.mcs.len();
+        let packed = g.pack_io_registers(None);
+
+        assert_eq!(packed.iter().map(|x| (x.name.as_str(), x.kind, x.pin.as_str())).collect::<Vec<_>>(), vec![
+            ("r", PackedRegisterKind::Input, "a"),
+            ("s", PackedRegisterKind::Output, "y"),
+        ]);
+        assert_eq!(g.mcs.len(), num_mcs - 2);
+
+        let a = g.mcs.get(find_mc(&g, "a"));
+        assert_eq!(a.reg_bits.as_ref().unwrap().dt_input, InputGraphRegInputType::Pin);
+        assert!(a.reg_feedback_used);
+        assert!(!a.io_feedback_used);
+
+        let y = g.mcs.get(find_mc(&g, "y"));
+        assert!(y.reg_bits.is_some());
+        assert_eq!(y.io_bits.as_ref().unwrap().input, Some(InputGraphIOInputType::Reg));
+        assert_eq!(y.net_names.xor.as_ref().unwrap(), "s_D");
+
+        assert_eq!(g.mcs.iter().filter(|x| x.reg_bits.is_some() && x.io_bits.is_none()).count(), 1);
+
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        assert!(matches!(do_par(&mut g, device_type, &XC2ParOptions::new(), None), PARResult::Success(_)));
+    }
+
+    #[test]
+    fn pack_io_registers_respects_loc() {
+        let mut g = input_graph();
+        let r = g.mcs.iter_idx().find(|&x| g.mcs.get(x).display_name() == "r").unwrap();
+        g.mcs.get_mut(r).requested_loc = Some(RequestedLocation{fb: 1, i: None});
+        let packed = g.pack_io_registers(None);
+
+        assert_eq!(packed.len(), 1);
+        assert_eq!(packed[0].kind, PackedRegisterKind::Output);
+    }
+}
@@ -0,0 +1,353 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Final pinout export. After a fit, the pin assignment can be written out as a UCF file (which can be passed back in
+// to lock the pins for the next run), as a CSV pin report, and as a KiCad symbol library.
+//
+// Pins are identified by their package pin numbers from the xc2bit package pin map. Not every package has one yet; for
+// those, the UCF and CSV fall back to the FBn_m site names, and no KiCad symbol can be made. The JTAG and power pins
+// aren't in the pin map either, so they have to be added to the KiCad symbol by hand.
+
+use std::io::Write;
+
+use xc2bit::*;
+
+use crate::*;
+use crate::objpool::*;
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PinoutPin {
+    pub fb: u32,
+    pub mc: u32,
+    /// Index of the IO block, or `None` for the dedicated input pin
+    pub iob: Option<u32>,
+    /// The package pin that this is bonded out to, if the package pin map is known
+    pub package_pin: Option<&'static str>,
+    pub bank: Option<usize>,
+    /// The signal on this pin, or `None` if it is unused
+    pub signal: Option<String>,
+    pub direction: Option<FitReportPinDirection>,
+    pub io_standard: Option<IOStandard>,
+    /// Whether this pin was reserved for the reference voltage of SSTL/HSTL inputs
+    pub is_vref: bool,
+}
+
+impl PinoutPin {
+    pub fn site_name(&self) -> String {
+        format!("FB{}_{}", self.fb + 1, self.mc + 1)
+    }
+
+    /// The special functions of this pin (e.g. GCK0), separated by slashes
+    pub fn functions(&self, device: XC2Device) -> String {
+        let mut ret = Vec::new();
+        if let Some((kind, idx)) = global_pin_at(device, self.fb, self.mc) {
+            ret.push(global_pin_name(kind, idx));
+        }
+        if get_cdrst(device) == Some((self.fb, self.mc)) {
+            ret.push("CDRST".to_owned());
+        }
+        if get_dge(device) == Some((self.fb, self.mc)) {
+            ret.push("DGE".to_owned());
+        }
+        if self.iob.is_none() {
+            ret.push("INPUT_ONLY".to_owned());
+        }
+        if self.is_vref {
+            ret.push("VREF".to_owned());
+        }
+        ret.join("/")
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Pinout {
+    pub device_type: XC2DeviceSpeedPackage,
+    /// Every pin that can carry a signal, used or not, in FB/MC order
+    pub pins: Vec<PinoutPin>,
+}
+
+// Quotes a CSV field if it needs it
+fn csv_field(s: &str) -> String {
+    if s.contains(',') || s.contains('"') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+fn kicad_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl Pinout {
+    pub fn new(device_type: XC2DeviceSpeedPackage, g: &InputGraph, go: &OutputGraph) -> Self {
+        let device = device_type.dev;
+
+        // If the pin map is known, the IOBs that aren't bonded out in this package are left out
+        let has_pin_map = has_package_pin_map(device_type);
+        let mut pins = Vec::new();
+        for fb in 0..device.num_fbs() as u32 {
+            for mc in 0..MCS_PER_FB as u32 {
+                if let Some(iob) = fb_mc_num_to_iob_num(device, fb, mc) {
+                    let package_pin = get_package_pin(device_type, fb, mc);
+                    if has_pin_map && package_pin.is_none() {
+                        continue;
+                    }
+                    pins.push(PinoutPin {
+                        fb,
+                        mc,
+                        iob: Some(iob),
+                        package_pin,
+                        bank: get_fb_bank(device, fb),
+                        signal: None,
+                        direction: None,
+                        io_standard: None,
+                        is_vref: false,
+                    });
+                }
+            }
+        }
+        if device == XC2Device::XC2C32 || device == XC2Device::XC2C32A {
+            pins.push(PinoutPin {
+                fb: device.num_fbs() as u32,
+                mc: 0,
+                iob: None,
+                package_pin: None,
+                bank: None,
+                signal: None,
+                direction: None,
+                io_standard: None,
+                is_vref: false,
+            });
+        }
+
+        for mc_idx in g.mcs.iter_idx() {
+            let mc = g.mcs.get(mc_idx);
+            if let Some(ref io_bits) = mc.io_bits {
+                let loc = go.mcs.get(ObjPoolIndex::from(mc_idx)).loc.unwrap();
+                if let Some(pin) = pins.iter_mut().find(|x| x.fb == loc.fb && x.mc == loc.i) {
+                    pin.signal = Some(mc.pad_name().to_owned());
+                    pin.direction = Some(FitReportPinDirection::of_macrocell(mc, io_bits));
+                    pin.io_standard = io_bits.io_standard;
+                    pin.is_vref = io_bits.is_vref;
+                }
+            }
+        }
+
+        Self {
+            device_type,
+            pins,
+        }
+    }
+
+    fn part_name(&self) -> String {
+        format!("{}-{}-{}", self.device_type.dev, self.device_type.spd, self.device_type.pkg)
+    }
+
+    /// Writes LOC (and IOSTANDARD) constraints for every signal, which lock the pins if passed back in
+    pub fn write_ucf<W: Write>(&self, mut writer: W) -> Result<(), std::io::Error> {
+        writeln!(writer, "# Pinout for {}", self.part_name())?;
+        for pin in &self.pins {
+            if let (Some(signal), false) = (&pin.signal, pin.is_vref) {
+                let constraint = PinConstraint {
+                    name: signal.clone(),
+                    location: Some(RequestedLocation{fb: pin.fb, i: Some(pin.mc)}),
+                    io_standard: pin.io_standard,
+                };
+                let functions = pin.functions(self.device_type.dev);
+                constraint.write_ucf(&mut writer, self.device_type,
+                    Some(&functions).filter(|x| !x.is_empty()).map(|x| x.as_str()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes a CSV pin report with one line for every pin, including the unused ones
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> Result<(), std::io::Error> {
+        writeln!(writer, "pin,site,signal,direction,iostandard,bank,iob,function")?;
+        for pin in &self.pins {
+            writeln!(writer, "{},{},{},{},{},{},{},{}",
+                pin.package_pin.unwrap_or(""),
+                pin.site_name(),
+                csv_field(pin.signal.as_deref().unwrap_or("")),
+                pin.direction.map(|x| format!("{:?}", x)).unwrap_or_default(),
+                pin.io_standard.map(|x| format!("{}", x)).unwrap_or_default(),
+                pin.bank.map(|x| format!("{}", x)).unwrap_or_default(),
+                pin.iob.map(|x| format!("{}", x)).unwrap_or_default(),
+                pin.functions(self.device_type.dev))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a KiCad (version 6 or later) symbol library containing one symbol with the given name, with a pin for
+    /// every package pin that can carry a signal. Inputs are on the left, and outputs and the unused pins are on the
+    /// right. This needs the package pin map, so nothing is written for packages without one.
+    pub fn write_kicad_symbol<W: Write>(&self, mut writer: W, symbol_name: &str) -> Result<(), std::io::Error> {
+        const GRID: f64 = 2.54;
+        const PIN_LENGTH: f64 = 2.0 * GRID;
+
+        // (electrical type, name, number)
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+        let mut unused = Vec::new();
+        for pin in &self.pins {
+            let number = if let Some(x) = pin.package_pin { x } else { continue };
+            match (&pin.signal, pin.direction) {
+                (Some(signal), Some(FitReportPinDirection::Input)) => left.push(("input", signal.clone(), number)),
+                (Some(signal), Some(FitReportPinDirection::Output)) => right.push(("output", signal.clone(), number)),
+                (Some(signal), Some(FitReportPinDirection::InOut)) =>
+                    right.push(("bidirectional", signal.clone(), number)),
+                _ => unused.push(("unspecified", pin.site_name(), number)),
+            }
+        }
+        if left.is_empty() && right.is_empty() && unused.is_empty() {
+            return Ok(());
+        }
+        right.extend(unused);
+
+        // Everything is kept on the 100 mil grid
+        let rows = left.len().max(right.len()) as f64;
+        let half_height = ((rows + 1.0) / 2.0).ceil() * GRID;
+        let half_width = 6.0 * GRID;
+
+        let name = kicad_escape(symbol_name);
+        let font = "(effects (font (size 1.27 1.27)))";
+        writeln!(writer, "(kicad_symbol_lib (version 20211014) (generator xc2par)")?;
+        writeln!(writer, "  (symbol \"{}\" (in_bom yes) (on_board yes)", name)?;
+        writeln!(writer, "    (property \"Reference\" \"U\" (id 0) (at 0 {:.2} 0) {})",
+            half_height + GRID, font)?;
+        writeln!(writer, "    (property \"Value\" \"{}\" (id 1) (at 0 {:.2} 0) {})",
+            self.part_name(), -half_height - GRID, font)?;
+        writeln!(writer, "    (property \"Footprint\" \"\" (id 2) (at 0 0 0) (effects (font (size 1.27 1.27)) hide))")?;
+        writeln!(writer, "    (property \"Datasheet\" \"\" (id 3) (at 0 0 0) (effects (font (size 1.27 1.27)) hide))")?;
+        writeln!(writer, "    (symbol \"{}_0_1\"", name)?;
+        writeln!(writer, "      (rectangle (start {:.2} {:.2}) (end {:.2} {:.2}) (stroke (width 0.254) (type default)) \
+            (fill (type background)))", -half_width, half_height, half_width, -half_height)?;
+        writeln!(writer, "    )")?;
+        writeln!(writer, "    (symbol \"{}_1_1\"", name)?;
+        let mut write_pin = |kind: &str, pin_name: &str, number: &str, x: f64, y: f64, angle: u32| {
+            writeln!(writer, "      (pin {} line (at {:.2} {:.2} {}) (length {:.2}) \
+                (name \"{}\" {}) (number \"{}\" {}))",
+                kind, x, y, angle, PIN_LENGTH,
+                kicad_escape(pin_name), font, kicad_escape(number), font)
+        };
+        for (i, (kind, pin_name, number)) in left.iter().enumerate() {
+            write_pin(kind, pin_name, number, -half_width - PIN_LENGTH, half_height - GRID * (i as f64 + 1.0), 0)?;
+        }
+        for (i, (kind, pin_name, number)) in right.iter().enumerate() {
+            write_pin(kind, pin_name, number, half_width + PIN_LENGTH, half_height - GRID * (i as f64 + 1.0), 180)?;
+        }
+        writeln!(writer, "    )")?;
+        writeln!(writer, "  )")?;
+        writeln!(writer, ")")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fit(input: &str, part: &str) -> (InputGraph, OutputGraph, XC2DeviceSpeedPackage) {
+        let g = IntermediateGraph::from_equations(input.as_bytes(), None).unwrap();
+        let mut input_graph = InputGraph::from_intermed_graph(&g, None).unwrap();
+        let device_type = XC2DeviceSpeedPackage::from_str(part).unwrap();
+        let output_graph = match do_par(&mut input_graph, device_type, &XC2ParOptions::new(), None) {
+            PARResult::Success(x) => x,
+            _ => panic!("design did not fit"),
+        };
+        (input_graph, output_graph, device_type)
+    }
+
+    #[test]
+    fn pinout_counter() {
+        let input = r#"
+            q0 := !q0;
+            q0.CLK = clk;
+            y = q0 & !a;
+        "#;
+        let (input_graph, output_graph, device_type) = fit(input, "xc2c32a-4-vq44");
+        let pinout = Pinout::new(device_type, &input_graph, &output_graph);
+
+        // 32 IOBs plus the input-only pin
+        assert_eq!(pinout.pins.len(), 33);
+        let used = pinout.pins.iter().filter_map(|x| x.signal.as_deref()).collect::<Vec<_>>();
+        assert_eq!(used.len(), 4);
+        for name in &["q0", "clk", "y", "a"] {
+            assert!(used.contains(name));
+        }
+
+        // The UCF locks every pin where it is, by package pin
+        let mut ucf = Vec::new();
+        pinout.write_ucf(&mut ucf).unwrap();
+        let ucf_text = String::from_utf8(ucf.clone()).unwrap();
+        assert!(!ucf_text.contains("LOC = \"FB"));
+        let constraints = parse_ucf(&ucf[..], device_type, None).unwrap();
+        assert_eq!(constraints.len(), 4);
+        for constraint in &constraints {
+            let pin = pinout.pins.iter().find(|x| x.signal.as_ref() == Some(&constraint.name)).unwrap();
+            assert_eq!(constraint.location, Some(RequestedLocation{fb: pin.fb, i: Some(pin.mc)}));
+            assert!(ucf_text.contains(&format!("LOC = \"{}\"", pin.package_pin.unwrap())));
+        }
+
+        let mut csv = Vec::new();
+        pinout.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 34);
+        assert!(csv.contains("\nP43,FB2_5,"));
+        assert!(csv.lines().any(|x| x.starts_with(",FB3_1,") && x.ends_with(",INPUT_ONLY")));
+        assert!(csv.lines().any(|x| x.starts_with("P30,FB1_8,") && x.ends_with(",1,7,GSR")));
+
+        let mut symbol = Vec::new();
+        pinout.write_kicad_symbol(&mut symbol, "counter").unwrap();
+        let symbol = String::from_utf8(symbol).unwrap();
+        assert!(symbol.starts_with("(kicad_symbol_lib"));
+        assert_eq!(symbol.matches("(pin ").count(), 32);
+        assert_eq!(symbol.matches('(').count(), symbol.matches(')').count());
+        let clk = pinout.pins.iter().find(|x| x.signal.as_deref() == Some("clk")).unwrap();
+        assert!(symbol.contains(&format!("(name \"clk\" (effects (font (size 1.27 1.27)))) (number \"{}\"",
+            clk.package_pin.unwrap())));
+    }
+
+    #[test]
+    fn pinout_partial_package() {
+        // Only the bonded IOBs are pins in the QFG32
+        let (input_graph, output_graph, device_type) = fit("y = a & b;\n", "xc2c32a-4-qfg32");
+        let pinout = Pinout::new(device_type, &input_graph, &output_graph);
+        assert_eq!(pinout.pins.len(), 20 + 1);
+        assert!(pinout.pins.iter().all(|x| x.package_pin.is_some() || x.iob.is_none()));
+
+        // Without a pin map, every IOB is listed by its site
+        let (input_graph, output_graph, device_type) = fit("y = a & b;\n", "xc2c64a-5-vq100");
+        let pinout = Pinout::new(device_type, &input_graph, &output_graph);
+        assert_eq!(pinout.pins.len(), 64);
+        let mut ucf = Vec::new();
+        pinout.write_ucf(&mut ucf).unwrap();
+        assert!(String::from_utf8(ucf).unwrap().contains("LOC = \"FB"));
+    }
+}
@@ -0,0 +1,610 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Pin planner. This proposes pin locations for a design that doesn't exist yet, so that the board can be laid out
+// before the logic is final. The input is a list of signals, one per line:
+//
+//     clk         input   clock       # goes on a GCK pin
+//     oe_n        input   oe          # goes on a GTS pin
+//     rst_n       input   sr          # goes on the GSR pin
+//     data[7:0]   inout               # a bus, expanded to data[7] ... data[0]
+//     led[3:0]    output  loc=FB2     # LOC can name a function block or a single macrocell (FB2_5)
+//
+// Signals with a clock/OE/set-reset role are put on the matching dedicated global pins. Other inputs are spread over
+// the pins so that the ZIA rows that can reach them are used as evenly as possible, since the ZIA is usually what stops
+// a late design change from fitting without moving pins. Outputs are spread evenly over the function blocks. The
+// dedicated global pins are only used for ordinary signals once everything else is full.
+//
+// Which IOBs a package bonds out, and to which package pins, comes from the xc2bit package pin map. Packages without a
+// map can only be planned if they bond out every IOB, and are then written using the same FBn_m names as the LOC
+// attribute. Either way, the result is a UCF file that can be passed back to xc2par with --constraints.
+
+use std::collections::HashSet;
+use std::error;
+use std::fmt;
+use std::io::{Read, Write};
+use slog::Drain;
+
+use xc2bit::*;
+
+use crate::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum PinPlanDirection {
+    Input,
+    Output,
+    InOut,
+}
+
+impl PinPlanDirection {
+    fn has_input(&self) -> bool {
+        *self != PinPlanDirection::Output
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PinPlanSignal {
+    pub name: String,
+    pub direction: PinPlanDirection,
+    /// Which kind of global net this signal should drive, if any. Only allowed for inputs.
+    pub role: Option<GlobalNetKind>,
+    pub location: Option<RequestedLocation>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PinPlanAssignment {
+    pub name: String,
+    pub direction: PinPlanDirection,
+    pub fb: u32,
+    pub mc: u32,
+    /// The global buffer that this pin can drive (e.g. GCK0), if the signal has a role and is on a matching pin
+    pub global: Option<(GlobalNetKind, usize)>,
+    /// How many ZIA rows can carry this pin into a function block (zero for outputs)
+    pub zia_rows: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PinPlan {
+    pub device_type: XC2DeviceSpeedPackage,
+    pub assignments: Vec<PinPlanAssignment>,
+}
+
+#[derive(Debug)]
+pub enum PinPlanError {
+    ReadError(std::io::Error),
+    MalformedLine(usize, String),
+    DuplicateSignal(String),
+    IllegalLocation(String),
+    LocationConflict(String),
+    IllegalRole(String),
+    OutOfPins(String),
+    PartiallyBondedPackage(String),
+}
+
+impl error::Error for PinPlanError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PinPlanError::ReadError(inner) => Some(inner),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PinPlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PinPlanError::ReadError(inner) => {
+                write!(f, "{}", inner)
+            },
+            PinPlanError::MalformedLine(line, s) => {
+                write!(f, "line {}: malformed input - {}", line, s)
+            },
+            PinPlanError::DuplicateSignal(s) => {
+                write!(f, "signal listed more than once - {}", s)
+            },
+            PinPlanError::IllegalLocation(s) => {
+                write!(f, "LOC is not a usable pin for this signal - {}", s)
+            },
+            PinPlanError::LocationConflict(s) => {
+                write!(f, "LOC is already used by another signal - {}", s)
+            },
+            PinPlanError::IllegalRole(s) => {
+                write!(f, "only inputs can drive global nets - {}", s)
+            },
+            PinPlanError::OutOfPins(s) => {
+                write!(f, "no pins left for signal - {}", s)
+            },
+            PinPlanError::PartiallyBondedPackage(s) => {
+                write!(f, "package does not bond out every I/O, and its pin map is not known - {}", s)
+            },
+        }
+    }
+}
+
+impl From<std::io::Error> for PinPlanError {
+    fn from(err: std::io::Error) -> Self {
+        PinPlanError::ReadError(err)
+    }
+}
+
+// Expands `name[hi:lo]` into one name per bit, in the order written. Anything else is a single signal.
+fn expand_bus(name: &str) -> Option<Vec<String>> {
+    if let Some((base, range)) = name.strip_suffix(']').and_then(|x| x.rsplit_once('[')) {
+        if let Some((a, b)) = range.split_once(':') {
+            let a = a.parse::<u32>().ok()?;
+            let b = b.parse::<u32>().ok()?;
+            if base.is_empty() {
+                return None;
+            }
+            let bits: Vec<u32> = if a >= b { (b..=a).rev().collect() } else { (a..=b).collect() };
+            return Some(bits.into_iter().map(|i| format!("{}[{}]", base, i)).collect());
+        }
+    }
+
+    Some(vec![name.to_owned()])
+}
+
+/// Reads a signal list for the pin planner
+pub fn parse_pin_plan_signals<R, L>(mut input: R, logger: L) -> Result<Vec<PinPlanSignal>, PinPlanError>
+    where R: Read, L: Into<Option<slog::Logger>> {
+
+    let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+
+    let mut data = String::new();
+    input.read_to_string(&mut data)?;
+
+    let mut ret: Vec<PinPlanSignal> = Vec::new();
+    let mut seen_names = HashSet::new();
+    for (line_i, line) in data.lines().enumerate() {
+        let line_num = line_i + 1;
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let malformed = || {
+            error!(logger, "pin planner - malformed line";
+                "code" => "PP001",
+                "line" => line_num,
+                "text" => line);
+            PinPlanError::MalformedLine(line_num, line.to_owned())
+        };
+
+        let mut tokens = line.split_whitespace();
+        let name = tokens.next().unwrap();
+        let direction = match tokens.next().map(|x| x.to_ascii_lowercase()).as_deref() {
+            Some("in") | Some("input") => PinPlanDirection::Input,
+            Some("out") | Some("output") => PinPlanDirection::Output,
+            Some("inout") | Some("bidir") => PinPlanDirection::InOut,
+            _ => return Err(malformed()),
+        };
+
+        let mut role = None;
+        let mut location = None;
+        for token in tokens {
+            if let Some(loc) = token.strip_prefix("loc=").or_else(|| token.strip_prefix("LOC=")) {
+                location = match RequestedLocation::parse_location(Some(loc), &logger) {
+                    Ok(Some(x)) => Some(x),
+                    _ => return Err(malformed()),
+                };
+                continue;
+            }
+
+            role = match token.to_ascii_lowercase().as_ref() {
+                "clock" | "clk" | "gck" => Some(GlobalNetKind::Clock),
+                "oe" | "gts" => Some(GlobalNetKind::OutputEnable),
+                "sr" | "setreset" | "gsr" => Some(GlobalNetKind::SetReset),
+                _ => return Err(malformed()),
+            };
+        }
+
+        if role.is_some() && direction != PinPlanDirection::Input {
+            error!(logger, "pin planner - only inputs can drive global nets";
+                "code" => "PP005",
+                "name" => name);
+            return Err(PinPlanError::IllegalRole(name.to_owned()));
+        }
+
+        let names = expand_bus(name).ok_or_else(malformed)?;
+        if location.is_some_and(|x: RequestedLocation| x.i.is_some()) && names.len() > 1 {
+            // A bus can't go on a single pin
+            return Err(malformed());
+        }
+
+        for name in names {
+            if !seen_names.insert(name.clone()) {
+                error!(logger, "pin planner - signal listed more than once";
+                    "code" => "PP002",
+                    "name" => &name);
+                return Err(PinPlanError::DuplicateSignal(name));
+            }
+
+            ret.push(PinPlanSignal {
+                name,
+                direction,
+                role,
+                location,
+            });
+        }
+    }
+
+    Ok(ret)
+}
+
+// One pin that a signal can be placed on
+struct PinPlanSite {
+    fb: u32,
+    mc: u32,
+    // The XC2C32(A) has one extra pin that can only be used as an input
+    input_only: bool,
+    // Which global buffer this pin can drive, if any
+    global: Option<(GlobalNetKind, usize)>,
+    // The ZIA rows that can carry this pin into a function block
+    zia_rows: Vec<usize>,
+    used: bool,
+}
+
+fn pin_plan_sites(device_type: XC2DeviceSpeedPackage) -> Vec<PinPlanSite> {
+    let device = device_type.dev;
+    let has_pin_map = has_package_pin_map(device_type);
+    let zia_rows = |input: XC2ZIAInput| -> Vec<usize> {
+        (0..INPUTS_PER_ANDTERM).filter(|&row| zia_table_get_row(device, row).contains(&input)).collect()
+    };
+
+    let mut ret = Vec::new();
+    for fb in 0..device.num_fbs() as u32 {
+        for mc in 0..MCS_PER_FB as u32 {
+            if let Some(iob) = fb_mc_num_to_iob_num(device, fb, mc) {
+                if has_pin_map && get_package_pin(device_type, fb, mc).is_none() {
+                    continue;
+                }
+                ret.push(PinPlanSite {
+                    fb,
+                    mc,
+                    input_only: false,
+                    global: global_pin_at(device, fb, mc),
+                    zia_rows: zia_rows(XC2ZIAInput::IBuf{ibuf: iob as u16}),
+                    used: false,
+                });
+            }
+        }
+    }
+    if device == XC2Device::XC2C32 || device == XC2Device::XC2C32A {
+        ret.push(PinPlanSite {
+            fb: device.num_fbs() as u32,
+            mc: 0,
+            input_only: true,
+            global: None,
+            zia_rows: zia_rows(XC2ZIAInput::DedicatedInput),
+            used: false,
+        });
+    }
+
+    ret
+}
+
+/// Proposes a pin for every signal in the list. Fails for packages that don't bond out every IOB if the package pin map
+/// isn't known.
+pub fn plan_pins<L>(signals: &[PinPlanSignal], device_type: XC2DeviceSpeedPackage, logger: L)
+    -> Result<PinPlan, PinPlanError> where L: Into<Option<slog::Logger>> {
+
+    let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+
+    if !device_type.is_fully_bonded() && !has_package_pin_map(device_type) {
+        error!(logger, "pin planner - package does not bond out every I/O, and its pin map is not known";
+            "code" => "PP009",
+            "part" => format!("{}-{}-{}", device_type.dev, device_type.spd, device_type.pkg),
+            "bonded" => device_type.num_bonded_iobs(),
+            "iobs" => device_type.dev.num_iobs());
+        return Err(PinPlanError::PartiallyBondedPackage(format!("{}-{}-{}", device_type.dev, device_type.spd,
+            device_type.pkg)));
+    }
+
+    let mut sites = pin_plan_sites(device_type);
+    // How heavily each ZIA row is used by the inputs placed so far
+    let mut zia_load = [0.0f64; INPUTS_PER_ANDTERM];
+    let mut fb_load = vec![0usize; device_type.dev.num_fbs()];
+    let mut assignments: Vec<Option<PinPlanAssignment>> = vec![None; signals.len()];
+
+    let place = |signal: &PinPlanSignal, site: &mut PinPlanSite, zia_load: &mut [f64], fb_load: &mut [usize]| {
+        site.used = true;
+        let global = site.global.filter(|x| Some(x.0) == signal.role);
+        let zia_rows = if signal.direction.has_input() { site.zia_rows.len() } else { 0 };
+        if signal.direction.has_input() {
+            for &row in &site.zia_rows {
+                zia_load[row] += 1.0 / site.zia_rows.len() as f64;
+            }
+        }
+        if let Some(x) = fb_load.get_mut(site.fb as usize) {
+            *x += 1;
+        }
+
+        debug!(logger, "pin planner - placed signal";
+            "name" => &signal.name,
+            "fb" => site.fb,
+            "mc" => site.mc,
+            "zia rows" => zia_rows);
+        PinPlanAssignment {
+            name: signal.name.clone(),
+            direction: signal.direction,
+            fb: site.fb,
+            mc: site.mc,
+            global,
+            zia_rows,
+        }
+    };
+
+    // Signals with a fixed pin go first
+    for (signal_i, signal) in signals.iter().enumerate() {
+        if let Some(RequestedLocation{fb, i: Some(mc)}) = signal.location {
+            let loc_str = format!("{} (FB{}_{})", signal.name, fb + 1, mc + 1);
+            let site = if let Some(site) = sites.iter_mut().find(|x| x.fb == fb && x.mc == mc) { site } else {
+                error!(logger, "pin planner - LOC is not a pin";
+                    "code" => "PP003",
+                    "name" => &signal.name,
+                    "fb" => fb,
+                    "mc" => mc);
+                return Err(PinPlanError::IllegalLocation(loc_str));
+            };
+            if site.input_only && signal.direction != PinPlanDirection::Input {
+                error!(logger, "pin planner - LOC is an input-only pin";
+                    "code" => "PP003",
+                    "name" => &signal.name,
+                    "fb" => fb,
+                    "mc" => mc);
+                return Err(PinPlanError::IllegalLocation(loc_str));
+            }
+            if site.used {
+                error!(logger, "pin planner - LOC is already used by another signal";
+                    "code" => "PP004",
+                    "name" => &signal.name,
+                    "fb" => fb,
+                    "mc" => mc);
+                return Err(PinPlanError::LocationConflict(loc_str));
+            }
+            if let Some(role) = signal.role {
+                if site.global.is_none_or(|x| x.0 != role) {
+                    warn!(logger, "pin planner - LOC of global signal is not on a dedicated pin";
+                        "code" => "PP008",
+                        "name" => &signal.name,
+                        "fb" => fb,
+                        "mc" => mc);
+                }
+            }
+
+            assignments[signal_i] = Some(place(signal, site, &mut zia_load, &mut fb_load));
+        }
+    }
+
+    let fb_allowed = |signal: &PinPlanSignal, site: &PinPlanSite| {
+        signal.location.is_none_or(|x| x.fb == site.fb)
+    };
+
+    // Then the signals that want a global net
+    for (signal_i, signal) in signals.iter().enumerate() {
+        if assignments[signal_i].is_some() {
+            continue;
+        }
+        if let Some(role) = signal.role {
+            if let Some(site) = sites.iter_mut()
+                .filter(|x| !x.used && x.global.is_some_and(|x| x.0 == role) && fb_allowed(signal, x))
+                .min_by_key(|x| x.global.unwrap().1) {
+
+                assignments[signal_i] = Some(place(signal, site, &mut zia_load, &mut fb_load));
+            } else {
+                warn!(logger, "pin planner - no dedicated pin left for global signal, placing it as a normal input";
+                    "code" => "PP007",
+                    "name" => &signal.name,
+                    "role" => format!("{:?}", role));
+            }
+        }
+    }
+
+    // Then everything else. Inputs go first since they are the ones that compete for the ZIA. Within each group,
+    // signals that are restricted to one function block go first so that they aren't crowded out.
+    let mut order = (0..signals.len()).filter(|&i| assignments[i].is_none()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| (!signals[i].direction.has_input(), signals[i].location.is_none()));
+    for signal_i in order {
+        let signal = &signals[signal_i];
+
+        let candidates = sites.iter().enumerate()
+            .filter(|(_, x)| !x.used && fb_allowed(signal, x))
+            .filter(|(_, x)| !x.input_only || signal.direction == PinPlanDirection::Input);
+        // Dedicated global pins are always the last choice
+        let best = if signal.direction.has_input() {
+            let score = |site: &PinPlanSite| -> f64 {
+                site.zia_rows.iter().map(|&row| 1.0 / (1.0 + zia_load[row])).sum()
+            };
+            candidates.min_by(|(_, a), (_, b)| {
+                a.global.is_some().cmp(&b.global.is_some())
+                    .then(score(b).partial_cmp(&score(a)).unwrap())
+            })
+        } else {
+            candidates.min_by_key(|(_, x)| (x.global.is_some(), fb_load[x.fb as usize]))
+        };
+
+        if let Some((site_i, _)) = best {
+            assignments[signal_i] = Some(place(signal, &mut sites[site_i], &mut zia_load, &mut fb_load));
+        } else {
+            error!(logger, "pin planner - no pins left for signal";
+                "code" => "PP006",
+                "name" => &signal.name);
+            return Err(PinPlanError::OutOfPins(signal.name.clone()));
+        }
+    }
+
+    Ok(PinPlan {
+        device_type,
+        assignments: assignments.into_iter().map(|x| x.unwrap()).collect(),
+    })
+}
+
+impl PinPlan {
+    /// Writes the plan out as LOC constraints in a UCF file
+    pub fn write_ucf<W: Write>(&self, mut writer: W) -> Result<(), std::io::Error> {
+        writeln!(writer, "# Pin plan for {}-{}-{}", self.device_type.dev, self.device_type.spd, self.device_type.pkg)?;
+        if !has_package_pin_map(self.device_type) {
+            writeln!(writer, "# Locations are macrocells (FBn_m); the package pin map is not available")?;
+        }
+        for x in &self.assignments {
+            let constraint = PinConstraint {
+                name: x.name.clone(),
+                location: Some(RequestedLocation{fb: x.fb, i: Some(x.mc)}),
+                io_standard: None,
+            };
+            let comment = x.global.map(|(kind, idx)| global_pin_name(kind, idx));
+            constraint.write_ucf(&mut writer, self.device_type, comment.as_deref())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a signal list, plans the pins, and writes the constraints
+pub fn xc2par_plan_pins<R, W, L>(input: R, device_type: XC2DeviceSpeedPackage, output: W, logger: L)
+    -> Result<PinPlan, PinPlanError> where R: Read, W: Write, L: Into<Option<slog::Logger>> {
+
+    let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+
+    let signals = parse_pin_plan_signals(input, logger.clone())?;
+    let plan = plan_pins(&signals, device_type, logger.clone())?;
+    plan.write_ucf(output)?;
+    info!(logger, "pin planner - done"; "signals" => plan.assignments.len());
+
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'a>(plan: &'a PinPlan, name: &str) -> &'a PinPlanAssignment {
+        plan.assignments.iter().find(|x| x.name == name).unwrap()
+    }
+
+    #[test]
+    fn parse_signal_list() {
+        let input = "
+            clk        input  clock   # main clock
+            data[3:0]  inout
+            led[0:1]   out    loc=FB2
+            fixed      output LOC=FB1_3
+        ";
+        let signals = parse_pin_plan_signals(input.as_bytes(), None).unwrap();
+        let names = signals.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["clk", "data[3]", "data[2]", "data[1]", "data[0]", "led[0]", "led[1]", "fixed"]);
+        assert_eq!(signals[0].role, Some(GlobalNetKind::Clock));
+        assert_eq!(signals[1].direction, PinPlanDirection::InOut);
+        assert_eq!(signals[5].location, Some(RequestedLocation{fb: 1, i: None}));
+        assert_eq!(signals[7].location, Some(RequestedLocation{fb: 0, i: Some(2)}));
+
+        assert!(matches!(parse_pin_plan_signals("a sideways".as_bytes(), None),
+            Err(PinPlanError::MalformedLine(1, _))));
+        assert!(matches!(parse_pin_plan_signals("a out clock".as_bytes(), None), Err(PinPlanError::IllegalRole(_))));
+        assert!(matches!(parse_pin_plan_signals("a[1:0] in\na[0] out".as_bytes(), None),
+            Err(PinPlanError::DuplicateSignal(_))));
+    }
+
+    #[test]
+    fn plan_globals_and_zia() {
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        let input = "
+            clk        input  clock
+            oe_n       input  oe
+            rst        input  sr
+            data[7:0]  input
+            q[7:0]     output
+            fixed      output loc=FB1_3
+        ";
+        let mut out = Vec::new();
+        let plan = xc2par_plan_pins(input.as_bytes(), device_type, &mut out, None).unwrap();
+
+        assert_eq!(plan.assignments.len(), 20);
+        assert_eq!(find(&plan, "clk").global, Some((GlobalNetKind::Clock, 0)));
+        assert_eq!((find(&plan, "clk").fb, find(&plan, "clk").mc), get_gck(device_type.dev, 0).unwrap());
+        assert_eq!((find(&plan, "oe_n").fb, find(&plan, "oe_n").mc), get_gts(device_type.dev, 0).unwrap());
+        assert_eq!((find(&plan, "rst").fb, find(&plan, "rst").mc), get_gsr(device_type.dev));
+        assert_eq!((find(&plan, "fixed").fb, find(&plan, "fixed").mc), (0, 2));
+
+        // Every signal gets its own pin, and the unused global pins are left alone
+        let pins = plan.assignments.iter().map(|x| (x.fb, x.mc)).collect::<HashSet<_>>();
+        assert_eq!(pins.len(), plan.assignments.len());
+        assert!(!pins.contains(&get_gck(device_type.dev, 1).unwrap()));
+
+        // The inputs should all be reachable, and the outputs should even out the pins used in each FB
+        assert!(plan.assignments.iter().filter(|x| x.direction == PinPlanDirection::Input).all(|x| x.zia_rows > 0));
+        let pins_in_fb = |fb| plan.assignments.iter().filter(|x| x.fb == fb).count();
+        assert_eq!(pins_in_fb(0), pins_in_fb(1));
+
+        let ucf = String::from_utf8(out).unwrap();
+        assert!(ucf.contains("NET \"fixed\" LOC = \"P36\";\n"));
+        assert!(ucf.contains("NET \"clk\" LOC = \"P43\"; # GCK0\n"));
+    }
+
+    #[test]
+    fn plan_errors() {
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        let plan = |input: &str| {
+            let signals = parse_pin_plan_signals(input.as_bytes(), None).unwrap();
+            plan_pins(&signals, device_type, None)
+        };
+
+        assert!(matches!(plan("a in loc=FB1_3\nb out loc=FB1_3"), Err(PinPlanError::LocationConflict(_))));
+        assert!(matches!(plan("a out loc=FB3_1"), Err(PinPlanError::IllegalLocation(_))));
+        assert!(plan("a in loc=FB3_1").is_ok());
+        assert!(matches!(plan("a[40:0] out"), Err(PinPlanError::OutOfPins(_))));
+
+        let unknown = XC2DeviceSpeedPackage::from_str("xc2c64a-5-vq44").unwrap();
+        assert!(matches!(plan_pins(&[], unknown, None), Err(PinPlanError::PartiallyBondedPackage(_))));
+
+        // Extra clocks still get a pin
+        let clocks = plan("c[3:0] in clock").unwrap();
+        assert_eq!(clocks.assignments.iter().filter(|x| x.global.is_some()).count(), NUM_BUFG_CLK);
+    }
+
+    #[test]
+    fn plan_partial_package() {
+        // The QFG32 package only bonds out 20 of the IOBs, plus the input-only pin
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-qfg32").unwrap();
+        let plan = |input: &str| {
+            let signals = parse_pin_plan_signals(input.as_bytes(), None).unwrap();
+            let mut out = Vec::new();
+            plan_pins(&signals, device_type, None).map(|plan| {
+                plan.write_ucf(&mut out).unwrap();
+                (plan, String::from_utf8(out).unwrap())
+            })
+        };
+
+        let (full, ucf) = plan("clk in clock
+d[18:0] inout
+x in").unwrap();
+        assert!(full.assignments.iter().all(|x| x.fb == 2 || get_package_pin(device_type, x.fb, x.mc).is_some()));
+        assert!(ucf.contains("NET \"d[0]\" LOC = \"P"));
+        // Only the input-only pin, which isn't in the pin map, is still written as a site name
+        assert!(!ucf.contains("LOC = \"FB1_") && !ucf.contains("LOC = \"FB2_"));
+        assert!(matches!(plan("q[20:0] out"), Err(PinPlanError::OutOfPins(_))));
+        assert!(matches!(plan("a out loc=FB1_1"), Err(PinPlanError::IllegalLocation(_))));
+    }
+}
@@ -0,0 +1,709 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Post-fit netlist export. The fitted design is written out as a Verilog netlist made of the same CoolRunner-II
+// primitives that the Yosys flow produces (ANDTERM, ORTERM, MACROCELL_XOR, FDCP and friends, IBUF, IOBUFE, BUFG*),
+// with a LOC attribute on every instance giving where it was placed. A matching SDF file annotates these instances with
+// delays from the device timing model so that the fitted design can be simulated with timing.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+use xc2bit::*;
+
+use crate::*;
+use crate::objpool::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum PostfitPortDirection {
+    Input,
+    Output,
+    InOut,
+}
+
+// Delays are stored in picoseconds
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum PostfitTiming {
+    IOPath(&'static str, &'static str, u32),
+    // The clock edge is `None` for registers that capture on both edges
+    Setup(&'static str, Option<&'static str>, &'static str, u32),
+    Hold(&'static str, Option<&'static str>, &'static str, u32),
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PostfitCell {
+    pub name: String,
+    pub cell_type: &'static str,
+    pub loc: String,
+    pub parameters: Vec<(&'static str, u32)>,
+    // Connections are Verilog expressions
+    pub connections: Vec<(&'static str, String)>,
+    pub timing: Vec<PostfitTiming>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PostfitNetlist {
+    pub module_name: String,
+    pub device_type: XC2DeviceSpeedPackage,
+    pub ports: Vec<(PostfitPortDirection, String)>,
+    pub wires: Vec<String>,
+    pub cells: Vec<PostfitCell>,
+}
+
+// Escapes a name for use as a Verilog identifier
+fn verilog_ident(name: &str) -> String {
+    let is_simple = name.chars().next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false) &&
+        name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if is_simple {
+        name.to_owned()
+    } else {
+        format!("\\{} ", name)
+    }
+}
+
+// Escapes a name for use as an SDF identifier
+fn sdf_ident(name: &str) -> String {
+    let mut ret = String::new();
+    for c in name.chars() {
+        if !c.is_ascii_alphanumeric() && c != '_' {
+            ret.push('\\');
+        }
+        ret.push(c);
+    }
+    ret
+}
+
+fn mc_loc(loc: AssignedLocation) -> String {
+    format!("FB{}_{}", loc.fb + 1, loc.i + 1)
+}
+
+fn pterm_loc(loc: AssignedLocation) -> String {
+    format!("FB{}_P{}", loc.fb + 1, loc.i)
+}
+
+struct NameTable {
+    used: HashSet<String>,
+}
+
+impl NameTable {
+    fn unique(&mut self, name: &str) -> String {
+        let mut ret = name.to_owned();
+        let mut i = 1;
+        while self.used.contains(&ret) {
+            ret = format!("{}${}", name, i);
+            i += 1;
+        }
+        self.used.insert(ret.clone());
+        ret
+    }
+}
+
+impl PostfitNetlist {
+    pub fn new(device_type: XC2DeviceSpeedPackage, module_name: &str, g: &InputGraph, go: &OutputGraph) -> Self {
+        let timing = XC2TimingModel::for_device(device_type);
+        let mut names = NameTable {
+            used: HashSet::new(),
+        };
+        let mut ports = Vec::new();
+        let mut wires = Vec::new();
+        let mut cells = Vec::new();
+
+        // Pads are named after the signal that goes through them, so that the netlist can be dropped into an existing
+        // testbench. This means that the internal nets usually end up being the ones that need to be renamed.
+        // A pin that drives a global buffer is read even if nothing in the ZIA uses it
+        let mut global_buffer_inputs = HashSet::new();
+        global_buffer_inputs.extend(g.bufg_clks.iter().map(|x| x.input));
+        global_buffer_inputs.extend(g.bufg_gts.iter().map(|x| x.input));
+        global_buffer_inputs.extend(g.bufg_gsr.iter().map(|x| x.input));
+        let pin_is_read = |mc_idx, mc: &InputGraphMacrocell| {
+            mc.io_feedback_used || global_buffer_inputs.contains(&mc_idx)
+        };
+
+        let mut port_names = HashMap::new();
+        for mc_idx in g.mcs.iter_idx() {
+            let mc = g.mcs.get(mc_idx);
+            if let Some(ref io_bits) = mc.io_bits {
+                let name = names.unique(mc.pad_name());
+                let direction = if io_bits.input.is_none() {
+                    PostfitPortDirection::Input
+                } else if pin_is_read(mc_idx, mc) {
+                    PostfitPortDirection::InOut
+                } else {
+                    PostfitPortDirection::Output
+                };
+                ports.push((direction, name.clone()));
+                port_names.insert(mc_idx, name);
+            }
+        }
+
+        let mut new_wire = |names: &mut NameTable, name: &str| {
+            let name = names.unique(name);
+            wires.push(name.clone());
+            name
+        };
+
+        // Every signal that is driven by part of a macrocell
+        let mut pin_nets = HashMap::new();
+        let mut xor_nets = HashMap::new();
+        let mut reg_nets = HashMap::new();
+        for mc_idx in g.mcs.iter_idx() {
+            let mc = g.mcs.get(mc_idx);
+            if mc.io_bits.as_ref().map(|x| x.input.is_none() || pin_is_read(mc_idx, mc)).unwrap_or(false) {
+                let name = mc.net_names.pin.clone().unwrap_or_else(|| format!("{}$pin", mc.name));
+                pin_nets.insert(mc_idx, new_wire(&mut names, &name));
+            }
+            if mc.xor_bits.is_some() {
+                let name = mc.net_names.xor.clone().unwrap_or_else(|| format!("{}$xor", mc.name));
+                xor_nets.insert(mc_idx, new_wire(&mut names, &name));
+            }
+            if mc.reg_bits.is_some() {
+                let name = mc.net_names.reg.clone().unwrap_or_else(|| format!("{}$reg", mc.name));
+                reg_nets.insert(mc_idx, new_wire(&mut names, &name));
+            }
+        }
+        let mut pterm_nets = HashMap::new();
+        for pterm_idx in g.pterms.iter_idx() {
+            pterm_nets.insert(pterm_idx, new_wire(&mut names, &g.pterms.get(pterm_idx).name));
+        }
+        let mut gck_nets = HashMap::new();
+        for gck_idx in g.bufg_clks.iter_idx() {
+            gck_nets.insert(gck_idx, new_wire(&mut names, &g.bufg_clks.get(gck_idx).name));
+        }
+        let mut gts_nets = HashMap::new();
+        for gts_idx in g.bufg_gts.iter_idx() {
+            gts_nets.insert(gts_idx, new_wire(&mut names, &g.bufg_gts.get(gts_idx).name));
+        }
+        let mut gsr_nets = HashMap::new();
+        for gsr_idx in g.bufg_gsr.iter_idx() {
+            gsr_nets.insert(gsr_idx, new_wire(&mut names, &g.bufg_gsr.get(gsr_idx).name));
+        }
+
+        let pterm_input_net = |&(input_type, mc_idx): &InputGraphPTermInput| {
+            verilog_ident(match input_type {
+                InputGraphPTermInputType::Pin => &pin_nets[&mc_idx],
+                InputGraphPTermInputType::Xor => &xor_nets[&mc_idx],
+                InputGraphPTermInputType::Reg => &reg_nets[&mc_idx],
+            })
+        };
+        let concat = |nets: Vec<String>| {
+            if nets.is_empty() {
+                String::new()
+            } else if nets.len() == 1 {
+                nets.into_iter().next().unwrap()
+            } else {
+                // Verilog concatenations are MSB first
+                format!("{{{}}}", nets.into_iter().rev().collect::<Vec<_>>().join(", "))
+            }
+        };
+
+        // Product terms that are used as clocks or other control signals are slightly slower
+        let mut control_pterms = HashSet::new();
+        for mc in g.mcs.iter() {
+            if let Some(InputGraphIOOEType::PTerm(pterm)) = mc.io_bits.as_ref().and_then(|x| x.oe) {
+                control_pterms.insert(pterm);
+            }
+            if let Some(ref reg_bits) = mc.reg_bits {
+                if let InputGraphRegClockType::PTerm(pterm) = reg_bits.clk_input {
+                    control_pterms.insert(pterm);
+                }
+                if let Some(InputGraphRegRSType::PTerm(pterm)) = reg_bits.set_input {
+                    control_pterms.insert(pterm);
+                }
+                if let Some(InputGraphRegRSType::PTerm(pterm)) = reg_bits.reset_input {
+                    control_pterms.insert(pterm);
+                }
+                if let Some(pterm) = reg_bits.ce_input {
+                    control_pterms.insert(pterm);
+                }
+            }
+        }
+
+        for pterm_idx in g.pterms.iter_idx() {
+            let pterm = g.pterms.get(pterm_idx);
+            let pterm_go = go.pterms.get(ObjPoolIndex::from(pterm_idx));
+            let delay = timing.t_zia + timing.t_and +
+                if control_pterms.contains(&pterm_idx) { timing.t_ptck } else { 0 };
+
+            let mut timing_arcs = Vec::new();
+            if !pterm.inputs_true.is_empty() {
+                timing_arcs.push(PostfitTiming::IOPath("IN", "OUT", delay));
+            }
+            if !pterm.inputs_comp.is_empty() {
+                timing_arcs.push(PostfitTiming::IOPath("IN_B", "OUT", delay));
+            }
+
+            cells.push(PostfitCell {
+                name: pterm.name.clone(),
+                cell_type: "ANDTERM",
+                loc: pterm_loc(pterm_go.loc.unwrap()),
+                parameters: vec![
+                    ("TRUE_INP", pterm.inputs_true.len() as u32),
+                    ("COMP_INP", pterm.inputs_comp.len() as u32),
+                ],
+                connections: vec![
+                    ("IN", concat(pterm.inputs_true.iter().map(pterm_input_net).collect())),
+                    ("IN_B", concat(pterm.inputs_comp.iter().map(pterm_input_net).collect())),
+                    ("OUT", verilog_ident(&pterm_nets[&pterm_idx])),
+                ],
+                timing: timing_arcs,
+            });
+        }
+
+        for (gck_idx, gck) in g.bufg_clks.iter_idx().map(|x| (x, g.bufg_clks.get(x))) {
+            let gck_go = go.bufg_clks.get(ObjPoolIndex::from(gck_idx));
+            cells.push(PostfitCell {
+                name: gck.name.clone(),
+                cell_type: "BUFG",
+                loc: format!("GCK{}", gck_go.loc.unwrap().i),
+                parameters: Vec::new(),
+                connections: vec![
+                    ("I", verilog_ident(&pin_nets[&gck.input])),
+                    ("O", verilog_ident(&gck_nets[&gck_idx])),
+                ],
+                timing: vec![PostfitTiming::IOPath("I", "O", timing.t_gck)],
+            });
+        }
+        for (gts_idx, gts) in g.bufg_gts.iter_idx().map(|x| (x, g.bufg_gts.get(x))) {
+            let gts_go = go.bufg_gts.get(ObjPoolIndex::from(gts_idx));
+            cells.push(PostfitCell {
+                name: gts.name.clone(),
+                cell_type: "BUFGTS",
+                loc: format!("GTS{}", gts_go.loc.unwrap().i),
+                parameters: vec![("INVERT", gts.invert as u32)],
+                connections: vec![
+                    ("I", verilog_ident(&pin_nets[&gts.input])),
+                    ("O", verilog_ident(&gts_nets[&gts_idx])),
+                ],
+                timing: vec![PostfitTiming::IOPath("I", "O", timing.t_gts)],
+            });
+        }
+        for (gsr_idx, gsr) in g.bufg_gsr.iter_idx().map(|x| (x, g.bufg_gsr.get(x))) {
+            cells.push(PostfitCell {
+                name: gsr.name.clone(),
+                cell_type: "BUFGSR",
+                loc: "GSR".to_owned(),
+                parameters: vec![("INVERT", gsr.invert as u32)],
+                connections: vec![
+                    ("I", verilog_ident(&pin_nets[&gsr.input])),
+                    ("O", verilog_ident(&gsr_nets[&gsr_idx])),
+                ],
+                timing: vec![PostfitTiming::IOPath("I", "O", timing.t_gsr)],
+            });
+        }
+
+        for mc_idx in g.mcs.iter_idx() {
+            let mc = g.mcs.get(mc_idx);
+            let loc = go.mcs.get(ObjPoolIndex::from(mc_idx)).loc.unwrap();
+
+            if let Some(ref xor_bits) = mc.xor_bits {
+                let orterm_net = if xor_bits.orterm_inputs.is_empty() {
+                    "1'b0".to_owned()
+                } else {
+                    let or_name = format!("{}$or", mc.name);
+                    let or_net = new_wire(&mut names, &or_name);
+                    cells.push(PostfitCell {
+                        name: or_name,
+                        cell_type: "ORTERM",
+                        loc: mc_loc(loc),
+                        parameters: vec![("WIDTH", xor_bits.orterm_inputs.len() as u32)],
+                        connections: vec![
+                            ("IN", concat(xor_bits.orterm_inputs.iter()
+                                .map(|x| verilog_ident(&pterm_nets[x])).collect())),
+                            ("OUT", verilog_ident(&or_net)),
+                        ],
+                        timing: vec![PostfitTiming::IOPath("IN", "OUT", timing.t_or)],
+                    });
+                    verilog_ident(&or_net)
+                };
+
+                cells.push(PostfitCell {
+                    name: format!("{}$xor", mc.name),
+                    cell_type: "MACROCELL_XOR",
+                    loc: mc_loc(loc),
+                    parameters: vec![("INVERT_OUT", xor_bits.invert_out as u32)],
+                    connections: vec![
+                        ("IN_PTC", xor_bits.andterm_input.map(|x| verilog_ident(&pterm_nets[&x]))
+                            .unwrap_or_else(|| "1'b0".to_owned())),
+                        ("IN_ORTERM", orterm_net),
+                        ("OUT", verilog_ident(&xor_nets[&mc_idx])),
+                    ],
+                    timing: vec![
+                        PostfitTiming::IOPath("IN_PTC", "OUT", timing.t_xor),
+                        PostfitTiming::IOPath("IN_ORTERM", "OUT", timing.t_xor),
+                    ],
+                });
+            }
+
+            if let Some(ref reg_bits) = mc.reg_bits {
+                let (cell_type, clk_port, dt_port) = match (reg_bits.mode, reg_bits.clkddr, reg_bits.clkinv) {
+                    (XC2MCRegMode::DFF, true, _) => ("FDDCP", "C", "D"),
+                    (XC2MCRegMode::DFF, false, false) => ("FDCP", "C", "D"),
+                    (XC2MCRegMode::DFF, false, true) => ("FDCP_N", "C", "D"),
+                    (XC2MCRegMode::DFFCE, true, _) => ("FDDCPE", "C", "D"),
+                    (XC2MCRegMode::DFFCE, false, false) => ("FDCPE", "C", "D"),
+                    (XC2MCRegMode::DFFCE, false, true) => ("FDCPE_N", "C", "D"),
+                    (XC2MCRegMode::TFF, true, _) => ("FTDCP", "C", "T"),
+                    (XC2MCRegMode::TFF, false, false) => ("FTCP", "C", "T"),
+                    (XC2MCRegMode::TFF, false, true) => ("FTCP_N", "C", "T"),
+                    (XC2MCRegMode::LATCH, _, false) => ("LDCP", "G", "D"),
+                    (XC2MCRegMode::LATCH, _, true) => ("LDCP_N", "G", "D"),
+                };
+
+                // The edge that timing checks are made against. Latches close on the edge that makes them opaque.
+                let clk_edge = match (reg_bits.mode, reg_bits.clkddr, reg_bits.clkinv) {
+                    (XC2MCRegMode::LATCH, _, false) => Some("negedge"),
+                    (XC2MCRegMode::LATCH, _, true) => Some("posedge"),
+                    (_, true, _) => None,
+                    (_, false, false) => Some("posedge"),
+                    (_, false, true) => Some("negedge"),
+                };
+
+                let clk_net = match reg_bits.clk_input {
+                    InputGraphRegClockType::PTerm(pterm) => &pterm_nets[&pterm],
+                    InputGraphRegClockType::GCK(gck) => &gck_nets[&gck],
+                };
+                let set_reset_net = |input: Option<InputGraphRegRSType>| match input {
+                    None => "1'b0".to_owned(),
+                    Some(InputGraphRegRSType::PTerm(pterm)) => verilog_ident(&pterm_nets[&pterm]),
+                    Some(InputGraphRegRSType::GSR(gsr)) => verilog_ident(&gsr_nets[&gsr]),
+                };
+                let dt_net = match reg_bits.dt_input {
+                    InputGraphRegInputType::Pin => &pin_nets[&mc_idx],
+                    InputGraphRegInputType::Xor => &xor_nets[&mc_idx],
+                };
+
+                let mut connections = vec![
+                    (clk_port, verilog_ident(clk_net)),
+                    ("PRE", set_reset_net(reg_bits.set_input)),
+                    ("CLR", set_reset_net(reg_bits.reset_input)),
+                    (dt_port, verilog_ident(dt_net)),
+                ];
+                let mut timing_arcs = vec![
+                    PostfitTiming::IOPath(clk_port, "Q", timing.t_co),
+                    PostfitTiming::Setup(dt_port, clk_edge, clk_port, timing.t_su),
+                    PostfitTiming::Hold(dt_port, clk_edge, clk_port, timing.t_hold),
+                ];
+                if reg_bits.set_input.is_some() {
+                    timing_arcs.push(PostfitTiming::IOPath("PRE", "Q", timing.t_ao));
+                }
+                if reg_bits.reset_input.is_some() {
+                    timing_arcs.push(PostfitTiming::IOPath("CLR", "Q", timing.t_ao));
+                }
+                if let Some(ce) = reg_bits.ce_input {
+                    connections.push(("CE", verilog_ident(&pterm_nets[&ce])));
+                    timing_arcs.push(PostfitTiming::Setup("CE", clk_edge, clk_port, timing.t_cesu));
+                }
+                connections.push(("Q", verilog_ident(&reg_nets[&mc_idx])));
+
+                cells.push(PostfitCell {
+                    name: format!("{}$ff", mc.name),
+                    cell_type,
+                    loc: mc_loc(loc),
+                    parameters: vec![("INIT", reg_bits.init_state as u32)],
+                    connections,
+                    timing: timing_arcs,
+                });
+            }
+
+            if let Some(ref io_bits) = mc.io_bits {
+                let port = verilog_ident(&port_names[&mc_idx]);
+                let pin_net = pin_nets.get(&mc_idx).map(|x| verilog_ident(x)).unwrap_or_else(String::new);
+
+                if let Some(input) = io_bits.input {
+                    let data_net = verilog_ident(match input {
+                        InputGraphIOInputType::Xor => &xor_nets[&mc_idx],
+                        InputGraphIOInputType::Reg => &reg_nets[&mc_idx],
+                    });
+                    let (i, e) = match io_bits.oe {
+                        None => (data_net, "1'b1".to_owned()),
+                        Some(InputGraphIOOEType::PTerm(pterm)) => (data_net, verilog_ident(&pterm_nets[&pterm])),
+                        Some(InputGraphIOOEType::GTS(gts)) => (data_net, verilog_ident(&gts_nets[&gts])),
+                        // Only ever drives low
+                        Some(InputGraphIOOEType::OpenDrain) => ("1'b0".to_owned(), format!("~{}", data_net)),
+                    };
+                    let t_out = timing.t_out + if io_bits.slew_is_fast { 0 } else { timing.t_slew };
+
+                    cells.push(PostfitCell {
+                        name: mc.name.clone(),
+                        cell_type: "IOBUFE",
+                        loc: mc_loc(loc),
+                        parameters: Vec::new(),
+                        connections: vec![
+                            ("I", i),
+                            ("E", e),
+                            ("O", pin_net),
+                            ("IO", port),
+                        ],
+                        timing: vec![
+                            PostfitTiming::IOPath("I", "IO", t_out),
+                            PostfitTiming::IOPath("E", "IO", timing.t_en),
+                            PostfitTiming::IOPath("IO", "O", timing.t_in),
+                        ],
+                    });
+                } else {
+                    cells.push(PostfitCell {
+                        name: mc.name.clone(),
+                        cell_type: "IBUF",
+                        loc: mc_loc(loc),
+                        parameters: Vec::new(),
+                        connections: vec![
+                            ("I", port),
+                            ("O", pin_net),
+                        ],
+                        timing: vec![PostfitTiming::IOPath("I", "O", timing.t_in)],
+                    });
+                }
+            }
+        }
+
+        // Instance names live in a different namespace from the nets, but they still need to be unique
+        let mut cell_names = NameTable {
+            used: HashSet::new(),
+        };
+        for cell in &mut cells {
+            cell.name = cell_names.unique(&cell.name);
+        }
+
+        Self {
+            module_name: module_name.to_owned(),
+            device_type,
+            ports,
+            wires,
+            cells,
+        }
+    }
+
+    pub fn write_verilog<W: Write>(&self, mut writer: W) -> Result<(), std::io::Error> {
+        writeln!(writer, "// Post-fit netlist for {}-{}-{}",
+            self.device_type.dev, self.device_type.spd, self.device_type.pkg)?;
+        writeln!(writer, "module {}({});", verilog_ident(&self.module_name),
+            self.ports.iter().map(|x| verilog_ident(&x.1)).collect::<Vec<_>>().join(", "))?;
+
+        for &(direction, ref name) in &self.ports {
+            let direction = match direction {
+                PostfitPortDirection::Input => "input",
+                PostfitPortDirection::Output => "output",
+                PostfitPortDirection::InOut => "inout",
+            };
+            writeln!(writer, "    {} {};", direction, verilog_ident(name))?;
+        }
+        writeln!(writer)?;
+
+        for name in &self.wires {
+            writeln!(writer, "    wire {};", verilog_ident(name))?;
+        }
+
+        for cell in &self.cells {
+            writeln!(writer, "\n    (* LOC = \"{}\" *)", cell.loc)?;
+            write!(writer, "    {}", cell.cell_type)?;
+            if !cell.parameters.is_empty() {
+                write!(writer, " #({})", cell.parameters.iter()
+                    .map(|&(name, value)| format!(".{}({})", name, value)).collect::<Vec<_>>().join(", "))?;
+            }
+            writeln!(writer, " {} (", verilog_ident(&cell.name))?;
+            for (i, &(port, ref expr)) in cell.connections.iter().enumerate() {
+                writeln!(writer, "        .{}({}){}", port, expr,
+                    if i == cell.connections.len() - 1 { "" } else { "," })?;
+            }
+            writeln!(writer, "    );")?;
+        }
+
+        writeln!(writer, "endmodule")?;
+
+        Ok(())
+    }
+
+    pub fn write_sdf<W: Write>(&self, mut writer: W) -> Result<(), std::io::Error> {
+        // Delays are in picoseconds, but written out in nanoseconds
+        let delay = |x: u32| {
+            let x = format!("{}.{:03}", x / 1000, x % 1000);
+            format!("({}:{}:{})", x, x, x)
+        };
+
+        writeln!(writer, "(DELAYFILE")?;
+        writeln!(writer, "  (SDFVERSION \"3.0\")")?;
+        writeln!(writer, "  (DESIGN \"{}\")", self.module_name)?;
+        writeln!(writer, "  (VENDOR \"xc2par\")")?;
+        writeln!(writer, "  (PROGRAM \"xc2par\")")?;
+        writeln!(writer, "  (VERSION \"{}\")", env!("CARGO_PKG_VERSION"))?;
+        writeln!(writer, "  (DIVIDER /)")?;
+        writeln!(writer, "  (PROCESS \"{}\")", self.device_type.spd)?;
+        writeln!(writer, "  (TIMESCALE 1ns)")?;
+
+        for cell in &self.cells {
+            if cell.timing.is_empty() {
+                continue;
+            }
+
+            writeln!(writer, "  (CELL")?;
+            writeln!(writer, "    (CELLTYPE \"{}\")", cell.cell_type)?;
+            writeln!(writer, "    (INSTANCE {})", sdf_ident(&cell.name))?;
+
+            let iopaths = cell.timing.iter().filter_map(|x| match *x {
+                PostfitTiming::IOPath(from, to, d) => Some((from, to, d)),
+                _ => None,
+            }).collect::<Vec<_>>();
+            if !iopaths.is_empty() {
+                writeln!(writer, "    (DELAY")?;
+                writeln!(writer, "      (ABSOLUTE")?;
+                for (from, to, d) in iopaths {
+                    writeln!(writer, "        (IOPATH {} {} {} {})", from, to, delay(d), delay(d))?;
+                }
+                writeln!(writer, "      )")?;
+                writeln!(writer, "    )")?;
+            }
+
+            let checks = cell.timing.iter().filter_map(|x| match *x {
+                PostfitTiming::Setup(data, edge, clk, d) => Some(("SETUP", data, edge, clk, d)),
+                PostfitTiming::Hold(data, edge, clk, d) => Some(("HOLD", data, edge, clk, d)),
+                _ => None,
+            }).collect::<Vec<_>>();
+            if !checks.is_empty() {
+                writeln!(writer, "    (TIMINGCHECK")?;
+                for (kind, data, edge, clk, d) in checks {
+                    let clk = match edge {
+                        Some(edge) => format!("({} {})", edge, clk),
+                        None => clk.to_owned(),
+                    };
+                    writeln!(writer, "      ({} {} {} {})", kind, data, clk, delay(d))?;
+                }
+                writeln!(writer, "    )")?;
+            }
+
+            writeln!(writer, "  )")?;
+        }
+
+        writeln!(writer, ")")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::File;
+    use std::io::Read;
+
+    fn fit(input: &str, part: &str) -> (InputGraph, OutputGraph, XC2DeviceSpeedPackage) {
+        let g = IntermediateGraph::from_equations(input.as_bytes(), None).unwrap();
+        let mut input_graph = InputGraph::from_intermed_graph(&g, None).unwrap();
+        let device_type = XC2DeviceSpeedPackage::from_str(part).unwrap();
+        let output_graph = match do_par(&mut input_graph, device_type, &XC2ParOptions::new(), None) {
+            PARResult::Success(x) => x,
+            _ => panic!("design did not fit"),
+        };
+        (input_graph, output_graph, device_type)
+    }
+
+    fn run_one_reftest(input_filename: &'static str) {
+        // Read original json
+        let input_path = std::path::Path::new(input_filename);
+        let mut input_data = Vec::new();
+        File::open(input_path).unwrap().read_to_end(&mut input_data).unwrap();
+        let yosys_netlist = yosys_netlist_json::Netlist::from_slice(&input_data).unwrap();
+        let intermed_graph = IntermediateGraph::from_yosys_netlist(&yosys_netlist, None).unwrap();
+        let mut input_graph = InputGraph::from_intermed_graph(&intermed_graph, None).unwrap();
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        let output_graph = match do_par(&mut input_graph, device_type, &XC2ParOptions::new(), None) {
+            PARResult::Success(x) => x,
+            _ => panic!("design did not fit"),
+        };
+        // This is what we get
+        let mut our_data = Vec::new();
+        PostfitNetlist::new(device_type, "top", &input_graph, &output_graph).write_verilog(&mut our_data).unwrap();
+
+        // Read reference netlist
+        let mut output_path = input_path.to_path_buf();
+        output_path.set_extension("out");
+        let mut output_data = Vec::new();
+        File::open(&output_path).unwrap().read_to_end(&mut output_data).unwrap();
+
+        assert_eq!(String::from_utf8(our_data).unwrap(), String::from_utf8(output_data).unwrap());
+    }
+
+    // Include list of actual tests to run
+    include!(concat!(env!("OUT_DIR"), "/postfit-reftests.rs"));
+
+    #[test]
+    fn postfit_counter() {
+        let input = r#"
+            q0 := !q0;
+            q1 := q1 $ q0;
+            q0.CLK = clk; q1.CLK = clk;
+            q1.AR = rst;
+            y = q1 & !a;
+            y.OE = en;
+        "#;
+        let (input_graph, output_graph, device_type) = fit(input, "xc2c32a-6-vq44");
+        let netlist = PostfitNetlist::new(device_type, "counter", &input_graph, &output_graph);
+
+        assert!(netlist.ports.contains(&(PostfitPortDirection::Input, "clk".to_owned())));
+        assert!(netlist.ports.contains(&(PostfitPortDirection::Output, "y".to_owned())));
+        let y = netlist.cells.iter()
+            .find(|x| x.cell_type == "IOBUFE" && x.connections.contains(&("IO", "y".to_owned()))).unwrap();
+        assert!(y.loc.starts_with("FB1_") || y.loc.starts_with("FB2_"));
+        assert!(netlist.cells.iter().any(|x| x.cell_type == "FDCP" && x.connections.iter().any(|x| x.0 == "CLR")));
+
+        let mut verilog = Vec::new();
+        netlist.write_verilog(&mut verilog).unwrap();
+        let verilog = String::from_utf8(verilog).unwrap();
+        assert!(verilog.starts_with("// Post-fit netlist for XC2C32A-6-VQ44\nmodule counter("));
+        assert!(verilog.contains(&format!("(* LOC = \"{}\" *)\n    IOBUFE y", y.loc)));
+        assert!(verilog.contains("ORTERM #(.WIDTH(2))"));
+
+        let mut sdf = Vec::new();
+        netlist.write_sdf(&mut sdf).unwrap();
+        let sdf = String::from_utf8(sdf).unwrap();
+        assert!(sdf.contains("(CELLTYPE \"FDCP\")"));
+        assert!(sdf.contains("(SETUP D (posedge C) (1.736:1.736:1.736))"));
+        assert!(sdf.contains("(IOPATH CLR Q (1.302:1.302:1.302) (1.302:1.302:1.302))"));
+        assert!(sdf.contains("(IOPATH IN_ORTERM OUT (0.289:0.289:0.289) (0.289:0.289:0.289))"));
+    }
+
+    #[test]
+    fn postfit_pin_to_pin_delay() {
+        let (input_graph, output_graph, device_type) = fit("y = a & !b;\n", "xc2c32a-4-vq44");
+        let netlist = PostfitNetlist::new(device_type, "gate", &input_graph, &output_graph);
+
+        let delay = |cell_type: &str, from: &str, to: &str| {
+            netlist.cells.iter().filter(|x| x.cell_type == cell_type).flat_map(|x| x.timing.iter())
+                .filter_map(|x| match *x {
+                    PostfitTiming::IOPath(a, b, delay) if a == from && b == to => Some(delay),
+                    _ => None,
+                }).next()
+        };
+        let xor_input = if delay("ORTERM", "IN", "OUT").is_some() { "IN_ORTERM" } else { "IN_PTC" };
+        let path = delay("IBUF", "I", "O").unwrap() + delay("ANDTERM", "IN", "OUT").unwrap() +
+            delay("ORTERM", "IN", "OUT").unwrap_or(0) + delay("MACROCELL_XOR", xor_input, "OUT").unwrap() +
+            delay("IOBUFE", "I", "IO").unwrap();
+
+        // The published delays are for fast slew rate outputs, and the equations frontend uses slow ones
+        let timing = XC2TimingModel::for_device(device_type);
+        let expected = if xor_input == "IN_ORTERM" { 4000 } else { 3800 };
+        assert_eq!(path, expected + timing.t_slew);
+    }
+}
@@ -0,0 +1,119 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// The random number generator used by PAR. This is the same xorshift128 generator as `rand::XorShiftRng`, but it is
+// implemented here so that the sequence of numbers (and therefore the fit) cannot change because of a different
+// version of `rand` or a different platform. In particular, `rand` samples a `usize` range using 32 or 64 random
+// bits depending on the width of `usize`; this always uses 64 bits, which matches what 64-bit hosts used to do.
+
+#[derive(Clone, Debug)]
+pub(crate) struct ParRng {
+    x: u32,
+    y: u32,
+    z: u32,
+    w: u32,
+}
+
+impl ParRng {
+    /// The seed must not be all zeros
+    pub(crate) fn from_seed(seed: [u32; 4]) -> Self {
+        assert!(seed != [0, 0, 0, 0], "PRNG seed cannot be all zeros");
+
+        Self {
+            x: seed[0],
+            y: seed[1],
+            z: seed[2],
+            w: seed[3],
+        }
+    }
+
+    pub(crate) fn next_u32(&mut self) -> u32 {
+        let t = self.x ^ (self.x << 11);
+        self.x = self.y;
+        self.y = self.z;
+        self.z = self.w;
+        self.w = self.w ^ (self.w >> 19) ^ (t ^ (t >> 8));
+        self.w
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        let hi = self.next_u32() as u64;
+        let lo = self.next_u32() as u64;
+        (hi << 32) | lo
+    }
+
+    /// Returns a number in [low, high) using rejection sampling
+    pub(crate) fn gen_range_u32(&mut self, low: u32, high: u32) -> u32 {
+        assert!(low < high);
+        let range = high - low;
+        let accept_zone = u32::MAX - u32::MAX % range;
+        loop {
+            let v = self.next_u32();
+            if v < accept_zone {
+                return low + v % range;
+            }
+        }
+    }
+
+    /// Returns a number in [low, high) using rejection sampling. This always consumes 64-bit values.
+    pub(crate) fn gen_range_usize(&mut self, low: usize, high: usize) -> usize {
+        assert!(low < high);
+        let range = (high - low) as u64;
+        let accept_zone = u64::MAX - u64::MAX % range;
+        loop {
+            let v = self.next_u64();
+            if v < accept_zone {
+                return low + (v % range) as usize;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    #[test]
+    fn prng_matches_xorshift() {
+        let mut ours = ParRng::from_seed([0, 0, 0, 1]);
+        let mut theirs: XorShiftRng = SeedableRng::from_seed([0, 0, 0, 1]);
+        for _ in 0..1000 {
+            assert_eq!(ours.next_u32(), theirs.next_u32());
+            assert_eq!(ours.gen_range_u32(0, 37), theirs.gen_range(0u32, 37));
+            assert_eq!(ours.gen_range_usize(3, 1000), theirs.gen_range(3u64, 1000) as usize);
+        }
+    }
+
+    #[test]
+    fn prng_known_sequence() {
+        // These must never change, or else fits will not be reproducible
+        let mut rng = ParRng::from_seed([0, 0, 0, 1]);
+        let values = (0..4).map(|_| rng.next_u32()).collect::<Vec<_>>();
+        assert_eq!(values, vec![1, 1, 1, 2056]);
+        assert_eq!(rng.gen_range_usize(0, 1000), 352);
+    }
+}
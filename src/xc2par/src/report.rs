@@ -0,0 +1,260 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Machine-readable fit reports. These are meant to be consumed by scripts (e.g. to track resource usage over time), so
+// the format is versioned. Any change that removes or changes the meaning of a field must bump the schema version;
+// adding new fields does not.
+
+use std::collections::HashSet;
+
+use serde_derive::{Deserialize, Serialize};
+
+use xc2bit::*;
+
+use crate::*;
+use crate::objpool::*;
+
+pub const FIT_REPORT_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum FitReportStatus {
+    Success,
+    Failure,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct FitReportResource {
+    pub used: u32,
+    pub available: u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct FitReportFunctionBlock {
+    /// Zero-based, unlike the FBn names in locations
+    pub fb: u32,
+    pub macrocells: FitReportResource,
+    pub pterms: FitReportResource,
+    pub zia_rows: FitReportResource,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct FitReportUtilization {
+    pub macrocells: FitReportResource,
+    pub pterms: FitReportResource,
+    pub io_pins: FitReportResource,
+    pub bufg_clk: FitReportResource,
+    pub bufg_gts: FitReportResource,
+    pub bufg_gsr: FitReportResource,
+    pub function_blocks: Vec<FitReportFunctionBlock>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum FitReportPinDirection {
+    Input,
+    Output,
+    InOut,
+}
+
+impl FitReportPinDirection {
+    pub(crate) fn of_macrocell(mc: &InputGraphMacrocell, io_bits: &InputGraphIOBuf) -> Self {
+        if io_bits.input.is_none() {
+            FitReportPinDirection::Input
+        } else if mc.io_feedback_used {
+            FitReportPinDirection::InOut
+        } else {
+            FitReportPinDirection::Output
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct FitReportPin {
+    pub name: String,
+    pub direction: FitReportPinDirection,
+    /// Location in FBn_i format
+    pub loc: String,
+    /// Index of the IO block, or `None` for the dedicated input pin
+    pub iob: Option<u32>,
+    /// Whether the location was fixed by a LOC constraint
+    pub constrained: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct FitReportPAR {
+    pub iterations: Option<u32>,
+    pub max_iterations: u32,
+    pub rng_seed: [u32; 4],
+    /// Reasons why the design did not fit
+    pub bottlenecks: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct FitReport {
+    pub schema_version: u32,
+    pub tool_version: String,
+    pub device: String,
+    pub status: FitReportStatus,
+    pub error: Option<String>,
+    pub utilization: Option<FitReportUtilization>,
+    pub pins: Vec<FitReportPin>,
+    /// Signals that were too wide for a single macrocell and had to be split up
+    #[serde(default)]
+    pub split_signals: Vec<SplitSignal>,
+    pub par: FitReportPAR,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl FitReport {
+    /// Creates an empty report for a failed run. The flow fills in the rest as it goes.
+    pub fn new(device_type: XC2DeviceSpeedPackage, options: &XC2ParOptions) -> Self {
+        Self {
+            schema_version: FIT_REPORT_SCHEMA_VERSION,
+            tool_version: env!("CARGO_PKG_VERSION").to_owned(),
+            device: format!("{}-{}-{}", device_type.dev, device_type.spd, device_type.pkg),
+            status: FitReportStatus::Failure,
+            error: None,
+            utilization: None,
+            pins: Vec::new(),
+            split_signals: Vec::new(),
+            par: FitReportPAR {
+                iterations: None,
+                max_iterations: options.max_iter,
+                rng_seed: options.rng_seed,
+                bottlenecks: Vec::new(),
+            },
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Fills in the utilization and pin assignments from a successful fit
+    pub fn add_fit_result(&mut self, device_type: XC2DeviceSpeedPackage, g: &InputGraph, go: &OutputGraph) {
+        let num_fbs = device_type.dev.num_fbs();
+        let has_inpin = device_type.dev == XC2Device::XC2C32 || device_type.dev == XC2Device::XC2C32A;
+        let resource = |used: usize, available: usize| FitReportResource {
+            used: used as u32,
+            available: available as u32,
+        };
+
+        let mut mc_sites = HashSet::new();
+        let mut num_io_pins = 0;
+        for mc_idx in g.mcs.iter_idx() {
+            let mc = g.mcs.get(mc_idx);
+            let loc = go.mcs.get(ObjPoolIndex::from(mc_idx)).loc.unwrap();
+            mc_sites.insert((loc.fb, loc.i));
+
+            if let Some(ref io_bits) = mc.io_bits {
+                num_io_pins += 1;
+                self.pins.push(FitReportPin {
+                    name: mc.pad_name().to_owned(),
+                    direction: FitReportPinDirection::of_macrocell(mc, io_bits),
+                    loc: format!("FB{}_{}", loc.fb + 1, loc.i + 1),
+                    iob: fb_mc_num_to_iob_num(device_type.dev, loc.fb, loc.i),
+                    constrained: mc.requested_loc.map(|x| x.i.is_some()).unwrap_or(false),
+                });
+            }
+        }
+        self.pins.sort_by(|a, b| a.name.cmp(&b.name));
+
+        // Identical p-terms used by several macrocells in a FB share one physical p-term
+        let pterm_sites = go.pterms.iter().map(|x| (x.loc.unwrap().fb, x.loc.unwrap().i)).collect::<HashSet<_>>();
+
+        let function_blocks = (0..num_fbs).map(|fb| {
+            let macrocells = mc_sites.iter().filter(|x| x.0 as usize == fb).count();
+            let pterms = pterm_sites.iter().filter(|x| x.0 as usize == fb).count();
+            let zia_rows = (0..INPUTS_PER_ANDTERM)
+                .filter(|&i| !matches!(go.zia[fb].get(i), XC2ZIAInput::Zero | XC2ZIAInput::One)).count();
+
+            FitReportFunctionBlock {
+                fb: fb as u32,
+                macrocells: resource(macrocells, MCS_PER_FB),
+                pterms: resource(pterms, ANDTERMS_PER_FB),
+                zia_rows: resource(zia_rows, INPUTS_PER_ANDTERM),
+            }
+        }).collect();
+
+        self.status = FitReportStatus::Success;
+        self.utilization = Some(FitReportUtilization {
+            macrocells: resource(mc_sites.len(), num_fbs * MCS_PER_FB),
+            pterms: resource(pterm_sites.len(), num_fbs * ANDTERMS_PER_FB),
+            io_pins: resource(num_io_pins, device_type.dev.num_iobs() + if has_inpin { 1 } else { 0 }),
+            bufg_clk: resource(g.bufg_clks.len(), NUM_BUFG_CLK),
+            bufg_gts: resource(g.bufg_gts.len(), NUM_BUFG_GTS),
+            bufg_gsr: resource(g.bufg_gsr.len(), NUM_BUFG_GSR),
+            function_blocks,
+        });
+        self.par.iterations = Some(go.iterations);
+    }
+
+    pub fn write_json<W: std::io::Write>(&self, writer: W) -> Result<(), serde_json::Error> {
+        serde_json::to_writer_pretty(writer, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_counter() {
+        let input = r#"
+            q0 := !q0;
+            q1 := q1 $ q0;
+            q0.CLK = clk; q1.CLK = clk;
+            y = q1 & !a;
+            z = q1 & !a # b;
+        "#;
+        let g = IntermediateGraph::from_equations(input.as_bytes(), None).unwrap();
+        let mut input_graph = InputGraph::from_intermed_graph(&g, None).unwrap();
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c64a-5-vq44").unwrap();
+        let options = XC2ParOptions::new();
+        let output_graph = match do_par(&mut input_graph, device_type, &options, None) {
+            PARResult::Success(x) => x,
+            _ => panic!("design did not fit"),
+        };
+
+        let mut report = FitReport::new(device_type, &options);
+        report.add_fit_result(device_type, &input_graph, &output_graph);
+
+        assert_eq!(report.status, FitReportStatus::Success);
+        assert_eq!(report.device, "XC2C64A-5-VQ44");
+        let utilization = report.utilization.as_ref().unwrap();
+        // y and z share the q1 & !a p-term
+        assert_eq!(output_graph.pterms.len(), 8);
+        assert_eq!(utilization.pterms.used, 7);
+        assert_eq!(utilization.pterms.available, 4 * ANDTERMS_PER_FB as u32);
+        assert_eq!(utilization.io_pins.used, 7);
+        assert_eq!(utilization.function_blocks.len(), 4);
+        assert_eq!(utilization.function_blocks.iter().map(|x| x.pterms.used).sum::<u32>(), 7);
+        let names = report.pins.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b", "clk", "q0", "q1", "y", "z"]);
+        assert_eq!(report.par.iterations, Some(output_graph.iterations));
+
+        let mut json = Vec::new();
+        report.write_json(&mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["schema_version"], FIT_REPORT_SCHEMA_VERSION);
+        assert_eq!(json["pins"][5]["direction"], "Output");
+    }
+}
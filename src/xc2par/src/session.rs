@@ -0,0 +1,548 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Staged PAR. `xc2par_complete_flow` runs everything in one go; a `ParSession` instead lets the caller run each stage
+// separately, look at (or modify) the intermediate state in between, get progress reports, and cancel the run.
+
+use std::collections::HashMap;
+
+use serde_derive::{Deserialize, Serialize};
+use slog::Drain;
+
+use xc2bit::*;
+
+use crate::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub enum ParStage {
+    Frontend,
+    Gather,
+    SanityCheck,
+    InitialPlacement,
+    Improvement,
+    Bitstream,
+    Verify,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct ParProgress {
+    pub stage: ParStage,
+    /// Only meaningful during `ParStage::Improvement`
+    pub iteration: u32,
+    pub max_iterations: u32,
+    /// Score of the best placement so far, once there is a placement. Lower is better, and 0 means done.
+    pub score: Option<u32>,
+}
+
+type ProgressHook<'a> = Box<dyn FnMut(&ParProgress) + 'a>;
+type IterationHook<'a> = Box<dyn FnMut(&InputGraph, &PARState) + 'a>;
+
+/// Runs the PAR flow one stage at a time. The stages must be run in order (each stage fails with
+/// `PARFlowError::StageNotRun` if the previous one has not been run), but the graph produced by a stage can be
+/// inspected and modified before the next one is run. Running a stage again throws away the results of the later ones.
+pub struct ParSession<'a> {
+    device_type: XC2DeviceSpeedPackage,
+    options: XC2ParOptions,
+    logger: slog::Logger,
+    source_locations: HashMap<String, DiagnosticSource>,
+    area_groups: Vec<AreaGroup>,
+    split_signals: Vec<SplitSignal>,
+    intermediate_graph: Option<IntermediateGraph>,
+    input_graph: Option<InputGraph>,
+    par_state: Option<PARState>,
+    output_graph: Option<OutputGraph>,
+    // Created when the first stage runs, so that the time limit covers the whole flow
+    stop: Option<PARStopCondition>,
+    progress_hook: Option<ProgressHook<'a>>,
+    cancel_hook: Option<Box<dyn FnMut() -> bool + 'a>>,
+    iteration_hook: Option<IterationHook<'a>>,
+}
+
+impl<'a> ParSession<'a> {
+    pub fn new<L: Into<Option<slog::Logger>>>(device_type: XC2DeviceSpeedPackage, options: &XC2ParOptions,
+        logger: L) -> Self {
+
+        Self {
+            device_type,
+            options: options.clone(),
+            logger: logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!())),
+            source_locations: HashMap::new(),
+            area_groups: Vec::new(),
+            split_signals: Vec::new(),
+            intermediate_graph: None,
+            input_graph: None,
+            par_state: None,
+            output_graph: None,
+            stop: None,
+            progress_hook: None,
+            cancel_hook: None,
+            iteration_hook: None,
+        }
+    }
+
+    /// Called at the start of every stage and after every iteration of the improvement stage
+    pub fn on_progress<F: FnMut(&ParProgress) + 'a>(&mut self, hook: F) -> &mut Self {
+        self.progress_hook = Some(Box::new(hook));
+
+        self
+    }
+
+    /// Called at the start of every stage and before every iteration of the improvement stage. If this returns true,
+    /// the stage fails with `PARFlowError::PARStopped`. The time limit and cancellation flag in `XC2ParOptions` are
+    /// also honored.
+    pub fn cancel_when<F: FnMut() -> bool + 'a>(&mut self, hook: F) -> &mut Self {
+        self.cancel_hook = Some(Box::new(hook));
+
+        self
+    }
+
+    /// Called after every iteration of the improvement stage with the current placement
+    pub fn on_iteration<F: FnMut(&InputGraph, &PARState) + 'a>(&mut self, hook: F) -> &mut Self {
+        self.iteration_hook = Some(Box::new(hook));
+
+        self
+    }
+
+    pub fn device_type(&self) -> XC2DeviceSpeedPackage {
+        self.device_type
+    }
+
+    /// Source locations of the cells and nets in the input, if it was a Yosys netlist
+    pub fn source_locations(&self) -> &HashMap<String, DiagnosticSource> {
+        &self.source_locations
+    }
+
+    pub fn intermediate_graph(&self) -> Option<&IntermediateGraph> {
+        self.intermediate_graph.as_ref()
+    }
+
+    pub fn intermediate_graph_mut(&mut self) -> Option<&mut IntermediateGraph> {
+        self.intermediate_graph.as_mut()
+    }
+
+    /// The signals that had to be split up into several macrocells by the gather stage
+    pub fn split_signals(&self) -> &[SplitSignal] {
+        &self.split_signals
+    }
+
+    pub fn input_graph(&self) -> Option<&InputGraph> {
+        self.input_graph.as_ref()
+    }
+
+    pub fn input_graph_mut(&mut self) -> Option<&mut InputGraph> {
+        self.input_graph.as_mut()
+    }
+
+    /// The placement in progress, between the initial placement and the end of the improvement stage
+    pub fn par_state(&self) -> Option<&PARState> {
+        self.par_state.as_ref()
+    }
+
+    /// The final result of PAR, once the improvement stage has succeeded
+    pub fn output_graph(&self) -> Option<&OutputGraph> {
+        self.output_graph.as_ref()
+    }
+
+    /// Skips the frontend and starts from an existing intermediate graph
+    pub fn set_intermediate_graph(&mut self, g: IntermediateGraph) -> &mut Self {
+        self.clear_after(ParStage::Frontend);
+        self.intermediate_graph = Some(g);
+
+        self
+    }
+
+    /// Skips the frontend and gather stages and starts from an existing input graph
+    pub fn set_input_graph(&mut self, g: InputGraph) -> &mut Self {
+        self.clear_after(ParStage::Gather);
+        self.input_graph = Some(g);
+
+        self
+    }
+
+    // Throws away everything produced by the stages after the given one, so that re-running a stage never leaves
+    // stale results from the previous run around
+    fn clear_after(&mut self, stage: ParStage) {
+        if stage < ParStage::Gather {
+            self.input_graph = None;
+            self.split_signals.clear();
+        }
+        if stage < ParStage::InitialPlacement {
+            self.par_state = None;
+        }
+        if stage < ParStage::Improvement {
+            self.output_graph = None;
+        }
+    }
+
+    fn start_stage(&mut self, stage: ParStage) -> Result<(), PARFlowError> {
+        if self.stop.is_none() {
+            self.stop = Some(PARStopCondition::from_options(&self.options));
+        }
+        if let Some(ref mut hook) = self.cancel_hook {
+            if hook() {
+                info!(self.logger, "PAR session - cancelled"; "stage" => format!("{:?}", stage));
+                return Err(PARFlowError::PARStopped(PARStopReason::Cancelled));
+            }
+        }
+
+        let progress = ParProgress {
+            stage,
+            iteration: self.par_state.as_ref().map(|x| x.iterations()).unwrap_or(0),
+            max_iterations: self.options.max_iter,
+            score: self.par_state.as_ref().map(|x| x.score()),
+        };
+        if let Some(ref mut hook) = self.progress_hook {
+            hook(&progress);
+        }
+
+        Ok(())
+    }
+
+    /// Reads the input in the format selected in the options and turns it into an intermediate graph
+    pub fn run_frontend<R: std::io::Read>(&mut self, input: R) -> Result<&IntermediateGraph, PARFlowError> {
+        self.start_stage(ParStage::Frontend)?;
+        self.clear_after(ParStage::Frontend);
+        self.intermediate_graph = None;
+        self.source_locations.clear();
+        self.area_groups.clear();
+
+        let logger = &self.logger;
+        let intermediate_graph = match self.options.input_fmt {
+            ParInputFormat::YosysJson | ParInputFormat::Blif => {
+                let yosys_netlist = if self.options.input_fmt == ParInputFormat::YosysJson {
+                    yosys_netlist_json::Netlist::from_reader(input)?
+                } else {
+                    read_blif_netlist(input, logger.new(o!("pass" => "blif")))?
+                };
+                self.area_groups = yosys_area_groups(&yosys_netlist, self.options.top_module.as_deref(),
+                    logger.new(o!("pass" => "area groups")))?;
+                let yosys_netlist = flatten_yosys_netlist(&yosys_netlist, self.options.top_module.as_deref(),
+                    logger.new(o!("pass" => "flatten")))?;
+                self.source_locations = yosys_source_locations(&yosys_netlist);
+                IntermediateGraph::from_flat_yosys_netlist(&yosys_netlist,
+                    logger.new(o!("pass" => "yosys -> intermediate")))?
+            },
+            ParInputFormat::Pla => {
+                IntermediateGraph::from_pla(input, logger.new(o!("pass" => "pla -> intermediate")))?
+            },
+            ParInputFormat::Equations => {
+                IntermediateGraph::from_equations(input, logger.new(o!("pass" => "equations -> intermediate")))?
+            },
+        };
+
+        Ok(self.intermediate_graph.insert(intermediate_graph))
+    }
+
+    /// Gathers the intermediate graph into macrocells and P-terms, then optimizes the logic
+    pub fn run_gather(&mut self) -> Result<&InputGraph, PARFlowError> {
+        self.start_stage(ParStage::Gather)?;
+        self.clear_after(ParStage::Gather);
+
+        let intermediate_graph = self.intermediate_graph.as_ref().ok_or(PARFlowError::StageNotRun(ParStage::Frontend))?;
+        let mut input_graph = InputGraph::from_intermed_graph(intermediate_graph,
+            self.logger.new(o!("pass" => "intermediate -> input")))?;
+        if !self.options.pin_constraints.is_empty() {
+            input_graph.apply_pin_constraints(&self.options.pin_constraints,
+                self.logger.new(o!("pass" => "constraints")));
+        }
+        if self.options.infer_global_nets {
+            input_graph.infer_global_nets(self.device_type.dev, self.logger.new(o!("pass" => "global nets")));
+        }
+        if self.options.pack_io_registers {
+            input_graph.pack_io_registers(self.logger.new(o!("pass" => "pack")));
+        }
+        if self.options.minimize_logic {
+            input_graph.minimize_logic(self.logger.new(o!("pass" => "minimize")));
+        }
+        self.split_signals = input_graph.split_wide_functions(self.logger.new(o!("pass" => "split")));
+        // Groups from the options come after the ones from the netlist so that they win
+        let area_groups = self.area_groups.iter().chain(self.options.area_groups.iter()).cloned().collect::<Vec<_>>();
+        if !area_groups.is_empty() {
+            input_graph.apply_area_groups(&area_groups, self.logger.new(o!("pass" => "area groups")));
+        }
+
+        Ok(self.input_graph.insert(input_graph))
+    }
+
+    /// Checks whether the design can possibly fit the device at all
+    pub fn run_sanity_check(&mut self) -> Result<(), PARFlowError> {
+        self.start_stage(ParStage::SanityCheck)?;
+        self.clear_after(ParStage::SanityCheck);
+
+        let logger = self.logger.new(o!("pass" => "PAR"));
+        let g = self.input_graph.as_mut().ok_or(PARFlowError::StageNotRun(ParStage::Gather))?;
+        let sanity_check = do_par_sanity_check(g, self.device_type, &logger);
+        if sanity_check != PARSanityResult::Ok {
+            let bottlenecks = explain_sanity_failure(g, self.device_type, sanity_check);
+            log_bottlenecks(&bottlenecks, &logger);
+            return Err(PARFlowError::PARSanityCheckFailed(sanity_check, bottlenecks));
+        }
+
+        Ok(())
+    }
+
+    pub fn run_initial_placement(&mut self) -> Result<&PARState, PARFlowError> {
+        self.start_stage(ParStage::InitialPlacement)?;
+        self.clear_after(ParStage::InitialPlacement);
+
+        let logger = self.logger.new(o!("pass" => "PAR"));
+        // start_stage has always set this up by now
+        let stop = self.stop.clone().unwrap_or_else(|| PARStopCondition::from_options(&self.options));
+        let g = self.input_graph.as_mut().ok_or(PARFlowError::StageNotRun(ParStage::Gather))?;
+        let state = PARState::with_stop_condition(g, self.device_type, &self.options, stop, &logger);
+        if state.is_none() {
+            let bottlenecks = explain_sanity_failure(g, self.device_type, PARSanityResult::FailureInitialPlacement);
+            log_bottlenecks(&bottlenecks, &logger);
+            return Err(PARFlowError::PARSanityCheckFailed(PARSanityResult::FailureInitialPlacement, bottlenecks));
+        }
+
+        Ok(self.par_state.insert(state.unwrap()))
+    }
+
+    /// Moves macrocells around until nothing is violated anymore or the maximum number of iterations is reached
+    pub fn run_improvement(&mut self) -> Result<&OutputGraph, PARFlowError> {
+        self.start_stage(ParStage::Improvement)?;
+
+        let logger = self.logger.new(o!("pass" => "PAR"));
+        let g = self.input_graph.as_ref().ok_or(PARFlowError::StageNotRun(ParStage::Gather))?;
+        let state = self.par_state.as_mut().ok_or(PARFlowError::StageNotRun(ParStage::InitialPlacement))?;
+
+        while !state.is_done() && state.iterations() < self.options.max_iter {
+            if let Some(ref mut hook) = self.cancel_hook {
+                if hook() {
+                    info!(logger, "PAR session - cancelled"; "iteration" => state.iterations());
+                    return Err(PARFlowError::PARStopped(PARStopReason::Cancelled));
+                }
+            }
+            if let Some(reason) = state.stop_reason() {
+                info!(logger, "PAR session - stopped"; "reason" => format!("{:?}", reason),
+                    "iteration" => state.iterations());
+                return Err(PARFlowError::PARStopped(reason));
+            }
+
+            state.step(g, self.device_type, &logger);
+
+            if let Some(ref mut hook) = self.iteration_hook {
+                hook(g, state);
+            }
+            if let Some(ref mut hook) = self.progress_hook {
+                hook(&ParProgress {
+                    stage: ParStage::Improvement,
+                    iteration: state.iterations(),
+                    max_iterations: self.options.max_iter,
+                    score: Some(state.score()),
+                });
+            }
+        }
+
+        if !state.is_done() {
+            let bottlenecks = state.explain_failure(g, self.device_type);
+            log_bottlenecks(&bottlenecks, &logger);
+            return Err(PARFlowError::PARIterationsExceeded(bottlenecks));
+        }
+
+        let state = self.par_state.take().unwrap();
+        Ok(self.output_graph.insert(state.finish(g, self.device_type, &logger)))
+    }
+
+    pub fn run_bitstream(&mut self) -> Result<XC2Bitstream, PARFlowError> {
+        self.start_stage(ParStage::Bitstream)?;
+
+        let g = self.input_graph.as_ref().ok_or(PARFlowError::StageNotRun(ParStage::Gather))?;
+        let go = self.output_graph.as_ref().ok_or(PARFlowError::StageNotRun(ParStage::Improvement))?;
+        Ok(produce_bitstream(self.device_type, g, go))
+    }
+
+    /// Checks that the logic in the bitstream matches the intermediate graph, failing with
+    /// `PARFlowError::EquivalenceCheckFailed` if it doesn't or if anything couldn't be checked
+    pub fn run_equivalence_check(&mut self, bitstream: &XC2Bitstream) -> Result<EquivalenceReport, PARFlowError> {
+        self.start_stage(ParStage::Verify)?;
+
+        let intermediate_graph = self.intermediate_graph.as_ref().ok_or(PARFlowError::StageNotRun(ParStage::Frontend))?;
+        let g = self.input_graph.as_ref().ok_or(PARFlowError::StageNotRun(ParStage::Gather))?;
+        let go = self.output_graph.as_ref().ok_or(PARFlowError::StageNotRun(ParStage::Improvement))?;
+        let report = check_equivalence(intermediate_graph, g, go, bitstream,
+            self.logger.new(o!("pass" => "equivalence")));
+        if !report.is_equivalent() {
+            return Err(PARFlowError::EquivalenceCheckFailed(report));
+        }
+
+        Ok(report)
+    }
+
+    /// Runs all of the stages in order, including the equivalence check if it is enabled in the options
+    pub fn run_all<R: std::io::Read>(&mut self, input: R) -> Result<XC2Bitstream, PARFlowError> {
+        self.run_frontend(input)?;
+        self.run_gather()?;
+        self.run_sanity_check()?;
+        self.run_initial_placement()?;
+        self.run_improvement()?;
+        let bitstream = self.run_bitstream()?;
+        if self.options.verify_equivalence {
+            self.run_equivalence_check(&bitstream)?;
+        }
+
+        Ok(bitstream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::{Cell, RefCell};
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+    use std::time::Duration;
+
+    const COUNTER: &str = r#"
+        q0 := !q0;
+        q1 := q1 $ q0;
+        q0.CLK = clk; q1.CLK = clk;
+        y = q1 & !a;
+    "#;
+
+    fn options() -> XC2ParOptions {
+        let mut options = XC2ParOptions::new();
+        options.input_format(ParInputFormat::Equations);
+        options
+    }
+
+    #[test]
+    fn session_stages() {
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        let stages = RefCell::new(Vec::new());
+        let mut session = ParSession::new(device_type, &options(), None);
+        session.on_progress(|x| stages.borrow_mut().push(x.stage));
+
+        let num_nodes = session.run_frontend(COUNTER.as_bytes()).unwrap().nodes.len();
+        assert!(num_nodes > 0);
+        let num_mcs = session.run_gather().unwrap().mcs.len();
+        assert_eq!(num_mcs, 5);
+        session.run_sanity_check().unwrap();
+        session.run_initial_placement().unwrap();
+        session.run_improvement().unwrap();
+        assert!(session.par_state().is_none());
+        assert_eq!(session.output_graph().unwrap().mcs.len(), num_mcs);
+        session.run_bitstream().unwrap();
+        drop(session);
+
+        let stages = stages.into_inner();
+        assert_eq!(stages[..5], [ParStage::Frontend, ParStage::Gather, ParStage::SanityCheck,
+            ParStage::InitialPlacement, ParStage::Improvement]);
+        assert_eq!(stages.last(), Some(&ParStage::Bitstream));
+    }
+
+    #[test]
+    fn session_stage_order() {
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        let mut session = ParSession::new(device_type, &options(), None);
+        match session.run_gather() {
+            Err(PARFlowError::StageNotRun(ParStage::Frontend)) => {},
+            x => panic!("unexpected result {:?}", x.map(|_| ())),
+        }
+
+        session.run_all(COUNTER.as_bytes()).unwrap();
+        assert!(session.output_graph().is_some());
+        // Re-running an earlier stage throws away the results of the later ones
+        let num_mcs = session.run_gather().unwrap().mcs.len();
+        assert_eq!(num_mcs, 5);
+        assert!(session.output_graph().is_none());
+        match session.run_bitstream() {
+            Err(PARFlowError::StageNotRun(ParStage::Improvement)) => {},
+            x => panic!("unexpected result {:?}", x.map(|_| ())),
+        }
+        session.run_frontend("y = a;\n".as_bytes()).unwrap();
+        assert!(session.input_graph().is_none());
+        assert_eq!(session.run_gather().unwrap().mcs.len(), 2);
+    }
+
+    #[test]
+    fn session_split_signals() {
+        // The 7-input parity needs 64 p-terms, which is more than a function block has
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        let mut session = ParSession::new(device_type, &options(), None);
+        session.run_all("y = a $ b $ c $ d $ e $ f $ g;\n".as_bytes()).unwrap();
+
+        let splits = session.split_signals();
+        assert_eq!(splits.len(), 1);
+        assert_eq!(splits[0].reason, SplitReason::TooManyPTerms(64));
+        assert!(!splits[0].new_mcs.is_empty());
+    }
+
+    #[test]
+    fn session_cancel() {
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        let stage = Cell::new(ParStage::Frontend);
+        let mut session = ParSession::new(device_type, &options(), None);
+        session.on_progress(|x| stage.set(x.stage));
+        // Cancel as soon as the initial placement is done
+        session.cancel_when(|| stage.get() == ParStage::InitialPlacement);
+
+        match session.run_all(COUNTER.as_bytes()) {
+            Err(PARFlowError::PARStopped(PARStopReason::Cancelled)) => {},
+            _ => panic!("PAR was not cancelled"),
+        }
+        // Everything up until the cancellation is still there
+        assert!(session.input_graph().is_some());
+        assert!(session.par_state().is_some());
+        assert!(session.output_graph().is_none());
+    }
+
+    #[test]
+    fn session_stop_condition() {
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+
+        let mut cancel_options = options();
+        cancel_options.cancel_flag(Some(Arc::new(AtomicBool::new(true))));
+        let mut session = ParSession::new(device_type, &cancel_options, None);
+        match session.run_all(COUNTER.as_bytes()) {
+            Err(PARFlowError::PARStopped(PARStopReason::Cancelled)) => {},
+            _ => panic!("PAR was not cancelled"),
+        }
+        // The best placement found so far is still there
+        assert!(session.par_state().is_some());
+
+        let mut deadline_options = options();
+        deadline_options.time_limit(Some(Duration::from_secs(0)));
+        let mut session = ParSession::new(device_type, &deadline_options, None);
+        match session.run_all(COUNTER.as_bytes()) {
+            Err(PARFlowError::PARStopped(PARStopReason::DeadlineExceeded)) => {},
+            _ => panic!("PAR did not stop at the deadline"),
+        }
+
+        // Time spent before PAR counts towards the limit too
+        let mut deadline_options = options();
+        deadline_options.time_limit(Some(Duration::from_millis(50)));
+        let mut session = ParSession::new(device_type, &deadline_options, None);
+        session.on_progress(|x| if x.stage == ParStage::Frontend {
+            std::thread::sleep(Duration::from_millis(100));
+        });
+        match session.run_all(COUNTER.as_bytes()) {
+            Err(PARFlowError::PARStopped(PARStopReason::DeadlineExceeded)) => {},
+            _ => panic!("PAR did not stop at the deadline"),
+        }
+    }
+}
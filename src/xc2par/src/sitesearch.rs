@@ -0,0 +1,296 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Search for an assignment of "things" to "sites", used for placing p-terms into the AND array and for routing inputs
+// through the ZIA. Every variable has a list of candidate sites and a class. Several variables can share a site only
+// if they are all of the same class (e.g. identical p-terms can share one AND term, but every ZIA row can only carry
+// one input).
+//
+// This is a backtracking search with the following improvements over plain chronological backtracking:
+// * The variable with the fewest remaining candidate sites is tried next (MRV)
+// * The sites that rule out the fewest candidates for other variables are tried first (LCV)
+// * After every assignment, all remaining variables are checked for having at least one usable site (forward checking)
+// * When a variable fails, the search jumps straight back to the most recent variable that actually contributed to
+//   the failure rather than just the previous one (conflict-directed backjumping)
+//
+// Forward checking and backjumping never change which solution is found, only how fast. MRV and LCV do change it, so
+// the search is first run in the original order for a limited number of steps. That way, everything that used to fit
+// quickly still gets exactly the same result, and the heuristics only kick in for the hard cases.
+
+use crate::engine::PARStopCondition;
+
+// How many variables the search in the original order is allowed to try to assign before switching to MRV/LCV
+const ORIGINAL_ORDER_BUDGET: u32 = 10000;
+
+pub(crate) struct SiteSearch<'a> {
+    candidate_sites: &'a [Vec<u32>],
+    classes: &'a [usize],
+    // Variables currently assigned to each site
+    site_vars: Vec<Vec<usize>>,
+    assignment: Vec<Option<u32>>,
+    num_assigned: usize,
+    most_assigned: usize,
+    use_heuristics: bool,
+    budget: Option<u32>,
+}
+
+enum SearchFailure {
+    Stopped,
+    OutOfBudget,
+    // Which variables that are currently assigned are responsible for the failure
+    Conflict(Vec<bool>),
+}
+
+impl<'a> SiteSearch<'a> {
+    pub(crate) fn new(candidate_sites: &'a [Vec<u32>], classes: &'a [usize], num_sites: usize) -> Self {
+        assert_eq!(candidate_sites.len(), classes.len());
+
+        Self {
+            candidate_sites,
+            classes,
+            site_vars: vec![Vec::new(); num_sites],
+            assignment: vec![None; candidate_sites.len()],
+            num_assigned: 0,
+            most_assigned: 0,
+            use_heuristics: false,
+            budget: Some(ORIGINAL_ORDER_BUDGET),
+        }
+    }
+
+    /// Returns the site chosen for every variable. If there is no solution (or the search was stopped), returns the
+    /// largest number of variables that were assigned at the same time instead.
+    pub(crate) fn solve(mut self, stop: &PARStopCondition) -> Result<Vec<u32>, u32> {
+        let mut result = self.search(stop);
+        if let Err(SearchFailure::OutOfBudget) = result {
+            // Everything is unassigned again at this point
+            self.use_heuristics = true;
+            self.budget = None;
+            result = self.search(stop);
+        }
+
+        match result {
+            Ok(()) => Ok(self.assignment.into_iter().map(|x| x.unwrap()).collect()),
+            Err(_) => Err(self.most_assigned as u32),
+        }
+    }
+
+    fn site_usable(&self, var: usize, site: u32) -> bool {
+        let site_vars = &self.site_vars[site as usize];
+        site_vars.is_empty() || self.classes[site_vars[0]] == self.classes[var]
+    }
+
+    fn num_usable_sites(&self, var: usize) -> usize {
+        self.candidate_sites[var].iter().filter(|&&site| self.site_usable(var, site)).count()
+    }
+
+    // Adds all of the assigned variables that are blocking a candidate site of `var` to `conflict`
+    fn add_blockers(&self, var: usize, conflict: &mut [bool]) {
+        for &site in &self.candidate_sites[var] {
+            if !self.site_usable(var, site) {
+                for &blocker in &self.site_vars[site as usize] {
+                    conflict[blocker] = true;
+                }
+            }
+        }
+    }
+
+    // How many candidate sites of other unassigned variables would become unusable by putting `var` into `site`
+    fn num_ruled_out(&self, var: usize, site: u32) -> usize {
+        if !self.site_vars[site as usize].is_empty() {
+            // Already restricted to this class
+            return 0;
+        }
+
+        (0..self.candidate_sites.len()).filter(|&other| {
+            other != var && self.assignment[other].is_none() && self.classes[other] != self.classes[var] &&
+                self.candidate_sites[other].contains(&site)
+        }).count()
+    }
+
+    fn assign(&mut self, var: usize, site: u32) {
+        self.assignment[var] = Some(site);
+        self.site_vars[site as usize].push(var);
+        self.num_assigned += 1;
+        if self.num_assigned > self.most_assigned {
+            self.most_assigned = self.num_assigned;
+        }
+    }
+
+    fn unassign(&mut self, var: usize) {
+        let site = self.assignment[var].take().unwrap();
+        let site_vars = &mut self.site_vars[site as usize];
+        let pos = site_vars.iter().position(|&x| x == var).unwrap();
+        site_vars.remove(pos);
+        self.num_assigned -= 1;
+    }
+
+    fn search(&mut self, stop: &PARStopCondition) -> Result<(), SearchFailure> {
+        if stop.check().is_some() {
+            return Err(SearchFailure::Stopped);
+        }
+        if let Some(ref mut budget) = self.budget {
+            if *budget == 0 {
+                return Err(SearchFailure::OutOfBudget);
+            }
+            *budget -= 1;
+        }
+
+        // MRV. Ties go to the variable that comes first.
+        let mut unassigned = (0..self.candidate_sites.len()).filter(|&var| self.assignment[var].is_none());
+        let var = if self.use_heuristics {
+            unassigned.min_by_key(|&var| self.num_usable_sites(var))
+        } else {
+            unassigned.next()
+        };
+        let var = if let Some(var) = var { var } else {
+            // Complete assignment, we are done
+            return Ok(());
+        };
+
+        // LCV. The sort is stable, so ties are tried in the original order.
+        let mut sites = self.candidate_sites[var].iter().cloned()
+            .filter(|&site| self.site_usable(var, site))
+            .collect::<Vec<_>>();
+        if self.use_heuristics {
+            sites.sort_by_key(|&site| self.num_ruled_out(var, site));
+        }
+
+        let mut conflict = vec![false; self.candidate_sites.len()];
+        self.add_blockers(var, &mut conflict);
+
+        for site in sites {
+            self.assign(var, site);
+
+            // Forward checking
+            let wiped_out = (0..self.candidate_sites.len())
+                .find(|&other| self.assignment[other].is_none() && self.num_usable_sites(other) == 0);
+            if let Some(wiped_out) = wiped_out {
+                let mut wiped_out_conflict = vec![false; self.candidate_sites.len()];
+                self.add_blockers(wiped_out, &mut wiped_out_conflict);
+                self.unassign(var);
+                merge_conflict(&mut conflict, &wiped_out_conflict, var);
+                continue;
+            }
+
+            match self.search(stop) {
+                Ok(()) => return Ok(()),
+                Err(SearchFailure::Stopped) => {
+                    self.unassign(var);
+                    return Err(SearchFailure::Stopped);
+                },
+                Err(SearchFailure::OutOfBudget) => {
+                    self.unassign(var);
+                    return Err(SearchFailure::OutOfBudget);
+                },
+                Err(SearchFailure::Conflict(inner_conflict)) => {
+                    self.unassign(var);
+                    if !inner_conflict[var] {
+                        // Nothing that we do here can fix this, so jump back further
+                        return Err(SearchFailure::Conflict(inner_conflict));
+                    }
+                    merge_conflict(&mut conflict, &inner_conflict, var);
+                },
+            }
+        }
+
+        Err(SearchFailure::Conflict(conflict))
+    }
+}
+
+fn merge_conflict(conflict: &mut [bool], other: &[bool], except: usize) {
+    for (i, &x) in other.iter().enumerate() {
+        if x && i != except {
+            conflict[i] = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_solution(candidate_sites: &[Vec<u32>], classes: &[usize], solution: &[u32]) {
+        for i in 0..solution.len() {
+            assert!(candidate_sites[i].contains(&solution[i]));
+            for j in 0..solution.len() {
+                if solution[i] == solution[j] {
+                    assert_eq!(classes[i], classes[j]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sitesearch_backjump() {
+        // 20 variables that can each go into either of their own two sites, followed by three variables that all want
+        // the same two sites. Chronological backtracking would try all 2^20 ways of placing the first 20 variables
+        // before giving up.
+        let mut candidate_sites = (0..20).map(|i| vec![2 * i, 2 * i + 1]).collect::<Vec<_>>();
+        for _ in 0..3 {
+            candidate_sites.push(vec![40, 41]);
+        }
+        let classes = (0..23).collect::<Vec<_>>();
+        let result = SiteSearch::new(&candidate_sites, &classes, 42).solve(&PARStopCondition::none());
+        assert_eq!(result, Err(22));
+    }
+
+    #[test]
+    fn sitesearch_dense() {
+        // Every variable can go to one of two sites, and the only solution needs variables to be placed in their
+        // second choice
+        let n = 30;
+        let mut candidate_sites = (0..n).map(|i| vec![i, i + 1]).collect::<Vec<_>>();
+        candidate_sites.push(vec![0]);
+        let classes = (0..(n as usize + 1)).collect::<Vec<_>>();
+        let result = SiteSearch::new(&candidate_sites, &classes, n as usize + 1).solve(&PARStopCondition::none());
+        let solution = result.unwrap();
+        check_solution(&candidate_sites, &classes, &solution);
+        assert_eq!(solution[0], 1);
+    }
+
+    #[test]
+    fn sitesearch_heuristics() {
+        // Same as above, but with MRV/LCV from the start
+        let n = 30;
+        let mut candidate_sites = (0..n).map(|i| vec![i, i + 1]).collect::<Vec<_>>();
+        candidate_sites.push(vec![0]);
+        let classes = (0..(n as usize + 1)).collect::<Vec<_>>();
+        let mut search = SiteSearch::new(&candidate_sites, &classes, n as usize + 1);
+        search.use_heuristics = true;
+        search.budget = None;
+        let solution = search.solve(&PARStopCondition::none()).unwrap();
+        check_solution(&candidate_sites, &classes, &solution);
+    }
+
+    #[test]
+    fn sitesearch_shared_class() {
+        // The first two variables are the same class and can share a site
+        let candidate_sites = vec![vec![0], vec![0, 1], vec![1]];
+        let classes = vec![0, 0, 1];
+        let solution = SiteSearch::new(&candidate_sites, &classes, 2).solve(&PARStopCondition::none()).unwrap();
+        check_solution(&candidate_sites, &classes, &solution);
+        assert_eq!(solution, vec![0, 0, 1]);
+    }
+}
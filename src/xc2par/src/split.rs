@@ -0,0 +1,399 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Decomposition of functions that are too wide to ever fit into a single function block. Wide sums-of-products are
+// broken into several buried macrocells that are then ORed together by the original macrocell, and wide AND terms are
+// broken into several buried macrocells that are then ANDed together. In both cases the partial results come back
+// through the ZIA, so this costs an extra pass through the device.
+
+use std::collections::HashSet;
+use std::fmt;
+use slog::Drain;
+use serde_derive::{Deserialize, Serialize};
+
+use xc2bit::*;
+
+use crate::*;
+use crate::objpool::*;
+
+// These are intentionally much smaller than the hardware limits so that the pieces still leave some room for other
+// logic in whichever function block they end up in.
+const SPLIT_MAX_PTERMS: usize = ANDTERMS_PER_FB / 2;
+const SPLIT_MAX_INPUTS: usize = INPUTS_PER_ANDTERM / 2;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum SplitReason {
+    TooManyPTerms(usize),
+    TooManyInputs(usize),
+}
+
+impl fmt::Display for SplitReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SplitReason::TooManyPTerms(n) => write!(f, "{} p-terms", n),
+            SplitReason::TooManyInputs(n) => write!(f, "{} inputs", n),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct SplitSignal {
+    pub name: String,
+    pub reason: SplitReason,
+    pub new_mcs: Vec<String>,
+}
+
+impl fmt::Display for SplitSignal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{}\" ({}) was split into {} buried macrocells", self.name, self.reason, self.new_mcs.len())
+    }
+}
+
+fn pterm_inputs(pt: &InputGraphPTerm) -> HashSet<InputGraphPTermInput> {
+    pt.inputs_true.iter().chain(pt.inputs_comp.iter()).copied().collect()
+}
+
+impl InputGraph {
+    // Creates a buried macrocell that just ORs together the given p-terms
+    fn add_buried_or(&mut self, name: String, orterm_inputs: Vec<ObjPoolIndex<InputGraphPTerm>>)
+        -> InputGraphPTermInput {
+
+        let mc_idx = self.mcs.insert(InputGraphMacrocell {
+            name,
+            requested_loc: None,
+            io_bits: None,
+            reg_bits: None,
+            xor_bits: Some(InputGraphXor {
+                orterm_inputs,
+                andterm_input: None,
+                invert_out: false,
+            }),
+            io_feedback_used: false,
+            reg_feedback_used: false,
+            xor_feedback_used: true,
+            net_names: InputGraphMacrocellNetNames::default(),
+            area_group: None,
+        });
+
+        (InputGraphPTermInputType::Xor, mc_idx)
+    }
+
+    /// Splits up any function that could never fit into a function block because it either uses more p-terms than a
+    /// function block has or uses more inputs than the ZIA can provide to a function block. Returns a list of the
+    /// signals that were split.
+    pub fn split_wide_functions<L: Into<Option<slog::Logger>>>(&mut self, logger: L) -> Vec<SplitSignal> {
+        let logger = logger.into().unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
+
+        let mut ret = Vec::new();
+
+        // Wide AND terms first so that every p-term is narrow enough when the sums-of-products are partitioned
+        for pt_idx in self.pterms.iter_idx().collect::<Vec<_>>() {
+            let num_inputs = pterm_inputs(self.pterms.get(pt_idx)).len();
+            if num_inputs <= INPUTS_PER_ANDTERM {
+                continue;
+            }
+
+            let pt_name = self.pterms.get(pt_idx).name.clone();
+            let mut new_mcs = Vec::new();
+            while pterm_inputs(self.pterms.get(pt_idx)).len() > INPUTS_PER_ANDTERM {
+                let pt = self.pterms.get(pt_idx);
+                let literals = pt.inputs_true.iter().map(|&x| (x, false))
+                    .chain(pt.inputs_comp.iter().map(|&x| (x, true)))
+                    .collect::<Vec<_>>();
+
+                let mut new_inputs = Vec::new();
+                for chunk in literals.chunks(SPLIT_MAX_INPUTS) {
+                    let name = format!("{}_SPLIT{}", pt_name, new_mcs.len());
+                    let chunk_pt = self.pterms.insert(InputGraphPTerm {
+                        name: format!("{}_PT", name),
+                        requested_loc: None,
+                        inputs_true: chunk.iter().filter(|x| !x.1).map(|x| x.0).collect(),
+                        inputs_comp: chunk.iter().filter(|x| x.1).map(|x| x.0).collect(),
+                    });
+                    new_inputs.push(self.add_buried_or(name.clone(), vec![chunk_pt]));
+                    new_mcs.push(name);
+                }
+
+                let pt = self.pterms.get_mut(pt_idx);
+                pt.inputs_true = new_inputs;
+                pt.inputs_comp = Vec::new();
+            }
+
+            let split = SplitSignal {
+                name: pt_name,
+                reason: SplitReason::TooManyInputs(num_inputs),
+                new_mcs,
+            };
+            info!(logger, "split - split wide p-term";
+                "name" => &split.name,
+                "inputs" => num_inputs,
+                "new macrocells" => split.new_mcs.len());
+            ret.push(split);
+        }
+
+        // Wide sums-of-products
+        for mc_idx in self.mcs.iter_idx().collect::<Vec<_>>() {
+            let mc = self.mcs.get(mc_idx);
+            let xor_bits = if let Some(ref xor_bits) = mc.xor_bits { xor_bits } else { continue; };
+
+            let num_pterms = xor_bits.orterm_inputs.len();
+            let num_inputs = xor_bits.orterm_inputs.iter()
+                .flat_map(|&x| pterm_inputs(self.pterms.get(x)))
+                .collect::<HashSet<_>>().len();
+            if num_pterms <= ANDTERMS_PER_FB && num_inputs <= INPUTS_PER_ANDTERM {
+                continue;
+            }
+
+            if xor_bits.orterm_inputs.iter().any(|&x| self.pterms.get(x).requested_loc.is_some()) {
+                warn!(logger, "split - cannot split function because its p-terms have LOC constraints";
+                    "code" => "SP001",
+                    "name" => &mc.name);
+                continue;
+            }
+
+            let mc_name = mc.name.clone();
+            let mut new_mcs = Vec::new();
+            loop {
+                let orterm_inputs = self.mcs.get(mc_idx).xor_bits.as_ref().unwrap().orterm_inputs.clone();
+                let cur_num_inputs = orterm_inputs.iter()
+                    .flat_map(|&x| pterm_inputs(self.pterms.get(x)))
+                    .collect::<HashSet<_>>().len();
+                if orterm_inputs.len() <= ANDTERMS_PER_FB && cur_num_inputs <= INPUTS_PER_ANDTERM {
+                    break;
+                }
+
+                // First-fit partitioning of the p-terms into groups that are each small enough
+                let mut groups: Vec<(Vec<ObjPoolIndex<InputGraphPTerm>>, HashSet<InputGraphPTermInput>)> = Vec::new();
+                for &pt_idx in &orterm_inputs {
+                    let inputs = pterm_inputs(self.pterms.get(pt_idx));
+                    let group = groups.iter_mut().find(|(pts, group_inputs)| {
+                        pts.len() < SPLIT_MAX_PTERMS && group_inputs.union(&inputs).count() <= SPLIT_MAX_INPUTS
+                    });
+                    if let Some((pts, group_inputs)) = group {
+                        pts.push(pt_idx);
+                        group_inputs.extend(inputs);
+                    } else {
+                        groups.push((vec![pt_idx], inputs));
+                    }
+                }
+
+                let mut new_orterm_inputs = Vec::with_capacity(groups.len());
+                for (pts, _) in groups {
+                    let name = format!("{}_SPLIT{}", mc_name, new_mcs.len());
+                    let feedback = self.add_buried_or(name.clone(), pts);
+                    new_orterm_inputs.push(self.pterms.insert(InputGraphPTerm {
+                        name: format!("{}_FB", name),
+                        requested_loc: None,
+                        inputs_true: vec![feedback],
+                        inputs_comp: Vec::new(),
+                    }));
+                    new_mcs.push(name);
+                }
+                self.mcs.get_mut(mc_idx).xor_bits.as_mut().unwrap().orterm_inputs = new_orterm_inputs;
+            }
+
+            let reason = if num_pterms > ANDTERMS_PER_FB {
+                SplitReason::TooManyPTerms(num_pterms)
+            } else {
+                SplitReason::TooManyInputs(num_inputs)
+            };
+            let split = SplitSignal {
+                name: mc_name,
+                reason,
+                new_mcs,
+            };
+            info!(logger, "split - split wide sum-of-products";
+                "name" => &split.name,
+                "p-terms" => num_pterms,
+                "inputs" => num_inputs,
+                "new macrocells" => split.new_mcs.len());
+            ret.push(split);
+        }
+
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_pin(g: &mut InputGraph, i: usize) -> InputGraphPTermInput {
+        let mc_idx = g.mcs.insert(InputGraphMacrocell {
+            name: format!("in{}", i),
+            requested_loc: None,
+            io_bits: Some(InputGraphIOBuf {
+                input: None,
+                oe: None,
+                schmitt_trigger: false,
+                termination_enabled: false,
+                slew_is_fast: false,
+                uses_data_gate: false,
+                io_standard: None,
+                is_vref: false,
+            }),
+            reg_bits: None,
+            xor_bits: None,
+            io_feedback_used: true,
+            reg_feedback_used: false,
+            xor_feedback_used: false,
+            net_names: InputGraphMacrocellNetNames::default(),
+            area_group: None,
+        });
+        (InputGraphPTermInputType::Pin, mc_idx)
+    }
+
+    fn add_output(g: &mut InputGraph, orterm_inputs: Vec<ObjPoolIndex<InputGraphPTerm>>)
+        -> ObjPoolIndex<InputGraphMacrocell> {
+
+        g.mcs.insert(InputGraphMacrocell {
+            name: "out".to_owned(),
+            requested_loc: None,
+            io_bits: Some(InputGraphIOBuf {
+                input: Some(InputGraphIOInputType::Xor),
+                oe: None,
+                schmitt_trigger: false,
+                termination_enabled: false,
+                slew_is_fast: false,
+                uses_data_gate: false,
+                io_standard: None,
+                is_vref: false,
+            }),
+            reg_bits: None,
+            xor_bits: Some(InputGraphXor {
+                orterm_inputs,
+                andterm_input: None,
+                invert_out: false,
+            }),
+            io_feedback_used: false,
+            reg_feedback_used: false,
+            xor_feedback_used: false,
+            net_names: InputGraphMacrocellNetNames::default(),
+            area_group: None,
+        })
+    }
+
+    fn new_graph() -> InputGraph {
+        InputGraph {
+            mcs: ObjPool::new(),
+            pterms: ObjPool::new(),
+            bufg_clks: ObjPool::new(),
+            bufg_gts: ObjPool::new(),
+            bufg_gsr: ObjPool::new(),
+            clock_div: None,
+            data_gate: None,
+        }
+    }
+
+    fn eval_xor(g: &InputGraph, mc_idx: ObjPoolIndex<InputGraphMacrocell>, pins: &[InputGraphPTermInput],
+        assignment: u64) -> bool {
+
+        let eval_input = |x: &InputGraphPTermInput| {
+            match x.0 {
+                InputGraphPTermInputType::Pin => {
+                    let i = pins.iter().position(|y| y == x).unwrap();
+                    assignment & (1 << i) != 0
+                },
+                InputGraphPTermInputType::Xor => eval_xor(g, x.1, pins, assignment),
+                InputGraphPTermInputType::Reg => unreachable!(),
+            }
+        };
+
+        let xor_bits = g.mcs.get(mc_idx).xor_bits.as_ref().unwrap();
+        xor_bits.orterm_inputs.iter().any(|&pt_idx| {
+            let pt = g.pterms.get(pt_idx);
+            pt.inputs_true.iter().all(&eval_input) && !pt.inputs_comp.iter().any(&eval_input)
+        }) != xor_bits.invert_out
+    }
+
+    fn check_fits(g: &InputGraph) {
+        for mc in g.mcs.iter() {
+            if let Some(ref xor_bits) = mc.xor_bits {
+                assert!(xor_bits.orterm_inputs.len() <= ANDTERMS_PER_FB);
+                let inputs = xor_bits.orterm_inputs.iter()
+                    .flat_map(|&x| pterm_inputs(g.pterms.get(x)))
+                    .collect::<HashSet<_>>();
+                assert!(inputs.len() <= INPUTS_PER_ANDTERM);
+            }
+        }
+    }
+
+    #[test]
+    fn split_too_many_pterms() {
+        // 7-input parity needs 64 p-terms
+        let mut g = new_graph();
+        let pins = (0..7).map(|i| add_pin(&mut g, i)).collect::<Vec<_>>();
+        let mut orterm_inputs = Vec::new();
+        for minterm in 0..128u64 {
+            if minterm.count_ones() % 2 == 1 {
+                orterm_inputs.push(g.pterms.insert(InputGraphPTerm {
+                    name: format!("pt{}", minterm),
+                    requested_loc: None,
+                    inputs_true: (0..7).filter(|i| minterm & (1 << i) != 0).map(|i| pins[i]).collect(),
+                    inputs_comp: (0..7).filter(|i| minterm & (1 << i) == 0).map(|i| pins[i]).collect(),
+                }));
+            }
+        }
+        let out = add_output(&mut g, orterm_inputs);
+
+        let splits = g.split_wide_functions(None);
+        assert_eq!(splits.len(), 1);
+        assert_eq!(splits[0].reason, SplitReason::TooManyPTerms(64));
+        check_fits(&g);
+        for assignment in 0..128u64 {
+            assert_eq!(eval_xor(&g, out, &pins, assignment), assignment.count_ones() % 2 == 1);
+        }
+
+        let device_type = XC2DeviceSpeedPackage::from_str("xc2c32a-4-vq44").unwrap();
+        if let PARResult::Success(_) = do_par(&mut g, device_type, &XC2ParOptions::new(), None) {} else {
+            panic!("PAR failed");
+        }
+    }
+
+    #[test]
+    fn split_too_many_inputs() {
+        // A single AND of 45 inputs, some of them inverted
+        let mut g = new_graph();
+        let pins = (0..45).map(|i| add_pin(&mut g, i)).collect::<Vec<_>>();
+        let pt = g.pterms.insert(InputGraphPTerm {
+            name: "pt".to_owned(),
+            requested_loc: None,
+            inputs_true: pins.iter().step_by(2).copied().collect(),
+            inputs_comp: pins.iter().skip(1).step_by(2).copied().collect(),
+        });
+        let out = add_output(&mut g, vec![pt]);
+
+        let splits = g.split_wide_functions(None);
+        assert_eq!(splits.len(), 1);
+        assert_eq!(splits[0].reason, SplitReason::TooManyInputs(45));
+        check_fits(&g);
+
+        let expected = (0..45).step_by(2).fold(0u64, |acc, i| acc | (1 << i));
+        assert!(eval_xor(&g, out, &pins, expected));
+        for i in 0..45 {
+            assert!(!eval_xor(&g, out, &pins, expected ^ (1 << i)));
+        }
+    }
+}
@@ -0,0 +1,708 @@
+/*
+Copyright (c) 2016-2017, Robert Ou <rqou@robertou.com> and contributors
+All rights reserved.
+
+Redistribution and use in source and binary forms, with or without
+modification, are permitted provided that the following conditions are met:
+
+1. Redistributions of source code must retain the above copyright notice,
+   this list of conditions and the following disclaimer.
+2. Redistributions in binary form must reproduce the above copyright notice,
+   this list of conditions and the following disclaimer in the documentation
+   and/or other materials provided with the distribution.
+
+THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS" AND
+ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE IMPLIED
+WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE ARE
+DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE LIABLE
+FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR CONSEQUENTIAL
+DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF SUBSTITUTE GOODS OR
+SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS INTERRUPTION) HOWEVER
+CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY,
+OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
+OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+*/
+
+// Support for the generic gate-level cells that a plain Yosys "synth" produces. The frontend collects these cells
+// separately, and this pass then collapses each cone of gates into a sum-of-products and emits the same
+// ANDTERM/ORTERM/MACROCELL_XOR/register nodes that the CoolRunner-II techmapping in Yosys would have produced.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use xc2bit::*;
+
+use crate::*;
+use crate::minimize::*;
+use crate::objpool::*;
+
+// Covers that get bigger than this while collapsing a cone cause the cone to be cut into smaller pieces
+const MAX_CONE_CUBES: usize = 256;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum GenericGateKind {
+    Buf,
+    Not,
+    And,
+    Nand,
+    Or,
+    Nor,
+    Xor,
+    Xnor,
+    AndNot,
+    OrNot,
+    Mux,
+    NMux,
+}
+
+impl GenericGateKind {
+    pub(crate) fn from_cell_type(cell_type: &str) -> Option<Self> {
+        match cell_type {
+            "$_BUF_" => Some(GenericGateKind::Buf),
+            "$_NOT_" => Some(GenericGateKind::Not),
+            "$_AND_" => Some(GenericGateKind::And),
+            "$_NAND_" => Some(GenericGateKind::Nand),
+            "$_OR_" => Some(GenericGateKind::Or),
+            "$_NOR_" => Some(GenericGateKind::Nor),
+            "$_XOR_" => Some(GenericGateKind::Xor),
+            "$_XNOR_" => Some(GenericGateKind::Xnor),
+            "$_ANDNOT_" => Some(GenericGateKind::AndNot),
+            "$_ORNOT_" => Some(GenericGateKind::OrNot),
+            "$_MUX_" => Some(GenericGateKind::Mux),
+            "$_NMUX_" => Some(GenericGateKind::NMux),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn input_names(&self) -> &'static [&'static str] {
+        match self {
+            &GenericGateKind::Buf | &GenericGateKind::Not => &["A"],
+            &GenericGateKind::Mux | &GenericGateKind::NMux => &["A", "B", "S"],
+            _ => &["A", "B"],
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct GenericGate {
+    pub(crate) name: String,
+    pub(crate) kind: GenericGateKind,
+    pub(crate) inputs: Vec<ObjPoolIndex<IntermediateGraphNet>>,
+    pub(crate) output: ObjPoolIndex<IntermediateGraphNet>,
+}
+
+// The polarity of each pin is true for active-high/rising-edge
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct GenericFFType {
+    pub(crate) mode: XC2MCRegMode,
+    pub(crate) clk_name: &'static str,
+    pub(crate) clk_pol: bool,
+    pub(crate) en_pol: Option<bool>,
+    pub(crate) set: Option<(&'static str, bool)>,
+    pub(crate) reset: Option<(&'static str, bool)>,
+}
+
+impl GenericFFType {
+    pub(crate) fn from_cell_type(cell_type: &str) -> Option<Self> {
+        if !cell_type.starts_with("$_") || !cell_type.ends_with('_') || cell_type.len() < 4 {
+            return None;
+        }
+        let parts = cell_type[2..cell_type.len() - 1].split('_').collect::<Vec<_>>();
+        if parts.len() != 2 {
+            return None;
+        }
+
+        let mut pols = Vec::new();
+        for c in parts[1].chars() {
+            pols.push(match c {
+                'P' | '1' => true,
+                'N' | '0' => false,
+                _ => return None,
+            });
+        }
+
+        // An async reset to 1 is actually a set
+        let rst = |pol: bool, val: bool| if val {
+            (None, Some(("R", pol)))
+        } else {
+            (Some(("R", pol)), None)
+        };
+
+        let (mode, clk_name, en_pol, reset, set) = match (parts[0], pols.len()) {
+            ("DFF", 1) => (XC2MCRegMode::DFF, "C", None, None, None),
+            ("DFF", 3) => {
+                let (reset, set) = rst(pols[1], pols[2]);
+                (XC2MCRegMode::DFF, "C", None, reset, set)
+            },
+            ("DFFE", 2) => (XC2MCRegMode::DFFCE, "C", Some(pols[1]), None, None),
+            ("DFFE", 4) => {
+                let (reset, set) = rst(pols[1], pols[2]);
+                (XC2MCRegMode::DFFCE, "C", Some(pols[3]), reset, set)
+            },
+            ("DFFSR", 3) => (XC2MCRegMode::DFF, "C", None, Some(("R", pols[2])), Some(("S", pols[1]))),
+            ("DFFSRE", 4) =>
+                (XC2MCRegMode::DFFCE, "C", Some(pols[3]), Some(("R", pols[2])), Some(("S", pols[1]))),
+            ("DLATCH", 1) => (XC2MCRegMode::LATCH, "E", None, None, None),
+            ("DLATCH", 3) => {
+                let (reset, set) = rst(pols[1], pols[2]);
+                (XC2MCRegMode::LATCH, "E", None, reset, set)
+            },
+            ("DLATCHSR", 3) => (XC2MCRegMode::LATCH, "E", None, Some(("R", pols[2])), Some(("S", pols[1]))),
+            _ => return None,
+        };
+
+        Some(GenericFFType {
+            mode,
+            clk_name,
+            clk_pol: pols[0],
+            en_pol,
+            set,
+            reset,
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct GenericFF {
+    pub(crate) name: String,
+    pub(crate) location: Option<RequestedLocation>,
+    pub(crate) mode: XC2MCRegMode,
+    pub(crate) clk_pol: bool,
+    pub(crate) clk: ObjPoolIndex<IntermediateGraphNet>,
+    pub(crate) en: Option<(ObjPoolIndex<IntermediateGraphNet>, bool)>,
+    pub(crate) set: Option<(ObjPoolIndex<IntermediateGraphNet>, bool)>,
+    pub(crate) reset: Option<(ObjPoolIndex<IntermediateGraphNet>, bool)>,
+    pub(crate) d: ObjPoolIndex<IntermediateGraphNet>,
+    pub(crate) q: ObjPoolIndex<IntermediateGraphNet>,
+    pub(crate) init_state: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum GenericPortDirection {
+    Input,
+    Output,
+}
+
+// A bit of a module port that is not connected to an IO buffer and needs one to be created for it
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct GenericPort {
+    pub(crate) name: String,
+    pub(crate) direction: GenericPortDirection,
+    pub(crate) net: ObjPoolIndex<IntermediateGraphNet>,
+}
+
+// Everything the frontend collected that still has to be mapped onto device primitives
+pub(crate) struct GenericLogic {
+    pub(crate) gates: Vec<GenericGate>,
+    pub(crate) ffs: Vec<GenericFF>,
+    pub(crate) ports: Vec<GenericPort>,
+    // Nets that carry the keep attribute
+    pub(crate) keep_nets: Vec<ObjPoolIndex<IntermediateGraphNet>>,
+}
+
+impl GenericLogic {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.gates.is_empty() && self.ffs.is_empty() && self.ports.is_empty()
+    }
+}
+
+pub(crate) fn cover_and(a: &[Cube], b: &[Cube]) -> Vec<Cube> {
+    let mut ret = Vec::new();
+    for x in a {
+        for y in b {
+            if x.intersects(y) {
+                ret.push(Cube {pos: x.pos | y.pos, neg: x.neg | y.neg});
+            }
+        }
+    }
+    remove_contained(&mut ret);
+    ret
+}
+
+pub(crate) fn cover_or(a: &[Cube], b: &[Cube]) -> Vec<Cube> {
+    let mut ret = a.iter().chain(b.iter()).cloned().collect::<Vec<_>>();
+    remove_contained(&mut ret);
+    ret
+}
+
+struct Lowering<'a> {
+    nodes: &'a mut ObjPool<IntermediateGraphNode>,
+    nets: &'a mut ObjPool<IntermediateGraphNet>,
+    vdd_net: ObjPoolIndex<IntermediateGraphNet>,
+    vss_net: ObjPoolIndex<IntermediateGraphNet>,
+
+    gates: HashMap<ObjPoolIndex<IntermediateGraphNet>, GenericGate>,
+    // Outputs of gates that need to become real macrocells because something other than another gate uses them
+    cut_nets: HashSet<ObjPoolIndex<IntermediateGraphNet>>,
+    cut_worklist: VecDeque<ObjPoolIndex<IntermediateGraphNet>>,
+    new_net_count: usize,
+}
+
+// The result of collapsing a cone: the nets that the cube variables refer to, and the cover itself
+type ConeCover = (Vec<ObjPoolIndex<IntermediateGraphNet>>, Vec<Cube>);
+
+impl<'a> Lowering<'a> {
+    fn new_net(&mut self, name: String) -> ObjPoolIndex<IntermediateGraphNet> {
+        self.new_net_count += 1;
+        self.nets.insert(IntermediateGraphNet {
+            name: Some(name),
+            source: None,
+            sinks: Vec::new(),
+        })
+    }
+
+    fn cut(&mut self, net: ObjPoolIndex<IntermediateGraphNet>) {
+        if self.gates.contains_key(&net) && self.cut_nets.insert(net) {
+            self.cut_worklist.push_back(net);
+        }
+    }
+
+    fn is_gate(&self, net: ObjPoolIndex<IntermediateGraphNet>, is_root: bool) -> bool {
+        self.gates.contains_key(&net) && (is_root || !self.cut_nets.contains(&net))
+    }
+
+    fn collect_vars(&self, net: ObjPoolIndex<IntermediateGraphNet>, is_root: bool,
+        vars: &mut Vec<ObjPoolIndex<IntermediateGraphNet>>,
+        visiting: &mut HashSet<ObjPoolIndex<IntermediateGraphNet>>,
+        visited: &mut HashSet<ObjPoolIndex<IntermediateGraphNet>>) -> Result<(), FrontendError> {
+
+        if net == self.vdd_net || net == self.vss_net {
+            return Ok(());
+        }
+
+        if self.is_gate(net, is_root) {
+            let gate = &self.gates[&net];
+            if visiting.contains(&net) {
+                return Err(FrontendError::CombinationalLoop(gate.name.to_owned()));
+            }
+            if visited.contains(&net) {
+                return Ok(());
+            }
+
+            visiting.insert(net);
+            for &input in &gate.inputs {
+                self.collect_vars(input, false, vars, visiting, visited)?;
+            }
+            visiting.remove(&net);
+            visited.insert(net);
+        } else if !vars.contains(&net) {
+            vars.push(net);
+        }
+
+        Ok(())
+    }
+
+    fn eval(&self, net: ObjPoolIndex<IntermediateGraphNet>, is_root: bool,
+        vars: &[ObjPoolIndex<IntermediateGraphNet>],
+        memo: &mut HashMap<ObjPoolIndex<IntermediateGraphNet>, Vec<Cube>>, budget: &mut Budget)
+        -> Option<Vec<Cube>> {
+
+        if net == self.vdd_net {
+            return Some(vec![Cube::UNIVERSE]);
+        }
+        if net == self.vss_net {
+            return Some(Vec::new());
+        }
+        if !self.is_gate(net, is_root) {
+            let var = vars.iter().position(|&x| x == net).unwrap();
+            return Some(vec![Cube {pos: 1 << var, neg: 0}]);
+        }
+        if let Some(x) = memo.get(&net) {
+            return Some(x.clone());
+        }
+
+        let gate = &self.gates[&net];
+        let mut inputs = Vec::with_capacity(gate.inputs.len());
+        for &input in &gate.inputs {
+            inputs.push(self.eval(input, false, vars, memo, budget)?);
+        }
+
+        let ret = match gate.kind {
+            GenericGateKind::Buf => inputs[0].clone(),
+            GenericGateKind::Not => complement(&inputs[0], budget)?,
+            GenericGateKind::And => cover_and(&inputs[0], &inputs[1]),
+            GenericGateKind::Nand => complement(&cover_and(&inputs[0], &inputs[1]), budget)?,
+            GenericGateKind::Or => cover_or(&inputs[0], &inputs[1]),
+            GenericGateKind::Nor => complement(&cover_or(&inputs[0], &inputs[1]), budget)?,
+            GenericGateKind::Xor | GenericGateKind::Xnor => {
+                let a_comp = complement(&inputs[0], budget)?;
+                let b_comp = complement(&inputs[1], budget)?;
+                if gate.kind == GenericGateKind::Xor {
+                    cover_or(&cover_and(&inputs[0], &b_comp), &cover_and(&a_comp, &inputs[1]))
+                } else {
+                    cover_or(&cover_and(&inputs[0], &inputs[1]), &cover_and(&a_comp, &b_comp))
+                }
+            },
+            GenericGateKind::AndNot => cover_and(&inputs[0], &complement(&inputs[1], budget)?),
+            GenericGateKind::OrNot => cover_or(&inputs[0], &complement(&inputs[1], budget)?),
+            GenericGateKind::Mux | GenericGateKind::NMux => {
+                let s_comp = complement(&inputs[2], budget)?;
+                let mux = cover_or(&cover_and(&s_comp, &inputs[0]), &cover_and(&inputs[2], &inputs[1]));
+                if gate.kind == GenericGateKind::Mux {
+                    mux
+                } else {
+                    complement(&mux, budget)?
+                }
+            },
+        };
+
+        if ret.len() > MAX_CONE_CUBES {
+            return None;
+        }
+
+        memo.insert(net, ret.clone());
+        Some(ret)
+    }
+
+    // Collapses the cone of gates driving the given net, cutting it into smaller pieces as needed
+    fn cone_cover(&mut self, net: ObjPoolIndex<IntermediateGraphNet>, logger: &slog::Logger)
+        -> Result<ConeCover, FrontendError> {
+
+        loop {
+            let mut vars = Vec::new();
+            self.collect_vars(net, true, &mut vars, &mut HashSet::new(), &mut HashSet::new())?;
+
+            if vars.len() <= 64 {
+                let mut budget = Budget(MINIMIZE_BUDGET);
+                if let Some(cover) = self.eval(net, true, &vars, &mut HashMap::new(), &mut budget) {
+                    return Ok((vars, cover));
+                }
+            }
+
+            // Too big, so turn the inputs of this gate into separate macrocells
+            let gate = &self.gates[&net];
+            debug!(logger, "techmap - cutting cone";
+                "gate" => &gate.name,
+                "vars" => vars.len());
+            let num_cuts = self.cut_nets.len();
+            for input in gate.inputs.clone() {
+                self.cut(input);
+            }
+            if self.cut_nets.len() == num_cuts {
+                // Nothing left to cut. This shouldn't happen with gates this small.
+                let gate = &self.gates[&net];
+                error!(logger, "techmap - cannot simplify gate"; "code" => "TM001", "gate" => &gate.name);
+                return Err(FrontendError::IllegalGenericCell(gate.name.to_owned()));
+            }
+        }
+    }
+
+    fn new_andterm(&mut self, name: String, vars: &[ObjPoolIndex<IntermediateGraphNet>], cube: &Cube)
+        -> ObjPoolIndex<IntermediateGraphNet> {
+
+        let output = self.new_net(name.clone());
+        let mut inputs_true = Vec::new();
+        let mut inputs_comp = Vec::new();
+        for (i, &var) in vars.iter().enumerate() {
+            if cube.pos & (1 << i) != 0 {
+                inputs_true.push(var);
+            }
+            if cube.neg & (1 << i) != 0 {
+                inputs_comp.push(var);
+            }
+        }
+
+        self.nodes.insert(IntermediateGraphNode {
+            name,
+            variant: IntermediateGraphNodeVariant::AndTerm {
+                inputs_true,
+                inputs_comp,
+                output,
+            },
+            location: None,
+        });
+
+        output
+    }
+
+    // Emits the ANDTERM/ORTERM/MACROCELL_XOR nodes for a sum-of-products driving the given net
+    fn emit_sop(&mut self, name: &str, output: ObjPoolIndex<IntermediateGraphNet>,
+        vars: &[ObjPoolIndex<IntermediateGraphNet>], cover: &[Cube], location: Option<RequestedLocation>) {
+
+        let (cover, invert_out) = minimize_function(cover).unwrap_or_else(|| (cover.to_owned(), false));
+
+        let orterm_input = if cover.is_empty() {
+            None
+        } else {
+            let mut inputs = Vec::with_capacity(cover.len());
+            for (i, cube) in cover.iter().enumerate() {
+                inputs.push(self.new_andterm(format!("{}_PT{}", name, i), vars, cube));
+            }
+
+            let or_output = self.new_net(format!("{}_OR", name));
+            self.nodes.insert(IntermediateGraphNode {
+                name: format!("{}_OR", name),
+                variant: IntermediateGraphNodeVariant::OrTerm {
+                    inputs,
+                    output: or_output,
+                },
+                location: None,
+            });
+            Some(or_output)
+        };
+
+        self.nodes.insert(IntermediateGraphNode {
+            name: name.to_owned(),
+            variant: IntermediateGraphNodeVariant::Xor {
+                orterm_input,
+                andterm_input: None,
+                invert_out,
+                output,
+            },
+            location,
+        });
+    }
+
+    // Creates a net that is driven by a macrocell XOR computing the same function as the given net
+    fn emit_xor_copy(&mut self, name: &str, net: ObjPoolIndex<IntermediateGraphNet>,
+        location: Option<RequestedLocation>, logger: &slog::Logger)
+        -> Result<ObjPoolIndex<IntermediateGraphNet>, FrontendError> {
+
+        let (vars, cover) = self.cone_cover(net, logger)?;
+        let output = self.new_net(name.to_owned());
+        self.emit_sop(name, output, &vars, &cover, location);
+        Ok(output)
+    }
+
+    // Creates a single p-term for a register or IO buffer control input. Returns None if it can never be active.
+    fn emit_control_pterm(&mut self, name: &str, net: ObjPoolIndex<IntermediateGraphNet>, active_high: bool,
+        logger: &slog::Logger) -> Result<Option<ObjPoolIndex<IntermediateGraphNet>>, FrontendError> {
+
+        let (vars, cover) = self.cone_cover(net, logger)?;
+        let mut budget = Budget(MINIMIZE_BUDGET);
+        let complemented = complement(&cover, &mut budget);
+        let simplified = complemented.and_then(|off| {
+            let (on, off) = if active_high { (cover, off) } else { (off, cover) };
+            minimize_cover(&on, &off, &mut budget)
+        });
+
+        match simplified {
+            Some(ref x) if x.is_empty() => Ok(None),
+            Some(ref x) if x.len() == 1 => Ok(Some(self.new_andterm(name.to_owned(), &vars, &x[0]))),
+            _ => {
+                // Needs an entire macrocell
+                self.cut(net);
+                let cube = if active_high {
+                    Cube {pos: 1, neg: 0}
+                } else {
+                    Cube {pos: 0, neg: 1}
+                };
+                Ok(Some(self.new_andterm(name.to_owned(), &[net], &cube)))
+            }
+        }
+    }
+}
+
+// Returns the set of nets that were only used internally by the generic gates. These are never driven by anything.
+pub(crate) fn lower_generic_logic(nodes: &mut ObjPool<IntermediateGraphNode>,
+    nets: &mut ObjPool<IntermediateGraphNet>,
+    vdd_net: ObjPoolIndex<IntermediateGraphNet>, vss_net: ObjPoolIndex<IntermediateGraphNet>,
+    generic: GenericLogic, logger: &slog::Logger)
+    -> Result<HashSet<ObjPoolIndex<IntermediateGraphNet>>, FrontendError> {
+
+    let GenericLogic {gates, ffs, ports, keep_nets} = generic;
+
+    // Figure out what the already-mapped nodes drive and use
+    let mut mapped_outputs = HashMap::new();
+    let mut mapped_inputs = Vec::new();
+    for node_idx in nodes.iter_idx() {
+        match nodes.get(node_idx).variant {
+            IntermediateGraphNodeVariant::AndTerm{ref inputs_true, ref inputs_comp, output} => {
+                mapped_inputs.extend(inputs_true.iter().cloned());
+                mapped_inputs.extend(inputs_comp.iter().cloned());
+                mapped_outputs.insert(output, node_idx);
+            },
+            IntermediateGraphNodeVariant::OrTerm{ref inputs, output} => {
+                mapped_inputs.extend(inputs.iter().cloned());
+                mapped_outputs.insert(output, node_idx);
+            },
+            IntermediateGraphNodeVariant::Xor{orterm_input, andterm_input, output, ..} => {
+                mapped_inputs.extend(orterm_input);
+                mapped_inputs.extend(andterm_input);
+                mapped_outputs.insert(output, node_idx);
+            },
+            IntermediateGraphNodeVariant::Reg{set_input, reset_input, ce_input, dt_input, clk_input, output, ..} => {
+                mapped_inputs.extend(set_input);
+                mapped_inputs.extend(reset_input);
+                mapped_inputs.extend(ce_input);
+                mapped_inputs.push(dt_input);
+                mapped_inputs.push(clk_input);
+                mapped_outputs.insert(output, node_idx);
+            },
+            IntermediateGraphNodeVariant::BufgClk{input, output} |
+            IntermediateGraphNodeVariant::BufgGTS{input, output, ..} |
+            IntermediateGraphNodeVariant::BufgGSR{input, output, ..} => {
+                mapped_inputs.push(input);
+                mapped_outputs.insert(output, node_idx);
+            },
+            IntermediateGraphNodeVariant::ClkDiv{input, output, cdrst, ..} => {
+                mapped_inputs.push(input);
+                mapped_inputs.extend(cdrst);
+                mapped_outputs.insert(output, node_idx);
+            },
+            IntermediateGraphNodeVariant::DataGate{input} => {
+                mapped_inputs.push(input);
+            },
+            IntermediateGraphNodeVariant::IOBuf{input, oe, output, ..} => {
+                mapped_inputs.extend(input);
+                mapped_inputs.extend(oe);
+                if let Some(output) = output {
+                    mapped_outputs.insert(output, node_idx);
+                }
+            },
+            IntermediateGraphNodeVariant::InBuf{output, ..} => {
+                mapped_outputs.insert(output, node_idx);
+            },
+        }
+    }
+
+    let gate_outputs = gates.iter().map(|x| x.output).collect::<HashSet<_>>();
+    let mut s = Lowering {
+        nodes,
+        nets,
+        vdd_net,
+        vss_net,
+        gates: gates.into_iter().map(|x| (x.output, x)).collect(),
+        cut_nets: HashSet::new(),
+        cut_worklist: VecDeque::new(),
+        new_net_count: 0,
+    };
+
+    for net in mapped_inputs {
+        s.cut(net);
+    }
+    // Nets that should be kept must not be collapsed into whatever uses them
+    for &net in &keep_nets {
+        s.cut(net);
+    }
+
+    // IO buffers for ports that don't have them
+    let ff_outputs = ffs.iter().map(|x| x.q).collect::<HashSet<_>>();
+    let mut claimed_outputs = HashSet::new();
+    for port in ports {
+        match port.direction {
+            GenericPortDirection::Input => {
+                debug!(logger, "techmap - adding input buffer"; "port" => &port.name);
+                s.nodes.insert(IntermediateGraphNode {
+                    name: port.name.clone(),
+                    variant: IntermediateGraphNodeVariant::InBuf {
+                        output: port.net,
+                        schmitt_trigger: false,
+                        termination_enabled: false,
+                        uses_data_gate: false,
+                        io_standard: None,
+                        port_name: Some(port.name),
+                    },
+                    location: None,
+                });
+            },
+            GenericPortDirection::Output => {
+                debug!(logger, "techmap - adding output buffer"; "port" => &port.name);
+                // The output buffer must be driven directly by a register or by an XOR
+                let is_reg_or_xor = ff_outputs.contains(&port.net) || mapped_outputs.get(&port.net)
+                    .is_some_and(|&x| matches!(s.nodes.get(x).variant,
+                        IntermediateGraphNodeVariant::Xor{..} | IntermediateGraphNodeVariant::Reg{..}));
+                let is_const = port.net == vdd_net || port.net == vss_net;
+                let input = if is_const || (is_reg_or_xor && claimed_outputs.insert(port.net)) {
+                    port.net
+                } else {
+                    s.emit_xor_copy(&format!("{}_XOR", port.name), port.net, None, logger)?
+                };
+
+                s.nodes.insert(IntermediateGraphNode {
+                    name: port.name.clone(),
+                    variant: IntermediateGraphNodeVariant::IOBuf {
+                        input: Some(input),
+                        oe: None,
+                        output: None,
+                        schmitt_trigger: false,
+                        termination_enabled: false,
+                        slew_is_fast: false,
+                        uses_data_gate: false,
+                        io_standard: None,
+                        port_name: Some(port.name),
+                    },
+                    location: None,
+                });
+            },
+        }
+    }
+
+    // Registers
+    for ff in ffs {
+        debug!(logger, "techmap - mapping register"; "name" => &ff.name);
+
+        // Every register gets its own XOR so that it never has to be shared
+        let dt_input = s.emit_xor_copy(&format!("{}_D", ff.name), ff.d, ff.location, logger)?;
+
+        let clk_input = if let Some(&IntermediateGraphNodeVariant::BufgClk{..}) |
+            Some(&IntermediateGraphNodeVariant::ClkDiv{..}) =
+            mapped_outputs.get(&ff.clk).map(|&x| &s.nodes.get(x).variant) {
+            ff.clk
+        } else if let Some(x) = s.emit_control_pterm(&format!("{}_CLK", ff.name), ff.clk, true, logger)? {
+            x
+        } else {
+            error!(logger, "techmap - register clock is constant"; "code" => "TM002", "name" => &ff.name);
+            return Err(FrontendError::IllegalGenericCell(ff.name));
+        };
+
+        let (mode, ce_input) = if let Some((en, en_pol)) = ff.en {
+            if (en == vdd_net && en_pol) || (en == vss_net && !en_pol) {
+                (XC2MCRegMode::DFF, None)
+            } else if let Some(x) = s.emit_control_pterm(&format!("{}_CE", ff.name), en, en_pol, logger)? {
+                (XC2MCRegMode::DFFCE, Some(x))
+            } else {
+                error!(logger, "techmap - register is never enabled"; "code" => "TM003", "name" => &ff.name);
+                return Err(FrontendError::IllegalGenericCell(ff.name));
+            }
+        } else {
+            (ff.mode, None)
+        };
+
+        let mut rs_input = |rs: Option<(ObjPoolIndex<IntermediateGraphNet>, bool)>, suffix: &str| {
+            if let Some((net, pol)) = rs {
+                if pol {
+                    if let Some(&IntermediateGraphNodeVariant::BufgGSR{..}) =
+                        mapped_outputs.get(&net).map(|&x| &s.nodes.get(x).variant) {
+                        return Ok(Some(net));
+                    }
+                }
+                s.emit_control_pterm(&format!("{}_{}", ff.name, suffix), net, pol, logger)
+            } else {
+                Ok(None)
+            }
+        };
+        let set_input = rs_input(ff.set, "S")?;
+        let reset_input = rs_input(ff.reset, "R")?;
+
+        s.nodes.insert(IntermediateGraphNode {
+            name: ff.name,
+            variant: IntermediateGraphNodeVariant::Reg {
+                mode,
+                clkinv: !ff.clk_pol,
+                clkddr: false,
+                init_state: ff.init_state,
+                set_input,
+                reset_input,
+                ce_input,
+                dt_input,
+                clk_input,
+                output: ff.q,
+            },
+            location: ff.location,
+        });
+    }
+
+    // Finally, everything that has to be a macrocell on its own. Processing these can cause even more cuts.
+    while let Some(net) = s.cut_worklist.pop_front() {
+        let (vars, cover) = s.cone_cover(net, logger)?;
+        let name = s.gates[&net].name.clone();
+        debug!(logger, "techmap - mapping gate output to macrocell"; "gate" => &name);
+        s.emit_sop(&name, net, &vars, &cover, None);
+    }
+
+    info!(logger, "techmap - mapped generic cells";
+        "macrocells" => s.cut_nets.len(),
+        "new nets" => s.new_net_count);
+
+    Ok(gate_outputs.into_iter().filter(|x| !s.cut_nets.contains(x)).collect())
+}